@@ -0,0 +1,42 @@
+//! Text vs binary DataChannel input parsing throughput.
+//!
+//! Run with `cargo bench --bench input_parsing`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use ivnc::webrtc::data_channel::{InputDataChannel, BIN_KEY, BIN_MOUSE_MOVE};
+
+fn mouse_move_frame() -> Vec<u8> {
+    let mut frame = vec![BIN_MOUSE_MOVE];
+    frame.extend_from_slice(&1234i32.to_le_bytes());
+    frame.extend_from_slice(&567i32.to_le_bytes());
+    frame.extend_from_slice(&1u32.to_le_bytes());
+    frame
+}
+
+fn key_frame() -> Vec<u8> {
+    let mut frame = vec![BIN_KEY];
+    frame.extend_from_slice(&0xff08u32.to_le_bytes());
+    frame.push(1);
+    frame
+}
+
+fn bench_input_parsing(c: &mut Criterion) {
+    let mouse = mouse_move_frame();
+    let key = key_frame();
+
+    c.bench_function("text_mouse_move", |b| {
+        b.iter(|| InputDataChannel::parse_input_text(black_box("m,1234,567,1,0")).unwrap())
+    });
+    c.bench_function("binary_mouse_move", |b| {
+        b.iter(|| InputDataChannel::parse_input_binary(black_box(&mouse)).unwrap())
+    });
+    c.bench_function("text_key", |b| {
+        b.iter(|| InputDataChannel::parse_input_text(black_box("k,0xff08,1")).unwrap())
+    });
+    c.bench_function("binary_key", |b| {
+        b.iter(|| InputDataChannel::parse_input_binary(black_box(&key)).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_input_parsing);
+criterion_main!(benches);
@@ -52,6 +52,15 @@ cp "$WEB_DIR"/lib/*.js "$DIST_DIR/lib/"
 cp "$WEB_DIR/manifest.json" "$DIST_DIR/"
 cp "$WEB_DIR/sw.js" "$DIST_DIR/"
 cp -r "$WEB_DIR/icons" "$DIST_DIR/icons"
+
+# Precompressed variants, served when the browser accepts them
+find "$DIST_DIR" -type f \( -name '*.js' -o -name '*.css' -o -name '*.html' -o -name '*.json' -o -name '*.svg' \) |
+while read -r f; do
+    gzip -9 -k -f "$f"
+    if command -v brotli >/dev/null 2>&1; then
+        brotli -q 11 -k -f "$f"
+    fi
+done
 echo "Frontend -> $DIST_DIR"
 ls -R "$DIST_DIR"
 
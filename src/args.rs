@@ -1,7 +1,7 @@
 use clap::Parser;
 use std::path::PathBuf;
 
-use crate::config;
+use ivnc::config;
 
 #[derive(Parser, Debug)]
 #[command(name = "ivnc")]
@@ -73,6 +73,39 @@ pub struct Args {
     #[arg(long, action)]
     pub tls: bool,
 
+    /// PEM certificate chain for HTTPS (implies --tls, requires --tls-key)
+    #[arg(long)]
+    pub tls_cert: Option<String>,
+
+    /// PEM private key for HTTPS
+    #[arg(long)]
+    pub tls_key: Option<String>,
+
+    /// Run preflight checks (libraries, plugins, a test encode) and exit
+    #[arg(long, action)]
+    pub selftest: bool,
+
+    /// Replay an input recording against a running server and exit
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+
+    /// Base URL of the server to replay against
+    #[arg(long, default_value = "http://127.0.0.1:8008")]
+    pub replay_url: String,
+
+    /// Replay speed multiplier
+    #[arg(long, default_value = "1.0")]
+    pub replay_speed: f64,
+
+    /// Encode a synthetic moving pattern with the configured settings,
+    /// print fps, latency and bitrate, and exit
+    #[arg(long, action)]
+    pub bench: bool,
+
+    /// Benchmark duration in seconds
+    #[arg(long, default_value = "10")]
+    pub bench_secs: u64,
+
     /// PID file path
     #[arg(long, default_value = "/var/run/ivnc.pid")]
     pub pidfile: PathBuf,
@@ -81,10 +114,15 @@ pub struct Args {
     #[cfg(feature = "mcp")]
     #[arg(long, action)]
     pub mcp_stdio: bool,
+
+    /// Enable or disable the MCP Streamable HTTP endpoint (true/false)
+    #[cfg(feature = "mcp")]
+    #[arg(long)]
+    pub mcp_http: Option<bool>,
 }
 
 impl Args {
-    pub fn load_config(&self) -> Result<config::Config, Box<dyn std::error::Error>> {
+    pub fn load_config(&self) -> ivnc::Result<config::Config> {
         config::Config::load(&self.config)
     }
 }
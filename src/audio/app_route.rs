@@ -0,0 +1,166 @@
+//! Per-application audio capture (`audio.app_id`)
+//!
+//! Instead of the desktop mix, capture only one application: a dedicated
+//! null sink is loaded (`pactl load-module module-null-sink
+//! sink_name=ivnc_app`), the application's playback streams are moved onto
+//! it with `pactl move-sink-input`, and capture reads the sink's monitor.
+//! Streams are matched by their `application.id`, `application.name` or
+//! `application.process.binary` property against the Wayland app_id, so
+//! `org.mozilla.firefox` matches a stream from `firefox`. Works with
+//! PulseAudio and pipewire-pulse.
+
+use std::process::Command;
+
+/// Null sink the application's streams are moved to
+pub const APP_SINK: &str = "ivnc_app";
+
+/// One `Sink Input #N` block of `pactl list sink-inputs`
+#[derive(Debug, Default, PartialEq)]
+struct SinkInput {
+    index: u32,
+    sink: String,
+    properties: Vec<(String, String)>,
+}
+
+fn pactl(args: &[&str]) -> Option<String> {
+    let output = Command::new("pactl").args(args).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Parse `pactl list sink-inputs` output (English locale)
+fn parse_sink_inputs(text: &str) -> Vec<SinkInput> {
+    let mut inputs = Vec::new();
+    let mut current: Option<SinkInput> = None;
+    for line in text.lines() {
+        if let Some(index) = line.strip_prefix("Sink Input #") {
+            inputs.extend(current.take());
+            current = index.trim().parse().ok().map(|index| SinkInput { index, ..Default::default() });
+            continue;
+        }
+        let Some(input) = current.as_mut() else { continue };
+        let line = line.trim();
+        if let Some(sink) = line.strip_prefix("Sink:") {
+            input.sink = sink.trim().to_string();
+        } else if let Some((key, value)) = line.split_once(" = ") {
+            input.properties.push((key.to_string(), value.trim_matches('"').to_string()));
+        }
+    }
+    inputs.extend(current);
+    inputs
+}
+
+/// True if a stream belongs to the application with Wayland `app_id`
+fn matches_app(input: &SinkInput, app_id: &str) -> bool {
+    let app_id = app_id.to_ascii_lowercase();
+    // Reverse-DNS app_ids: also accept the last component
+    let short = app_id.rsplit('.').next().unwrap_or(&app_id);
+    input
+        .properties
+        .iter()
+        .filter(|(key, _)| {
+            matches!(key.as_str(), "application.id" | "application.name" | "application.process.binary")
+        })
+        .map(|(_, value)| value.to_ascii_lowercase())
+        .any(|value| value == app_id || value == short)
+}
+
+/// Load the null sink unless it is already there
+fn ensure_app_sink() -> bool {
+    let Some(sinks) = pactl(&["list", "sinks", "short"]) else {
+        return false;
+    };
+    if sinks.lines().any(|l| l.split('\t').nth(1) == Some(APP_SINK)) {
+        return true;
+    }
+    let sink_name = format!("sink_name={}", APP_SINK);
+    let loaded = pactl(&[
+        "load-module",
+        "module-null-sink",
+        &sink_name,
+        "sink_properties=device.description=iVnc-App",
+    ])
+    .is_some();
+    if loaded {
+        log::info!("Loaded PulseAudio null sink '{}' for per-app capture", APP_SINK);
+    }
+    loaded
+}
+
+/// Move the application's playback streams onto the app sink. Returns how
+/// many of its streams are on the sink now, or None if PulseAudio could
+/// not be queried.
+pub fn route_app_streams(app_id: &str) -> Option<usize> {
+    if !ensure_app_sink() {
+        return None;
+    }
+    // The sink's index, for streams listed by index rather than name
+    let sink_index = pactl(&["list", "sinks", "short"])?
+        .lines()
+        .find(|l| l.split('\t').nth(1) == Some(APP_SINK))
+        .and_then(|l| l.split('\t').next().map(str::to_string));
+    let inputs = parse_sink_inputs(&pactl(&["list", "sink-inputs"])?);
+    let mut routed = 0;
+    for input in inputs.iter().filter(|i| matches_app(i, app_id)) {
+        if input.sink == APP_SINK || sink_index.as_deref() == Some(input.sink.as_str()) {
+            routed += 1;
+            continue;
+        }
+        let index = input.index.to_string();
+        if pactl(&["move-sink-input", &index, APP_SINK]).is_some() {
+            log::info!("Moved sink input #{} of '{}' to {}", input.index, app_id, APP_SINK);
+            routed += 1;
+        }
+    }
+    Some(routed)
+}
+
+/// Capture source for `app_id`: the app sink's monitor once the app's
+/// streams are on it, or None (the full mix) if the app plays nothing yet
+pub fn app_source(app_id: &str) -> Option<String> {
+    match route_app_streams(app_id) {
+        Some(n) if n > 0 => Some(format!("{}.monitor", APP_SINK)),
+        Some(_) => {
+            log::warn!("No audio streams from '{}'; capturing the full mix", app_id);
+            None
+        }
+        None => {
+            log::warn!("Cannot set up per-app audio for '{}' (pactl failed); capturing the full mix", app_id);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SINK_INPUTS: &str = "Sink Input #41
+\tDriver: protocol-native.c
+\tSink: 0
+\tProperties:
+\t\tapplication.name = \"Firefox\"
+\t\tapplication.process.binary = \"firefox\"
+Sink Input #57
+\tSink: ivnc_app
+\tProperties:
+\t\tapplication.name = \"mpv\"
+";
+
+    #[test]
+    fn parses_sink_inputs() {
+        let inputs = parse_sink_inputs(SINK_INPUTS);
+        assert_eq!(inputs.len(), 2);
+        assert_eq!((inputs[0].index, inputs[0].sink.as_str()), (41, "0"));
+        assert!(inputs[0].properties.contains(&("application.process.binary".into(), "firefox".into())));
+        assert_eq!((inputs[1].index, inputs[1].sink.as_str()), (57, "ivnc_app"));
+    }
+
+    #[test]
+    fn matches_by_name_binary_or_reverse_dns_suffix() {
+        let inputs = parse_sink_inputs(SINK_INPUTS);
+        assert!(matches_app(&inputs[0], "firefox"));
+        assert!(matches_app(&inputs[0], "org.mozilla.firefox"));
+        assert!(matches_app(&inputs[1], "MPV"));
+        assert!(!matches_app(&inputs[0], "mpv"));
+    }
+}
@@ -6,4 +6,6 @@ mod runtime;
 #[cfg(not(feature = "audio"))]
 mod runtime;
 
-pub use runtime::{run_audio_capture, AudioConfig, AudioPacket};
+pub mod app_route;
+
+pub use runtime::{resolve_backend, run_audio_capture, validate_source, AudioConfig, AudioPacket, AudioStatus};
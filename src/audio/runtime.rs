@@ -1,27 +1,54 @@
 //! Audio runtime implementation.
 
-use std::sync::atomic::AtomicBool;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
+use crate::config::AudioBackend;
+
+/// First reconnect delay; doubles per consecutive failure
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+/// Upper bound on the reconnect delay
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
 /// Audio configuration
 #[derive(Debug, Clone)]
 pub struct AudioConfig {
+    /// Capture backend, already resolved by [`resolve_backend`]
+    pub backend: AudioBackend,
+    /// Source to capture from (device or monitor name); None = default
+    pub source: Option<String>,
     /// Sample rate (Hz)
     pub sample_rate: u32,
     /// Channel count (1 or 2)
     pub channels: u16,
     /// Target bitrate (bps)
     pub bitrate: u32,
+    /// Consecutive failed (re)opens before giving up; 0 = retry forever
+    pub max_retries: u32,
+    /// Capture only this application's audio (PulseAudio); see [`super::app_route`]
+    pub app_id: Option<String>,
+    /// Opus in-band FEC, tuned for this expected packet loss (percent)
+    pub fec_loss_percent: Option<u8>,
+    /// Suppress silent frames (discontinuous transmission)
+    pub dtx: bool,
 }
 
 impl AudioConfig {
     #[allow(dead_code)]
     pub fn with_bitrate(&self, bitrate: u32) -> Self {
         Self {
+            backend: self.backend,
+            source: self.source.clone(),
             sample_rate: self.sample_rate,
             channels: self.channels,
             bitrate,
+            max_retries: self.max_retries,
+            app_id: self.app_id.clone(),
+            fec_loss_percent: self.fec_loss_percent,
+            dtx: self.dtx,
         }
     }
 }
@@ -31,35 +58,313 @@ impl AudioConfig {
 #[allow(dead_code)]
 pub struct AudioPacket {
     pub data: Vec<u8>,
+    /// 20 ms frames suppressed by DTX right before this one; the RTP
+    /// timestamp skips over them
+    pub gap_frames: u32,
+    /// When the frame's first sample was captured, for sender reports
+    pub captured_at: Instant,
 }
 
-#[cfg(all(not(feature = "audio"), not(feature = "pulseaudio")))]
+/// Capture health changes, broadcast to clients as `audio,<status>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioStatus {
+    /// The source failed and is being re-opened
+    Reconnecting,
+    /// Capture is running again after a failure
+    Recovered,
+    /// Retries exhausted; audio stays off until restart
+    Failed,
+}
+
+impl AudioStatus {
+    /// Text message sent to clients
+    pub fn message(&self) -> &'static str {
+        match self {
+            AudioStatus::Reconnecting => "audio,reconnecting",
+            AudioStatus::Recovered => "audio,ok",
+            AudioStatus::Failed => "audio,failed",
+        }
+    }
+}
+
+/// Pick the backend to capture from. `Auto` probes PulseAudio, PipeWire
+/// and ALSA in that order; if none answers yet, PulseAudio (ALSA in builds
+/// without it) is used and the retry loop waits for it to come up.
+pub fn resolve_backend(requested: AudioBackend) -> AudioBackend {
+    if requested != AudioBackend::Auto {
+        return requested;
+    }
+    [AudioBackend::Pulse, AudioBackend::Pipewire, AudioBackend::Alsa]
+        .into_iter()
+        .find(|&backend| backend_available(backend))
+        .unwrap_or(if cfg!(feature = "pulseaudio") { AudioBackend::Pulse } else { AudioBackend::Alsa })
+}
+
+fn backend_available(backend: AudioBackend) -> bool {
+    match backend {
+        AudioBackend::Auto => false,
+        // Also true for pipewire-pulse, which is fine: it speaks the protocol
+        AudioBackend::Pulse => {
+            (cfg!(feature = "pulseaudio") || gst_element_exists("pulsesrc"))
+                && std::process::Command::new("pactl")
+                    .arg("info")
+                    .output()
+                    .is_ok_and(|o| o.status.success())
+        }
+        AudioBackend::Pipewire => {
+            let runtime_dir = std::env::var_os("PIPEWIRE_RUNTIME_DIR")
+                .or_else(|| std::env::var_os("XDG_RUNTIME_DIR"));
+            gst_element_exists("pipewiresrc")
+                && runtime_dir.is_some_and(|dir| std::path::Path::new(&dir).join("pipewire-0").exists())
+        }
+        AudioBackend::Alsa => {
+            (cfg!(feature = "audio") || gst_element_exists("alsasrc"))
+                && std::fs::read_to_string("/proc/asound/cards")
+                    .is_ok_and(|cards| !cards.trim().is_empty() && !cards.contains("no soundcards"))
+        }
+    }
+}
+
+/// Capture sources the backend offers, or None if they cannot be listed
+pub fn list_sources(backend: AudioBackend) -> Option<Vec<String>> {
+    let (cmd, args): (&str, &[&str]) = match backend {
+        AudioBackend::Pulse | AudioBackend::Auto => ("pactl", &["list", "sources", "short"]),
+        AudioBackend::Alsa => ("arecord", &["-L"]),
+        AudioBackend::Pipewire => return None,
+    };
+    let output = std::process::Command::new(cmd)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|o| o.status.success())?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    Some(match backend {
+        // Device names are unindented, descriptions indented below them
+        AudioBackend::Alsa => text
+            .lines()
+            .filter(|l| !l.is_empty() && !l.starts_with(char::is_whitespace))
+            .map(str::to_string)
+            .collect(),
+        _ => text.lines().filter_map(|l| l.split('\t').nth(1)).map(str::to_string).collect(),
+    })
+}
+
+/// Find `requested` among `available`; a sink name resolves to its monitor
+fn match_source(requested: &str, available: &[String]) -> Option<String> {
+    if available.iter().any(|s| s == requested) {
+        return Some(requested.to_string());
+    }
+    let monitor = format!("{}.monitor", requested);
+    available.contains(&monitor).then_some(monitor)
+}
+
+/// Check the configured source against what the backend offers. Returns
+/// the name to capture from, or None (default source) after logging the
+/// available sources if it does not exist.
+pub fn validate_source(backend: AudioBackend, requested: &str) -> Option<String> {
+    let Some(available) = list_sources(backend) else {
+        log::info!("Cannot list {} sources; using '{}' unchecked", backend.as_str(), requested);
+        return Some(requested.to_string());
+    };
+    match match_source(requested, &available) {
+        Some(source) => Some(source),
+        None => {
+            log::error!(
+                "Audio source '{}' not found, capturing the default source instead. Available: {}",
+                requested,
+                if available.is_empty() { "(none)".to_string() } else { available.join(", ") }
+            );
+            None
+        }
+    }
+}
+
+fn gst_element_exists(name: &str) -> bool {
+    gstreamer::init().is_ok() && gstreamer::ElementFactory::find(name).is_some()
+}
+
+/// Delay before reconnect attempt `attempt` (1-based)
+fn retry_delay(attempt: u32) -> Duration {
+    RETRY_BASE_DELAY
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(RETRY_MAX_DELAY)
+}
+
+/// Sleep for `delay`, waking early if `running` is cleared
+fn sleep_while_running(delay: Duration, running: &AtomicBool) {
+    let deadline = std::time::Instant::now() + delay;
+    while running.load(Ordering::Relaxed) {
+        let left = deadline.saturating_duration_since(std::time::Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        std::thread::sleep(left.min(Duration::from_millis(100)));
+    }
+}
+
+/// Capture audio until `running` is cleared, re-opening the source with
+/// exponential backoff when it fails (PulseAudio restart, device unplugged).
+/// `on_status` is told when capture drops, comes back or is given up on.
 pub fn run_audio_capture(
     config: AudioConfig,
-    _sender: mpsc::UnboundedSender<AudioPacket>,
+    sender: mpsc::UnboundedSender<AudioPacket>,
     running: Arc<AtomicBool>,
+    on_status: impl Fn(AudioStatus),
+) -> Result<(), Box<dyn std::error::Error>> {
+    retry_capture(
+        config.max_retries,
+        &running,
+        |on_open| capture_once(&config, &sender, &running, on_open),
+        on_status,
+        retry_delay,
+    )
+}
+
+/// How often per-app capture re-routes the app's new streams
+#[cfg(any(feature = "audio", feature = "pulseaudio"))]
+const APP_ROUTE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Retry loop behind [`run_audio_capture`]. `capture` calls its argument
+/// once the source is open, which resets the failure count.
+fn retry_capture(
+    max_retries: u32,
+    running: &AtomicBool,
+    mut capture: impl FnMut(&dyn Fn()) -> Result<(), Box<dyn std::error::Error>>,
+    on_status: impl Fn(AudioStatus),
+    backoff: impl Fn(u32) -> Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let failures = Cell::new(0u32);
+    let on_open = || {
+        if failures.replace(0) > 0 {
+            log::info!("Audio capture recovered");
+            on_status(AudioStatus::Recovered);
+        }
+    };
+    loop {
+        let err = match capture(&on_open) {
+            Ok(()) => return Ok(()),
+            Err(_) if !running.load(Ordering::Relaxed) => return Ok(()),
+            Err(e) => e,
+        };
+        let attempt = failures.get() + 1;
+        failures.set(attempt);
+        if max_retries > 0 && attempt > max_retries {
+            on_status(AudioStatus::Failed);
+            return Err(format!("giving up after {} retries: {}", max_retries, err).into());
+        }
+        let delay = backoff(attempt);
+        log::warn!("Audio capture failed ({}); reconnecting in {:?} (attempt {})", err, delay, attempt);
+        on_status(AudioStatus::Reconnecting);
+        sleep_while_running(delay, running);
+    }
+}
+
+#[cfg(all(not(feature = "audio"), not(feature = "pulseaudio")))]
+fn capture_once(
+    config: &AudioConfig,
+    _sender: &mpsc::UnboundedSender<AudioPacket>,
+    running: &AtomicBool,
+    _on_open: &dyn Fn(),
 ) -> Result<(), Box<dyn std::error::Error>> {
     let _ = (config.sample_rate, config.channels, config.bitrate);
-    while running.load(std::sync::atomic::Ordering::Relaxed) {
-        std::thread::sleep(std::time::Duration::from_millis(100));
+    while running.load(Ordering::Relaxed) {
+        std::thread::sleep(Duration::from_millis(100));
     }
     Ok(())
 }
 
-#[cfg(all(feature = "audio", not(feature = "pulseaudio")))]
-pub fn run_audio_capture(
-    config: AudioConfig,
-    sender: mpsc::UnboundedSender<AudioPacket>,
-    running: Arc<AtomicBool>,
+/// Dispatch to the configured backend. PulseAudio uses libpulse and ALSA
+/// uses cpal when built in; everything else goes through GStreamer.
+#[cfg(any(feature = "audio", feature = "pulseaudio"))]
+fn capture_once(
+    config: &AudioConfig,
+    sender: &mpsc::UnboundedSender<AudioPacket>,
+    running: &Arc<AtomicBool>,
+    on_open: &dyn Fn(),
+) -> Result<(), Box<dyn std::error::Error>> {
+    match config.backend {
+        AudioBackend::Pulse if config.app_id.is_some() => capture_app(config, sender, running, on_open),
+        #[cfg(feature = "pulseaudio")]
+        AudioBackend::Pulse => capture_pulse(config, sender, running, on_open),
+        #[cfg(not(feature = "pulseaudio"))]
+        AudioBackend::Pulse => capture_gst("pulsesrc", "device", config, sender, running, on_open),
+        #[cfg(feature = "audio")]
+        AudioBackend::Alsa => capture_cpal(config, sender, running, on_open),
+        #[cfg(not(feature = "audio"))]
+        AudioBackend::Alsa => capture_gst("alsasrc", "device", config, sender, running, on_open),
+        AudioBackend::Pipewire => capture_gst("pipewiresrc", "target-object", config, sender, running, on_open),
+        AudioBackend::Auto => {
+            let resolved = AudioConfig { backend: resolve_backend(AudioBackend::Auto), ..config.clone() };
+            capture_once(&resolved, sender, running, on_open)
+        }
+    }
+}
+
+/// Per-app capture: route the app's streams to the app sink and capture
+/// its monitor, or the full mix while the app plays nothing. A watcher
+/// keeps routing streams the app opens later; if capture fell back to the
+/// full mix, it restarts capture on the app sink once the app shows up.
+#[cfg(any(feature = "audio", feature = "pulseaudio"))]
+fn capture_app(
+    config: &AudioConfig,
+    sender: &mpsc::UnboundedSender<AudioPacket>,
+    running: &Arc<AtomicBool>,
+    on_open: &dyn Fn(),
+) -> Result<(), Box<dyn std::error::Error>> {
+    use super::app_route;
+
+    let app_id = config.app_id.as_deref().unwrap_or_default();
+    loop {
+        let app_source = app_route::app_source(app_id);
+        let on_app_sink = app_source.is_some();
+        let routed = AudioConfig { source: app_source.or_else(|| config.source.clone()), app_id: None, ..config.clone() };
+        let attempt = Arc::new(AtomicBool::new(true));
+        let switch = AtomicBool::new(false);
+        let result = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                while attempt.load(Ordering::Relaxed) {
+                    sleep_while_running(APP_ROUTE_INTERVAL, &attempt);
+                    if !running.load(Ordering::Relaxed) {
+                        attempt.store(false, Ordering::Relaxed);
+                    } else if attempt.load(Ordering::Relaxed)
+                        && app_route::route_app_streams(app_id).is_some_and(|n| n > 0)
+                        && !on_app_sink
+                    {
+                        log::info!("'{}' started playing; switching capture to {}", app_id, app_route::APP_SINK);
+                        switch.store(true, Ordering::Relaxed);
+                        attempt.store(false, Ordering::Relaxed);
+                    }
+                }
+            });
+            let result = capture_once(&routed, sender, &attempt, on_open);
+            attempt.store(false, Ordering::Relaxed);
+            result
+        });
+        if !switch.load(Ordering::Relaxed) || !running.load(Ordering::Relaxed) {
+            return result;
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+fn capture_cpal(
+    config: &AudioConfig,
+    sender: &mpsc::UnboundedSender<AudioPacket>,
+    running: &Arc<AtomicBool>,
+    on_open: &dyn Fn(),
 ) -> Result<(), Box<dyn std::error::Error>> {
     use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-    use opus::{Application, Bitrate, Channels, Encoder};
+    use opus::Channels;
     use std::collections::VecDeque;
 
     let host = cpal::default_host();
-    let device = host
-        .default_input_device()
-        .ok_or("No default input device")?;
+    let device = match config.source.as_deref() {
+        Some(name) => host
+            .input_devices()?
+            .find(|d| d.name().is_ok_and(|n| n == name))
+            .ok_or_else(|| format!("No input device named '{}'", name))?,
+        None => host.default_input_device().ok_or("No default input device")?,
+    };
     let supported_config = {
         let mut selected = None;
         if let Ok(configs) = device.supported_input_configs() {
@@ -88,13 +393,14 @@ pub fn run_audio_capture(
         _ => return Err("Unsupported channel count".into()),
     };
 
-    let mut encoder = Encoder::new(sample_rate, channels, Application::Audio)?;
-    encoder.set_bitrate(Bitrate::Bits(config.bitrate as i32))?;
+    let encoder = OpusEncoder::new(config, sample_rate, channels)?;
     let encoder = Arc::new(std::sync::Mutex::new(encoder));
 
     let frame_size = (sample_rate / 50) as usize; // 20ms
     let samples_per_frame = frame_size * channel_count as usize;
     let buffer = Arc::new(std::sync::Mutex::new(VecDeque::<i16>::new()));
+    // Set by the stream error callback, e.g. when the device goes away
+    let failed = Arc::new(AtomicBool::new(false));
 
     let stream = match supported_config.sample_format() {
         cpal::SampleFormat::F32 => {
@@ -105,7 +411,7 @@ pub fn run_audio_capture(
             device.build_input_stream(
                 &supported_config.config(),
                 move |data: &[f32], _| {
-                    if !running_clone.load(std::sync::atomic::Ordering::Relaxed) {
+                    if !running_clone.load(Ordering::Relaxed) {
                         return;
                     }
                     let mut buf = buffer_clone.lock().unwrap();
@@ -116,7 +422,13 @@ pub fn run_audio_capture(
                     let mut enc = encoder_clone.lock().unwrap();
                     encode_ready_frames(&mut enc, &mut buf, samples_per_frame, &sender_clone);
                 },
-                |err| eprintln!("Audio stream error: {:?}", err),
+                {
+                    let failed = failed.clone();
+                    move |err| {
+                        log::warn!("Audio stream error: {:?}", err);
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                },
                 None,
             )?
         }
@@ -128,7 +440,7 @@ pub fn run_audio_capture(
             device.build_input_stream(
                 &supported_config.config(),
                 move |data: &[i16], _| {
-                    if !running_clone.load(std::sync::atomic::Ordering::Relaxed) {
+                    if !running_clone.load(Ordering::Relaxed) {
                         return;
                     }
                     let mut buf = buffer_clone.lock().unwrap();
@@ -138,7 +450,13 @@ pub fn run_audio_capture(
                     let mut enc = encoder_clone.lock().unwrap();
                     encode_ready_frames(&mut enc, &mut buf, samples_per_frame, &sender_clone);
                 },
-                |err| eprintln!("Audio stream error: {:?}", err),
+                {
+                    let failed = failed.clone();
+                    move |err| {
+                        log::warn!("Audio stream error: {:?}", err);
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                },
                 None,
             )?
         }
@@ -150,7 +468,7 @@ pub fn run_audio_capture(
             device.build_input_stream(
                 &supported_config.config(),
                 move |data: &[u16], _| {
-                    if !running_clone.load(std::sync::atomic::Ordering::Relaxed) {
+                    if !running_clone.load(Ordering::Relaxed) {
                         return;
                     }
                     let mut buf = buffer_clone.lock().unwrap();
@@ -161,15 +479,25 @@ pub fn run_audio_capture(
                     let mut enc = encoder_clone.lock().unwrap();
                     encode_ready_frames(&mut enc, &mut buf, samples_per_frame, &sender_clone);
                 },
-                |err| eprintln!("Audio stream error: {:?}", err),
+                {
+                    let failed = failed.clone();
+                    move |err| {
+                        log::warn!("Audio stream error: {:?}", err);
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                },
                 None,
             )?
         }
     };
 
     stream.play()?;
-    while running.load(std::sync::atomic::Ordering::Relaxed) {
-        std::thread::sleep(std::time::Duration::from_millis(100));
+    on_open();
+    while running.load(Ordering::Relaxed) {
+        if failed.load(Ordering::Relaxed) {
+            return Err("audio input stream failed".into());
+        }
+        std::thread::sleep(Duration::from_millis(100));
     }
     drop(stream);
 
@@ -216,16 +544,103 @@ fn detect_pulse_monitor_source() -> Option<String> {
     None
 }
 
+/// Capture through a GStreamer source `element`, converted to interleaved
+/// S16LE at the configured rate and channel count. A configured source is
+/// set through the element's `device_property`.
+#[cfg(any(feature = "audio", feature = "pulseaudio"))]
+fn capture_gst(
+    element: &str,
+    device_property: &str,
+    config: &AudioConfig,
+    sender: &mpsc::UnboundedSender<AudioPacket>,
+    running: &AtomicBool,
+    on_open: &dyn Fn(),
+) -> Result<(), Box<dyn std::error::Error>> {
+    use gstreamer as gst;
+    use gstreamer::prelude::*;
+    use gstreamer_app as gst_app;
+    use opus::Channels;
+    use std::collections::VecDeque;
+
+    /// Tear the pipeline down on every exit path
+    struct StopOnDrop(gst::Pipeline);
+    impl Drop for StopOnDrop {
+        fn drop(&mut self) {
+            let _ = self.0.set_state(gst::State::Null);
+        }
+    }
+
+    let channels = match config.channels {
+        1 => Channels::Mono,
+        2 => Channels::Stereo,
+        _ => return Err("Unsupported channel count".into()),
+    };
+    let mut encoder = OpusEncoder::new(config, config.sample_rate, channels)?;
+    let samples_per_frame = (config.sample_rate / 50) as usize * config.channels as usize; // 20ms
+    let mut buffer = VecDeque::<i16>::new();
+
+    gst::init()?;
+    let description = format!(
+        "{} name=src ! audioconvert ! audioresample ! \
+         audio/x-raw,format=S16LE,layout=interleaved,rate={},channels={} ! \
+         appsink name=sink sync=false",
+        element, config.sample_rate, config.channels
+    );
+    let pipeline = gst::parse::launch(&description)?
+        .downcast::<gst::Pipeline>()
+        .map_err(|_| "audio capture description is not a pipeline")?;
+    if let (Some(device), Some(src)) = (config.source.as_deref(), pipeline.by_name("src")) {
+        src.set_property(device_property, device);
+    }
+    let appsink = pipeline
+        .by_name("sink")
+        .and_then(|e| e.downcast::<gst_app::AppSink>().ok())
+        .ok_or("audio capture pipeline has no appsink")?;
+    let bus = pipeline.bus().ok_or("audio capture pipeline has no bus")?;
+    let _stop = StopOnDrop(pipeline.clone());
+    pipeline.set_state(gst::State::Playing)?;
+
+    let mut opened = false;
+    while running.load(Ordering::Relaxed) {
+        if let Some(msg) = bus.pop_filtered(&[gst::MessageType::Error, gst::MessageType::Eos]) {
+            return Err(match msg.view() {
+                gst::MessageView::Error(err) => format!("{} error: {}", element, err.error()),
+                _ => format!("{} reached end of stream", element),
+            }
+            .into());
+        }
+        let Some(sample) = appsink.try_pull_sample(gst::ClockTime::from_mseconds(100)) else {
+            continue;
+        };
+        if !opened {
+            log::info!("GStreamer audio capture opened ({}, source: {:?})", element, config.source);
+            on_open();
+            opened = true;
+        }
+        let Some(buf) = sample.buffer() else {
+            continue;
+        };
+        let map = buf.map_readable()?;
+        for chunk in map.as_slice().chunks_exact(2) {
+            buffer.push_back(i16::from_le_bytes([chunk[0], chunk[1]]));
+        }
+        encode_ready_frames(&mut encoder, &mut buffer, samples_per_frame, sender);
+    }
+
+    Ok(())
+}
+
 #[cfg(feature = "pulseaudio")]
-pub fn run_audio_capture(
-    config: AudioConfig,
-    sender: mpsc::UnboundedSender<AudioPacket>,
-    running: Arc<AtomicBool>,
+fn capture_pulse(
+    config: &AudioConfig,
+    sender: &mpsc::UnboundedSender<AudioPacket>,
+    running: &AtomicBool,
+    on_open: &dyn Fn(),
 ) -> Result<(), Box<dyn std::error::Error>> {
     use libpulse_binding::sample::{Format, Spec};
     use libpulse_binding::stream::Direction;
     use libpulse_simple_binding::Simple;
-    use opus::{Application, Bitrate, Channels, Encoder};
+    use opus::Channels;
     use std::collections::VecDeque;
 
     let channels = match config.channels {
@@ -240,70 +655,216 @@ pub fn run_audio_capture(
         channels: config.channels as u8,
     };
 
-    let mut encoder = Encoder::new(config.sample_rate, channels, Application::Audio)?;
-    encoder.set_bitrate(Bitrate::Bits(config.bitrate as i32))?;
+    let mut encoder = OpusEncoder::new(config, config.sample_rate, channels)?;
 
     let frame_size = (config.sample_rate / 50) as usize; // 20ms
     let samples_per_frame = frame_size * config.channels as usize;
     let mut buffer = VecDeque::<i16>::new();
     let mut read_buf = vec![0u8; samples_per_frame * 2];
 
-    // Outer loop: reconnect to PulseAudio on errors (timeout, disconnect, etc.)
-    while running.load(std::sync::atomic::Ordering::Relaxed) {
-        // Re-detect source each attempt (PulseAudio may start after iVnc)
-        let source = std::env::var("PULSE_SOURCE").ok().or_else(|| {
-            detect_pulse_monitor_source()
-        });
-        let source_ref = source.as_deref();
-
-        let simple = match Simple::new(
-            None, "ivnc", Direction::Record, source_ref, "capture", &spec, None, None,
-        ) {
-            Ok(s) => {
-                log::info!("PulseAudio capture opened (source: {:?})", source_ref);
-                s
-            }
-            Err(e) => {
-                log::warn!("PulseAudio connect failed (retrying in 3s): {}", e);
-                std::thread::sleep(std::time::Duration::from_secs(3));
-                continue;
-            }
-        };
+    // Re-detect the source on every attempt (PulseAudio may start after iVnc)
+    let source = config.source.clone()
+        .or_else(|| std::env::var("PULSE_SOURCE").ok())
+        .or_else(detect_pulse_monitor_source);
+    let source_ref = source.as_deref();
 
-        // Inner loop: read audio frames until error
-        while running.load(std::sync::atomic::Ordering::Relaxed) {
-            match simple.read(&mut read_buf) {
-                Ok(()) => {
-                    for chunk in read_buf.chunks_exact(2) {
-                        buffer.push_back(i16::from_le_bytes([chunk[0], chunk[1]]));
-                    }
-                    encode_ready_frames(&mut encoder, &mut buffer, samples_per_frame, &sender);
-                }
-                Err(e) => {
-                    log::warn!("PulseAudio read error (reconnecting): {}", e);
-                    buffer.clear();
-                    break; // break inner loop → reconnect in outer loop
-                }
-            }
+    let simple = Simple::new(
+        None, "ivnc", Direction::Record, source_ref, "capture", &spec, None, None,
+    )
+    .map_err(|e| format!("PulseAudio connect failed: {}", e))?;
+    log::info!("PulseAudio capture opened (source: {:?})", source_ref);
+    on_open();
+
+    while running.load(Ordering::Relaxed) {
+        simple
+            .read(&mut read_buf)
+            .map_err(|e| format!("PulseAudio read error: {}", e))?;
+        for chunk in read_buf.chunks_exact(2) {
+            buffer.push_back(i16::from_le_bytes([chunk[0], chunk[1]]));
         }
+        encode_ready_frames(&mut encoder, &mut buffer, samples_per_frame, sender);
     }
 
     Ok(())
 }
 
+/// Opus encoder with the configured FEC and DTX behaviour
+#[cfg(any(feature = "audio", feature = "pulseaudio"))]
+struct OpusEncoder {
+    encoder: opus::Encoder,
+    dtx: Option<Dtx>,
+}
+
+#[cfg(any(feature = "audio", feature = "pulseaudio"))]
+impl OpusEncoder {
+    fn new(config: &AudioConfig, sample_rate: u32, channels: opus::Channels) -> Result<Self, opus::Error> {
+        let mut encoder = opus::Encoder::new(sample_rate, channels, opus::Application::Audio)?;
+        encoder.set_bitrate(opus::Bitrate::Bits(config.bitrate as i32))?;
+        if let Some(loss) = config.fec_loss_percent {
+            // FEC only kicks in with a non-zero expected loss
+            encoder.set_inband_fec(true)?;
+            encoder.set_packet_loss_perc(loss as i32)?;
+        }
+        Ok(Self { encoder, dtx: config.dtx.then(Dtx::default) })
+    }
+}
+
+/// Length of one Opus frame
+#[cfg(any(feature = "audio", feature = "pulseaudio"))]
+const FRAME_DURATION: Duration = Duration::from_millis(20);
+
+/// Frames DTX lets through during silence, one per 400 ms as Opus DTX
+/// does, so the receiver keeps its comfort noise going
+#[cfg(any(feature = "audio", feature = "pulseaudio"))]
+const DTX_KEEPALIVE_FRAMES: u32 = 20;
+
+/// Silence suppression. The opus bindings do not expose `OPUS_SET_DTX`, so
+/// it is done on the input: frames of digital silence after the first are
+/// not encoded, except for a keep-alive frame every 400 ms.
+#[cfg(any(feature = "audio", feature = "pulseaudio"))]
+#[derive(Default)]
+struct Dtx {
+    /// Consecutive silent frames so far
+    silent_run: u32,
+    /// Frames suppressed since the last one sent
+    suppressed: u32,
+}
+
+#[cfg(any(feature = "audio", feature = "pulseaudio"))]
+impl Dtx {
+    /// Whether to send `frame`
+    fn admit(&mut self, frame: &[i16]) -> bool {
+        // Monitor sources deliver exact zeros (or dither of ±1) when idle
+        if frame.iter().any(|s| s.unsigned_abs() > 1) {
+            self.silent_run = 0;
+            return true;
+        }
+        self.silent_run += 1;
+        // The first silent frame goes out so the decoder fades to silence
+        if self.silent_run == 1 || self.silent_run % DTX_KEEPALIVE_FRAMES == 0 {
+            return true;
+        }
+        self.suppressed += 1;
+        false
+    }
+
+    /// Frames suppressed before the frame just admitted
+    fn take_gap(&mut self) -> u32 {
+        std::mem::take(&mut self.suppressed)
+    }
+}
+
 #[cfg(any(feature = "audio", feature = "pulseaudio"))]
 fn encode_ready_frames(
-    encoder: &mut opus::Encoder,
+    encoder: &mut OpusEncoder,
     buffer: &mut std::collections::VecDeque<i16>,
     samples_per_frame: usize,
     sender: &mpsc::UnboundedSender<AudioPacket>,
 ) {
+    // The newest buffered sample was captured just now
+    let now = Instant::now();
     while buffer.len() >= samples_per_frame {
+        let captured_at = now
+            .checked_sub(FRAME_DURATION.mul_f64(buffer.len() as f64 / samples_per_frame as f64))
+            .unwrap_or(now);
         let frame: Vec<i16> = buffer.drain(..samples_per_frame).collect();
+        let gap_frames = match encoder.dtx.as_mut() {
+            Some(dtx) if !dtx.admit(&frame) => continue,
+            Some(dtx) => dtx.take_gap(),
+            None => 0,
+        };
         let mut out = vec![0u8; 4000];
-        if let Ok(len) = encoder.encode(&frame, &mut out) {
+        if let Ok(len) = encoder.encoder.encode(&frame, &mut out) {
             out.truncate(len);
-            let _ = sender.send(AudioPacket { data: out });
+            let _ = sender.send(AudioPacket { data: out, gap_frames, captured_at });
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sink_names_resolve_to_their_monitor() {
+        let available = vec!["ivnc_sink.monitor".to_string(), "alsa_input.usb-mic".to_string()];
+        assert_eq!(match_source("alsa_input.usb-mic", &available).as_deref(), Some("alsa_input.usb-mic"));
+        assert_eq!(match_source("ivnc_sink", &available).as_deref(), Some("ivnc_sink.monitor"));
+        assert_eq!(match_source("ivnc_sink.monitor", &available).as_deref(), Some("ivnc_sink.monitor"));
+        assert_eq!(match_source("other_sink", &available), None);
+    }
+
+    #[cfg(any(feature = "audio", feature = "pulseaudio"))]
+    #[test]
+    fn dtx_suppresses_silence_with_keepalives() {
+        let mut dtx = Dtx::default();
+        let (sound, silence) = ([0i16, 500, -500, 0], [0i16, 1, -1, 0]);
+        assert!(dtx.admit(&sound));
+        // First silent frame is sent, then one per keep-alive interval
+        let sent: Vec<u32> = (1..=45).filter(|_| dtx.admit(&silence)).collect();
+        assert_eq!(sent, [1, 20, 40]);
+        assert_eq!(dtx.take_gap(), 18 + 19 + 5);
+        assert_eq!(dtx.take_gap(), 0);
+        assert!(dtx.admit(&sound));
+    }
+
+    #[test]
+    fn retry_delay_backs_off_to_a_cap() {
+        assert_eq!(retry_delay(1), Duration::from_secs(1));
+        assert_eq!(retry_delay(3), Duration::from_secs(4));
+        assert_eq!(retry_delay(6), RETRY_MAX_DELAY);
+        assert_eq!(retry_delay(u32::MAX), RETRY_MAX_DELAY);
+    }
+
+    #[test]
+    fn gives_up_after_max_consecutive_failures() {
+        let running = AtomicBool::new(true);
+        let statuses = std::cell::RefCell::new(Vec::new());
+        let mut attempts = 0;
+        let result = retry_capture(
+            2,
+            &running,
+            |_on_open| {
+                attempts += 1;
+                Err("no source".into())
+            },
+            |s| statuses.borrow_mut().push(s),
+            |_| Duration::ZERO,
+        );
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+        assert_eq!(
+            statuses.into_inner(),
+            [AudioStatus::Reconnecting, AudioStatus::Reconnecting, AudioStatus::Failed]
+        );
+    }
+
+    #[test]
+    fn successful_open_resets_the_failure_count() {
+        let running = AtomicBool::new(true);
+        let statuses = std::cell::RefCell::new(Vec::new());
+        let mut attempts = 0;
+        // A flapping source opens every time and then drops: never two
+        // failures in a row, so max_retries = 1 is never exceeded
+        let result = retry_capture(
+            1,
+            &running,
+            |on_open: &dyn Fn()| {
+                attempts += 1;
+                on_open();
+                if attempts == 4 {
+                    running.store(false, Ordering::Relaxed);
+                    return Ok(());
+                }
+                Err("stream lost".into())
+            },
+            |s| statuses.borrow_mut().push(s),
+            |_| Duration::ZERO,
+        );
+        assert!(result.is_ok());
+        assert_eq!(attempts, 4);
+        let statuses = statuses.into_inner();
+        assert!(!statuses.contains(&AudioStatus::Failed));
+        assert_eq!(statuses.iter().filter(|s| **s == AudioStatus::Recovered).count(), 3);
+    }
+}
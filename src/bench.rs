@@ -0,0 +1,228 @@
+//! Synthetic-load benchmark (`ivnc --bench`)
+//!
+//! Feeds the configured encoder pipeline a moving test pattern at the
+//! configured resolution, frame rate and codec for a fixed time, pulling RTP
+//! the way the server does, and prints achieved fps, encode latency
+//! percentiles and bitrate. No compositor, client or apps are needed, so
+//! runs are comparable across encoder settings and machines.
+//!
+//! Encode latency is measured from pushing a frame to the RTP packet that
+//! ends it (marker bit). Encoders that drop frames skew it slightly. With
+//! `encoding.encode_queue_depth` set, frames go through the encode thread as
+//! in the server, and the latency includes their time in the queue; the
+//! hand-off line shows how long the producer was held up either way.
+
+use ::gstreamer as gst;
+use ivnc::frame_clock::FrameClock;
+use ivnc::gstreamer::{EncodeQueue, VideoPipeline};
+use ivnc::Config;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Pixels the pattern scrolls per frame
+const SCROLL_STEP: usize = 8;
+
+/// Scrolling test pattern with enough texture to keep encoders busy
+struct TestPattern {
+    width: usize,
+    height: usize,
+    /// Two pattern widths per row so any offset is a contiguous slice
+    rows: Vec<u8>,
+    offset: usize,
+}
+
+impl TestPattern {
+    fn new(width: u32, height: u32) -> Self {
+        let (width, height) = (width as usize, height as usize);
+        let mut rows = vec![0u8; width * 2 * height * 4];
+        for y in 0..height {
+            for x in 0..width * 2 {
+                let px = (y * width * 2 + x) * 4;
+                let xx = x % width;
+                rows[px] = (xx * 255 / width) as u8;
+                rows[px + 1] = (y * 255 / height) as u8;
+                rows[px + 2] = ((xx ^ y) & 0xff) as u8;
+            }
+        }
+        Self { width, height, rows, offset: 0 }
+    }
+
+    /// Write the next frame (XRGB) into `frame` and advance the scroll
+    fn next_frame(&mut self, frame: &mut [u8]) {
+        let row_len = self.width * 4;
+        for y in 0..self.height {
+            let src = (y * self.width * 2 + self.offset) * 4;
+            frame[y * row_len..(y + 1) * row_len].copy_from_slice(&self.rows[src..src + row_len]);
+        }
+        self.offset = (self.offset + SCROLL_STEP) % self.width;
+    }
+}
+
+#[derive(Default)]
+struct Tally {
+    frames: u64,
+    packets: u64,
+    bytes: u64,
+    latencies_ms: Vec<f64>,
+}
+
+impl Tally {
+    /// Pull pending RTP; `pushed` holds push times of frames not yet seen
+    fn drain(&mut self, pipeline: &VideoPipeline, pushed: &mut VecDeque<Instant>, timeout_ms: u64) {
+        while let Some(sample) = pipeline.try_pull_sample_timeout(timeout_ms) {
+            let Some(buffer) = sample.buffer() else { continue };
+            let Ok(map) = buffer.map_readable() else { continue };
+            let data = map.as_slice();
+            self.packets += 1;
+            self.bytes += data.len() as u64;
+            // Marker bit: last packet of a frame
+            if data.len() >= 12 && data[1] & 0x80 != 0 {
+                self.frames += 1;
+                if let Some(at) = pushed.pop_front() {
+                    self.latencies_ms.push(at.elapsed().as_secs_f64() * 1000.0);
+                }
+            }
+        }
+    }
+}
+
+/// Value at `p` (0-100) of sorted samples, nearest rank
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Run the benchmark for `duration` and print a summary. Returns false if
+/// the pipeline could not be built or produced no output.
+pub fn run(config: &Config, duration: Duration) -> bool {
+    if let Err(e) = gst::init() {
+        eprintln!("Failed to initialize GStreamer: {}", e);
+        return false;
+    }
+    let (width, height) = (config.display.width, config.display.height);
+    let fps = config.encoding.target_fps.max(1);
+    let pipeline = match VideoPipeline::new(ivnc::server::pipeline_config_for(config, width, height, 1, 1.0)) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Failed to build the encoder pipeline: {}", e);
+            return false;
+        }
+    };
+    if let Err(e) = pipeline.start() {
+        eprintln!("Failed to start the encoder pipeline: {}", e);
+        return false;
+    }
+    println!(
+        "Benchmarking {:?} via {} at {}x{} @ {} fps for {}s...",
+        config.webrtc.video_codec,
+        pipeline.encoder_name(),
+        width,
+        height,
+        fps,
+        duration.as_secs()
+    );
+
+    let encode_queue = match config.encoding.encode_queue_depth {
+        0 => None,
+        depth => match EncodeQueue::new(depth) {
+            Ok(queue) => Some(queue),
+            Err(e) => {
+                eprintln!("Failed to start the encode thread: {}", e);
+                let _ = pipeline.stop();
+                return false;
+            }
+        },
+    };
+
+    let mut pattern = TestPattern::new(width, height);
+    let frame_len = (width * height * 4) as usize;
+    let mut frame = vec![0u8; frame_len];
+    let mut pushed = VecDeque::new();
+    let mut tally = Tally::default();
+    let mut handoffs_ms = Vec::new();
+    let mut clock = FrameClock::new(fps, Instant::now());
+    let mut skipped = 0;
+    let mut frames_pushed = 0u64;
+    let mut queue_dropped = 0u64;
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        pattern.next_frame(&mut frame);
+        // The queue takes ownership, like rendered frames in the server
+        let owned = encode_queue.is_some().then(|| std::mem::replace(&mut frame, vec![0u8; frame_len]));
+        let handoff_start = Instant::now();
+        match (&encode_queue, owned) {
+            (Some(queue), Some(owned)) => {
+                if !queue.submit((pipeline.frame_pusher(), owned, None)) {
+                    queue_dropped += 1;
+                    tally.drain(&pipeline, &mut pushed, 0);
+                    skipped += clock.wait();
+                    continue;
+                }
+            }
+            _ => {
+                if let Err(e) = pipeline.push_frame(&frame, None) {
+                    eprintln!("Frame push failed: {}", e);
+                    let _ = pipeline.stop();
+                    return false;
+                }
+            }
+        }
+        handoffs_ms.push(handoff_start.elapsed().as_secs_f64() * 1000.0);
+        pushed.push_back(Instant::now());
+        frames_pushed += 1;
+        tally.drain(&pipeline, &mut pushed, 0);
+        skipped += clock.wait();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    // Let in-flight frames come out, without counting them toward the rate
+    let (frames, bytes) = (tally.frames, tally.bytes);
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while !pushed.is_empty() && Instant::now() < deadline {
+        tally.drain(&pipeline, &mut pushed, 50);
+    }
+    drop(encode_queue);
+    let _ = pipeline.stop();
+
+    if tally.packets == 0 {
+        eprintln!("No RTP output from the encoder");
+        return false;
+    }
+    let mut latencies = tally.latencies_ms;
+    latencies.sort_by(|a, b| a.total_cmp(b));
+    handoffs_ms.sort_by(|a, b| a.total_cmp(b));
+    println!();
+    println!("{:<22} {}", "encoder", pipeline.encoder_name());
+    match config.encoding.encode_queue_depth {
+        0 => println!("{:<22} synchronous", "encode mode"),
+        depth => println!("{:<22} encode thread, queue depth {}", "encode mode", depth),
+    }
+    println!("{:<22} {}x{}", "resolution", width, height);
+    println!("{:<22} {} / {}", "frames pushed / out", frames_pushed, tally.frames);
+    println!("{:<22} {}", "slots skipped", skipped);
+    if config.encoding.encode_queue_depth > 0 {
+        println!("{:<22} {}", "dropped (queue full)", queue_dropped);
+    }
+    println!("{:<22} {:.1} (target {})", "achieved fps", frames as f64 / elapsed, fps);
+    println!("{:<22} {:.0} kbps (target {})", "bitrate", bytes as f64 * 8.0 / elapsed / 1000.0, config.webrtc.video_bitrate);
+    println!("{:<22} {}", "RTP packets", tally.packets);
+    println!(
+        "{:<22} p50 {:.1} / p95 {:.1} / p99 {:.1} / max {:.1}",
+        "encode latency (ms)",
+        percentile(&latencies, 50.0),
+        percentile(&latencies, 95.0),
+        percentile(&latencies, 99.0),
+        latencies.last().copied().unwrap_or(0.0)
+    );
+    println!(
+        "{:<22} p50 {:.2} / p95 {:.2} / p99 {:.2} / max {:.2}",
+        "hand-off (ms)",
+        percentile(&handoffs_ms, 50.0),
+        percentile(&handoffs_ms, 95.0),
+        percentile(&handoffs_ms, 99.0),
+        handoffs_ms.last().copied().unwrap_or(0.0)
+    );
+    true
+}
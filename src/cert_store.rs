@@ -0,0 +1,139 @@
+//! Persistent self-signed certificates
+//!
+//! By default the HTTPS acceptor and the WebRTC DTLS stack generate a fresh
+//! certificate on every start, so the fingerprint changes across restarts.
+//! When a certificate directory is configured, the generated certificate and
+//! key are written there once (DER, key mode 0600) and reused afterwards.
+
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use log::info;
+
+use crate::error::{Error, Result};
+
+/// DER-encoded certificate and PKCS#8 private key
+#[derive(Clone)]
+pub struct StoredCert {
+    pub cert_der: Vec<u8>,
+    pub key_der: Vec<u8>,
+}
+
+/// Generate a new self-signed certificate for `sans`
+pub fn generate(sans: &[&str]) -> Result<StoredCert> {
+    let sans: Vec<String> = sans.iter().map(|s| s.to_string()).collect();
+    let cert = rcgen::generate_simple_self_signed(sans)
+        .map_err(|e| Error::Io(std::io::Error::other(format!("certificate generation failed: {}", e))))?;
+    Ok(StoredCert {
+        cert_der: cert.cert.der().to_vec(),
+        key_der: cert.key_pair.serialize_der(),
+    })
+}
+
+/// Load `<name>.crt.der` / `<name>.key.der` from `dir`, or generate and
+/// store them if neither exists. Refuses a key readable by group/others or
+/// owned by another user.
+pub fn load_or_generate(dir: &Path, name: &str, sans: &[&str]) -> Result<StoredCert> {
+    let (cert_path, key_path) = paths(dir, name);
+
+    match (cert_path.exists(), key_path.exists()) {
+        (true, true) => {
+            check_private(&key_path)?;
+            let stored = StoredCert {
+                cert_der: fs::read(&cert_path).map_err(Error::Io)?,
+                key_der: fs::read(&key_path).map_err(Error::Io)?,
+            };
+            info!("Reusing {} certificate from {}", name, cert_path.display());
+            Ok(stored)
+        }
+        (false, false) => {
+            let stored = generate(sans)?;
+            store(dir, name, &stored)?;
+            info!("Generated {} certificate, saved to {}", name, cert_path.display());
+            Ok(stored)
+        }
+        _ => Err(Error::Config(format!(
+            "{} and {} must both exist or both be absent",
+            cert_path.display(),
+            key_path.display()
+        ))),
+    }
+}
+
+fn paths(dir: &Path, name: &str) -> (PathBuf, PathBuf) {
+    (dir.join(format!("{}.crt.der", name)), dir.join(format!("{}.key.der", name)))
+}
+
+fn store(dir: &Path, name: &str, stored: &StoredCert) -> Result<()> {
+    if !dir.exists() {
+        fs::create_dir_all(dir).map_err(Error::Io)?;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700)).map_err(Error::Io)?;
+    }
+    let (cert_path, key_path) = paths(dir, name);
+    write_file(&key_path, &stored.key_der, 0o600)?;
+    write_file(&cert_path, &stored.cert_der, 0o644)
+}
+
+fn write_file(path: &Path, data: &[u8], mode: u32) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode)
+        .open(path)
+        .map_err(Error::Io)?;
+    file.write_all(data).map_err(Error::Io)
+}
+
+fn check_private(path: &Path) -> Result<()> {
+    let meta = fs::metadata(path).map_err(Error::Io)?;
+    if meta.mode() & 0o077 != 0 {
+        return Err(Error::Config(format!(
+            "{} is accessible by other users (mode {:o}); chmod 600 it",
+            path.display(),
+            meta.mode() & 0o777
+        )));
+    }
+    let euid = unsafe { libc::geteuid() };
+    if meta.uid() != euid {
+        return Err(Error::Config(format!(
+            "{} is owned by uid {}, expected {}",
+            path.display(),
+            meta.uid(),
+            euid
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(tag: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ivnc-cert-{}-{}", tag, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn generated_cert_is_reused() {
+        let dir = temp_dir("reuse");
+        let first = load_or_generate(&dir, "https", &["localhost"]).unwrap();
+        let second = load_or_generate(&dir, "https", &["localhost"]).unwrap();
+        assert_eq!(first.cert_der, second.cert_der);
+        assert_eq!(first.key_der, second.key_der);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn world_readable_key_is_rejected() {
+        let dir = temp_dir("perms");
+        load_or_generate(&dir, "dtls", &["ivnc"]).unwrap();
+        let (_, key_path) = paths(&dir, "dtls");
+        fs::set_permissions(&key_path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(matches!(load_or_generate(&dir, "dtls", &["ivnc"]), Err(Error::Config(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
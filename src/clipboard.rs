@@ -1,17 +1,134 @@
 //! Clipboard message handling (text + binary, single or multipart).
 
+use crate::config::ClipboardOverflowPolicy;
 use crate::web::SharedState;
 use crate::system_clipboard;
 use base64::Engine;
-use log::{info, warn};
+use log::{debug, info, warn};
 use std::sync::Arc;
 
-const MAX_CLIPBOARD_BYTES: usize = 16 * 1024 * 1024;
+/// X11 selection targets that carry text
+const X11_TEXT_TARGETS: [&str; 4] = ["UTF8_STRING", "STRING", "TEXT", "COMPOUND_TEXT"];
+
+/// Prefixes of browser → server clipboard messages
+const CLIPBOARD_PREFIXES: [&str; 10] = ["cw,", "c,", "ch,", "chs,", "ck,", "cb,", "cws,", "cbs,", "cwd,", "cbd,"];
+
+/// Whether a DataChannel text message belongs to the clipboard protocol
+pub fn is_clipboard_message(message: &str) -> bool {
+    CLIPBOARD_PREFIXES.iter().any(|prefix| message.starts_with(prefix)) || message == "cwe" || message == "cbe"
+}
+
+/// Browser (or MCP) clipboard content waiting to become the compositor's
+/// selection
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipboardContent {
+    pub text: String,
+    /// Rich-text representation, offered as `text/html`
+    pub html: Option<String>,
+}
+
+impl ClipboardContent {
+    pub fn text(text: String) -> Self {
+        Self { text, html: None }
+    }
+
+    /// HTML with a plain-text fallback derived from it
+    pub fn html(html: String) -> Self {
+        Self { text: html_to_text(&html), html: Some(html) }
+    }
+
+    /// HTML together with the plain text the browser had for it
+    pub fn html_with_text(html: String, text: String) -> Self {
+        Self { text, html: Some(html) }
+    }
+
+    /// MIME types to offer: the configured text types, plus `text/html`
+    /// when there is HTML
+    pub fn mime_types(&self, configured: &[String]) -> Vec<String> {
+        let mut types: Vec<String> = configured.iter().filter(|m| !is_html_mime(m)).cloned().collect();
+        if self.html.is_some() {
+            types.insert(0, "text/html".to_string());
+        }
+        types
+    }
+
+    /// Content for a MIME type a client asked for; None unless the type was
+    /// offered
+    pub fn data_for(&self, mime: &str, offered: &[String]) -> Option<&[u8]> {
+        if !offered.iter().any(|m| m == mime) {
+            return None;
+        }
+        match &self.html {
+            Some(html) if is_html_mime(mime) => Some(html.as_bytes()),
+            _ => Some(self.text.as_bytes()),
+        }
+    }
+}
+
+/// `text/*` or an X11 text target
+pub fn is_text_mime(mime: &str) -> bool {
+    mime.starts_with("text/") || X11_TEXT_TARGETS.contains(&mime)
+}
+
+fn is_html_mime(mime: &str) -> bool {
+    mime == "text/html" || mime.starts_with("text/html;")
+}
+
+/// Rough plain-text rendering of HTML: tags dropped, block ends as line
+/// breaks, common entities decoded
+pub fn html_to_text(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find(['<', '&']) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with('<') {
+            let Some(end) = rest.find('>') else { break };
+            let closing = rest[1..].starts_with('/');
+            let tag = rest[1..end].trim_start_matches('/').to_ascii_lowercase();
+            let name = tag.split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or("");
+            let block = matches!(name, "p" | "div" | "li" | "tr" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6");
+            if (name == "br" || closing && block) && !out.is_empty() && !out.ends_with('\n') {
+                out.push('\n');
+            }
+            rest = &rest[end + 1..];
+        } else {
+            let entity_end = rest.find(';').filter(|&end| end <= 8);
+            let decoded = entity_end.and_then(|end| match &rest[1..end] {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" | "#39" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => None,
+            });
+            match (decoded, entity_end) {
+                (Some(c), Some(end)) => {
+                    out.push(c);
+                    rest = &rest[end + 1..];
+                }
+                _ => {
+                    out.push('&');
+                    rest = &rest[1..];
+                }
+            }
+        }
+    }
+    if !rest.starts_with('<') {
+        out.push_str(rest);
+    }
+    out.trim().to_string()
+}
 
 pub struct ClipboardReceiver {
     state: Arc<SharedState>,
+    /// Session the messages come from; `ck,` typing is subject to its
+    /// input control
+    session_id: Option<Arc<str>>,
     buffer: Option<Vec<u8>>,
     total_size: usize,
+    received: usize,
     mime_type: String,
     in_progress: bool,
     is_binary: bool,
@@ -21,16 +138,28 @@ impl ClipboardReceiver {
     pub fn new(state: Arc<SharedState>) -> Self {
         Self {
             state,
+            session_id: None,
             buffer: None,
             total_size: 0,
+            received: 0,
             mime_type: "text/plain".to_string(),
             in_progress: false,
             is_binary: false,
         }
     }
 
+    /// Receiver for a WebRTC session's DataChannel
+    pub fn for_session(state: Arc<SharedState>, session_id: Arc<str>) -> Self {
+        Self { session_id: Some(session_id), ..Self::new(state) }
+    }
+
     pub fn handle_message(&mut self, message: &str) -> bool {
-        if !self.state.config.input.enable_clipboard {
+        if !self.state.clipboard_enabled() {
+            // Consume them anyway, so `c,` is not parsed as input either
+            if is_clipboard_message(message) {
+                debug!("Clipboard disabled; dropping {} byte clipboard message", message.len());
+                return true;
+            }
             return false;
         }
 
@@ -45,6 +174,18 @@ impl ClipboardReceiver {
             self.handle_single_text(payload);
             return true;
         }
+        if let Some(payload) = message.strip_prefix("ch,") {
+            self.handle_single_html(payload);
+            return true;
+        }
+        if let Some(payload) = message.strip_prefix("chs,") {
+            self.start_multipart("text/html", payload, false);
+            return true;
+        }
+        if let Some(payload) = message.strip_prefix("ck,") {
+            self.type_as_keystrokes(payload);
+            return true;
+        }
         if message.starts_with("cb,") {
             let payload = message.trim_start_matches("cb,");
             self.handle_single_binary(payload);
@@ -81,32 +222,128 @@ impl ClipboardReceiver {
     fn handle_single_text(&self, base64_payload: &str) {
         match decode_base64(base64_payload) {
             Some(bytes) => {
-                if bytes.len() > MAX_CLIPBOARD_BYTES {
-                    warn!("Clipboard payload exceeds limit ({} bytes)", bytes.len());
+                let total = bytes.len();
+                let Some(bytes) = self.state.limit_clipboard(bytes, total, true) else {
                     return;
-                }
-                let encoded = base64::engine::general_purpose::STANDARD.encode(&bytes);
-                let _ = self.state.clipboard_incoming_tx.send(encoded.clone());
-                self.state.set_clipboard(encoded);
+                };
+                self.offer_text(bytes);
             }
             None => warn!("Failed to decode clipboard text payload"),
         }
     }
 
-    fn handle_single_binary(&self, payload: &str) {
-        if !self.state.runtime_settings.binary_clipboard_enabled() {
-            warn!("Binary clipboard is disabled; ignoring payload");
+    /// Type clipboard text as key events instead of pasting, for remote apps
+    /// that never read the clipboard. Does not change the clipboard itself.
+    fn type_as_keystrokes(&self, base64_payload: &str) {
+        if self.session_id.as_ref().is_some_and(|id| !self.state.input_allowed(id)) {
+            debug!("Ignoring keystroke paste: another session has input control");
             return;
         }
+        let Some(bytes) = decode_base64(base64_payload) else {
+            warn!("Failed to decode keystroke paste payload");
+            return;
+        };
+        let total = bytes.len();
+        let Some(bytes) = self.state.limit_clipboard(bytes, total, true) else {
+            return;
+        };
+        let text = String::from_utf8_lossy(&bytes).into_owned();
+        let chars = text.chars().count();
+        if chars > crate::input::KEYSTROKE_WARN_CHARS {
+            warn!(
+                "Typing {} clipboard chars as keystrokes; this takes about {} s (cw, paste is faster)",
+                chars,
+                chars * 80 / 1000
+            );
+        } else {
+            info!("Typing {} clipboard chars as keystrokes", chars);
+        }
+        let state = self.state.clone();
+        let session_id = self.session_id.clone();
+        tokio::spawn(async move {
+            let _typing = state.keystroke_typing_lock.lock().await;
+            crate::input::type_keystrokes(&state, session_id.as_ref(), &text).await;
+        });
+    }
+
+    /// Hand browser text to the compositor selection and the other clients
+    fn offer_text(&self, bytes: Vec<u8>) {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&bytes);
+        match String::from_utf8(bytes) {
+            Ok(text) => {
+                let _ = self.state.clipboard_incoming_tx.send(ClipboardContent::text(text));
+            }
+            // Every offered type is text; don't hand apps invalid UTF-8
+            Err(_) => warn!("Clipboard text is not valid UTF-8; not offering it to remote apps"),
+        }
+        self.state.set_clipboard(encoded);
+    }
+
+    /// `ch,{base64_html}[,{base64_text}]`: HTML and, optionally, the plain
+    /// text the browser has for the same selection
+    fn handle_single_html(&self, payload: &str) {
+        let (html_b64, text_b64) = match payload.split_once(',') {
+            Some((html, text)) => (html, Some(text)),
+            None => (payload, None),
+        };
+        let Some(html) = decode_base64(html_b64) else {
+            warn!("Failed to decode clipboard HTML payload");
+            return;
+        };
+        let total = html.len();
+        let Some(html) = self.state.limit_clipboard(html, total, true) else {
+            return;
+        };
+        let text = text_b64.and_then(decode_base64).and_then(|bytes| String::from_utf8(bytes).ok());
+        self.offer_html(html, text);
+    }
+
+    /// HTML from the browser is text, so it becomes the compositor selection
+    /// like plain text, offered as `text/html` as well
+    fn offer_html(&self, bytes: Vec<u8>, text: Option<String>) {
+        match String::from_utf8(bytes) {
+            Ok(html) => {
+                let content = match text {
+                    Some(text) => ClipboardContent::html_with_text(html, text),
+                    None => ClipboardContent::html(html),
+                };
+                info!("Clipboard HTML from browser: {} bytes", content.html.as_ref().map_or(0, |h| h.len()));
+                let encoded = base64::engine::general_purpose::STANDARD.encode(content.text.as_bytes());
+                let html = content.html.clone().unwrap_or_default();
+                let _ = self.state.clipboard_incoming_tx.send(content);
+                self.state.set_clipboard(encoded);
+                self.state.set_clipboard_html(html.as_bytes());
+            }
+            Err(_) => warn!("Clipboard HTML is not valid UTF-8; ignoring it"),
+        }
+    }
+
+    fn handle_single_binary(&self, payload: &str) {
         let mut parts = payload.splitn(2, ',');
         let mime = parts.next().unwrap_or("application/octet-stream");
         let b64 = parts.next().unwrap_or_default();
+        if is_html_mime(mime) {
+            match decode_base64(b64) {
+                Some(bytes) => {
+                    let total = bytes.len();
+                    if let Some(bytes) = self.state.limit_clipboard(bytes, total, true) {
+                        self.offer_html(bytes, None);
+                    }
+                }
+                None => warn!("Failed to decode clipboard HTML payload"),
+            }
+            return;
+        }
+        if !self.state.runtime_settings.binary_clipboard_enabled() {
+            warn!("Binary clipboard is disabled; ignoring payload");
+            return;
+        }
         match decode_base64(b64) {
             Some(bytes) => {
-                if bytes.len() > MAX_CLIPBOARD_BYTES {
-                    warn!("Clipboard payload exceeds limit ({} bytes)", bytes.len());
+                let total = bytes.len();
+                let Some(bytes) = self.state.limit_clipboard(bytes, total, false) else {
                     return;
-                }
+                };
                 if system_clipboard::write(mime, &bytes) {
                     self.state.mark_clipboard_written(mime, &bytes);
                 }
@@ -124,12 +361,22 @@ impl ClipboardReceiver {
                 return;
             }
         };
-        if total_size == 0 || total_size > MAX_CLIPBOARD_BYTES {
-            warn!("Multipart clipboard size {} exceeds limit", total_size);
+        if total_size == 0 {
+            warn!("Invalid multipart clipboard size: 0");
             return;
         }
-        self.buffer = Some(Vec::with_capacity(total_size));
+        let limit = self.state.config.input.max_clipboard_bytes;
+        if total_size > limit
+            && self.state.config.input.clipboard_overflow == ClipboardOverflowPolicy::Reject
+        {
+            warn!("Multipart clipboard size {} exceeds limit {}", total_size, limit);
+            self.state.notify_clipboard_too_large(total_size);
+            return;
+        }
+        // In truncate mode only the first `limit` bytes are kept.
+        self.buffer = Some(Vec::with_capacity(total_size.min(limit)));
         self.total_size = total_size;
+        self.received = 0;
         self.mime_type = mime.to_string();
         self.in_progress = true;
         self.is_binary = is_binary;
@@ -137,6 +384,12 @@ impl ClipboardReceiver {
     }
 
     fn start_multipart_binary(&mut self, payload: &str) {
+        if payload.split(',').next().is_some_and(is_html_mime) {
+            // HTML is text; not subject to the binary clipboard switch
+            let total = payload.split_once(',').map_or("0", |(_, size)| size);
+            self.start_multipart("text/html", total, false);
+            return;
+        }
         if !self.state.runtime_settings.binary_clipboard_enabled() {
             warn!("Binary clipboard is disabled; ignoring multipart start");
             return;
@@ -154,12 +407,15 @@ impl ClipboardReceiver {
         }
         if let Some(chunk) = decode_base64(base64_payload) {
             if let Some(buffer) = self.buffer.as_mut() {
-                if buffer.len().saturating_add(chunk.len()) > self.total_size {
+                if self.received.saturating_add(chunk.len()) > self.total_size {
                     warn!("Clipboard chunk exceeds declared size; aborting transfer");
                     self.reset();
                     return;
                 }
-                buffer.extend_from_slice(&chunk);
+                self.received += chunk.len();
+                let limit = self.state.config.input.max_clipboard_bytes;
+                let keep = limit.saturating_sub(buffer.len()).min(chunk.len());
+                buffer.extend_from_slice(&chunk[..keep]);
             }
         } else {
             warn!("Failed to decode clipboard chunk");
@@ -178,17 +434,26 @@ impl ClipboardReceiver {
                 return;
             }
         };
-        if buffer.len() != self.total_size {
+        if self.received != self.total_size {
             warn!(
                 "Clipboard multipart size mismatch: expected {}, got {}",
                 self.total_size,
-                buffer.len()
+                self.received
             );
             self.reset();
             return;
         }
+        let Some(buffer) = self
+            .state
+            .limit_clipboard(buffer, self.total_size, !self.is_binary)
+        else {
+            self.reset();
+            return;
+        };
 
-        if self.is_binary {
+        if is_html_mime(&self.mime_type) {
+            self.offer_html(buffer, None);
+        } else if self.is_binary {
             if system_clipboard::write(&self.mime_type, &buffer) {
                 self.state.mark_clipboard_written(&self.mime_type, &buffer);
             }
@@ -198,9 +463,7 @@ impl ClipboardReceiver {
             if system_clipboard::write("text/plain", &buffer) {
                 self.state.mark_clipboard_written("text/plain", &buffer);
             }
-            let encoded = base64::engine::general_purpose::STANDARD.encode(buffer);
-            let _ = self.state.clipboard_incoming_tx.send(encoded.clone());
-            self.state.set_clipboard(encoded);
+            self.offer_text(buffer);
         }
         self.reset();
     }
@@ -208,6 +471,7 @@ impl ClipboardReceiver {
     fn reset(&mut self) {
         self.buffer = None;
         self.total_size = 0;
+        self.received = 0;
         self.mime_type = "text/plain".to_string();
         self.in_progress = false;
         self.is_binary = false;
@@ -219,3 +483,131 @@ fn decode_base64(payload: &str) -> Option<Vec<u8>> {
         .decode(payload)
         .ok()
 }
+
+/// Drop a trailing partial UTF-8 sequence left behind by byte-level truncation.
+pub fn trim_partial_utf8(data: &mut Vec<u8>) {
+    if let Err(err) = std::str::from_utf8(data) {
+        if err.error_len().is_none() {
+            data.truncate(err.valid_up_to());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn shared_state(enable_clipboard: bool) -> Arc<SharedState> {
+        let mut config = Config::default();
+        config.input.enable_clipboard = enable_clipboard;
+        SharedState::for_test(config).0
+    }
+
+    #[test]
+    fn disabled_clipboard_drops_browser_messages() {
+        let state = shared_state(false);
+        let mut receiver = ClipboardReceiver::new(state.clone());
+        // Consumed, so the legacy `c,` is not parsed as an input event either
+        assert!(receiver.handle_message("cw,aGk="));
+        assert!(receiver.handle_message("c,aGk="));
+        assert!(receiver.handle_message("cws,text/plain,4"));
+        assert!(receiver.handle_message("cwe"));
+        assert!(!receiver.handle_message("m,10,20,0,0"));
+        assert!(state.clipboard_incoming_rx.lock().unwrap().try_recv().is_err());
+        assert_eq!(*state.clipboard.lock().unwrap(), None);
+
+        let state = shared_state(true);
+        let mut receiver = ClipboardReceiver::new(state.clone());
+        assert!(receiver.handle_message("cw,aGk="));
+        let content = state.clipboard_incoming_rx.lock().unwrap().try_recv().unwrap();
+        assert_eq!(content.text, "hi");
+    }
+
+    #[test]
+    fn disabled_clipboard_is_not_broadcast() {
+        let state = shared_state(false);
+        let mut rx = state.text_sender.subscribe();
+        assert!(!state.publish_remote_clipboard("text/plain", b"hi".to_vec(), 2));
+        assert!(!state.publish_remote_clipboard("text/html", b"<b>hi</b>".to_vec(), 9));
+        assert!(rx.try_recv().is_err());
+
+        let state = shared_state(true);
+        let mut rx = state.text_sender.subscribe();
+        assert!(state.publish_remote_clipboard("text/plain", b"hi".to_vec(), 2));
+        assert_eq!(rx.try_recv().unwrap(), "clipboard,aGk=");
+    }
+
+    #[test]
+    fn parses_html_clipboard_messages() {
+        let state = shared_state(true);
+        let mut receiver = ClipboardReceiver::new(state.clone());
+        let next = || state.clipboard_incoming_rx.lock().unwrap().try_recv().unwrap();
+
+        // HTML with the browser's own plain text
+        assert!(receiver.handle_message("ch,PGI+aGk8L2I+,Ym9sZCBoaQ=="));
+        assert_eq!(next(), ClipboardContent::html_with_text("<b>hi</b>".into(), "bold hi".into()));
+        // HTML only: the text is derived from it
+        assert!(receiver.handle_message("ch,PGI+aGk8L2I+"));
+        assert_eq!(next(), ClipboardContent::html("<b>hi</b>".into()));
+        assert_eq!(*state.clipboard.lock().unwrap(), Some("aGk=".to_string()));
+
+        // Multipart HTML, split inside a tag
+        assert!(receiver.handle_message("chs,9"));
+        assert!(receiver.handle_message("cwd,PGI+aGk8"));
+        assert!(receiver.handle_message("cwd,L2I+"));
+        assert!(receiver.handle_message("cwe"));
+        assert_eq!(next(), ClipboardContent::html("<b>hi</b>".into()));
+
+        // Undecodable HTML is dropped
+        assert!(receiver.handle_message("ch,not base64!"));
+        assert!(state.clipboard_incoming_rx.lock().unwrap().try_recv().is_err());
+    }
+
+    #[test]
+    fn recognizes_clipboard_messages() {
+        assert!(is_clipboard_message("cbd,AAAA"));
+        assert!(is_clipboard_message("cbe"));
+        assert!(!is_clipboard_message("close,3"));
+        assert!(!is_clipboard_message("cursor_mode,server"));
+    }
+
+    #[test]
+    fn test_trim_partial_utf8() {
+        let mut data = "héllo".as_bytes().to_vec();
+        data.truncate(2); // splits the two-byte 'é'
+        trim_partial_utf8(&mut data);
+        assert_eq!(data, b"h");
+
+        let mut complete = "héllo".as_bytes().to_vec();
+        trim_partial_utf8(&mut complete);
+        assert_eq!(complete, "héllo".as_bytes());
+    }
+
+    #[test]
+    fn offered_types_and_content() {
+        let configured: Vec<String> = vec!["text/plain".into(), "UTF8_STRING".into(), "text/html".into()];
+        let plain = ClipboardContent::text("hi".into());
+        let offered = plain.mime_types(&configured);
+        // No text/html without HTML content, even when configured
+        assert_eq!(offered, ["text/plain", "UTF8_STRING"]);
+        assert_eq!(plain.data_for("UTF8_STRING", &offered), Some(&b"hi"[..]));
+        assert_eq!(plain.data_for("text/html", &offered), None);
+        assert_eq!(plain.data_for("image/png", &offered), None);
+
+        let rich = ClipboardContent::html("<p>a &amp; <b>b</b></p><p>c</p>".into());
+        assert_eq!(rich.text, "a & b\nc");
+        let offered = rich.mime_types(&configured);
+        assert_eq!(offered, ["text/html", "text/plain", "UTF8_STRING"]);
+        assert_eq!(rich.data_for("text/html", &offered), Some(rich.html.as_deref().unwrap().as_bytes()));
+        assert_eq!(rich.data_for("text/plain", &offered), Some(&b"a & b\nc"[..]));
+    }
+
+    #[test]
+    fn html_to_text_handles_breaks_entities_and_stray_ampersands() {
+        assert_eq!(html_to_text("one<br>two<br/>three"), "one\ntwo\nthree");
+        assert_eq!(html_to_text("<ul><li>x</li><li>y</li></ul>"), "x\ny");
+        assert_eq!(html_to_text("Q&A &lt;tag&gt; &unknown; ok"), "Q&A <tag> &unknown; ok");
+        assert!(is_text_mime("STRING") && is_text_mime("text/rtf") && !is_text_mime("image/png"));
+    }
+}
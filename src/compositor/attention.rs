@@ -0,0 +1,100 @@
+//! Attention requests from background windows
+//!
+//! A window that asks for activation (xdg-activation) without having focus
+//! is flagged until it is focused or destroyed, and each new request is
+//! queued once for clients as `attention,<id>`. Minimized windows are not
+//! on the taskbar, so their requests are flagged without being announced;
+//! the flag shows once they are restored.
+
+use std::collections::HashSet;
+use std::hash::Hash;
+
+/// Flagged windows and the requests not yet sent to clients
+#[derive(Debug)]
+pub struct Attention<K> {
+    flagged: HashSet<K>,
+    pending: Vec<K>,
+}
+
+impl<K> Default for Attention<K> {
+    fn default() -> Self {
+        Self { flagged: HashSet::new(), pending: Vec::new() }
+    }
+}
+
+impl<K: Eq + Hash + Clone> Attention<K> {
+    /// Handle an activation request from `window`; true if it is newly
+    /// flagged (the taskbar changed)
+    pub fn request(&mut self, window: K, focused: bool, minimized: bool) -> bool {
+        if focused || !self.flagged.insert(window.clone()) {
+            return false;
+        }
+        if !minimized {
+            self.pending.push(window);
+        }
+        true
+    }
+
+    /// Focusing or destroying a window answers its request; true if it was
+    /// flagged
+    pub fn clear(&mut self, window: &K) -> bool {
+        self.pending.retain(|w| w != window);
+        self.flagged.remove(window)
+    }
+
+    pub fn contains(&self, window: &K) -> bool {
+        self.flagged.contains(window)
+    }
+
+    /// Requests to announce, oldest first
+    pub fn take_pending(&mut self) -> Vec<K> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_flagged_until_focus_or_destroy() {
+        let mut attention = Attention::default();
+        assert!(attention.request("mail", false, false));
+        // Repeats don't announce again
+        assert!(!attention.request("mail", false, false));
+        assert_eq!(attention.take_pending(), ["mail"]);
+        assert!(attention.take_pending().is_empty());
+        assert!(attention.contains(&"mail"));
+
+        // The focused window needs no attention
+        assert!(!attention.request("editor", true, false));
+        assert!(!attention.contains(&"editor"));
+
+        assert!(attention.clear(&"mail"));
+        assert!(!attention.contains(&"mail"));
+        assert!(!attention.clear(&"mail"));
+        // Flagged again once it loses focus
+        assert!(attention.request("mail", false, false));
+        assert_eq!(attention.take_pending(), ["mail"]);
+    }
+
+    #[test]
+    fn destroyed_windows_are_not_announced() {
+        let mut attention = Attention::default();
+        attention.request("build", false, false);
+        attention.request("chat", false, false);
+        assert!(attention.clear(&"build"));
+        assert_eq!(attention.take_pending(), ["chat"]);
+    }
+
+    #[test]
+    fn minimized_windows_are_flagged_but_not_announced() {
+        let mut attention = Attention::default();
+        assert!(attention.request("build", false, true));
+        assert!(attention.contains(&"build"));
+        assert!(attention.take_pending().is_empty());
+        // Already flagged when restored: no late announcement
+        assert!(!attention.request("build", false, false));
+        assert!(attention.take_pending().is_empty());
+    }
+}
@@ -0,0 +1,331 @@
+//! Client-drawn cursor images
+//!
+//! Named cursors reach the browser as CSS cursor names. When a client sets a
+//! surface as its cursor instead (custom I-beams, resize handles, themed
+//! cursors), the image is read from the surface's SHM buffer and sent as a
+//! PNG together with its hotspot, so the browser positions it correctly.
+//!
+//! With server-side cursor rendering (`SET_NATIVE_CURSOR_RENDERING,1`) the
+//! same image is blended into the frame instead. There is no cursor theme,
+//! so named cursors are all drawn as a built-in arrow.
+//!
+//! A cursor surface with a buffer scale above 1 carries a HiDPI image; it is
+//! resampled to the size it is shown at (logical pixels in the browser).
+
+use smithay::{
+    backend::renderer::utils::RendererSurfaceStateUserData,
+    input::pointer::CursorImageSurfaceData,
+    reexports::wayland_server::{
+        backend::ObjectId,
+        protocol::{wl_buffer::WlBuffer, wl_shm, wl_surface::WlSurface},
+        Resource,
+    },
+    wayland::{compositor::with_states, shm::with_buffer_contents},
+};
+use xxhash_rust::xxh64::xxh64;
+
+/// A cursor surface's current image
+pub struct CursorImage {
+    /// Offset of the pointer position within the image
+    pub hotspot: (i32, i32),
+    pub png: Vec<u8>,
+}
+
+/// An unencoded cursor image for blending into frames
+#[derive(Debug, Clone)]
+pub struct CursorBitmap {
+    /// Offset of the pointer position within the image
+    pub hotspot: (i32, i32),
+    pub width: usize,
+    pub height: usize,
+    /// Tightly packed RGBA
+    pub rgba: Vec<u8>,
+    /// Image pixels per logical pixel (the surface's buffer scale)
+    pub scale: f64,
+}
+
+/// Last PNG encoding of a client-drawn cursor. Clients commit their cursor
+/// surface without changing the image (re-attached buffers, frame
+/// callbacks), so it is only re-encoded for another buffer or new contents.
+#[derive(Default)]
+pub struct CursorPngCache {
+    entry: Option<(ObjectId, u64, Vec<u8>)>,
+}
+
+impl CursorPngCache {
+    fn png(&mut self, buffer: &WlBuffer, bitmap: &CursorBitmap) -> Option<Vec<u8>> {
+        let hash = xxh64(&bitmap.rgba, bitmap.width as u64);
+        if let Some((id, cached_hash, png)) = &self.entry {
+            if *id == buffer.id() && *cached_hash == hash {
+                return Some(png.clone());
+            }
+        }
+        let png = encode_png(bitmap.width as u32, bitmap.height as u32, &bitmap.rgba)?;
+        self.entry = Some((buffer.id(), hash, png.clone()));
+        Some(png)
+    }
+}
+
+/// Built-in arrow: `#` outline, `.` fill, space transparent
+const ARROW: [&str; 17] = [
+    "#",
+    "##",
+    "#.#",
+    "#..#",
+    "#...#",
+    "#....#",
+    "#.....#",
+    "#......#",
+    "#.......#",
+    "#........#",
+    "#.....#####",
+    "#..#..#",
+    "#.# #..#",
+    "##  #..#",
+    "#    #..#",
+    "     #..#",
+    "      ##",
+];
+
+impl CursorBitmap {
+    /// The built-in arrow, hotspot at its tip
+    pub fn arrow() -> Self {
+        let width = ARROW.iter().map(|row| row.len()).max().unwrap_or(0);
+        let mut rgba = vec![0u8; width * ARROW.len() * 4];
+        for (y, row) in ARROW.iter().enumerate() {
+            for (x, c) in row.bytes().enumerate() {
+                let px = match c {
+                    b'#' => [0, 0, 0, 0xff],
+                    b'.' => [0xff, 0xff, 0xff, 0xff],
+                    _ => continue,
+                };
+                let i = (y * width + x) * 4;
+                rgba[i..i + 4].copy_from_slice(&px);
+            }
+        }
+        Self { hotspot: (0, 0), width, height: ARROW.len(), rgba, scale: 1.0 }
+    }
+
+    /// The image resampled (nearest neighbour) for display at `scale`
+    /// image pixels per logical pixel
+    pub fn at_scale(&self, scale: f64) -> Self {
+        let factor = scale / self.scale;
+        if (factor - 1.0).abs() < f64::EPSILON || factor <= 0.0 || self.width == 0 || self.height == 0 {
+            return self.clone();
+        }
+        let width = ((self.width as f64 * factor).round() as usize).max(1);
+        let height = ((self.height as f64 * factor).round() as usize).max(1);
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            let sy = ((y as f64 / factor) as usize).min(self.height - 1);
+            for x in 0..width {
+                let sx = ((x as f64 / factor) as usize).min(self.width - 1);
+                rgba.extend_from_slice(&self.rgba[(sy * self.width + sx) * 4..][..4]);
+            }
+        }
+        let hotspot = (
+            (self.hotspot.0 as f64 * factor).round() as i32,
+            (self.hotspot.1 as f64 * factor).round() as i32,
+        );
+        Self { hotspot, width, height, rgba, scale }
+    }
+
+    /// Alpha-blend onto a tightly packed XRGB8888 frame (XBGR8888 when
+    /// `bgr`) with the hotspot at `pos`; clipped to the frame
+    pub fn blend(&self, pixels: &mut [u8], width: usize, height: usize, pos: (i32, i32), bgr: bool) {
+        if pixels.len() < width * height * 4 {
+            return;
+        }
+        let (x0, y0) = (pos.0 - self.hotspot.0, pos.1 - self.hotspot.1);
+        for cy in 0..self.height {
+            let y = y0 + cy as i32;
+            if y < 0 || y >= height as i32 {
+                continue;
+            }
+            for cx in 0..self.width {
+                let x = x0 + cx as i32;
+                if x < 0 || x >= width as i32 {
+                    continue;
+                }
+                let src = &self.rgba[(cy * self.width + cx) * 4..][..4];
+                let alpha = src[3] as u32;
+                if alpha == 0 {
+                    continue;
+                }
+                let dst = &mut pixels[(y as usize * width + x as usize) * 4..][..4];
+                // Memory order B, G, R, X (or R, G, B, X)
+                let channels = if bgr { [src[0], src[1], src[2]] } else { [src[2], src[1], src[0]] };
+                for (d, s) in dst.iter_mut().zip(channels) {
+                    *d = ((s as u32 * alpha + *d as u32 * (255 - alpha)) / 255) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Read a cursor surface's image and hotspot at logical size for the
+/// browser; None without an SHM buffer in a supported format
+pub fn surface_cursor(surface: &WlSurface, cache: &mut CursorPngCache) -> Option<CursorImage> {
+    let (buffer, bitmap) = read_surface_cursor(surface)?;
+    let bitmap = bitmap.at_scale(1.0);
+    Some(CursorImage { hotspot: bitmap.hotspot, png: cache.png(&buffer, &bitmap)? })
+}
+
+/// Like `surface_cursor`, without the PNG encoding or resampling
+pub fn surface_cursor_bitmap(surface: &WlSurface) -> Option<CursorBitmap> {
+    read_surface_cursor(surface).map(|(_, bitmap)| bitmap)
+}
+
+fn read_surface_cursor(surface: &WlSurface) -> Option<(WlBuffer, CursorBitmap)> {
+    let (hotspot, buffer, scale) = with_states(surface, |states| {
+        let hotspot = states
+            .data_map
+            .get::<CursorImageSurfaceData>()
+            .map(|data| data.lock().unwrap().hotspot)
+            .unwrap_or_default();
+        let (buffer, scale) = states
+            .data_map
+            .get::<RendererSurfaceStateUserData>()
+            .map(|data| {
+                let data = data.lock().unwrap();
+                (data.buffer().map(|b| (**b).clone()), data.buffer_scale())
+            })
+            .unwrap_or((None, 1));
+        (hotspot, buffer, scale.max(1))
+    });
+    let buffer = buffer?;
+    let (width, height, rgba) = with_buffer_contents(&buffer, |ptr, len, data| {
+        // SAFETY: smithay maps the whole pool for the duration of the call
+        let pool = unsafe { std::slice::from_raw_parts(ptr, len) };
+        let (width, height) = (data.width.max(0) as usize, data.height.max(0) as usize);
+        let rgba = shm_to_rgba(pool, data.offset as usize, width, height, data.stride as usize, data.format)?;
+        Some((width, height, rgba))
+    })
+    .ok()??;
+    // The hotspot is in surface (logical) coordinates
+    let hotspot = (hotspot.x * scale, hotspot.y * scale);
+    Some((buffer, CursorBitmap { hotspot, width, height, rgba, scale: scale as f64 }))
+}
+
+/// Convert an ARGB8888/XRGB8888 SHM image (memory order B, G, R, A) to
+/// tightly packed RGBA
+fn shm_to_rgba(
+    pool: &[u8],
+    offset: usize,
+    width: usize,
+    height: usize,
+    stride: usize,
+    format: wl_shm::Format,
+) -> Option<Vec<u8>> {
+    let opaque = match format {
+        wl_shm::Format::Argb8888 => false,
+        wl_shm::Format::Xrgb8888 => true,
+        _ => return None,
+    };
+    if width == 0 || height == 0 || stride < width * 4 {
+        return None;
+    }
+    let end = offset.checked_add(stride * (height - 1) + width * 4)?;
+    let image = pool.get(offset..end)?;
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        for px in image[y * stride..y * stride + width * 4].chunks_exact(4) {
+            rgba.extend_from_slice(&[px[2], px[1], px[0], if opaque { 0xff } else { px[3] }]);
+        }
+    }
+    Some(rgba)
+}
+
+fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().ok()?;
+    writer.write_image_data(rgba).ok()?;
+    writer.finish().ok()?;
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shm_conversion_honours_offset_stride_and_alpha() {
+        // 2x2 image at offset 4 with 4 bytes of row padding
+        let mut pool = vec![0u8; 4];
+        pool.extend_from_slice(&[1, 2, 3, 0x80, 4, 5, 6, 0x40, 9, 9, 9, 9]);
+        pool.extend_from_slice(&[7, 8, 9, 0xff, 10, 11, 12, 0]);
+        let rgba = shm_to_rgba(&pool, 4, 2, 2, 12, wl_shm::Format::Argb8888).unwrap();
+        assert_eq!(rgba, [3, 2, 1, 0x80, 6, 5, 4, 0x40, 9, 8, 7, 0xff, 12, 11, 10, 0]);
+
+        let opaque = shm_to_rgba(&pool, 4, 2, 2, 12, wl_shm::Format::Xrgb8888).unwrap();
+        assert!(opaque.chunks_exact(4).all(|px| px[3] == 0xff));
+
+        // Out of the pool, or a format we do not convert
+        assert!(shm_to_rgba(&pool, 8, 2, 2, 12, wl_shm::Format::Argb8888).is_none());
+        assert!(shm_to_rgba(&pool, 4, 2, 2, 12, wl_shm::Format::Rgb565).is_none());
+    }
+
+    #[test]
+    fn blend_places_hotspot_and_clips() {
+        // 2x1 cursor: opaque red, then half-transparent white; hotspot on the second pixel
+        let cursor = CursorBitmap {
+            hotspot: (1, 0),
+            width: 2,
+            height: 1,
+            rgba: vec![0xff, 0, 0, 0xff, 0xff, 0xff, 0xff, 0x80],
+            scale: 1.0,
+        };
+        let mut frame = vec![0u8; 3 * 2 * 4];
+        cursor.blend(&mut frame, 3, 2, (1, 1), false);
+        // Row 1: red at x=0 (B, G, R, X), blended white at x=1
+        assert_eq!(&frame[12..16], &[0, 0, 0xff, 0]);
+        assert_eq!(&frame[16..19], &[0x80, 0x80, 0x80]);
+        assert!(frame[..12].iter().all(|&b| b == 0));
+
+        let mut bgr = vec![0u8; 3 * 2 * 4];
+        cursor.blend(&mut bgr, 3, 2, (1, 1), true);
+        assert_eq!(&bgr[12..16], &[0xff, 0, 0, 0]);
+
+        // Partly off the left edge: only the hotspot pixel lands
+        let mut clipped = vec![0u8; 3 * 2 * 4];
+        cursor.blend(&mut clipped, 3, 2, (0, 0), false);
+        assert_eq!(&clipped[0..3], &[0x80, 0x80, 0x80]);
+        assert!(clipped[4..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn arrow_has_outline_and_fill() {
+        let arrow = CursorBitmap::arrow();
+        assert_eq!(arrow.hotspot, (0, 0));
+        assert_eq!(&arrow.rgba[..4], &[0, 0, 0, 0xff]);
+        // Row 2 is "#.#": white fill in the middle
+        assert_eq!(&arrow.rgba[(2 * arrow.width + 1) * 4..][..4], &[0xff, 0xff, 0xff, 0xff]);
+        // Spaces are transparent
+        assert_eq!(arrow.rgba[(16 * arrow.width) * 4 + 3], 0);
+    }
+
+    #[test]
+    fn hidpi_cursor_is_resampled_with_its_hotspot() {
+        // 4x2 image at buffer scale 2: left half red, right half blue,
+        // hotspot at logical (1, 0)
+        let red = [0xff, 0, 0, 0xff];
+        let blue = [0, 0, 0xff, 0xff];
+        let row: Vec<u8> = [red, red, blue, blue].concat();
+        let cursor = CursorBitmap { hotspot: (2, 0), width: 4, height: 2, rgba: row.repeat(2), scale: 2.0 };
+
+        let logical = cursor.at_scale(1.0);
+        assert_eq!((logical.width, logical.height, logical.hotspot), (2, 1, (1, 0)));
+        assert_eq!(logical.rgba, [red, blue].concat());
+
+        // Drawn into a scale-3 output it grows to 6x3
+        let large = cursor.at_scale(3.0);
+        assert_eq!((large.width, large.height, large.hotspot), (6, 3, (3, 0)));
+        assert_eq!(&large.rgba[..12], [red, red, red].concat());
+
+        // Already at the requested scale: unchanged
+        assert_eq!(cursor.at_scale(2.0).rgba, cursor.rgba);
+    }
+}
@@ -1,9 +1,10 @@
 //! Compositor and SHM buffer handlers
 
-use crate::compositor::{grabs::resize_grab, state::ClientState, Compositor};
+use crate::compositor::{grabs::resize_grab, placement, state::ClientState, Compositor};
 use smithay::{
     backend::renderer::utils::on_commit_buffer_handler,
     delegate_compositor, delegate_shm,
+    input::pointer::CursorImageStatus,
     reexports::{
         wayland_protocols::xdg::shell::server::xdg_toplevel,
         wayland_server::{
@@ -34,6 +35,10 @@ impl CompositorHandler for Compositor {
 
     fn commit(&mut self, surface: &WlSurface) {
         on_commit_buffer_handler::<Self>(surface);
+        if matches!(&self.cursor_status, CursorImageStatus::Surface(cursor) if cursor == surface) {
+            // New image (or hotspot) for a client-drawn cursor
+            self.cursor_serial += 1;
+        }
         if !is_sync_subsurface(surface) {
             let mut root = surface.clone();
             while let Some(parent) = get_parent(&root) {
@@ -106,9 +111,7 @@ impl CompositorHandler for Compositor {
                             }
                         }
                         // Center based on bbox so CSD shadows stay on screen
-                        let x = (out_size.w - bw).max(0) / 2 - bbox.loc.x;
-                        let y = (out_size.h - bh).max(0) / 2 - bbox.loc.y;
-                        self.space.map_element(window, (x, y), true);
+                        self.space.map_element(window, placement::centered(bbox, out_size), true);
                     }
                 } else if !self.titlebar_adjusted.contains(&surface_id) {
                     // CSD compensation: GTK CSS hides the headerbar but the app
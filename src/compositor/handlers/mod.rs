@@ -14,7 +14,12 @@ use smithay::input::{Seat, SeatHandler, SeatState};
 use smithay::reexports::wayland_server::protocol::wl_surface::WlSurface;
 use smithay::reexports::wayland_server::Resource;
 use smithay::utils::Serial;
+use smithay::wayland::compositor::with_states;
+use smithay::wayland::fractional_scale::{with_fractional_scale, FractionalScaleHandler};
 use smithay::wayland::output::OutputHandler;
+use smithay::wayland::xdg_activation::{
+    XdgActivationHandler, XdgActivationState, XdgActivationToken, XdgActivationTokenData,
+};
 use smithay::wayland::selection::data_device::{
     set_data_device_focus,
     DataDeviceHandler, DataDeviceState, WaylandDndGrabHandler,
@@ -25,7 +30,10 @@ use smithay::wayland::shell::xdg::decoration::XdgDecorationHandler;
 use smithay::reexports::wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode;
 use smithay::reexports::wayland_protocols::xdg::shell::server::xdg_toplevel;
 use smithay::wayland::shell::xdg::ToplevelSurface;
-use smithay::{delegate_data_device, delegate_output, delegate_seat, delegate_text_input_manager, delegate_xdg_decoration};
+use smithay::{
+    delegate_data_device, delegate_fractional_scale, delegate_output, delegate_seat,
+    delegate_text_input_manager, delegate_xdg_activation, delegate_xdg_decoration,
+};
 
 impl SeatHandler for Compositor {
     type KeyboardFocus = WlSurface;
@@ -42,6 +50,7 @@ impl SeatHandler for Compositor {
         image: smithay::input::pointer::CursorImageStatus,
     ) {
         self.cursor_status = image;
+        self.cursor_serial += 1;
     }
 
     fn focus_changed(&mut self, seat: &Seat<Self>, focused: Option<&WlSurface>) {
@@ -75,6 +84,11 @@ impl SeatHandler for Compositor {
             });
             toplevel.send_pending_configure();
         }
+
+        // Focusing a window answers its attention request
+        if focused.is_some_and(|surface| self.attention.clear(&surface.id())) {
+            self.taskbar_dirty = true;
+        }
     }
 }
 
@@ -126,6 +140,7 @@ impl SelectionHandler for Compositor {
         // the selection is still the old compositor-owned one.
         log::info!("new_selection: deferring clipboard read for mime={}", mime);
         self.clipboard_pending_mime = Some(mime);
+        self.clipboard_pending_html = mime_types.iter().any(|m| m == "text/html");
     }
 
     fn send_selection(
@@ -137,10 +152,13 @@ impl SelectionHandler for Compositor {
         _user_data: &Self::SelectionUserData,
     ) {
         log::info!("send_selection called: mime={}, has_pending_paste={}", mime_type, self.pending_paste.is_some());
-        if let Some(ref text) = self.pending_paste {
-            if mime_type.contains("text") || mime_type.contains("string") || mime_type.contains("utf8") {
-                let mut file = std::fs::File::from(fd);
-                let _ = file.write_all(text.as_bytes());
+        if let Some(ref content) = self.pending_paste {
+            match content.data_for(&mime_type, &self.pending_paste_mimes) {
+                Some(data) => {
+                    let mut file = std::fs::File::from(fd);
+                    let _ = file.write_all(data);
+                }
+                None => log::warn!("send_selection: {} was not offered", mime_type),
             }
         }
     }
@@ -182,6 +200,45 @@ impl OutputHandler for Compositor {}
 delegate_output!(Compositor);
 delegate_text_input_manager!(Compositor);
 
+impl FractionalScaleHandler for Compositor {
+    fn new_fractional_scale(&mut self, surface: WlSurface) {
+        let scale = self.output_scale;
+        with_states(&surface, |states| {
+            with_fractional_scale(states, |fractional| fractional.set_preferred_scale(scale));
+        });
+    }
+}
+delegate_fractional_scale!(Compositor);
+
+/// Activation requests only flag the window for the taskbar: the browser
+/// decides whether to switch, so background apps cannot steal focus.
+impl XdgActivationHandler for Compositor {
+    fn activation_state(&mut self) -> &mut XdgActivationState {
+        &mut self.xdg_activation_state
+    }
+
+    fn request_activation(
+        &mut self,
+        _token: XdgActivationToken,
+        _token_data: XdgActivationTokenData,
+        surface: WlSurface,
+    ) {
+        let Some(idx) = self.window_registry.iter().position(|wl| wl.id() == surface.id()) else {
+            return;
+        };
+        let focused = self.seat.get_keyboard()
+            .and_then(|keyboard| keyboard.current_focus())
+            .is_some_and(|focus| focus.id() == surface.id());
+        // Minimized windows are unmapped from the space
+        let minimized = !self.space.elements().any(|w| w.toplevel().unwrap().wl_surface() == &surface);
+        if self.attention.request(surface.id(), focused, minimized) {
+            log::info!("Window {} requested attention{}", idx, if minimized { " (minimized)" } else { "" });
+            self.taskbar_dirty = true;
+        }
+    }
+}
+delegate_xdg_activation!(Compositor);
+
 impl XdgDecorationHandler for Compositor {
     fn new_decoration(&mut self, toplevel: ToplevelSurface) {
         toplevel.with_pending_state(|state| {
@@ -29,7 +29,7 @@ use smithay::{
 
 use crate::compositor::{
     grabs::{MoveSurfaceGrab, ResizeSurfaceGrab},
-    Compositor,
+    placement, Compositor,
 };
 
 /// Check if `child` is a descendant process of `ancestor` via /proc ppid chain.
@@ -96,7 +96,24 @@ impl XdgShellHandler for Compositor {
         let output_geo = self.space.outputs().next()
             .and_then(|o| self.space.output_geometry(o));
 
-        self.space.map_element(window, (0, 0), false);
+        // Unless the focus policy gives the new window focus, it stays
+        // below the window the user is typing in
+        let keyboard = self.seat.get_keyboard().unwrap();
+        let current_focus = keyboard.current_focus();
+        let take_focus = self.focus_new_windows.should_focus(current_focus.is_some());
+        let focused_window = current_focus.filter(|_| !take_focus).and_then(|focus| {
+            self.space.elements()
+                .find(|w| w.toplevel().unwrap().wl_surface() == &focus)
+                .cloned()
+        });
+        let stack: Vec<Window> = self.space.elements().cloned().collect();
+
+        self.space.map_element(window.clone(), (0, 0), false);
+        // map_element put it on top: raise what belongs above it
+        let order = placement::stack_new(stack, window.clone(), focused_window.as_ref());
+        for above in order.iter().skip_while(|w| **w != window).skip(1) {
+            self.space.raise_element(above, false);
+        }
 
         // Main window (not dialog): set fullscreen to fill the screen.
         // Exception: windows with app_id "ivnc-pake-windowed" should not be fullscreened
@@ -137,10 +154,14 @@ impl XdgShellHandler for Compositor {
             self.dialog_surfaces.insert(surface.wl_surface().id().protocol_id());
         }
 
-        // Auto-focus the new window so it receives keyboard input
-        let keyboard = self.seat.get_keyboard().unwrap();
-        let focus_serial = smithay::utils::SERIAL_COUNTER.next_serial();
-        keyboard.set_focus(self, Some(surface.wl_surface().clone()), focus_serial);
+        // Auto-focus the new window so it receives keyboard input, unless
+        // the focus policy keeps it where the user is typing
+        if take_focus {
+            let focus_serial = smithay::utils::SERIAL_COUNTER.next_serial();
+            keyboard.set_focus(self, Some(surface.wl_surface().clone()), focus_serial);
+        } else {
+            log::info!("new_toplevel: not focused (focus_new_windows={})", self.focus_new_windows.as_str());
+        }
 
         // Register all non-dialog windows in the taskbar
         if !is_dialog {
@@ -336,6 +357,7 @@ impl XdgShellHandler for Compositor {
         // Remove only the destroyed surface from window registry (not siblings)
         let surf_id = surface.wl_surface().id();
         self.window_registry.retain(|wl| wl.id() != surf_id);
+        self.attention.clear(&surf_id);
     }
 
     fn grab(&mut self, surface: PopupSurface, seat: wl_seat::WlSeat, serial: Serial) {
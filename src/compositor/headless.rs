@@ -9,15 +9,18 @@ use smithay::{
         damage::OutputDamageTracker,
         element::surface::WaylandSurfaceRenderElement,
         pixman::PixmanRenderer,
-        ExportMem, Bind, Offscreen,
+        ExportMem, Bind, Offscreen, Texture,
     },
     desktop::space::render_output,
-    output::{Mode, Output, PhysicalProperties, Subpixel},
+    output::{Mode, Output, PhysicalProperties, Scale, Subpixel},
     utils::{Rectangle, Size},
 };
 use log::{info, warn};
 use pixman::Image;
 
+use super::DisplayError;
+use crate::config::PixelFormat;
+
 /// Headless backend that renders to an in-memory Pixman buffer
 pub struct HeadlessBackend {
     renderer: PixmanRenderer,
@@ -26,17 +29,62 @@ pub struct HeadlessBackend {
     damage_tracker: OutputDamageTracker,
     width: u32,
     height: u32,
+    /// Layout frames are read back in, as the renderer reported it
+    pixel_format: PixelFormat,
+}
+
+/// DRM fourcc of a capture pixel format
+fn drm_fourcc(format: PixelFormat) -> DrmFourcc {
+    match format {
+        PixelFormat::Xrgb8888 => DrmFourcc::Xrgb8888,
+        PixelFormat::Argb8888 => DrmFourcc::Argb8888,
+        PixelFormat::Xbgr8888 => DrmFourcc::Xbgr8888,
+        PixelFormat::Abgr8888 => DrmFourcc::Abgr8888,
+    }
+}
+
+/// Capture pixel format of a DRM fourcc, if it is one
+fn pixel_format_of(fourcc: DrmFourcc) -> Option<PixelFormat> {
+    [PixelFormat::Xrgb8888, PixelFormat::Argb8888, PixelFormat::Xbgr8888, PixelFormat::Abgr8888]
+        .into_iter()
+        .find(|&format| drm_fourcc(format) == fourcc)
+}
+
+/// Read one pixel of `buffer` back as `format` and return the layout the
+/// renderer actually delivered
+fn probe_readback(
+    renderer: &mut PixmanRenderer,
+    buffer: &mut Image<'static, 'static>,
+    format: PixelFormat,
+) -> Result<PixelFormat, DisplayError> {
+    let framebuffer = renderer.bind(buffer)
+        .map_err(|e| DisplayError::BufferFailed(format!("bind for readback probe: {:?}", e)))?;
+    let mapping = renderer
+        .copy_framebuffer(&framebuffer, Rectangle::new((0, 0).into(), (1, 1).into()), drm_fourcc(format))
+        .map_err(|e| DisplayError::BufferFailed(format!("readback probe: {:?}", e)))?;
+    match mapping.format() {
+        Some(fourcc) => pixel_format_of(fourcc).ok_or_else(|| {
+            DisplayError::RendererFailed(format!("renderer reads back {:?}, not {}", fourcc, format.as_str()))
+        }),
+        None => Err(DisplayError::RendererFailed("renderer reads back an unknown layout".into())),
+    }
 }
 
 impl HeadlessBackend {
-    /// Create a new headless backend with the given dimensions
-    pub fn new(width: u32, height: u32) -> Result<Self, Box<dyn std::error::Error>> {
+    /// Create a new headless backend with the given dimensions, reading
+    /// frames back in `pixel_format`
+    pub fn new(width: u32, height: u32, pixel_format: PixelFormat) -> Result<Self, DisplayError> {
         let mut renderer = PixmanRenderer::new()
-            .map_err(|e| format!("Failed to create Pixman renderer: {:?}", e))?;
+            .map_err(|e| DisplayError::RendererFailed(format!("{:?}", e)))?;
 
         let size = Size::from((width as i32, height as i32));
-        let buffer: Image<'static, 'static> = renderer.create_buffer(DrmFourcc::Xrgb8888, size)
-            .map_err(|e| format!("Failed to create offscreen buffer: {:?}", e))?;
+        let mut buffer: Image<'static, 'static> = renderer.create_buffer(drm_fourcc(pixel_format), size)
+            .map_err(|e| DisplayError::BufferFailed(format!("offscreen buffer: {:?}", e)))?;
+        let readback = probe_readback(&mut renderer, &mut buffer, pixel_format)?;
+        if readback != pixel_format {
+            warn!("Renderer reads back {} instead of {}", readback.as_str(), pixel_format.as_str());
+        }
+        let pixel_format = readback;
 
         let output = Output::new(
             "ivnc-headless".to_string(),
@@ -58,9 +106,18 @@ impl HeadlessBackend {
 
         let damage_tracker = OutputDamageTracker::from_output(&output);
 
-        info!("Headless backend created: {}x{} @ 60Hz (Pixman)", width, height);
+        info!(
+            "Headless backend created: {}x{} @ 60Hz (Pixman, {})",
+            width, height, pixel_format.as_str()
+        );
+
+        Ok(Self { renderer, buffer, output, damage_tracker, width, height, pixel_format })
+    }
 
-        Ok(Self { renderer, buffer, output, damage_tracker, width, height })
+    /// Layout of the pixels `render_frame` returns, as probed from the
+    /// renderer at startup
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
     }
 
     pub fn output(&self) -> &Output {
@@ -119,7 +176,7 @@ impl HeadlessBackend {
                 let region = Rectangle::new((0, 0).into(), size);
 
                 let mapping = match self.renderer.copy_framebuffer(
-                    &framebuffer, region, DrmFourcc::Xrgb8888,
+                    &framebuffer, region, drm_fourcc(self.pixel_format),
                 ) {
                     Ok(m) => m,
                     Err(e) => { warn!("Failed to copy framebuffer: {:?}", e); return None; }
@@ -134,10 +191,10 @@ impl HeadlessBackend {
         }
     }
 
-    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), DisplayError> {
         let size = Size::from((width as i32, height as i32));
-        self.buffer = self.renderer.create_buffer(DrmFourcc::Xrgb8888, size)
-            .map_err(|e| format!("Failed to create buffer: {:?}", e))?;
+        self.buffer = self.renderer.create_buffer(drm_fourcc(self.pixel_format), size)
+            .map_err(|e| DisplayError::BufferFailed(format!("{:?}", e)))?;
 
         let mode = Mode {
             size: (width as i32, height as i32).into(),
@@ -151,6 +208,19 @@ impl HeadlessBackend {
         Ok(())
     }
 
+    /// Set the output scale advertised to clients. The buffer keeps its
+    /// pixel size; the logical output shrinks by the scale
+    pub fn set_scale(&mut self, scale: f64) {
+        let scale = if scale.fract() == 0.0 {
+            Scale::Integer(scale as i32)
+        } else {
+            Scale::Fractional(scale)
+        };
+        self.output.change_current_state(None, None, Some(scale), None);
+        self.damage_tracker = OutputDamageTracker::from_output(&self.output);
+        info!("Headless output scale set to {:?}", scale);
+    }
+
     pub fn reset_damage(&mut self) {
         self.damage_tracker = OutputDamageTracker::from_output(&self.output);
     }
@@ -7,6 +7,40 @@ pub mod state;
 pub mod headless;
 pub mod handlers;
 pub mod grabs;
+pub mod seats;
+pub mod cursor;
+pub mod attention;
+pub mod placement;
+pub mod scale;
 
 pub use state::Compositor;
 pub use headless::HeadlessBackend;
+
+use std::error::Error;
+use std::fmt;
+
+/// Compositor and headless display errors
+#[derive(Debug)]
+pub enum DisplayError {
+    /// Wayland display or event loop creation failed
+    InitFailed(String),
+    /// Event loop dispatch failed
+    DispatchFailed(String),
+    /// Pixman renderer creation failed
+    RendererFailed(String),
+    /// Offscreen buffer allocation failed
+    BufferFailed(String),
+}
+
+impl fmt::Display for DisplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplayError::InitFailed(msg) => write!(f, "Display init failed: {}", msg),
+            DisplayError::DispatchFailed(msg) => write!(f, "Event loop dispatch failed: {}", msg),
+            DisplayError::RendererFailed(msg) => write!(f, "Renderer creation failed: {}", msg),
+            DisplayError::BufferFailed(msg) => write!(f, "Buffer allocation failed: {}", msg),
+        }
+    }
+}
+
+impl Error for DisplayError {}
@@ -0,0 +1,97 @@
+//! Window placement within the output
+//!
+//! Dialogs are centered on the output using their bounding box, so CSD
+//! shadows stay on screen. When the display shrinks, other windows are
+//! moved back inside it by their geometry (shadows may stick out); a
+//! window larger than the output keeps its top-left corner visible. A new
+//! window that doesn't take focus is stacked below the focused one.
+
+use smithay::utils::{Logical, Point, Rectangle, Size};
+
+/// Location that centers a window on the output. `bbox` is relative to the
+/// window location, as `Window::bbox` returns it.
+pub fn centered(bbox: Rectangle<i32, Logical>, output: Size<i32, Logical>) -> Point<i32, Logical> {
+    (
+        (output.w - bbox.size.w).max(0) / 2 - bbox.loc.x,
+        (output.h - bbox.size.h).max(0) / 2 - bbox.loc.y,
+    )
+        .into()
+}
+
+/// Location closest to `loc` at which `rect` (relative to the window
+/// location) lies within the output
+pub fn clamped(
+    loc: Point<i32, Logical>,
+    rect: Rectangle<i32, Logical>,
+    output: Size<i32, Logical>,
+) -> Point<i32, Logical> {
+    let clamp = |pos: i32, offset: i32, size: i32, max: i32| (pos + offset).min(max - size).max(0) - offset;
+    (
+        clamp(loc.x, rect.loc.x, rect.size.w, output.w),
+        clamp(loc.y, rect.loc.y, rect.size.h, output.h),
+    )
+        .into()
+}
+
+/// Stacking order, bottom to top, once `new` is mapped into `stack`: on
+/// top, or right below `focused` when it doesn't take focus, so the window
+/// being typed in stays visible
+pub fn stack_new<T: PartialEq>(mut stack: Vec<T>, new: T, focused: Option<&T>) -> Vec<T> {
+    let at = focused
+        .and_then(|focused| stack.iter().position(|w| w == focused))
+        .unwrap_or(stack.len());
+    stack.insert(at, new);
+    stack
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Logical> {
+        Rectangle::new((x, y).into(), (w, h).into())
+    }
+
+    #[test]
+    fn dialog_near_edge_is_recentered() {
+        // 400x300 dialog with a 20px shadow near the bottom-right corner of
+        // a 1920x1080 display that shrinks to 1280x720
+        let bbox = rect(-20, -20, 440, 340);
+        let output: Size<i32, Logical> = (1280, 720).into();
+        let before: Point<i32, Logical> = (1500, 760).into();
+        assert!(before.x + bbox.loc.x + bbox.size.w > output.w);
+        let after = centered(bbox, output);
+        assert_eq!(after, (440, 210).into());
+        // The whole bounding box, shadow included, is inside the output
+        assert!(after.x + bbox.loc.x >= 0 && after.x + bbox.loc.x + bbox.size.w <= output.w);
+        assert!(after.y + bbox.loc.y >= 0 && after.y + bbox.loc.y + bbox.size.h <= output.h);
+        // Too large for the output: pinned to the top-left corner
+        assert_eq!(centered(rect(0, 0, 1600, 900), output), (0, 0).into());
+    }
+
+    #[test]
+    fn windows_are_clamped_into_a_smaller_output() {
+        let output: Size<i32, Logical> = (1280, 720).into();
+        let geometry = rect(0, 0, 800, 600);
+        // Moved left and up just enough
+        assert_eq!(clamped((1000, 500).into(), geometry, output), (480, 120).into());
+        // Already inside: untouched
+        assert_eq!(clamped((10, 20).into(), geometry, output), (10, 20).into());
+        // Larger than the output: top-left corner stays visible
+        assert_eq!(clamped((300, 300).into(), rect(0, 0, 1920, 1080), output), (0, 0).into());
+        // Geometry offset inside the surface (CSD shadow) is honoured
+        assert_eq!(clamped((1000, 0).into(), rect(10, 10, 800, 600), output), (470, 0).into());
+    }
+
+    #[test]
+    fn unfocused_new_window_is_stacked_below_the_focused_one() {
+        // Takes focus (or nothing focused): on top
+        assert_eq!(stack_new(vec!["a", "b"], "new", None), ["a", "b", "new"]);
+        // Focus stays on "b", topmost: the new window goes just below it
+        assert_eq!(stack_new(vec!["a", "b"], "new", Some(&"b")), ["a", "new", "b"]);
+        // Windows above the focused one stay above the new one too
+        assert_eq!(stack_new(vec!["a", "b", "c"], "new", Some(&"a")), ["new", "a", "b", "c"]);
+        // A focused surface with no window in the stack: on top
+        assert_eq!(stack_new(vec!["a"], "new", Some(&"gone")), ["a", "new"]);
+    }
+}
@@ -0,0 +1,48 @@
+//! Display pixels and logical coordinates at the output scale
+//!
+//! A client output scale (`scale,N`) keeps the display's pixel size and
+//! shrinks the compositor space to display / scale logical pixels.
+
+use smithay::utils::{Logical, Point, Size};
+
+/// Logical position of a display pixel
+pub fn display_to_logical(x: f64, y: f64, scale: f64) -> Point<f64, Logical> {
+    (x / scale, y / scale).into()
+}
+
+/// Display pixel of a logical position
+pub fn logical_to_display(loc: Point<f64, Logical>, scale: f64) -> (i32, i32) {
+    ((loc.x * scale).round() as i32, (loc.y * scale).round() as i32)
+}
+
+/// Logical size of a display of `width`x`height` pixels
+pub fn logical_size(width: u32, height: u32, scale: f64) -> Size<i32, Logical> {
+    ((width as f64 / scale).round() as i32, (height as f64 / scale).round() as i32).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_pixels_round_trip_at_a_fractional_scale() {
+        let scale = 1.5;
+        assert_eq!(display_to_logical(300.0, 150.0, scale), (200.0, 100.0).into());
+        assert_eq!(logical_to_display((200.0, 100.0).into(), scale), (300, 150));
+        // Every display pixel maps back to itself, not a neighbour
+        for scale in [1.25, 1.5, 160.0 / 120.0] {
+            for x in 0..1920 {
+                let loc = display_to_logical(x as f64, (x % 1080) as f64, scale);
+                assert_eq!(logical_to_display(loc, scale), (x, x % 1080), "scale {}", scale);
+            }
+        }
+    }
+
+    #[test]
+    fn toplevels_fill_the_logical_output() {
+        assert_eq!(logical_size(1920, 1080, 1.0), (1920, 1080).into());
+        assert_eq!(logical_size(1920, 1080, 2.0), (960, 540).into());
+        assert_eq!(logical_size(3840, 2160, 2.0), (1920, 1080).into());
+        assert_eq!(logical_size(1920, 1080, 1.5), (1280, 720).into());
+    }
+}
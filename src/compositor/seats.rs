@@ -0,0 +1,225 @@
+//! Per-session seats for multi-seat mode
+//!
+//! With `input.multi_seat` every WebRTC session drives its own `wl_seat`
+//! (pointer + keyboard), so collaborators no longer fight over one cursor
+//! or keyboard focus. Input from a session is handled by swapping its seat
+//! into `Compositor::seat` for the duration of the event, so the injection
+//! code stays seat-agnostic. Seats of closed sessions are parked and reused,
+//! since Wayland clients may keep references to a seat global.
+//!
+//! With server-side key repeat each seat has its own repeater, so a held key
+//! repeats on the seat (and focused window) of the session that pressed it.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use smithay::input::Seat;
+
+use super::Compositor;
+use crate::input::KeyRepeater;
+
+/// Cursor colours handed out to sessions in order
+const SEAT_COLORS: [&str; 8] = [
+    "#e6194b", "#3cb44b", "#4363d8", "#f58231", "#911eb4", "#42d4f4", "#f032e6", "#9a6324",
+];
+
+/// One session's seat and the pointer state that goes with it
+pub struct SessionSeat {
+    pub seat: Seat<Compositor>,
+    pub cursor_pos: (f64, f64),
+    pub button_mask: u32,
+    pub color: &'static str,
+    /// The seat's held key, with server-side key repeat
+    pub key_repeater: Option<KeyRepeater>,
+}
+
+/// Seats owned by sessions, plus parked seats for reuse
+#[derive(Default)]
+pub struct SessionSeats {
+    active: HashMap<Arc<str>, SessionSeat>,
+    parked: Vec<Seat<Compositor>>,
+    created: usize,
+    /// A cursor moved or a seat came or went since the last `take_changes`
+    changed: bool,
+}
+
+impl SessionSeats {
+    pub fn contains(&self, session: &str) -> bool {
+        self.active.contains_key(session)
+    }
+
+    /// Swap the session's seat into `current` and hand out its entry, which
+    /// then holds the seat that was in `current`. Undo with `put_back`.
+    /// None if the session has no seat yet.
+    pub fn swap_in(&mut self, current: &mut Seat<Compositor>, session: &str) -> Option<SessionSeat> {
+        let mut entry = self.active.remove(session)?;
+        std::mem::swap(current, &mut entry.seat);
+        Some(entry)
+    }
+
+    /// Undo `swap_in`
+    pub fn put_back(&mut self, current: &mut Seat<Compositor>, session: Arc<str>, mut entry: SessionSeat) {
+        std::mem::swap(current, &mut entry.seat);
+        self.active.insert(session, entry);
+    }
+
+    /// A parked seat if there is one; otherwise the caller creates one named
+    /// `next_seat_name()`
+    pub fn unpark(&mut self) -> Option<Seat<Compositor>> {
+        self.parked.pop()
+    }
+
+    pub fn next_seat_name(&mut self) -> String {
+        self.created += 1;
+        format!("ivnc-{}", self.created)
+    }
+
+    /// Give a new session `seat`, picking the first unused colour
+    pub fn assign(
+        &mut self,
+        session: Arc<str>,
+        seat: Seat<Compositor>,
+        cursor_pos: (f64, f64),
+        key_repeater: Option<KeyRepeater>,
+    ) {
+        let color = SEAT_COLORS
+            .iter()
+            .copied()
+            .find(|c| !self.active.values().any(|s| s.color == *c))
+            .unwrap_or(SEAT_COLORS[self.active.len() % SEAT_COLORS.len()]);
+        self.changed = true;
+        self.active.insert(session, SessionSeat { seat, cursor_pos, button_mask: 0, color, key_repeater });
+    }
+
+    /// Key repeats due by `now`, as (session, keysym, count)
+    pub fn due_repeats(&mut self, now: Instant) -> Vec<(Arc<str>, u32, u32)> {
+        self.active
+            .iter_mut()
+            .filter_map(|(session, s)| {
+                let (keysym, count) = s.key_repeater.as_mut()?.due(now)?;
+                Some((session.clone(), keysym, count))
+            })
+            .collect()
+    }
+
+    /// Detach a closed session's seat; the caller clears its focus
+    pub fn release(&mut self, session: &str) -> Option<Seat<Compositor>> {
+        let seat = self.active.remove(session)?.seat;
+        self.changed = true;
+        Some(seat)
+    }
+
+    pub fn park(&mut self, seat: Seat<Compositor>) {
+        self.parked.push(seat);
+    }
+
+    pub fn mark_changed(&mut self) {
+        self.changed = true;
+    }
+
+    /// `[{"session":..,"color":..,"x":..,"y":..}]` if anything changed since
+    /// the last call
+    pub fn take_changes(&mut self) -> Option<String> {
+        if !std::mem::take(&mut self.changed) {
+            return None;
+        }
+        let mut seats: Vec<_> = self.active.iter().collect();
+        seats.sort_by(|a, b| a.0.cmp(b.0));
+        let list: Vec<serde_json::Value> = seats
+            .into_iter()
+            .map(|(session, s)| {
+                serde_json::json!({
+                    "session": &**session,
+                    "color": s.color,
+                    "x": s.cursor_pos.0.round() as i64,
+                    "y": s.cursor_pos.1.round() as i64,
+                })
+            })
+            .collect();
+        Some(serde_json::Value::Array(list).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smithay::input::SeatState;
+    use std::time::Duration;
+
+    fn seats(names: &[&str]) -> Vec<Seat<Compositor>> {
+        let mut state = SeatState::<Compositor>::new();
+        names.iter().map(|name| state.new_seat(*name)).collect()
+    }
+
+    #[test]
+    fn session_seat_is_swapped_in_and_put_back() {
+        let [mut current, own]: [Seat<Compositor>; 2] = seats(&["main", "ivnc-1"]).try_into().ok().unwrap();
+        let mut session_seats = SessionSeats::default();
+        let session: Arc<str> = Arc::from("a");
+        assert!(session_seats.swap_in(&mut current, &session).is_none());
+        assert_eq!(current.name(), "main");
+
+        session_seats.assign(session.clone(), own, (10.0, 20.0), None);
+        let mut entry = session_seats.swap_in(&mut current, &session).unwrap();
+        assert_eq!(current.name(), "ivnc-1");
+        assert_eq!(entry.seat.name(), "main");
+        assert!(!session_seats.contains(&session));
+        entry.cursor_pos = (30.0, 40.0);
+        entry.button_mask = 1;
+        session_seats.put_back(&mut current, session.clone(), entry);
+        assert_eq!(current.name(), "main");
+
+        // The next event finds the same seat and pointer state
+        let entry = session_seats.swap_in(&mut current, &session).unwrap();
+        assert_eq!(current.name(), "ivnc-1");
+        assert_eq!((entry.cursor_pos, entry.button_mask), ((30.0, 40.0), 1));
+        session_seats.put_back(&mut current, session, entry);
+        assert_eq!(current.name(), "main");
+    }
+
+    #[test]
+    fn released_seats_are_parked_and_reused() {
+        let [a, b]: [Seat<Compositor>; 2] = seats(&["ivnc-1", "ivnc-2"]).try_into().ok().unwrap();
+        let mut session_seats = SessionSeats::default();
+        session_seats.assign(Arc::from("a"), a, (0.0, 0.0), None);
+        session_seats.assign(Arc::from("b"), b, (5.0, 6.0), None);
+        let json: serde_json::Value = serde_json::from_str(&session_seats.take_changes().unwrap()).unwrap();
+        assert_eq!(json[0]["color"], SEAT_COLORS[0]);
+        assert_eq!(json[1], serde_json::json!({ "session": "b", "color": SEAT_COLORS[1], "x": 5, "y": 6 }));
+        assert_eq!(session_seats.take_changes(), None);
+
+        let seat = session_seats.release("a").unwrap();
+        assert_eq!(seat.name(), "ivnc-1");
+        assert!(session_seats.release("a").is_none());
+        session_seats.park(seat);
+        let reused = session_seats.unpark().unwrap();
+        assert_eq!(reused.name(), "ivnc-1");
+        // The freed colour goes to the next session
+        session_seats.assign(Arc::from("c"), reused, (0.0, 0.0), None);
+        let json: serde_json::Value = serde_json::from_str(&session_seats.take_changes().unwrap()).unwrap();
+        assert_eq!(json[1], serde_json::json!({ "session": "c", "color": SEAT_COLORS[0], "x": 0, "y": 0 }));
+    }
+
+    #[test]
+    fn key_repeats_are_routed_to_the_pressing_seat() {
+        let [mut current, a, b]: [Seat<Compositor>; 3] =
+            seats(&["main", "ivnc-1", "ivnc-2"]).try_into().ok().unwrap();
+        let mut session_seats = SessionSeats::default();
+        session_seats.assign(Arc::from("a"), a, (0.0, 0.0), Some(KeyRepeater::new(100, 10)));
+        session_seats.assign(Arc::from("b"), b, (0.0, 0.0), Some(KeyRepeater::new(100, 10)));
+
+        let start = Instant::now();
+        let mut entry = session_seats.swap_in(&mut current, "b").unwrap();
+        entry.key_repeater.as_mut().unwrap().press(0x61, start);
+        session_seats.put_back(&mut current, Arc::from("b"), entry);
+
+        assert!(session_seats.due_repeats(start).is_empty());
+        assert_eq!(
+            session_seats.due_repeats(start + Duration::from_millis(100)),
+            vec![(Arc::<str>::from("b"), 0x61, 1)]
+        );
+        session_seats.release("b");
+        assert!(session_seats.due_repeats(start + Duration::from_secs(1)).is_empty());
+    }
+}
@@ -8,20 +8,22 @@ use smithay::{
     reexports::{
         calloop::{generic::Generic, EventLoop, Interest, LoopSignal, Mode, PostAction},
         wayland_server::{
-            backend::{ClientData, ClientId, DisconnectReason},
+            backend::{ClientData, ClientId, DisconnectReason, ObjectId},
             protocol::wl_surface::WlSurface,
-            Display, DisplayHandle,
+            Display, DisplayHandle, Resource,
         },
     },
-    utils::{Logical, Point},
+    utils::{Logical, Point, Transform},
     wayland::{
-        compositor::{CompositorClientState, CompositorState},
+        compositor::{send_surface_state, CompositorClientState, CompositorState},
+        fractional_scale::{with_fractional_scale, FractionalScaleManagerState},
         output::OutputManagerState,
         selection::data_device::DataDeviceState,
         shell::xdg::{XdgShellState, decoration::XdgDecorationState},
         shm::ShmState,
         socket::ListeningSocketSource,
         text_input::TextInputManagerState,
+        xdg_activation::XdgActivationState,
     },
 };
 
@@ -44,17 +46,28 @@ pub struct Compositor {
     pub xdg_decoration_state: XdgDecorationState,
     pub popups: PopupManager,
     pub text_input_manager_state: TextInputManagerState,
+    pub fractional_scale_manager_state: FractionalScaleManagerState,
+    pub xdg_activation_state: XdgActivationState,
 
     pub seat: Seat<Self>,
 
+    /// Per-session seats (multi-seat mode only)
+    pub session_seats: super::seats::SessionSeats,
+
     /// Current cursor status from Wayland clients, updated by SeatHandler::cursor_image
     pub cursor_status: smithay::input::pointer::CursorImageStatus,
 
+    /// Bumped when the cursor changes or its surface commits a new image
+    pub cursor_serial: u64,
+
     /// Set by surface commit, cleared after rendering
     pub needs_redraw: bool,
 
-    /// Text pending for clipboard paste injection
-    pub pending_paste: Option<String>,
+    /// Browser clipboard content served as the compositor-owned selection
+    pub pending_paste: Option<crate::clipboard::ClipboardContent>,
+
+    /// MIME types offered for `pending_paste`
+    pub pending_paste_mimes: Vec<String>,
 
     /// Clipboard content set by a Wayland client (to broadcast to browser)
     pub clipboard_outgoing: Option<String>,
@@ -66,6 +79,9 @@ pub struct Compositor {
     /// (smithay updates seat selection AFTER new_selection returns, so we must defer)
     pub clipboard_pending_mime: Option<String>,
 
+    /// The client selection also offers text/html: read it after the text
+    pub clipboard_pending_html: bool,
+
     /// Suppress client clipboard reads shortly after browser→compositor clipboard set.
     /// When set_data_device_selection is called, the focused client may re-assert its
     /// own selection, triggering new_selection with stale content. We skip those.
@@ -96,6 +112,26 @@ pub struct Compositor {
     /// Chromium's Ozone/Wayland layer may ignore keyboard events received before
     /// wl_pointer.enter, so we re-send wl_keyboard.enter on first pointer motion.
     pub kbd_focus_needs_reenter: bool,
+
+    /// Windows that asked for attention (xdg-activation) and have not been
+    /// focused since, with the requests not yet sent as `attention,<id>`
+    pub attention: super::attention::Attention<ObjectId>,
+
+    /// Whether new toplevels take keyboard focus (`input.focus_new_windows`)
+    pub focus_new_windows: crate::config::FocusNewWindows,
+
+    /// Server-side key repeat (delay ms, rate) when `input.key_repeat` is
+    /// on; session seats then get their own repeater and their keyboards
+    /// advertise no client-side repeat, like the main seat
+    pub server_key_repeat: Option<(u32, u32)>,
+
+    /// Wayland output scale set by the client's `scale,N`. The space is in
+    /// logical pixels (display pixels / scale)
+    pub output_scale: f64,
+
+    /// Raw evdev keycodes pressed by `KeyboardKeycode` events (MCP) and not
+    /// released yet; a keyboard reset releases them with the modifiers
+    pub held_keycodes: HashSet<u32>,
 }
 
 impl Compositor {
@@ -111,6 +147,8 @@ impl Compositor {
         let data_device_state = DataDeviceState::new::<Self>(&dh);
         let xdg_decoration_state = XdgDecorationState::new::<Self>(&dh);
         let text_input_manager_state = TextInputManagerState::new::<Self>(&dh);
+        let fractional_scale_manager_state = FractionalScaleManagerState::new::<Self>(&dh);
+        let xdg_activation_state = XdgActivationState::new::<Self>(&dh);
 
         let mut seat_state = SeatState::new();
         let mut seat: Seat<Self> = seat_state.new_wl_seat(&dh, "ivnc");
@@ -135,14 +173,20 @@ impl Compositor {
             data_device_state,
             xdg_decoration_state,
             text_input_manager_state,
+            fractional_scale_manager_state,
+            xdg_activation_state,
             popups,
             seat,
+            session_seats: Default::default(),
             cursor_status: smithay::input::pointer::CursorImageStatus::default_named(),
+            cursor_serial: 0,
             needs_redraw: false,
             pending_paste: None,
+            pending_paste_mimes: Vec::new(),
             clipboard_outgoing: None,
             clipboard_read_fd: None,
             clipboard_pending_mime: None,
+            clipboard_pending_html: false,
             clipboard_suppress_until: None,
             titlebar_adjusted: HashSet::new(),
             csd_retry_count: 0,
@@ -152,6 +196,11 @@ impl Compositor {
             dialog_surfaces: HashSet::new(),
             browser_unfullscreened: HashSet::new(),
             kbd_focus_needs_reenter: true,
+            attention: Default::default(),
+            focus_new_windows: Default::default(),
+            server_key_repeat: None,
+            output_scale: 1.0,
+            held_keycodes: HashSet::new(),
         }
     }
 
@@ -187,6 +236,132 @@ impl Compositor {
         socket_name
     }
 
+    /// Swap the session's seat into `self.seat`, creating one on first use.
+    /// Pair with `put_back_session_seat`.
+    pub fn swap_in_session_seat(&mut self, session: &Arc<str>, cursor_pos: (f64, f64)) -> super::seats::SessionSeat {
+        if !self.session_seats.contains(session) {
+            let seat = self.session_seats.unpark().unwrap_or_else(|| {
+                let name = self.session_seats.next_seat_name();
+                let mut seat = self.seat_state.new_wl_seat(&self.display_handle, name);
+                let (delay, rate) = if self.server_key_repeat.is_some() { (0, 0) } else { (200, 25) };
+                seat.add_keyboard(Default::default(), delay, rate).unwrap();
+                seat.add_pointer();
+                seat
+            });
+            log::info!("Session {} got its own seat", session);
+            let key_repeater = self
+                .server_key_repeat
+                .map(|(delay_ms, rate)| crate::input::KeyRepeater::new(delay_ms, rate));
+            self.session_seats.assign(session.clone(), seat, cursor_pos, key_repeater);
+        }
+        self.session_seats
+            .swap_in(&mut self.seat, session)
+            .expect("session seat was just assigned")
+    }
+
+    /// Undo `swap_in_session_seat`
+    pub fn put_back_session_seat(&mut self, session: Arc<str>, entry: super::seats::SessionSeat) {
+        self.session_seats.put_back(&mut self.seat, session, entry);
+    }
+
+    /// Drop a closed session's focus and park its seat for reuse
+    pub fn release_session_seat(&mut self, session: &str) {
+        let Some(seat) = self.session_seats.release(session) else {
+            return;
+        };
+        let serial = smithay::utils::SERIAL_COUNTER.next_serial();
+        if let Some(keyboard) = seat.get_keyboard() {
+            keyboard.set_focus(self, None, serial);
+        }
+        if let Some(pointer) = seat.get_pointer() {
+            let time = (self.start_time.elapsed().as_millis() & 0xFFFFFFFF) as u32;
+            pointer.motion(
+                self,
+                None,
+                &smithay::input::pointer::MotionEvent { location: (0.0, 0.0).into(), serial, time },
+            );
+            pointer.frame(self);
+        }
+        log::info!("Session {} seat released", session);
+        self.session_seats.park(seat);
+    }
+
+    /// Logical position of a display pixel
+    pub fn display_to_logical(&self, x: f64, y: f64) -> Point<f64, Logical> {
+        super::scale::display_to_logical(x, y, self.output_scale)
+    }
+
+    /// Pointer position in display pixels
+    pub fn pointer_display_location(&self) -> Option<(i32, i32)> {
+        let loc = self.seat.get_pointer()?.current_location();
+        Some(super::scale::logical_to_display(loc, self.output_scale))
+    }
+
+    /// Record a new output scale and tell mapped surfaces to render at it:
+    /// wp_fractional_scale clients get the exact value, the rest the
+    /// rounded-up integer buffer scale
+    pub fn set_output_scale(&mut self, scale: f64) {
+        self.output_scale = scale;
+        for window in self.space.elements() {
+            window.with_surfaces(|surface, states| {
+                with_fractional_scale(states, |fractional| fractional.set_preferred_scale(scale));
+                send_surface_state(surface, states, scale.ceil() as i32, Transform::Normal);
+            });
+        }
+    }
+
+    /// Configure all non-dialog toplevels to fill a display of
+    /// `width`x`height` pixels at the current output scale
+    pub fn configure_toplevels(&self, width: u32, height: u32) {
+        let size = super::scale::logical_size(width, height, self.output_scale);
+        for window in self.space.elements() {
+            let toplevel = window.toplevel().unwrap();
+            let surface_id = toplevel.wl_surface().id().protocol_id();
+            if self.dialog_surfaces.contains(&surface_id) {
+                continue;
+            }
+            toplevel.with_pending_state(|state| {
+                state.size = Some(size);
+            });
+            toplevel.send_pending_configure();
+        }
+    }
+
+    /// Move windows back inside the output after it shrank: dialogs are
+    /// re-centered, other windows clamped by their geometry
+    pub fn keep_windows_in_bounds(&mut self) {
+        let Some(output) = self.space.outputs().next().and_then(|o| self.space.output_geometry(o)) else {
+            return;
+        };
+        // Bottom to top, with the location each window should end up at
+        let placements: Vec<(Window, Point<i32, Logical>, bool)> = self
+            .space
+            .elements()
+            .filter_map(|window| {
+                let loc = self.space.element_location(window)?;
+                let surface_id = window.toplevel()?.wl_surface().id().protocol_id();
+                let target = if self.dialog_surfaces.contains(&surface_id) {
+                    super::placement::centered(window.bbox(), output.size)
+                } else {
+                    super::placement::clamped(loc, window.geometry(), output.size)
+                };
+                Some((window.clone(), target, target != loc))
+            })
+            .collect();
+        if !placements.iter().any(|(_, _, moved)| *moved) {
+            return;
+        }
+        // Re-mapping an element raises it, so re-map all of them in stacking
+        // order to keep it
+        for (window, target, moved) in placements {
+            if moved {
+                log::info!("Keeping window in bounds: moved to {:?}", target);
+            }
+            self.space.map_element(window, target, false);
+        }
+        self.needs_redraw = true;
+    }
+
     pub fn surface_under(
         &self,
         pos: Point<f64, Logical>,
@@ -1,5 +1,8 @@
 //! Configuration management for ivnc
 
+use crate::error::{Error, Result};
+use crate::input::{MAX_MOUSE_SENSITIVITY, MIN_MOUSE_SENSITIVITY};
+use crate::runtime_settings::MAX_OUTPUT_SCALE;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
@@ -47,6 +50,327 @@ impl VideoCodec {
     }
 }
 
+/// What to do with clipboard payloads that exceed `max_clipboard_bytes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardOverflowPolicy {
+    /// Drop the payload and notify clients with `clipboard_too_large`
+    #[default]
+    Reject,
+    /// Keep the first `max_clipboard_bytes` bytes and notify clients with `clipboard_truncated`
+    Truncate,
+}
+
+/// Pointer acceleration curve applied to relative mouse motion
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PointerAcceleration {
+    /// Motion is scaled by `mouse_sensitivity` only
+    #[default]
+    Linear,
+    /// Moves faster than a few pixels per event are additionally scaled by
+    /// their speed, so fast flicks travel farther
+    Quadratic,
+}
+
+impl PointerAcceleration {
+    #[allow(dead_code)]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PointerAcceleration::Linear => "linear",
+            PointerAcceleration::Quadratic => "quadratic",
+        }
+    }
+}
+
+/// Who may send input when several sessions share the desktop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ControlPolicy {
+    /// Every session's input is injected
+    #[default]
+    Off,
+    /// The first session to request control keeps it until it releases
+    First,
+    /// Requests wait in line; control passes on when the holder releases
+    Queue,
+    /// Control is granted by an operator (MCP `grant_control`)
+    Operator,
+}
+
+impl ControlPolicy {
+    #[allow(dead_code)]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ControlPolicy::Off => "off",
+            ControlPolicy::First => "first",
+            ControlPolicy::Queue => "queue",
+            ControlPolicy::Operator => "operator",
+        }
+    }
+}
+
+/// Whether a newly mapped window takes keyboard focus
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FocusNewWindows {
+    /// Every new window is focused
+    #[default]
+    Always,
+    /// Focus only moves on click or window switch
+    Never,
+    /// Focus a new window only when no window has focus
+    IfNoneFocused,
+}
+
+impl FocusNewWindows {
+    /// Whether a new window gets focus, given whether any window has it
+    pub fn should_focus(&self, focused: bool) -> bool {
+        match self {
+            FocusNewWindows::Always => true,
+            FocusNewWindows::Never => false,
+            FocusNewWindows::IfNoneFocused => !focused,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FocusNewWindows::Always => "always",
+            FocusNewWindows::Never => "never",
+            FocusNewWindows::IfNoneFocused => "if_none_focused",
+        }
+    }
+}
+
+/// What input recordings leave out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordRedaction {
+    /// Record everything
+    None,
+    /// Drop text input and clipboard contents
+    Text,
+    /// Also drop printable key presses outside shortcuts (Ctrl/Alt/Meta/Super)
+    #[default]
+    Keys,
+}
+
+impl RecordRedaction {
+    #[allow(dead_code)]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordRedaction::None => "none",
+            RecordRedaction::Text => "text",
+            RecordRedaction::Keys => "keys",
+        }
+    }
+}
+
+/// Network environment the server is deployed in; controls ICE candidate gathering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkProfile {
+    /// Trusted LAN: advertise only directly reachable host addresses, no lookups
+    Lan,
+    /// Full gathering: public candidate, resolved Host header, listen address
+    #[default]
+    Wan,
+}
+
+impl NetworkProfile {
+    #[allow(dead_code)]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NetworkProfile::Lan => "lan",
+            NetworkProfile::Wan => "wan",
+        }
+    }
+}
+
+/// What appsrc does when frames back up in front of the encoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AppsrcQueuePolicy {
+    /// Drop the oldest queued frames (leaky downstream), keeping latency low
+    #[default]
+    Leaky,
+    /// Block the push until there is room, so every frame is encoded
+    Block,
+}
+
+impl AppsrcQueuePolicy {
+    #[allow(dead_code)]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AppsrcQueuePolicy::Leaky => "leaky",
+            AppsrcQueuePolicy::Block => "block",
+        }
+    }
+}
+
+/// Receiver feedback the egress bandwidth estimate is built from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum CongestionControl {
+    /// Transport-wide CC: the browser reports per-packet arrival times and
+    /// str0m estimates the bandwidth (send-side BWE)
+    #[default]
+    TransportCc,
+    /// REMB: the browser estimates the bandwidth and reports the result
+    GoogRemb,
+}
+
+impl CongestionControl {
+    #[allow(dead_code)]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CongestionControl::TransportCc => "transport-cc",
+            CongestionControl::GoogRemb => "goog-remb",
+        }
+    }
+}
+
+/// Byte layout of captured frames, in DRM fourcc naming (components from
+/// the most significant byte of a little-endian 32-bit pixel)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PixelFormat {
+    #[default]
+    Xrgb8888,
+    Argb8888,
+    Xbgr8888,
+    Abgr8888,
+}
+
+impl PixelFormat {
+    #[allow(dead_code)]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PixelFormat::Xrgb8888 => "xrgb8888",
+            PixelFormat::Argb8888 => "argb8888",
+            PixelFormat::Xbgr8888 => "xbgr8888",
+            PixelFormat::Abgr8888 => "abgr8888",
+        }
+    }
+
+    /// GStreamer raw video format with the same memory layout (GStreamer
+    /// names list bytes in memory order)
+    pub fn gst_format(&self) -> &'static str {
+        match self {
+            PixelFormat::Xrgb8888 => "BGRx",
+            PixelFormat::Argb8888 => "BGRA",
+            PixelFormat::Xbgr8888 => "RGBx",
+            PixelFormat::Abgr8888 => "RGBA",
+        }
+    }
+
+    pub fn from_gst_format(format: &str) -> Option<Self> {
+        [PixelFormat::Xrgb8888, PixelFormat::Argb8888, PixelFormat::Xbgr8888, PixelFormat::Abgr8888]
+            .into_iter()
+            .find(|f| f.gst_format() == format)
+    }
+
+    /// Red in byte 0 and blue in byte 2, the reverse of XRGB8888
+    pub fn is_bgr(&self) -> bool {
+        matches!(self, PixelFormat::Xbgr8888 | PixelFormat::Abgr8888)
+    }
+}
+
+/// Quantization range of the encoded video
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorRange {
+    /// 0-255, matching the full-range RGB framebuffer
+    #[default]
+    Full,
+    /// 16-235 (TV range), for decoders that ignore the range flag
+    Limited,
+}
+
+impl ColorRange {
+    #[allow(dead_code)]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ColorRange::Full => "full",
+            ColorRange::Limited => "limited",
+        }
+    }
+}
+
+/// YUV matrix and primaries of the encoded video
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMatrix {
+    #[default]
+    Bt709,
+    Bt601,
+}
+
+impl ColorMatrix {
+    #[allow(dead_code)]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ColorMatrix::Bt709 => "bt709",
+            ColorMatrix::Bt601 => "bt601",
+        }
+    }
+}
+
+/// Screen corner for the stats overlay
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverlayPosition {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Amount of detail in the stats overlay
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlayVerbosity {
+    #[default]
+    Basic,
+    Full,
+}
+
+/// Real-time scheduling policy for the compositor and encode threads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RealtimePolicy {
+    /// Normal (SCHED_OTHER) scheduling
+    #[default]
+    Off,
+    /// SCHED_RR
+    Rr,
+    /// SCHED_FIFO
+    Fifo,
+}
+
+impl RealtimePolicy {
+    #[allow(dead_code)]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RealtimePolicy::Off => "off",
+            RealtimePolicy::Rr => "rr",
+            RealtimePolicy::Fifo => "fifo",
+        }
+    }
+}
+
+/// What the compositor watchdog does when the loop stalls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchdogAction {
+    /// Log the stall and emit a `compositor_stalled` event
+    #[default]
+    Log,
+    /// Log, then abort the process so a supervisor restarts it
+    Abort,
+}
+
 /// Hardware encoder selection
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 #[serde(rename_all = "lowercase")]
@@ -99,6 +423,23 @@ pub struct Config {
     /// WebRTC configuration
     #[serde(default)]
     pub webrtc: WebRTCConfig,
+
+    /// MCP server configuration (used when built with the `mcp` feature)
+    #[serde(default)]
+    pub mcp: McpConfig,
+
+    /// Debug stats overlay burned into the video
+    #[serde(default)]
+    pub overlay: OverlayConfig,
+
+    /// Experimental WebTransport server (used when built with the
+    /// `webtransport` feature)
+    #[serde(default)]
+    pub webtransport: WebTransportConfig,
+
+    /// Per-host overrides of the UI config served at `/ui-config`
+    #[serde(default)]
+    pub ui: UiOverridesConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -114,8 +455,44 @@ pub struct ServerConfig {
 
     /// Group to run as
     pub group: Option<String>,
+
+    /// Pin the compositor loop thread to these CPUs (empty = no pinning)
+    #[serde(default)]
+    pub compositor_cpu: Vec<usize>,
+
+    /// Pin the GStreamer streaming (encode) threads to these CPUs
+    /// (empty = no pinning)
+    #[serde(default)]
+    pub encode_cpu: Vec<usize>,
+
+    /// Real-time scheduling for the compositor loop and encode threads;
+    /// needs CAP_SYS_NICE or an RLIMIT_RTPRIO allowance
+    #[serde(default)]
+    pub realtime: RealtimePolicy,
+
+    /// Real-time priority (1-99) used when `realtime` is not off
+    #[serde(default = "default_realtime_priority")]
+    pub realtime_priority: i32,
+
+    /// Report the compositor loop as stalled after this many seconds
+    /// without progress (0 = no watchdog)
+    #[serde(default = "default_watchdog_timeout_secs")]
+    pub watchdog_timeout_secs: u64,
+
+    /// Action taken when the watchdog detects a stall
+    #[serde(default)]
+    pub watchdog_action: WatchdogAction,
+
+    /// Interval of the stats tick: loop stats, the `stats,` broadcast to
+    /// clients and the MCP/overlay refresh
+    #[serde(default = "default_stats_interval_ms")]
+    pub stats_interval_ms: u64,
 }
 
+/// Upper bound (and default) for `display.max_width` / `max_height`
+pub const MAX_DISPLAY_WIDTH: u32 = 7680;
+pub const MAX_DISPLAY_HEIGHT: u32 = 4320;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DisplayConfig {
     /// Screen width in pixels
@@ -126,6 +503,76 @@ pub struct DisplayConfig {
 
     /// Refresh rate in Hz
     pub refresh_rate: u32,
+
+    /// Layout the renderer reads frames back in and the encoder pipeline
+    /// expects. Screenshots and the frame callback are converted to or
+    /// documented as XRGB8888 regardless.
+    #[serde(default)]
+    pub pixel_format: PixelFormat,
+
+    /// Largest width clients and MCP may resize the display to; larger
+    /// requests are clamped
+    #[serde(default = "default_max_display_width")]
+    pub max_width: u32,
+
+    /// Largest height clients and MCP may resize the display to
+    #[serde(default = "default_max_display_height")]
+    pub max_height: u32,
+
+    /// Lock the display to this aspect ratio ("16:9"): resize requests
+    /// shrink to the largest size of that ratio that fits, and the client
+    /// letterboxes. Unset = free resize
+    #[serde(default)]
+    pub aspect_ratio: Option<String>,
+
+    /// Apply a resize only after requests pause this long (ms), so dragging
+    /// a browser window edge rebuilds the pipeline once (0 = immediately)
+    #[serde(default = "default_resize_debounce_ms")]
+    pub resize_debounce_ms: u64,
+
+    /// Highest Wayland output scale a client may request with `scale,N`
+    /// for HiDPI screens. 1.0 (default) ignores the request: not every app
+    /// renders correctly at a (fractional) scale
+    #[serde(default = "default_max_output_scale")]
+    pub max_output_scale: f64,
+
+    /// After the display shrinks, move windows back inside it: dialogs are
+    /// re-centered, other windows clamped so none ends up unreachable
+    #[serde(default = "default_keep_windows_in_bounds")]
+    pub keep_windows_in_bounds: bool,
+}
+
+impl DisplayConfig {
+    /// Size a resize request is applied at: each dimension clamped to the
+    /// configured maximum. None if either is zero.
+    pub fn clamp_size(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+        Some((width.min(self.max_width), height.min(self.max_height)))
+    }
+
+    /// `aspect_ratio` as (width, height) terms; None when unset or invalid
+    pub fn locked_aspect_ratio(&self) -> Option<(u32, u32)> {
+        let (w, h) = self.aspect_ratio.as_deref()?.split_once(':')?;
+        let (w, h) = (w.trim().parse::<u32>().ok()?, h.trim().parse::<u32>().ok()?);
+        (w > 0 && h > 0).then_some((w, h))
+    }
+
+    /// Largest size within `width`x`height` with the locked aspect ratio;
+    /// unchanged under free resize
+    pub fn fit_aspect(&self, width: u32, height: u32) -> (u32, u32) {
+        let Some((rw, rh)) = self.locked_aspect_ratio() else {
+            return (width, height);
+        };
+        let (w, h, rw, rh) = (width as u64, height as u64, rw as u64, rh as u64);
+        if w * rh > h * rw {
+            // Too wide: full height, bars left and right
+            (((h * rw + rh / 2) / rh).max(1) as u32, height)
+        } else {
+            (width, ((w * rh + rw / 2) / rw).max(1) as u32)
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -151,9 +598,66 @@ pub struct HttpConfig {
     #[serde(default = "default_basic_auth_password")]
     pub basic_auth_password: String,
 
+    /// Compress text responses (HTML/JS/CSS/JSON) with gzip or brotli
+    #[serde(default = "default_http_compression")]
+    pub compression: bool,
+
+    /// Offer deflate compression of large signaling messages (SDP) to
+    /// clients that request the `ivnc-deflate` WebSocket subprotocol
+    #[serde(default = "default_signaling_compression")]
+    pub signaling_compression: bool,
+
+    /// Serve `/snapshot` (JPEG) and `/stream.mjpeg` for viewers without
+    /// WebRTC. Requires the `mcp` feature for frame capture.
+    #[serde(default)]
+    pub snapshot_enabled: bool,
+
+    /// Connections allowed to sit in protocol classification or the TLS
+    /// handshake at once; further connections are closed immediately
+    #[serde(default = "default_max_pending_connections")]
+    pub max_pending_connections: usize,
+
+    /// How long a new connection may stay silent before it is closed
+    /// unclassified (port scanners, idle health checks); also bounds the
+    /// TLS handshake that follows
+    #[serde(default = "default_classify_timeout_ms")]
+    pub classify_timeout_ms: u64,
+
+    /// Extra wait for more bytes when the first ones are ambiguous (a lone
+    /// TLS/DTLS record marker, a partial HTTP method) before guessing
+    #[serde(default = "default_partial_peek_ms")]
+    pub partial_peek_ms: u64,
+
+    /// Export per-thread CPU usage in `/metrics` (walks `/proc/self/task`
+    /// every second, so off by default)
+    #[serde(default)]
+    pub thread_cpu_metrics: bool,
+
+    /// Frame rate cap for `/stream.mjpeg`
+    #[serde(default = "default_mjpeg_fps")]
+    pub mjpeg_fps: u32,
+
+    /// JPEG quality (1-100) for snapshots and the MJPEG stream
+    #[serde(default = "default_snapshot_quality")]
+    pub snapshot_quality: u8,
+
     /// Enable TLS with self-signed certificate (set via --tls CLI flag)
     #[serde(default)]
     pub tls: bool,
+
+    /// Directory to persist the self-signed HTTPS certificate in, so its
+    /// fingerprint survives restarts. Unset = new certificate every start.
+    #[serde(default)]
+    pub tls_cert_dir: Option<String>,
+
+    /// PEM certificate chain to serve instead of a self-signed one
+    /// (e.g. Let's Encrypt fullchain.pem). Requires `tls_key`.
+    #[serde(default)]
+    pub tls_cert: Option<String>,
+
+    /// PEM private key matching `tls_cert`
+    #[serde(default)]
+    pub tls_key: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -163,6 +667,31 @@ pub struct EncodingConfig {
 
     /// Maximum FPS
     pub max_fps: u32,
+
+    /// Drop frames instead of queueing them once the encoder backlog would
+    /// take longer than this to drain (0 = never drop)
+    #[serde(default)]
+    pub latency_budget_ms: u64,
+
+    /// With a latency budget set, also drop frames while anything is queued
+    /// and process CPU usage is at or above this percentage of all available
+    /// cores (100 = every core busy)
+    #[serde(default = "default_cpu_budget_percent")]
+    pub cpu_budget_percent: f64,
+
+    /// Frames that may wait for the encode thread; newer frames are dropped
+    /// while it is full (0 = push frames synchronously from the main loop)
+    #[serde(default = "default_encode_queue_depth")]
+    pub encode_queue_depth: usize,
+
+    /// Full appsrc queue: drop the oldest frames or block the push
+    #[serde(default)]
+    pub appsrc_queue: AppsrcQueuePolicy,
+
+    /// appsrc queue limit in bytes (0 = no byte limit; the queue is still
+    /// bounded in time by `webrtc.max_latency_ms`)
+    #[serde(default)]
+    pub appsrc_max_bytes: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -173,7 +702,9 @@ pub struct InputConfig {
     /// Enable mouse input
     pub enable_mouse: bool,
 
-    /// Enable clipboard sync
+    /// Enable clipboard sync. When off, browser clipboard messages are
+    /// dropped, remote selections are never read or broadcast, and the MCP
+    /// clipboard tools are withheld.
     pub enable_clipboard: bool,
 
     /// Enable binary clipboard sync
@@ -184,6 +715,18 @@ pub struct InputConfig {
     #[serde(default)]
     pub enable_commands: bool,
 
+    /// Maximum clipboard payload size in bytes (applies in both directions)
+    #[serde(default = "default_max_clipboard_bytes")]
+    pub max_clipboard_bytes: usize,
+
+    /// Policy for clipboard payloads above `max_clipboard_bytes`
+    #[serde(default)]
+    pub clipboard_overflow: ClipboardOverflowPolicy,
+
+    /// MIME types offered to remote apps for text pasted from the browser.
+    /// `text/html` is added automatically for HTML content.
+    #[serde(default = "default_clipboard_mime_types")]
+    pub clipboard_mime_types: Vec<String>,
 
     /// Allowed file transfer directions ("upload", "download")
     #[serde(default = "default_file_transfers")]
@@ -193,8 +736,81 @@ pub struct InputConfig {
     #[serde(default = "default_upload_dir")]
     pub upload_dir: String,
 
-    /// Mouse sensitivity multiplier
+    /// Mouse sensitivity multiplier for relative motion (pointer lock);
+    /// adjustable at runtime via SETTINGS `mouse_sensitivity`
     pub mouse_sensitivity: f64,
+
+    /// Acceleration curve for relative motion
+    #[serde(default)]
+    pub pointer_acceleration: PointerAcceleration,
+
+    /// Synthesize key repeats in the compositor while a key is held, instead
+    /// of relying on each Wayland client's own repeat timer
+    #[serde(default)]
+    pub key_repeat: bool,
+
+    /// Hold time before the first repeat (ms)
+    #[serde(default = "default_key_repeat_delay_ms")]
+    pub key_repeat_delay_ms: u32,
+
+    /// Repeats per second once repeating
+    #[serde(default = "default_key_repeat_rate")]
+    pub key_repeat_rate: u32,
+
+    /// Give every session its own Wayland seat (pointer + keyboard focus)
+    /// instead of sharing one
+    #[serde(default)]
+    pub multi_seat: bool,
+
+    /// Only let one session at a time send input
+    #[serde(default)]
+    pub control_policy: ControlPolicy,
+
+    /// Record injected input to per-session files in this directory
+    #[serde(default)]
+    pub record_dir: Option<String>,
+
+    /// What recordings leave out, to keep typed secrets off disk
+    #[serde(default)]
+    pub record_redaction: RecordRedaction,
+
+    /// Accept recordings for replay on `POST /api/input/replay`
+    #[serde(default)]
+    pub allow_replay: bool,
+
+    /// Broadcast the pointer position as `cursorpos,x,y` at most this many
+    /// times per second while it moves (0 = off)
+    #[serde(default)]
+    pub cursor_position_hz: u32,
+
+    /// Whether new windows take keyboard focus ("always", "never" or
+    /// "if_none_focused"); the latter two stop background apps stealing it
+    #[serde(default)]
+    pub focus_new_windows: FocusNewWindows,
+}
+
+/// Audio capture backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioBackend {
+    /// First available of PulseAudio, PipeWire, ALSA
+    #[default]
+    Auto,
+    Pulse,
+    Pipewire,
+    Alsa,
+}
+
+impl AudioBackend {
+    #[allow(dead_code)]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AudioBackend::Auto => "auto",
+            AudioBackend::Pulse => "pulse",
+            AudioBackend::Pipewire => "pipewire",
+            AudioBackend::Alsa => "alsa",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -202,6 +818,16 @@ pub struct AudioConfig {
     /// Enable audio streaming
     pub enabled: bool,
 
+    /// Capture backend: "auto", "pulse", "pipewire" or "alsa"
+    #[serde(default)]
+    pub backend: AudioBackend,
+
+    /// Source to capture: a PulseAudio source or sink name (a sink means
+    /// its monitor), ALSA device or PipeWire node. Unset = default sink's
+    /// monitor.
+    #[serde(default)]
+    pub source: Option<String>,
+
     /// Sample rate (Hz)
     pub sample_rate: u32,
 
@@ -210,6 +836,32 @@ pub struct AudioConfig {
 
     /// Bitrate (bps)
     pub bitrate: u32,
+
+    /// Consecutive failed attempts to re-open the capture source before
+    /// audio is given up on (0 = retry forever)
+    #[serde(default = "default_audio_max_retries")]
+    pub max_retries: u32,
+
+    /// Capture only the application with this Wayland app_id (PulseAudio
+    /// or pipewire-pulse): its streams are moved to a dedicated null sink.
+    /// Unset = the whole desktop mix.
+    #[serde(default)]
+    pub app_id: Option<String>,
+
+    /// Opus in-band forward error correction: each packet also carries a
+    /// low-bitrate copy of the previous frame, so a single lost packet can
+    /// be recovered without waiting for retransmission
+    #[serde(default)]
+    pub fec: bool,
+
+    /// Packet loss (percent) the FEC data is sized for
+    #[serde(default = "default_audio_fec_loss_percent")]
+    pub fec_loss_percent: u8,
+
+    /// Discontinuous transmission: stop sending during digital silence
+    /// (a keep-alive frame every 400 ms)
+    #[serde(default)]
+    pub dtx: bool,
 }
 
 /// WebRTC streaming configuration
@@ -230,6 +882,20 @@ pub struct WebRTCConfig {
     #[serde(default = "default_candidate_from_host_header")]
     pub candidate_from_host_header: bool,
 
+    /// "lan" skips the public candidate and DNS resolution and advertises
+    /// host addresses only; "wan" (default) does full gathering
+    #[serde(default)]
+    pub network_profile: NetworkProfile,
+
+    /// Time budget for resolving ICE candidate addresses (ms). When it
+    /// expires the session proceeds with the candidates gathered so far.
+    #[serde(default = "default_ice_gathering_timeout_ms")]
+    pub ice_gathering_timeout_ms: u64,
+
+    /// Maximum number of ICE candidates advertised per session
+    #[serde(default = "default_ice_max_candidates")]
+    pub ice_max_candidates: usize,
+
     /// Video codec selection
     #[serde(default)]
     pub video_codec: VideoCodec,
@@ -254,9 +920,62 @@ pub struct WebRTCConfig {
     #[serde(default = "default_pipeline_latency_ms")]
     pub pipeline_latency_ms: u32,
 
+    /// Latency budget from frame push to RTP out, in ms: bounds appsrc's
+    /// queue and how long a frame's packets are held waiting for its end
+    #[serde(default = "default_max_latency_ms")]
+    pub max_latency_ms: u32,
+
     /// Keyframe interval in frames
     #[serde(default = "default_keyframe_interval")]
     pub keyframe_interval: u32,
+
+    /// Color range of the encoded video, signaled in the bitstream (H.264
+    /// VUI, VP9/AV1 color config). Encoders that cannot signal it fall back
+    /// to limited range, which decoders assume by default.
+    #[serde(default)]
+    pub color_range: ColorRange,
+
+    /// YUV matrix and primaries of the encoded video
+    #[serde(default)]
+    pub color_matrix: ColorMatrix,
+
+    /// Bits per sample of the encoded video: 8, or 10 for VP9 (profile 2)
+    /// and AV1 when the encoder accepts 10-bit input; otherwise 8 is used
+    #[serde(default = "default_bit_depth")]
+    pub bit_depth: u8,
+
+    /// Extra wait after DTLS/SRTP is established before replaying the
+    /// cached keyframe to a new session (0 = replay immediately)
+    #[serde(default = "default_keyframe_replay_delay_ms")]
+    pub keyframe_replay_delay_ms: u64,
+
+    /// Directory to persist the DTLS certificate in, keeping the SDP
+    /// fingerprint stable across restarts (requires the `tls` feature).
+    /// Unset = new certificate every start.
+    #[serde(default)]
+    pub dtls_cert_dir: Option<String>,
+
+    /// Halve the encoded resolution when every viewer's bandwidth estimate
+    /// drops below `congestion_downscale_below_kbps`
+    #[serde(default)]
+    pub congestion_downscale: bool,
+
+    /// A viewer counts as constrained after its estimate stays below this
+    /// for `congestion_hold_secs`
+    #[serde(default = "default_congestion_downscale_below_kbps")]
+    pub congestion_downscale_below_kbps: u32,
+
+    /// ...and unconstrained again after it stays above this for the same time
+    #[serde(default = "default_congestion_restore_above_kbps")]
+    pub congestion_restore_above_kbps: u32,
+
+    /// How long an estimate must stay past a threshold before switching
+    #[serde(default = "default_congestion_hold_secs")]
+    pub congestion_hold_secs: u64,
+
+    /// Bandwidth feedback to negotiate: "transport-cc" or "goog-remb"
+    #[serde(default)]
+    pub congestion_control: CongestionControl,
 }
 
 impl Default for WebRTCConfig {
@@ -266,17 +985,138 @@ impl Default for WebRTCConfig {
             tcp_only: true,
             public_candidate: None,
             candidate_from_host_header: true,
+            network_profile: NetworkProfile::Wan,
+            ice_gathering_timeout_ms: default_ice_gathering_timeout_ms(),
+            ice_max_candidates: default_ice_max_candidates(),
             video_codec: VideoCodec::H264,
             video_bitrate: 8000,       // 8 Mbps default (screen content needs higher bitrate)
             video_bitrate_max: 16000,  // 16 Mbps max
             video_bitrate_min: 1000,   // 1 Mbps min
             hardware_encoder: HardwareEncoder::Auto,
             pipeline_latency_ms: 50,
+            max_latency_ms: default_max_latency_ms(),
             keyframe_interval: 60,
+            color_range: ColorRange::Full,
+            color_matrix: ColorMatrix::Bt709,
+            bit_depth: default_bit_depth(),
+            keyframe_replay_delay_ms: default_keyframe_replay_delay_ms(),
+            dtls_cert_dir: None,
+            congestion_downscale: false,
+            congestion_downscale_below_kbps: default_congestion_downscale_below_kbps(),
+            congestion_restore_above_kbps: default_congestion_restore_above_kbps(),
+            congestion_hold_secs: default_congestion_hold_secs(),
+            congestion_control: CongestionControl::default(),
         }
     }
 }
 
+/// MCP (Model Context Protocol) server configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpConfig {
+    /// Serve the MCP Streamable HTTP transport on the main HTTP port
+    #[serde(default = "default_mcp_http_enabled")]
+    pub http_enabled: bool,
+
+    /// URL path for the MCP Streamable HTTP endpoint
+    #[serde(default = "default_mcp_http_path")]
+    pub http_path: String,
+
+    /// Serve the HTTP endpoint even when basic auth is disabled
+    #[serde(default)]
+    pub allow_unauthenticated: bool,
+
+    /// Tools available to callers without a scope token (unset = all tools)
+    #[serde(default)]
+    pub allowed_tools: Option<Vec<String>>,
+
+    /// Token-based tool scopes, selected with the `X-MCP-Token` header
+    #[serde(default)]
+    pub scopes: Vec<McpScopeConfig>,
+}
+
+impl Default for McpConfig {
+    fn default() -> Self {
+        Self {
+            http_enabled: default_mcp_http_enabled(),
+            http_path: default_mcp_http_path(),
+            allow_unauthenticated: false,
+            allowed_tools: None,
+            scopes: Vec::new(),
+        }
+    }
+}
+
+/// Debug stats overlay configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OverlayConfig {
+    /// Draw the overlay onto every encoded frame
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Corner to draw in
+    #[serde(default)]
+    pub position: OverlayPosition,
+
+    /// "basic" (fps, bitrate) or "full" (adds keyframes, sessions, size, cpu)
+    #[serde(default)]
+    pub verbosity: OverlayVerbosity,
+}
+
+/// Experimental WebTransport (HTTP/3) transport configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebTransportConfig {
+    /// Serve WebTransport sessions alongside WebRTC
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// UDP port for QUIC (unset = same number as the HTTP port)
+    #[serde(default)]
+    pub port: Option<u16>,
+}
+
+/// UI overrides selected by the request's Host header
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UiOverridesConfig {
+    /// Host-specific UI configs; requests for other hosts get the default
+    #[serde(default)]
+    pub hosts: Vec<UiHostConfig>,
+}
+
+/// UI visibility overrides for one host. Unset fields keep the default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UiHostConfig {
+    /// Host name to match (case-insensitive, port ignored)
+    pub host: String,
+
+    #[serde(default)]
+    pub show_sidebar: Option<bool>,
+
+    #[serde(default)]
+    pub show_video_settings: Option<bool>,
+
+    #[serde(default)]
+    pub show_screen_settings: Option<bool>,
+
+    #[serde(default)]
+    pub show_audio_settings: Option<bool>,
+
+    #[serde(default)]
+    pub show_stats: Option<bool>,
+
+    #[serde(default)]
+    pub show_clipboard: Option<bool>,
+}
+
+/// A set of MCP tools granted to callers presenting `token`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpScopeConfig {
+    /// Secret presented in the `X-MCP-Token` header
+    pub token: String,
+
+    /// Tool names allowed for this token ("*" allows all)
+    pub tools: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoggingConfig {
     /// Log level
@@ -297,11 +1137,25 @@ impl Default for Config {
                 pidfile: PathBuf::from("/var/run/ivnc.pid"),
                 user: None,
                 group: None,
+                compositor_cpu: Vec::new(),
+                encode_cpu: Vec::new(),
+                realtime: RealtimePolicy::Off,
+                realtime_priority: default_realtime_priority(),
+                watchdog_timeout_secs: default_watchdog_timeout_secs(),
+                watchdog_action: WatchdogAction::Log,
+                stats_interval_ms: default_stats_interval_ms(),
             },
             display: DisplayConfig {
                 width: 1920,
                 height: 1080,
                 refresh_rate: 60,
+                pixel_format: PixelFormat::Xrgb8888,
+                max_width: default_max_display_width(),
+                max_height: default_max_display_height(),
+                aspect_ratio: None,
+                resize_debounce_ms: default_resize_debounce_ms(),
+                max_output_scale: default_max_output_scale(),
+                keep_windows_in_bounds: default_keep_windows_in_bounds(),
             },
             http: HttpConfig {
                 host: "0.0.0.0".to_string(),
@@ -310,11 +1164,28 @@ impl Default for Config {
                 basic_auth_enabled: true,
                 basic_auth_user: "user".to_string(),
                 basic_auth_password: "mypasswd".to_string(),
+                compression: default_http_compression(),
+                signaling_compression: default_signaling_compression(),
+                max_pending_connections: default_max_pending_connections(),
+                classify_timeout_ms: default_classify_timeout_ms(),
+                partial_peek_ms: default_partial_peek_ms(),
+                snapshot_enabled: false,
+                thread_cpu_metrics: false,
+                mjpeg_fps: default_mjpeg_fps(),
+                snapshot_quality: default_snapshot_quality(),
                 tls: false,
+                tls_cert_dir: None,
+                tls_cert: None,
+                tls_key: None,
             },
             encoding: EncodingConfig {
                 target_fps: 30,
                 max_fps: 60,
+                latency_budget_ms: 0,
+                cpu_budget_percent: default_cpu_budget_percent(),
+                encode_queue_depth: default_encode_queue_depth(),
+                appsrc_queue: AppsrcQueuePolicy::default(),
+                appsrc_max_bytes: 0,
             },
             input: InputConfig {
                 enable_keyboard: true,
@@ -322,15 +1193,36 @@ impl Default for Config {
                 enable_clipboard: true,
                 enable_binary_clipboard: false,
                 enable_commands: false,
+                max_clipboard_bytes: default_max_clipboard_bytes(),
+                clipboard_overflow: ClipboardOverflowPolicy::Reject,
+                clipboard_mime_types: default_clipboard_mime_types(),
                 file_transfers: default_file_transfers(),
                 upload_dir: default_upload_dir(),
                 mouse_sensitivity: 1.0,
+                pointer_acceleration: PointerAcceleration::default(),
+                key_repeat: false,
+                key_repeat_delay_ms: default_key_repeat_delay_ms(),
+                key_repeat_rate: default_key_repeat_rate(),
+                multi_seat: false,
+                control_policy: ControlPolicy::Off,
+                record_dir: None,
+                record_redaction: RecordRedaction::default(),
+                allow_replay: false,
+                cursor_position_hz: 0,
+                focus_new_windows: FocusNewWindows::default(),
             },
             audio: AudioConfig {
                 enabled: cfg!(feature = "pulseaudio"),
+                backend: AudioBackend::default(),
+                source: None,
                 sample_rate: 48_000,
                 channels: 2,
                 bitrate: 128_000,
+                max_retries: default_audio_max_retries(),
+                app_id: None,
+                fec: false,
+                fec_loss_percent: default_audio_fec_loss_percent(),
+                dtx: false,
             },
             logging: LoggingConfig {
                 level: "info".to_string(),
@@ -338,13 +1230,17 @@ impl Default for Config {
                 format: "json".to_string(),
             },
             webrtc: WebRTCConfig::default(),
+            mcp: McpConfig::default(),
+            overlay: OverlayConfig::default(),
+            webtransport: WebTransportConfig::default(),
+            ui: UiOverridesConfig::default(),
         }
     }
 }
 
 impl Config {
     /// Load configuration from TOML file
-    pub fn load(path: &PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn load(path: &PathBuf) -> Result<Self> {
         if !path.exists() {
             return Ok(Config::default());
         }
@@ -355,17 +1251,100 @@ impl Config {
     }
 
     /// Validate configuration
-    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn validate(&self) -> Result<()> {
         if self.display.width == 0 || self.display.height == 0 {
-            return Err("Display dimensions must be non-zero".into());
+            return Err(Error::Config("Display dimensions must be non-zero".into()));
+        }
+
+        if !(1..=MAX_DISPLAY_WIDTH).contains(&self.display.max_width)
+            || !(1..=MAX_DISPLAY_HEIGHT).contains(&self.display.max_height)
+        {
+            return Err(Error::Config(format!(
+                "display.max_width/max_height must be between 1x1 and {}x{}",
+                MAX_DISPLAY_WIDTH, MAX_DISPLAY_HEIGHT
+            )));
+        }
+
+        if self.display.aspect_ratio.is_some() && self.display.locked_aspect_ratio().is_none() {
+            return Err(Error::Config("display.aspect_ratio must look like \"16:9\"".into()));
+        }
+
+        if !(1.0..=MAX_OUTPUT_SCALE).contains(&self.display.max_output_scale) {
+            return Err(Error::Config(format!(
+                "display.max_output_scale must be between 1.0 and {}",
+                MAX_OUTPUT_SCALE
+            )));
+        }
+
+        if self.display.width > self.display.max_width || self.display.height > self.display.max_height {
+            return Err(Error::Config("Display dimensions cannot exceed display.max_width/max_height".into()));
         }
 
         if self.encoding.target_fps > self.encoding.max_fps {
-            return Err("Target FPS cannot exceed max FPS".into());
+            return Err(Error::Config("Target FPS cannot exceed max FPS".into()));
+        }
+
+        if !self.server.compositor_cpu.is_empty() || !self.server.encode_cpu.is_empty() {
+            let allowed = crate::cpu_affinity::allowed_cpus()?;
+            crate::cpu_affinity::check_cpus("server.compositor_cpu", &self.server.compositor_cpu, &allowed)
+                .and_then(|_| crate::cpu_affinity::check_cpus("server.encode_cpu", &self.server.encode_cpu, &allowed))
+                .map_err(Error::Config)?;
+        }
+
+        if self.server.realtime != RealtimePolicy::Off && !(1..=99).contains(&self.server.realtime_priority) {
+            return Err(Error::Config("server.realtime_priority must be between 1 and 99".into()));
+        }
+        if !(100..=60_000).contains(&self.server.stats_interval_ms) {
+            return Err(Error::Config("server.stats_interval_ms must be between 100 and 60000".into()));
+        }
+
+        if self.encoding.cpu_budget_percent <= 0.0 {
+            return Err(Error::Config("Encoding cpu_budget_percent must be positive".into()));
+        }
+        if self.input.cursor_position_hz > 240 {
+            return Err(Error::Config("Input cursor_position_hz must be at most 240".into()));
+        }
+        if self.encoding.encode_queue_depth > 16 {
+            return Err(Error::Config("Encoding encode_queue_depth must be at most 16".into()));
         }
 
         if self.http.basic_auth_enabled && self.http.basic_auth_password.is_empty() {
-            return Err("Basic auth is enabled but password is empty".into());
+            return Err(Error::Config("Basic auth is enabled but password is empty".into()));
+        }
+
+        if self.http.tls_cert.is_some() != self.http.tls_key.is_some() {
+            return Err(Error::Config("HTTP tls_cert and tls_key must be set together".into()));
+        }
+        if !(MIN_MOUSE_SENSITIVITY..=MAX_MOUSE_SENSITIVITY).contains(&self.input.mouse_sensitivity) {
+            return Err(Error::Config(format!(
+                "input.mouse_sensitivity must be between {} and {}",
+                MIN_MOUSE_SENSITIVITY, MAX_MOUSE_SENSITIVITY
+            )));
+        }
+        if self.input.key_repeat && (self.input.key_repeat_rate == 0 || self.input.key_repeat_rate > 100) {
+            return Err(Error::Config("input.key_repeat_rate must be between 1 and 100".into()));
+        }
+        if self.webrtc.pipeline_latency_ms > self.webrtc.max_latency_ms {
+            return Err(Error::Config("webrtc.pipeline_latency_ms cannot exceed webrtc.max_latency_ms".into()));
+        }
+        if self.webrtc.congestion_downscale
+            && self.webrtc.congestion_restore_above_kbps <= self.webrtc.congestion_downscale_below_kbps
+        {
+            return Err(Error::Config(
+                "webrtc.congestion_restore_above_kbps must be greater than congestion_downscale_below_kbps".into(),
+            ));
+        }
+        if self.http.max_pending_connections == 0 {
+            return Err(Error::Config("HTTP max_pending_connections must be at least 1".into()));
+        }
+        if self.http.classify_timeout_ms == 0 {
+            return Err(Error::Config("HTTP classify_timeout_ms must be greater than 0".into()));
+        }
+        if self.http.mjpeg_fps == 0 || self.http.mjpeg_fps > 60 {
+            return Err(Error::Config("HTTP mjpeg_fps must be between 1 and 60".into()));
+        }
+        if self.http.snapshot_quality == 0 || self.http.snapshot_quality > 100 {
+            return Err(Error::Config("HTTP snapshot_quality must be between 1 and 100".into()));
         }
 
         for entry in &self.input.file_transfers {
@@ -374,45 +1353,98 @@ impl Config {
                 continue;
             }
             if value != "upload" && value != "download" {
-                return Err("Input file_transfers must contain \"upload\" or \"download\"".into());
+                return Err(Error::Config("Input file_transfers must contain \"upload\" or \"download\"".into()));
             }
         }
 
+        if self.input.max_clipboard_bytes == 0 {
+            return Err(Error::Config("Input max_clipboard_bytes must be non-zero".into()));
+        }
+        if self.input.clipboard_mime_types.is_empty() {
+            return Err(Error::Config("Input clipboard_mime_types must not be empty".into()));
+        }
+        if let Some(mime) = self.input.clipboard_mime_types.iter().find(|m| !crate::clipboard::is_text_mime(m)) {
+            return Err(Error::Config(format!(
+                "Input clipboard_mime_types: {:?} is not a text type (text/* or STRING, TEXT, UTF8_STRING)",
+                mime
+            )));
+        }
+
         if !self.webrtc.tcp_only {
-            return Err("WebRTC tcp_only must be true in this build".into());
+            return Err(Error::Config("WebRTC tcp_only must be true in this build".into()));
         }
 
         if let Some(ref candidate) = self.webrtc.public_candidate {
             if candidate.parse::<std::net::SocketAddr>().is_err() {
-                return Err("WebRTC public_candidate must be in ip:port format".into());
+                return Err(Error::Config("WebRTC public_candidate must be in ip:port format".into()));
+            }
+        }
+
+        if self.webrtc.ice_gathering_timeout_ms == 0 {
+            return Err(Error::Config("WebRTC ice_gathering_timeout_ms must be non-zero".into()));
+        }
+
+        if self.webrtc.ice_max_candidates == 0 {
+            return Err(Error::Config("WebRTC ice_max_candidates must be at least 1".into()));
+        }
+
+        if !self.mcp.http_path.starts_with('/') || self.mcp.http_path.len() < 2 {
+            return Err(Error::Config("MCP http_path must start with '/' and not be the root path".into()));
+        }
+
+        for (i, scope) in self.mcp.scopes.iter().enumerate() {
+            if scope.token.is_empty() {
+                return Err(Error::Config("MCP scope token must not be empty".into()));
+            }
+            if self.mcp.scopes[..i].iter().any(|other| other.token == scope.token) {
+                return Err(Error::Config("MCP scope tokens must be unique".into()));
+            }
+        }
+
+        for (i, host) in self.ui.hosts.iter().enumerate() {
+            let name = ui::normalize_host(&host.host);
+            if name.is_empty() {
+                return Err(Error::Config("ui.hosts host must not be empty".into()));
+            }
+            if self.ui.hosts[..i].iter().any(|other| ui::normalize_host(&other.host) == name) {
+                return Err(Error::Config(format!("ui.hosts lists host '{}' more than once", name)));
             }
         }
 
         if self.audio.enabled {
             if self.audio.sample_rate == 0 {
-                return Err("Audio sample rate must be non-zero".into());
+                return Err(Error::Config("Audio sample rate must be non-zero".into()));
             }
             if self.audio.channels == 0 || self.audio.channels > 2 {
-                return Err("Audio channels must be 1 or 2".into());
+                return Err(Error::Config("Audio channels must be 1 or 2".into()));
             }
             if self.audio.bitrate == 0 {
-                return Err("Audio bitrate must be non-zero".into());
+                return Err(Error::Config("Audio bitrate must be non-zero".into()));
+            }
+            if self.audio.source.as_deref().is_some_and(|s| s.trim().is_empty()) {
+                return Err(Error::Config("audio.source must not be empty (omit it for the default)".into()));
+            }
+            if self.audio.fec && !(1..=50).contains(&self.audio.fec_loss_percent) {
+                return Err(Error::Config("audio.fec_loss_percent must be between 1 and 50".into()));
             }
         }
 
         // WebRTC validation
         if self.webrtc.enabled {
             if self.webrtc.video_bitrate == 0 {
-                return Err("WebRTC video bitrate must be non-zero".into());
+                return Err(Error::Config("WebRTC video bitrate must be non-zero".into()));
             }
             if self.webrtc.video_bitrate_min > self.webrtc.video_bitrate {
-                return Err("WebRTC min bitrate cannot exceed target bitrate".into());
+                return Err(Error::Config("WebRTC min bitrate cannot exceed target bitrate".into()));
             }
             if self.webrtc.video_bitrate > self.webrtc.video_bitrate_max {
-                return Err("WebRTC target bitrate cannot exceed max bitrate".into());
+                return Err(Error::Config("WebRTC target bitrate cannot exceed max bitrate".into()));
             }
             if self.webrtc.keyframe_interval == 0 {
-                return Err("WebRTC keyframe interval must be non-zero".into());
+                return Err(Error::Config("WebRTC keyframe interval must be non-zero".into()));
+            }
+            if !matches!(self.webrtc.bit_depth, 8 | 10) {
+                return Err(Error::Config("WebRTC bit_depth must be 8 or 10".into()));
             }
         }
 
@@ -422,13 +1454,98 @@ impl Config {
 
 #[cfg(test)]
 mod tests {
-    use super::Config;
+    use super::{Config, Error, UiOverridesConfig};
+
+    #[test]
+    fn pixel_formats_map_to_the_same_memory_layout() {
+        use super::PixelFormat;
+        for format in [PixelFormat::Xrgb8888, PixelFormat::Argb8888, PixelFormat::Xbgr8888, PixelFormat::Abgr8888] {
+            // DRM names go from the most significant byte, so on little
+            // endian memory order is the name reversed
+            let memory_order: String = format.as_str()[..4].chars().rev().collect();
+            assert_eq!(format.gst_format().to_lowercase(), memory_order);
+            assert_eq!(PixelFormat::from_gst_format(format.gst_format()), Some(format));
+            assert_eq!(format.is_bgr(), memory_order.starts_with('r'));
+        }
+        assert_eq!(PixelFormat::from_gst_format("I420"), None);
+    }
 
     #[test]
     fn validate_rejects_invalid_dimensions() {
         let mut cfg = Config::default();
         cfg.display.width = 0;
-        assert!(cfg.validate().is_err());
+        assert!(matches!(cfg.validate(), Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn max_display_size_clamps_and_validates() {
+        use super::MAX_DISPLAY_WIDTH;
+        let mut cfg = Config::default();
+        cfg.display.max_width = 2560;
+        cfg.display.max_height = 1440;
+        assert!(cfg.validate().is_ok());
+        assert_eq!(cfg.display.clamp_size(1280, 720), Some((1280, 720)));
+        assert_eq!(cfg.display.clamp_size(3840, 1200), Some((2560, 1200)));
+        assert_eq!(cfg.display.clamp_size(0, 720), None);
+
+        cfg.display.max_width = 1280;
+        assert!(matches!(cfg.validate(), Err(Error::Config(_))));
+        cfg.display.max_width = MAX_DISPLAY_WIDTH + 1;
+        assert!(matches!(cfg.validate(), Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn aspect_ratio_lock_fits_inside_request() {
+        let mut cfg = Config::default();
+        assert_eq!(cfg.display.fit_aspect(1000, 1000), (1000, 1000));
+
+        cfg.display.aspect_ratio = Some("16:9".into());
+        assert!(cfg.validate().is_ok());
+        assert_eq!(cfg.display.fit_aspect(1920, 1080), (1920, 1080));
+        // Too wide: height kept
+        assert_eq!(cfg.display.fit_aspect(2560, 1080), (1920, 1080));
+        // Too tall: width kept, height rounded to nearest
+        assert_eq!(cfg.display.fit_aspect(1366, 1024), (1366, 768));
+        assert_eq!(cfg.display.fit_aspect(1000, 1000), (1000, 563));
+
+        cfg.display.aspect_ratio = Some("wide".into());
+        assert!(matches!(cfg.validate(), Err(Error::Config(_))));
+        cfg.display.aspect_ratio = Some("4:0".into());
+        assert!(matches!(cfg.validate(), Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn max_output_scale_is_validated() {
+        let mut cfg = Config::default();
+        assert_eq!(cfg.display.max_output_scale, 1.0);
+        cfg.display.max_output_scale = 2.5;
+        assert!(cfg.validate().is_ok());
+        cfg.display.max_output_scale = 0.5;
+        assert!(matches!(cfg.validate(), Err(Error::Config(_))));
+        cfg.display.max_output_scale = f64::NAN;
+        assert!(matches!(cfg.validate(), Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn ui_hosts_parse_and_must_be_unique() {
+        let ui: UiOverridesConfig = toml::from_str(
+            r#"
+            [[hosts]]
+            host = "kiosk.example.com"
+            show_sidebar = false
+
+            [[hosts]]
+            host = "Kiosk.Example.com:8443"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(ui.hosts[0].show_sidebar, Some(false));
+        assert_eq!(ui.hosts[0].show_stats, None);
+        let mut cfg = Config::default();
+        cfg.ui = ui;
+        assert!(matches!(cfg.validate(), Err(Error::Config(_))));
+        cfg.ui.hosts.pop();
+        assert!(cfg.validate().is_ok());
     }
 
     #[test]
@@ -438,6 +1555,106 @@ mod tests {
         cfg.audio.channels = 3;
         assert!(cfg.validate().is_err());
     }
+
+    #[test]
+    fn validate_rejects_unavailable_cpus() {
+        let mut cfg = Config::default();
+        cfg.server.encode_cpu = crate::cpu_affinity::allowed_cpus().unwrap()[..1].to_vec();
+        assert!(cfg.validate().is_ok());
+        cfg.server.compositor_cpu = vec![100_000];
+        assert!(matches!(cfg.validate(), Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn validate_checks_realtime_priority_only_when_enabled() {
+        let mut cfg = Config::default();
+        cfg.server.realtime_priority = 0;
+        assert!(cfg.validate().is_ok());
+        cfg.server.realtime = super::RealtimePolicy::Fifo;
+        assert!(cfg.validate().is_err());
+        cfg.server.realtime_priority = 50;
+        assert!(cfg.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_empty_audio_source() {
+        let mut cfg = Config::default();
+        cfg.audio.enabled = true;
+        cfg.audio.source = Some(" ".into());
+        assert!(cfg.validate().is_err());
+        cfg.audio.source = Some("ivnc_sink.monitor".into());
+        assert!(cfg.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_checks_fec_loss_only_with_fec() {
+        let mut cfg = Config::default();
+        cfg.audio.enabled = true;
+        cfg.audio.fec_loss_percent = 0;
+        assert!(cfg.validate().is_ok());
+        cfg.audio.fec = true;
+        assert!(cfg.validate().is_err());
+        cfg.audio.fec_loss_percent = 10;
+        assert!(cfg.validate().is_ok());
+    }
+
+    #[test]
+    fn congestion_control_accepts_only_known_mechanisms() {
+        #[derive(serde::Deserialize)]
+        struct Doc {
+            cc: super::CongestionControl,
+        }
+        let parse = |v: &str| toml::from_str::<Doc>(&format!("cc = \"{}\"", v)).map(|d| d.cc);
+        assert_eq!(parse("transport-cc").unwrap(), super::CongestionControl::TransportCc);
+        assert_eq!(parse("goog-remb").unwrap(), super::CongestionControl::GoogRemb);
+        assert!(parse("remb").is_err());
+        assert_eq!(Config::default().webrtc.congestion_control, super::CongestionControl::TransportCc);
+    }
+
+    #[test]
+    fn focus_new_windows_policy() {
+        use super::FocusNewWindows;
+        #[derive(serde::Deserialize)]
+        struct Doc {
+            focus: FocusNewWindows,
+        }
+        let parse = |v: &str| toml::from_str::<Doc>(&format!("focus = \"{}\"", v)).map(|d| d.focus);
+        assert_eq!(parse("if_none_focused").unwrap(), FocusNewWindows::IfNoneFocused);
+        assert!(parse("sometimes").is_err());
+        assert_eq!(Config::default().input.focus_new_windows, FocusNewWindows::Always);
+
+        assert!(FocusNewWindows::Always.should_focus(true));
+        assert!(!FocusNewWindows::Never.should_focus(false));
+        assert!(FocusNewWindows::IfNoneFocused.should_focus(false));
+        assert!(!FocusNewWindows::IfNoneFocused.should_focus(true));
+    }
+
+    #[test]
+    fn validate_keeps_pipeline_latency_within_budget() {
+        let mut cfg = Config::default();
+        cfg.webrtc.pipeline_latency_ms = cfg.webrtc.max_latency_ms;
+        assert!(cfg.validate().is_ok());
+        cfg.webrtc.pipeline_latency_ms += 1;
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_zero_clipboard_limit() {
+        let mut cfg = Config::default();
+        cfg.input.max_clipboard_bytes = 0;
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn validate_accepts_only_text_clipboard_mime_types() {
+        let mut cfg = Config::default();
+        cfg.input.clipboard_mime_types.extend(["STRING".to_string(), "text/html".to_string()]);
+        assert!(cfg.validate().is_ok());
+        cfg.input.clipboard_mime_types.push("image/png".into());
+        assert!(cfg.validate().is_err());
+        cfg.input.clipboard_mime_types.clear();
+        assert!(cfg.validate().is_err());
+    }
 }
 
 fn default_basic_auth_enabled() -> bool {
@@ -468,8 +1685,53 @@ fn default_upload_dir() -> String {
     "~/Desktop".to_string()
 }
 
+fn default_max_clipboard_bytes() -> usize {
+    16 * 1024 * 1024
+}
+
+fn default_clipboard_mime_types() -> Vec<String> {
+    vec!["text/plain;charset=utf-8".into(), "text/plain".into(), "UTF8_STRING".into()]
+}
+
+fn default_mcp_http_enabled() -> bool {
+    true
+}
+
+fn default_mcp_http_path() -> String {
+    "/mcp".to_string()
+}
+
 fn default_video_bitrate() -> u32 { 8000 }
 fn default_video_bitrate_max() -> u32 { 16000 }
 fn default_video_bitrate_min() -> u32 { 1000 }
 fn default_pipeline_latency_ms() -> u32 { 50 }
+fn default_max_latency_ms() -> u32 { 100 }
 fn default_keyframe_interval() -> u32 { 60 }
+fn default_bit_depth() -> u8 { 8 }
+fn default_ice_gathering_timeout_ms() -> u64 { 2000 }
+fn default_ice_max_candidates() -> usize { 1 }
+fn default_http_compression() -> bool { true }
+fn default_signaling_compression() -> bool { true }
+fn default_audio_max_retries() -> u32 { 20 }
+fn default_audio_fec_loss_percent() -> u8 { 10 }
+fn default_max_pending_connections() -> usize { 256 }
+fn default_classify_timeout_ms() -> u64 { 10_000 }
+fn default_partial_peek_ms() -> u64 { 200 }
+fn default_mjpeg_fps() -> u32 { 5 }
+fn default_congestion_downscale_below_kbps() -> u32 { 1500 }
+fn default_congestion_restore_above_kbps() -> u32 { 3000 }
+fn default_congestion_hold_secs() -> u64 { 5 }
+fn default_key_repeat_delay_ms() -> u32 { 500 }
+fn default_key_repeat_rate() -> u32 { 25 }
+fn default_snapshot_quality() -> u8 { 80 }
+fn default_keyframe_replay_delay_ms() -> u64 { 0 }
+fn default_cpu_budget_percent() -> f64 { 90.0 }
+fn default_encode_queue_depth() -> usize { 2 }
+fn default_realtime_priority() -> i32 { 10 }
+fn default_watchdog_timeout_secs() -> u64 { 10 }
+fn default_stats_interval_ms() -> u64 { 1000 }
+fn default_max_display_width() -> u32 { MAX_DISPLAY_WIDTH }
+fn default_max_display_height() -> u32 { MAX_DISPLAY_HEIGHT }
+fn default_resize_debounce_ms() -> u64 { 200 }
+fn default_max_output_scale() -> f64 { 1.0 }
+fn default_keep_windows_in_bounds() -> bool { true }
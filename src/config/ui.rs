@@ -1,8 +1,9 @@
 //! UI configuration derived from runtime config and environment overrides.
 
-use crate::config::Config;
+use crate::config::{Config, UiHostConfig};
 use log::warn;
 use serde::Serialize;
+use std::collections::HashMap;
 use std::env;
 
 #[derive(Debug, Clone, Serialize)]
@@ -15,6 +16,43 @@ pub struct UiConfig {
     pub input: UiInput,
     pub clipboard: UiToggle,
     pub stats: UiToggle,
+    pub capabilities: UiCapabilities,
+}
+
+/// Features the server has enabled, as enforced server-side; the UI hides
+/// controls for the rest. Unaffected by the `IVNC_*` UI overrides.
+#[derive(Debug, Clone, Serialize)]
+pub struct UiCapabilities {
+    pub clipboard: bool,
+    pub binary_clipboard: bool,
+    pub file_upload: bool,
+    pub file_download: bool,
+    pub commands: bool,
+    pub audio: bool,
+    pub mouse: bool,
+    pub keyboard: bool,
+    /// `scale,N` is honored (`display.max_output_scale` above 1)
+    pub output_scale: bool,
+}
+
+impl UiCapabilities {
+    pub fn from_config(config: &Config) -> Self {
+        let input = &config.input;
+        let transfer = |direction: &str| {
+            input.file_transfers.iter().any(|entry| entry.trim().eq_ignore_ascii_case(direction))
+        };
+        Self {
+            clipboard: input.enable_clipboard,
+            binary_clipboard: input.enable_clipboard && input.enable_binary_clipboard,
+            file_upload: transfer("upload"),
+            file_download: transfer("download"),
+            commands: input.enable_commands,
+            audio: config.audio.enabled,
+            mouse: input.enable_mouse,
+            keyboard: input.enable_keyboard,
+            output_scale: config.display.max_output_scale > 1.0,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -145,12 +183,51 @@ impl UiConfig {
                 enabled: clipboard_enabled.value,
             },
             stats: UiToggle { enabled: true },
+            capabilities: UiCapabilities::from_config(config),
         }
     }
 
     pub fn to_json(&self) -> String {
         serde_json::to_string(self).unwrap_or_else(|_| "{}".to_string())
     }
+
+    /// This config with one host's `[[ui.hosts]]` overrides applied
+    pub fn with_host_overrides(&self, host: &UiHostConfig) -> Self {
+        let mut config = self.clone();
+        let ui = &mut config.ui;
+        let overrides = [
+            (&mut ui.show_sidebar, host.show_sidebar),
+            (&mut ui.show_video_settings, host.show_video_settings),
+            (&mut ui.show_screen_settings, host.show_screen_settings),
+            (&mut ui.show_audio_settings, host.show_audio_settings),
+            (&mut ui.show_stats, host.show_stats),
+            (&mut ui.show_clipboard, host.show_clipboard),
+        ];
+        for (field, value) in overrides {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+        config
+    }
+
+    /// Configs for every `[[ui.hosts]]` entry, keyed by normalized host
+    pub fn host_overrides(&self, hosts: &[UiHostConfig]) -> HashMap<String, UiConfig> {
+        hosts
+            .iter()
+            .map(|host| (normalize_host(&host.host), self.with_host_overrides(host)))
+            .collect()
+    }
+}
+
+/// Lowercase a Host header value and strip its port (`[::1]:8008` -> `::1`)
+pub fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or(rest),
+        None => host.split(':').next().unwrap_or(host),
+    };
+    name.to_ascii_lowercase()
 }
 
 fn env_bool(key: &str, default_value: bool) -> UiBool {
@@ -321,3 +398,54 @@ fn parse_range_u32(raw: &str) -> Option<(u32, u32, bool)> {
     let value = raw.trim().parse::<u32>().ok()?;
     Some((value, value, false))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_names_are_normalized() {
+        assert_eq!(normalize_host("Kiosk.Example.com:8443"), "kiosk.example.com");
+        assert_eq!(normalize_host("kiosk.example.com"), "kiosk.example.com");
+        assert_eq!(normalize_host("[::1]:8008"), "::1");
+    }
+
+    #[test]
+    fn host_overrides_keep_unset_fields() {
+        let base = UiConfig::from_env(&Config::default());
+        let host = UiHostConfig {
+            host: "kiosk.example.com".into(),
+            show_sidebar: Some(false),
+            ..Default::default()
+        };
+        let configs = base.host_overrides(&[host]);
+        let kiosk = &configs["kiosk.example.com"];
+        assert!(!kiosk.ui.show_sidebar);
+        assert_eq!(kiosk.ui.show_stats, base.ui.show_stats);
+    }
+
+    #[test]
+    fn capabilities_follow_config() {
+        let mut config = Config::default();
+        let json: serde_json::Value = serde_json::from_str(&UiConfig::from_env(&config).to_json()).unwrap();
+        assert_eq!(json["capabilities"]["clipboard"], true);
+        assert_eq!(json["capabilities"]["file_upload"], true);
+        assert_eq!(json["capabilities"]["output_scale"], false);
+
+        config.input.enable_clipboard = false;
+        config.input.enable_binary_clipboard = true;
+        config.input.file_transfers = vec!["download".into()];
+        config.input.enable_commands = true;
+        config.display.max_output_scale = 2.0;
+        let json: serde_json::Value = serde_json::from_str(&UiConfig::from_env(&config).to_json()).unwrap();
+        let capabilities = &json["capabilities"];
+        assert_eq!(capabilities["clipboard"], false);
+        assert_eq!(capabilities["binary_clipboard"], false);
+        assert_eq!(capabilities["file_upload"], false);
+        assert_eq!(capabilities["file_download"], true);
+        assert_eq!(capabilities["commands"], true);
+        assert_eq!(capabilities["output_scale"], true);
+        // Existing fields are unchanged
+        assert!(json["clipboard"]["enabled"].is_boolean());
+    }
+}
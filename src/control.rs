@@ -0,0 +1,207 @@
+//! Input control arbitration for shared sessions
+//!
+//! With `input.control_policy` set, only the session holding control may
+//! send input; input from every other session is dropped. Sessions ask with
+//! `request_control` and give it up with `release_control` over the
+//! DataChannel, and every change is broadcast as `control,{json}` so UIs can
+//! show who is driving. Policies:
+//!
+//! - `first`: a request succeeds only while nobody holds control.
+//! - `queue`: requests wait in line; release passes control to the next one.
+//! - `operator`: requests are only recorded; control is handed out with the
+//!   `grant_control` MCP tool or by the current holder (`grant_control,{id}`).
+//!
+//! Input, resize requests and keystroke pastes (`ck,`) from WebRTC and
+//! WebTransport sessions all go through the arbiter. When control moves to
+//! another session, the previous holder's keys and buttons are released so
+//! nothing stays pressed. MCP and other local input is never filtered.
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::config::ControlPolicy;
+
+/// `request_control`, `release_control` or `grant_control,{id}`
+pub fn is_control_request(text: &str) -> bool {
+    text == "request_control" || text == "release_control" || text.starts_with("grant_control,")
+}
+
+/// Who holds input control and who is waiting for it
+#[derive(Debug, Default)]
+pub struct ControlArbiter {
+    policy: ControlPolicy,
+    holder: Option<String>,
+    /// Waiting sessions in request order (queued or awaiting the operator)
+    waiting: VecDeque<String>,
+    /// Connected sessions; control can only go to one of these
+    sessions: HashSet<String>,
+}
+
+impl ControlArbiter {
+    pub fn new(policy: ControlPolicy) -> Self {
+        Self { policy, holder: None, waiting: VecDeque::new(), sessions: HashSet::new() }
+    }
+
+    /// Register a connected session
+    pub fn join(&mut self, session: &str) {
+        self.sessions.insert(session.to_string());
+    }
+
+    /// Forget a closed session, releasing control if it held it; true if
+    /// the state changed
+    pub fn leave(&mut self, session: &str) -> bool {
+        self.sessions.remove(session);
+        self.release(session)
+    }
+
+    pub fn knows(&self, session: &str) -> bool {
+        self.sessions.contains(session)
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.policy != ControlPolicy::Off
+    }
+
+    /// Whether input from `session` should be injected
+    pub fn allows(&self, session: &str) -> bool {
+        !self.enabled() || self.holder.as_deref() == Some(session)
+    }
+
+    pub fn holder(&self) -> Option<&str> {
+        self.holder.as_deref()
+    }
+
+    /// Handle `request_control`; true if the state changed
+    pub fn request(&mut self, session: &str) -> bool {
+        if !self.enabled() || !self.knows(session) || self.holder.as_deref() == Some(session) {
+            return false;
+        }
+        match self.policy {
+            ControlPolicy::Off => false,
+            ControlPolicy::First | ControlPolicy::Queue if self.holder.is_none() => {
+                self.holder = Some(session.to_string());
+                true
+            }
+            ControlPolicy::First => false,
+            ControlPolicy::Queue | ControlPolicy::Operator => {
+                if self.waiting.iter().any(|s| s == session) {
+                    return false;
+                }
+                self.waiting.push_back(session.to_string());
+                true
+            }
+        }
+    }
+
+    /// Handle `release_control` or a closed session; true if the state changed
+    pub fn release(&mut self, session: &str) -> bool {
+        let was_waiting = self.waiting.len();
+        self.waiting.retain(|s| s != session);
+        if self.holder.as_deref() != Some(session) {
+            return self.waiting.len() != was_waiting;
+        }
+        self.holder = match self.policy {
+            ControlPolicy::Queue => self.waiting.pop_front(),
+            _ => None,
+        };
+        true
+    }
+
+    /// Give control to `session` (None revokes it). Used by the operator
+    /// and for hand-offs by the current holder. Unknown sessions are refused.
+    pub fn grant(&mut self, session: Option<&str>) -> bool {
+        if !self.enabled() || self.holder.as_deref() == session || session.is_some_and(|s| !self.knows(s)) {
+            return false;
+        }
+        if let Some(session) = session {
+            self.waiting.retain(|s| s != session);
+        }
+        self.holder = session.map(str::to_string);
+        true
+    }
+
+    /// `{"policy":..,"holder":..,"waiting":[..]}`
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "policy": self.policy.as_str(),
+            "holder": self.holder,
+            "waiting": self.waiting,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn off_allows_everyone() {
+        let mut arbiter = ControlArbiter::new(ControlPolicy::Off);
+        arbiter.join("a");
+        assert!(arbiter.allows("a"));
+        assert!(!arbiter.request("a"));
+        assert!(arbiter.allows("b"));
+    }
+
+    #[test]
+    fn first_come_refuses_while_held() {
+        let mut arbiter = ControlArbiter::new(ControlPolicy::First);
+        arbiter.join("a");
+        arbiter.join("b");
+        assert!(!arbiter.allows("a"));
+        assert!(arbiter.request("a"));
+        assert!(!arbiter.request("b"));
+        assert!(arbiter.allows("a") && !arbiter.allows("b"));
+        assert!(arbiter.release("a"));
+        assert_eq!(arbiter.holder(), None);
+        assert!(arbiter.request("b"));
+        assert_eq!(arbiter.holder(), Some("b"));
+    }
+
+    #[test]
+    fn queue_passes_control_in_order() {
+        let mut arbiter = ControlArbiter::new(ControlPolicy::Queue);
+        for session in ["a", "b", "c"] {
+            arbiter.join(session);
+        }
+        arbiter.request("a");
+        arbiter.request("b");
+        arbiter.request("c");
+        assert!(!arbiter.request("b"));
+        // A waiting session that leaves drops out of the line
+        assert!(arbiter.release("b"));
+        assert!(arbiter.release("a"));
+        assert_eq!(arbiter.holder(), Some("c"));
+        assert!(!arbiter.release("x"));
+    }
+
+    #[test]
+    fn operator_grants_explicitly() {
+        let mut arbiter = ControlArbiter::new(ControlPolicy::Operator);
+        arbiter.join("a");
+        assert!(arbiter.request("a"));
+        assert_eq!(arbiter.holder(), None);
+        assert!(arbiter.grant(Some("a")));
+        assert!(arbiter.allows("a"));
+        assert_eq!(arbiter.to_json()["waiting"], serde_json::json!([]));
+        assert!(arbiter.release("a"));
+        assert_eq!(arbiter.holder(), None);
+        assert!(!arbiter.grant(None));
+    }
+
+    #[test]
+    fn unknown_sessions_are_refused() {
+        let mut arbiter = ControlArbiter::new(ControlPolicy::Operator);
+        arbiter.join("a");
+        assert!(!arbiter.grant(Some("typo")));
+        assert!(!arbiter.request("typo"));
+        assert_eq!(arbiter.holder(), None);
+        assert!(arbiter.grant(Some("a")));
+        // A session that left can't be handed control either
+        arbiter.join("b");
+        assert!(!arbiter.leave("b"));
+        assert!(!arbiter.knows("b"));
+        assert!(!arbiter.grant(Some("b")));
+        assert!(arbiter.leave("a"));
+        assert_eq!(arbiter.holder(), None);
+    }
+}
@@ -0,0 +1,98 @@
+//! CPU pinning and real-time scheduling for the compositor loop and
+//! GStreamer streaming threads
+//!
+//! `server.compositor_cpu` pins the thread running the compositor loop and
+//! `server.encode_cpu` pins every GStreamer streaming thread (appsrc, convert,
+//! encoder, payloader). Pinning avoids migration jitter on big and NUMA
+//! machines, but a pinned thread cannot move off a busy core, so the chosen
+//! cores should be kept free of other load (e.g. with `isolcpus`). Software
+//! encoders spread work over their own threads, which inherit the pinning, so
+//! give `encode_cpu` several cores when using x264/vp8.
+//!
+//! `server.realtime` moves the same threads to SCHED_RR or SCHED_FIFO so
+//! frame-timed work is not delayed by normal-priority load. This needs
+//! CAP_SYS_NICE (or an RLIMIT_RTPRIO allowance); without it the threads keep
+//! normal scheduling and a warning is logged.
+
+use std::io;
+
+use crate::config::RealtimePolicy;
+
+/// CPUs this process may run on (its current affinity mask)
+pub fn allowed_cpus() -> io::Result<Vec<usize>> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    let ret = unsafe { libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((0..libc::CPU_SETSIZE as usize).filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) }).collect())
+}
+
+/// Restrict the calling thread to `cpus`; threads it spawns afterwards
+/// inherit the mask
+pub fn pin_current_thread(cpus: &[usize]) -> io::Result<()> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for &cpu in cpus {
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+    // pid 0 is the calling thread for sched_setaffinity
+    let ret = unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Switch the calling thread to real-time scheduling; a no-op for `Off`
+pub fn set_current_thread_realtime(policy: RealtimePolicy, priority: i32) -> io::Result<()> {
+    let policy = match policy {
+        RealtimePolicy::Off => return Ok(()),
+        RealtimePolicy::Rr => libc::SCHED_RR,
+        RealtimePolicy::Fifo => libc::SCHED_FIFO,
+    };
+    let param = libc::sched_param { sched_priority: priority };
+    // pthread_setschedparam returns the error number instead of setting errno
+    match unsafe { libc::pthread_setschedparam(libc::pthread_self(), policy, &param) } {
+        0 => Ok(()),
+        err => Err(io::Error::from_raw_os_error(err)),
+    }
+}
+
+/// Hint appended to real-time scheduling failures
+pub fn realtime_hint(err: &io::Error) -> &'static str {
+    if err.raw_os_error() == Some(libc::EPERM) {
+        " (needs CAP_SYS_NICE or an RLIMIT_RTPRIO limit)"
+    } else {
+        ""
+    }
+}
+
+/// Check a configured CPU list against the CPUs the process may use
+pub fn check_cpus(key: &str, cpus: &[usize], allowed: &[usize]) -> Result<(), String> {
+    match cpus.iter().find(|cpu| !allowed.contains(cpu)) {
+        Some(cpu) => Err(format!(
+            "{}: CPU {} is not available to this process (allowed: {:?})",
+            key, cpu, allowed
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_cpus_outside_the_allowed_set() {
+        let allowed = [0, 1, 2, 3];
+        assert!(check_cpus("server.encode_cpu", &[], &allowed).is_ok());
+        assert!(check_cpus("server.encode_cpu", &[1, 3], &allowed).is_ok());
+        let err = check_cpus("server.encode_cpu", &[2, 8], &allowed).unwrap_err();
+        assert!(err.starts_with("server.encode_cpu: CPU 8"));
+    }
+
+    #[test]
+    fn current_process_has_cpus() {
+        assert!(!allowed_cpus().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+//! Crate-level error type
+//!
+//! Wraps the per-module error enums so library consumers can match on the
+//! failure kind instead of inspecting a boxed trait object.
+
+use std::error::Error as StdError;
+use std::fmt;
+
+use crate::compositor::DisplayError;
+use crate::gstreamer::GstError;
+use crate::webrtc::WebRTCError;
+
+/// Result alias using the crate [`Error`]
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Top-level ivnc error
+#[derive(Debug)]
+pub enum Error {
+    /// Configuration could not be parsed or failed validation
+    Config(String),
+    /// GStreamer pipeline error
+    Gstreamer(GstError),
+    /// WebRTC session/signaling error
+    WebRTC(WebRTCError),
+    /// Compositor / headless display error
+    Display(DisplayError),
+    /// I/O error
+    Io(std::io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(msg) => write!(f, "Configuration error: {}", msg),
+            Error::Gstreamer(e) => write!(f, "GStreamer error: {}", e),
+            Error::WebRTC(e) => write!(f, "WebRTC error: {}", e),
+            Error::Display(e) => write!(f, "Display error: {}", e),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Config(_) => None,
+            Error::Gstreamer(e) => Some(e),
+            Error::WebRTC(e) => Some(e),
+            Error::Display(e) => Some(e),
+            Error::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<GstError> for Error {
+    fn from(e: GstError) -> Self {
+        Error::Gstreamer(e)
+    }
+}
+
+impl From<WebRTCError> for Error {
+    fn from(e: WebRTCError) -> Self {
+        Error::WebRTC(e)
+    }
+}
+
+impl From<DisplayError> for Error {
+    fn from(e: DisplayError) -> Self {
+        Error::Display(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Error::Config(e.to_string())
+    }
+}
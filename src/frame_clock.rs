@@ -0,0 +1,148 @@
+//! Deadline-based frame pacing for the compositor loop
+//!
+//! Deadlines sit on a fixed grid (`start + n * period`) rather than being
+//! measured from the end of the previous frame, so render/encode time does
+//! not accumulate as drift. When a frame overruns its budget the clock skips
+//! the missed slots instead of trying to catch up with a burst of frames.
+//!
+//! [`LatencyBudget`] covers the other side: when the encoder cannot keep up,
+//! frames are dropped before rendering instead of piling up in its queue.
+
+use std::time::{Duration, Instant};
+
+pub struct FrameClock {
+    period: Duration,
+    next: Instant,
+}
+
+impl FrameClock {
+    pub fn new(fps: u32, now: Instant) -> Self {
+        let period = Duration::from_secs(1) / fps.max(1);
+        Self { period, next: now + period }
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Sleep until the next deadline and move to the following one.
+    /// Returns the number of frame slots skipped because the loop overran.
+    pub fn wait(&mut self) -> u64 {
+        let now = Instant::now();
+        if let Some(remaining) = self.next.checked_duration_since(now) {
+            std::thread::sleep(remaining);
+        }
+        self.advance(now)
+    }
+
+    /// Step past the current deadline, skipping any slots that `now` is
+    /// already beyond so the next deadline is always in the future.
+    fn advance(&mut self, now: Instant) -> u64 {
+        if now <= self.next {
+            self.next += self.period;
+            return 0;
+        }
+        let missed = ((now - self.next).as_nanos() / self.period.as_nanos()) as u64;
+        self.next += self.period * (missed as u32 + 1);
+        missed
+    }
+}
+
+/// How often frame drops are logged while they keep happening
+const DROP_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Decides when to drop a frame to keep encoder latency bounded
+pub struct LatencyBudget {
+    budget: Duration,
+    cpu_limit: f64,
+    /// Drops since the last log line
+    unlogged: u64,
+    last_log: Option<Instant>,
+}
+
+impl LatencyBudget {
+    pub fn new(budget_ms: u64, cpu_limit_percent: f64) -> Self {
+        Self {
+            budget: Duration::from_millis(budget_ms),
+            cpu_limit: cpu_limit_percent,
+            unlogged: 0,
+            last_log: None,
+        }
+    }
+
+    /// True if the next frame should be dropped: the frames already waiting
+    /// for the encoder take longer than the budget to drain, or the CPU is
+    /// saturated while anything is still queued.
+    pub fn should_drop(&mut self, queued_frames: u64, period: Duration, cpu_percent: f64) -> bool {
+        let backlog = period.saturating_mul(queued_frames.min(u32::MAX as u64) as u32);
+        let drop = backlog > self.budget || (queued_frames > 0 && cpu_percent >= self.cpu_limit);
+        if drop {
+            self.unlogged += 1;
+        }
+        drop
+    }
+
+    /// Drops to report, at most once per `DROP_LOG_INTERVAL`
+    pub fn take_log(&mut self, now: Instant) -> Option<u64> {
+        if self.unlogged == 0 || self.last_log.is_some_and(|at| now < at + DROP_LOG_INTERVAL) {
+            return None;
+        }
+        self.last_log = Some(now);
+        Some(std::mem::take(&mut self.unlogged))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deadlines_stay_on_the_grid() {
+        let start = Instant::now();
+        let mut clock = FrameClock::new(50, start);
+        // A frame that finished early or late within its slot does not shift later deadlines
+        assert_eq!(clock.advance(start + Duration::from_millis(5)), 0);
+        assert_eq!(clock.next, start + Duration::from_millis(40));
+        assert_eq!(clock.advance(start + Duration::from_millis(40)), 0);
+        assert_eq!(clock.next, start + Duration::from_millis(60));
+    }
+
+    #[test]
+    fn overruns_skip_missed_slots() {
+        let start = Instant::now();
+        let mut clock = FrameClock::new(50, start);
+        // Deadline was 20ms; at 75ms the 40ms and 60ms slots are gone
+        assert_eq!(clock.advance(start + Duration::from_millis(75)), 2);
+        assert_eq!(clock.next, start + Duration::from_millis(80));
+    }
+
+    #[test]
+    fn zero_fps_is_treated_as_one() {
+        assert_eq!(FrameClock::new(0, Instant::now()).period(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn drops_when_the_backlog_exceeds_the_budget() {
+        let period = Duration::from_millis(33);
+        let mut budget = LatencyBudget::new(100, 95.0);
+        assert!(!budget.should_drop(0, period, 50.0));
+        assert!(!budget.should_drop(3, period, 50.0));
+        assert!(budget.should_drop(4, period, 50.0));
+        // Saturated CPU drops as soon as anything is queued
+        assert!(budget.should_drop(1, period, 99.0));
+        assert!(!budget.should_drop(0, period, 99.0));
+    }
+
+    #[test]
+    fn drop_logging_is_throttled() {
+        let start = Instant::now();
+        let mut budget = LatencyBudget::new(0, 100.0);
+        assert_eq!(budget.take_log(start), None);
+        budget.should_drop(1, Duration::from_millis(10), 0.0);
+        budget.should_drop(1, Duration::from_millis(10), 0.0);
+        assert_eq!(budget.take_log(start), Some(2));
+        budget.should_drop(1, Duration::from_millis(10), 0.0);
+        assert_eq!(budget.take_log(start + Duration::from_secs(1)), None);
+        assert_eq!(budget.take_log(start + DROP_LOG_INTERVAL), Some(1));
+    }
+}
@@ -0,0 +1,139 @@
+//! Hand-off of rendered frames to a dedicated encode thread
+//!
+//! With `encoding.encode_queue_depth` above zero the compositor loop does
+//! not push frames into appsrc itself: it queues them for an `ivnc-encode`
+//! thread and goes straight back to input dispatch, so a slow push (buffer
+//! copy, appsrc back-pressure) no longer delays input. The queue is bounded;
+//! when it is full the new frame is dropped, since the next render carries
+//! the latest content anyway. Depth 0 keeps the synchronous push.
+//!
+//! Each job carries the [`FramePusher`] of the pipeline it was rendered for,
+//! so frames still queued across a pipeline rebuild go to the old pipeline
+//! (and fail quietly) instead of reaching an encoder of a different size.
+//!
+//! Behind this queue sits appsrc's own queue (`encoding.appsrc_queue`). With
+//! the default leaky policy appsrc drops its oldest frames once more than
+//! `webrtc.max_latency_ms` of video waits there, so a push never blocks and
+//! this queue rarely fills. With `block` the push waits for the encoder:
+//! every frame reaching appsrc is encoded, and the backlog surfaces here as
+//! dropped new frames (or, at depth 0, as a stalled main loop).
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use gstreamer as gst;
+use log::debug;
+
+use super::FramePusher;
+
+/// Bounded single-producer single-consumer queue drained by a worker thread
+pub struct FrameQueue<T: Send + 'static> {
+    tx: Option<SyncSender<T>>,
+    /// Items queued or being consumed
+    pending: Arc<AtomicUsize>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> FrameQueue<T> {
+    /// Start a worker thread named `name` that passes items to `consume`
+    pub fn spawn(
+        name: &str,
+        depth: usize,
+        mut consume: impl FnMut(T) + Send + 'static,
+    ) -> std::io::Result<Self> {
+        let (tx, rx) = mpsc::sync_channel::<T>(depth.max(1));
+        let pending = Arc::new(AtomicUsize::new(0));
+        let worker_pending = pending.clone();
+        let worker = std::thread::Builder::new().name(name.into()).spawn(move || {
+            for item in rx {
+                consume(item);
+                worker_pending.fetch_sub(1, Ordering::Relaxed);
+            }
+        })?;
+        Ok(Self { tx: Some(tx), pending, worker: Some(worker) })
+    }
+
+    /// Queue an item without blocking; false if the queue was full (or the
+    /// worker is gone) and the item was dropped
+    pub fn submit(&self, item: T) -> bool {
+        let Some(tx) = self.tx.as_ref() else {
+            return false;
+        };
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        match tx.try_send(item) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                self.pending.fetch_sub(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    /// Items handed off but not fully consumed yet
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+}
+
+impl<T: Send + 'static> Drop for FrameQueue<T> {
+    fn drop(&mut self) {
+        // Closing the channel ends the worker once it has drained the queue
+        self.tx.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// A frame for the encode thread, with the running time to stamp it with
+pub type EncodeJob = (FramePusher, Vec<u8>, Option<gst::ClockTime>);
+
+/// Frames queued for the encode thread
+pub type EncodeQueue = FrameQueue<EncodeJob>;
+
+impl EncodeQueue {
+    /// Start the encode thread with room for `depth` frames
+    pub fn new(depth: usize) -> std::io::Result<Self> {
+        Self::spawn("ivnc-encode", depth, |(pusher, pixels, pts): EncodeJob| {
+            if let Err(e) = pusher.push(pixels, pts) {
+                // Expected for frames queued before a pipeline rebuild
+                debug!("Encode thread failed to push frame: {}", e);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn drops_when_full_and_drains_on_drop() {
+        let (gate_tx, gate_rx) = mpsc::channel::<()>();
+        let gate_rx = Mutex::new(gate_rx);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let worker_seen = seen.clone();
+        let queue = FrameQueue::spawn("test-queue", 2, move |item: u32| {
+            // Hold the worker until the test lets it go
+            let _ = gate_rx.lock().unwrap().recv();
+            worker_seen.lock().unwrap().push(item);
+        })
+        .unwrap();
+
+        // Room for two in the channel plus at most one taken by the worker
+        assert!(queue.submit(1));
+        assert!(queue.submit(2));
+        let accepted: Vec<u32> = [1, 2].into_iter().chain((3..10).filter(|&i| queue.submit(i))).collect();
+        assert!(accepted == [1, 2] || accepted == [1, 2, 3], "{:?}", accepted);
+        assert_eq!(queue.pending(), accepted.len());
+
+        for _ in 0..accepted.len() {
+            gate_tx.send(()).unwrap();
+        }
+        drop(queue);
+        assert_eq!(*seen.lock().unwrap(), accepted);
+    }
+}
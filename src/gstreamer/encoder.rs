@@ -313,6 +313,16 @@ impl EncoderSelection {
     }
 }
 
+/// Whether `encoder` writes the input caps' colorimetry into the bitstream
+/// (H.264 VUI, VP9/AV1 color config). Decoders assume limited range for
+/// streams that do not say otherwise.
+pub fn signals_color_range(encoder: &str) -> bool {
+    matches!(
+        encoder,
+        "x264enc" | "nvh264enc" | "vp9enc" | "rav1enc" | "qsvh264enc" | "qsvvp9enc" | "qsvav1enc"
+    )
+}
+
 /// Get a list of all available encoders for diagnostics
 pub fn list_available_encoders() -> Vec<(String, VideoCodec, HardwareEncoder)> {
     let mut result = Vec::new();
@@ -326,6 +336,84 @@ pub fn list_available_encoders() -> Vec<(String, VideoCodec, HardwareEncoder)> {
     result
 }
 
+/// A pipeline dependency satisfied by any one of several elements
+#[derive(Debug, Clone)]
+pub struct ElementRequirement {
+    /// What the element is for, e.g. "H264 encoder"
+    pub role: String,
+    /// Acceptable element names, in preference order
+    pub any_of: Vec<&'static str>,
+}
+
+impl ElementRequirement {
+    fn new(role: impl Into<String>, any_of: Vec<&'static str>) -> Self {
+        Self { role: role.into(), any_of }
+    }
+
+    /// True if at least one candidate element is registered
+    pub fn satisfied(&self) -> bool {
+        self.any_of.iter().any(|name| element_available(name))
+    }
+
+    /// Debian packages providing the candidates (deduplicated, in order)
+    pub fn packages(&self) -> Vec<&'static str> {
+        let mut pkgs: Vec<&'static str> = Vec::new();
+        for name in &self.any_of {
+            let pkg = element_package(name);
+            if !pkgs.contains(&pkg) {
+                pkgs.push(pkg);
+            }
+        }
+        pkgs
+    }
+}
+
+/// Debian package that ships a GStreamer element
+pub fn element_package(name: &str) -> &'static str {
+    match name {
+        "appsrc" | "appsink" | "videoconvert" => "gstreamer1.0-plugins-base",
+        "rtph264pay" | "rtpvp8pay" | "rtpvp9pay" | "vp8enc" | "vp9enc" => "gstreamer1.0-plugins-good",
+        "x264enc" => "gstreamer1.0-plugins-ugly",
+        "rtpav1pay" | "rav1enc" => "gstreamer1.0-plugins-rs",
+        n if n.starts_with("vaapi") => "gstreamer1.0-vaapi",
+        _ => "gstreamer1.0-plugins-bad",
+    }
+}
+
+/// Elements the video pipeline cannot run without for `codec` / `hw_pref`.
+///
+/// With `Auto` any encoder for the codec will do; an explicit preference
+/// requires an encoder of that type.
+pub fn required_elements(codec: VideoCodec, hw_pref: HardwareEncoder) -> Vec<ElementRequirement> {
+    let payloader = match codec {
+        VideoCodec::H264 => "rtph264pay",
+        VideoCodec::VP8 => "rtpvp8pay",
+        VideoCodec::VP9 => "rtpvp9pay",
+        VideoCodec::AV1 => "rtpav1pay",
+    };
+
+    let encoders: Vec<&'static str> = [NVENC_ENCODERS, QSV_ENCODERS, VAAPI_ENCODERS, SOFTWARE_ENCODERS]
+        .iter()
+        .flat_map(|table| table.iter())
+        .filter(|e| e.codec == codec)
+        .filter(|e| hw_pref == HardwareEncoder::Auto || e.encoder_type == hw_pref)
+        .map(|e| e.name)
+        .collect();
+    let encoder_role = if hw_pref == HardwareEncoder::Auto {
+        format!("{:?} encoder", codec)
+    } else {
+        format!("{:?} {:?} encoder", codec, hw_pref)
+    };
+
+    vec![
+        ElementRequirement::new("frame source", vec!["appsrc"]),
+        ElementRequirement::new("RTP sink", vec!["appsink"]),
+        ElementRequirement::new("color conversion", vec!["videoconvert"]),
+        ElementRequirement::new(encoder_role, encoders),
+        ElementRequirement::new(format!("{:?} RTP payloader", codec), vec![payloader]),
+    ]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -341,4 +429,21 @@ mod tests {
         // Should at least fall back to x264enc or similar
         assert!(!selection.info.name.is_empty());
     }
+
+    #[test]
+    fn test_required_elements_follow_preference() {
+        let auto = required_elements(VideoCodec::H264, HardwareEncoder::Auto);
+        let encoder = &auto[3];
+        assert!(encoder.any_of.contains(&"x264enc"));
+        assert!(encoder.any_of.contains(&"nvh264enc"));
+        assert!(auto.iter().any(|r| r.any_of == vec!["rtph264pay"]));
+
+        let nvenc = required_elements(VideoCodec::H264, HardwareEncoder::Nvenc);
+        assert_eq!(nvenc[3].any_of, vec!["nvh264enc", "nvv4l2h264enc"]);
+        assert_eq!(nvenc[3].packages(), vec!["gstreamer1.0-plugins-bad"]);
+
+        // No NVENC VP8 encoder exists, so the requirement can never be met
+        let impossible = required_elements(VideoCodec::VP8, HardwareEncoder::Nvenc);
+        assert!(impossible[3].any_of.is_empty());
+    }
 }
@@ -5,8 +5,10 @@
 
 pub mod pipeline;
 pub mod encoder;
+pub mod encode_queue;
 
-pub use pipeline::{VideoPipeline, PipelineConfig};
+pub use pipeline::{VideoPipeline, PipelineConfig, FramePusher};
+pub use encode_queue::EncodeQueue;
 
 
 use std::error::Error;
@@ -7,8 +7,10 @@
 #![allow(dead_code)]
 //! - RTP packetization for WebRTC
 
-use super::{GstError, encoder::EncoderSelection};
-use crate::config::{VideoCodec, HardwareEncoder, WebRTCConfig};
+use super::{GstError, encoder::{self, EncoderSelection}};
+use crate::config::{
+    AppsrcQueuePolicy, ColorMatrix, ColorRange, PixelFormat, VideoCodec, HardwareEncoder, RealtimePolicy, WebRTCConfig,
+};
 use gstreamer as gst;
 use gstreamer::prelude::*;
 use gstreamer_app as gst_app;
@@ -51,6 +53,42 @@ pub struct PipelineConfig {
     pub keyframe_interval: u32,
     /// Pipeline latency in ms
     pub latency_ms: u32,
+    /// Latency budget (ms); caps the video queued in appsrc
+    pub max_latency_ms: u32,
+    /// What appsrc does when its queue is full
+    pub queue_policy: AppsrcQueuePolicy,
+    /// appsrc queue limit in bytes (0 = none)
+    pub queue_max_bytes: u64,
+    /// Encode at width/divisor x height/divisor (1 = native resolution)
+    pub scale_divisor: u32,
+    /// Client-requested render scale (`s,`), applied on top of the divisor
+    pub render_scale: f64,
+    /// Pin streaming threads to these CPUs (empty = no pinning)
+    pub encode_cpus: Vec<usize>,
+    /// Real-time scheduling for streaming threads (None = normal)
+    pub realtime: Option<(RealtimePolicy, i32)>,
+    /// Color range of the encoded video
+    pub color_range: ColorRange,
+    /// YUV matrix and primaries of the encoded video
+    pub color_matrix: ColorMatrix,
+    /// Bits per sample; 10 where codec and encoder allow, else 8
+    pub bit_depth: u8,
+    /// Layout of the frames pushed into appsrc
+    pub pixel_format: PixelFormat,
+}
+
+impl PipelineConfig {
+    /// Resolution handed to the encoder, rounded down to even numbers for 4:2:0
+    pub fn encoded_size(&self) -> (u32, u32) {
+        let d = self.scale_divisor.max(1);
+        let scale = |v: u32| ((v as f64 * self.render_scale) as u32 / d) & !1;
+        (scale(self.width).max(2), scale(self.height).max(2))
+    }
+
+    /// Whether frames go through videoscale before the encoder
+    pub fn is_scaled(&self) -> bool {
+        self.scale_divisor > 1 || self.render_scale < 1.0
+    }
 }
 
 impl From<&WebRTCConfig> for PipelineConfig {
@@ -64,6 +102,17 @@ impl From<&WebRTCConfig> for PipelineConfig {
             hardware_encoder: config.hardware_encoder,
             keyframe_interval: config.keyframe_interval,
             latency_ms: config.pipeline_latency_ms,
+            max_latency_ms: config.max_latency_ms,
+            queue_policy: AppsrcQueuePolicy::default(),
+            queue_max_bytes: 0,
+            scale_divisor: 1,
+            render_scale: 1.0,
+            encode_cpus: Vec::new(),
+            realtime: None,
+            color_range: config.color_range,
+            color_matrix: config.color_matrix,
+            bit_depth: config.bit_depth,
+            pixel_format: PixelFormat::Xrgb8888,
         }
     }
 }
@@ -79,13 +128,67 @@ impl Default for PipelineConfig {
             hardware_encoder: HardwareEncoder::Auto,
             keyframe_interval: 60,
             latency_ms: 50,
+            max_latency_ms: 100,
+            queue_policy: AppsrcQueuePolicy::default(),
+            queue_max_bytes: 0,
+            scale_divisor: 1,
+            render_scale: 1.0,
+            encode_cpus: Vec::new(),
+            realtime: None,
+            color_range: ColorRange::Full,
+            color_matrix: ColorMatrix::Bt709,
+            bit_depth: 8,
+            pixel_format: PixelFormat::Xrgb8888,
         }
     }
 }
 
+/// GStreamer colorimetry (`range:matrix:transfer:primaries`) for the YUV
+/// handed to the encoder
+pub fn encoder_colorimetry(range: ColorRange, matrix: ColorMatrix) -> String {
+    // GstVideoColorRange: 1 = 0-255, 2 = 16-235
+    let range = match range {
+        ColorRange::Full => 1,
+        ColorRange::Limited => 2,
+    };
+    // GstVideoColorMatrix / GstVideoColorPrimaries; transfer is BT.709 (5)
+    // for both, as in GStreamer's own "bt601" and "bt709"
+    let (matrix, primaries) = match matrix {
+        ColorMatrix::Bt709 => (3, 1),
+        ColorMatrix::Bt601 => (4, 4),
+    };
+    format!("{}:{}:5:{}", range, matrix, primaries)
+}
+
+/// 10-bit raw format to feed an encoder whose sink caps are `sink_caps`,
+/// preferring the semi-planar layout hardware encoders use
+pub fn ten_bit_format(sink_caps: &str) -> Option<&'static str> {
+    ["P010_10LE", "I420_10LE"].into_iter().find(|format| sink_caps.contains(format))
+}
+
 /// RTP packet callback type
 pub type RtpCallback = Box<dyn Fn(&[u8], u32, u64) + Send + Sync>;
 
+/// Feeds frames into one pipeline's appsrc; cheap to clone and `Send`
+#[derive(Clone)]
+pub struct FramePusher {
+    appsrc: gst_app::AppSrc,
+    frame_count: Arc<AtomicU64>,
+}
+
+impl FramePusher {
+    /// Push a raw frame, handing its memory to GStreamer without a copy.
+    /// `pts` is its running time; None lets appsrc stamp it on arrival.
+    pub fn push(&self, data: Vec<u8>, pts: Option<gst::ClockTime>) -> Result<(), GstError> {
+        let mut buffer = gst::Buffer::from_mut_slice(data);
+        buffer.get_mut().unwrap().set_pts(pts);
+        self.appsrc.push_buffer(buffer)
+            .map_err(|e| GstError::PipelineFailed(format!("appsrc push failed: {:?}", e)))?;
+        self.frame_count.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
 /// Video pipeline for GStreamer-based encoding
 pub struct VideoPipeline {
     pipeline: gst::Pipeline,
@@ -95,6 +198,8 @@ pub struct VideoPipeline {
     state: Arc<AtomicBool>,
     frame_count: Arc<AtomicU64>,
     encoder_element: String,
+    /// Bit depth the encoder is fed: 10 only where the 10-bit path exists
+    bit_depth: u8,
 }
 
 impl VideoPipeline {
@@ -104,10 +209,11 @@ impl VideoPipeline {
 
         let pipeline = gst::Pipeline::new();
 
-        // Create appsrc for receiving raw frames from compositor
+        // Create appsrc for receiving raw frames from compositor; the
+        // framebuffer is full-range sRGB
         let caps_str = format!(
-            "video/x-raw,format=BGRx,width={},height={},framerate={}/1",
-            config.width, config.height, config.framerate
+            "video/x-raw,format={},width={},height={},framerate={}/1,colorimetry=sRGB",
+            config.pixel_format.gst_format(), config.width, config.height, config.framerate
         );
         let caps = caps_str.parse::<gst::Caps>()
             .map_err(|e| GstError::PipelineFailed(format!("Invalid caps: {}", e)))?;
@@ -119,8 +225,23 @@ impl VideoPipeline {
             .is_live(true)
             .do_timestamp(true)
             .build();
+        // Queue limit by duration (GStreamer 1.20+), so the budget holds at
+        // any resolution
+        if appsrc.has_property("max-time", None) {
+            appsrc.set_property("max-time", config.max_latency_ms as u64 * 1_000_000);
+        }
+        // 0 would mean no room at all; u64::MAX leaves it to max-time
+        appsrc.set_property("max-bytes", if config.queue_max_bytes == 0 { u64::MAX } else { config.queue_max_bytes });
+        match config.queue_policy {
+            AppsrcQueuePolicy::Block => appsrc.set_property("block", true),
+            // leaky-type is GStreamer 1.20+; older appsrc keeps queueing
+            AppsrcQueuePolicy::Leaky if appsrc.has_property("leaky-type", None) => {
+                appsrc.set_property_from_str("leaky-type", "downstream");
+            }
+            AppsrcQueuePolicy::Leaky => warn!("appsrc has no leaky-type (GStreamer < 1.20); frames will queue"),
+        }
 
-        // videoconvert: BGRx -> I420 for encoder
+        // videoconvert: RGB -> YUV for encoder
         let convert = gst::ElementFactory::make("videoconvert")
             .build()
             .map_err(|e| GstError::PipelineFailed(format!("Failed to create videoconvert: {}", e)))?;
@@ -131,8 +252,66 @@ impl VideoPipeline {
         )?;
         info!("Using encoder: {} for codec {:?}", encoder_name, config.codec);
 
+        // Pin the YUV colorimetry so videoconvert converts with it and the
+        // encoder signals it; full range only where it can be signaled
+        let color_range = if config.color_range == ColorRange::Full
+            && !encoder::signals_color_range(&encoder_name)
+        {
+            info!("{} cannot signal full range, encoding limited range", encoder_name);
+            ColorRange::Limited
+        } else {
+            config.color_range
+        };
+        let colorimetry = encoder_colorimetry(color_range, config.color_matrix);
+        let mut color_caps = format!("video/x-raw,colorimetry={}", colorimetry);
+        let mut bit_depth = 8;
+        if config.bit_depth == 10 {
+            match Self::ten_bit_input(&encoder, config.codec) {
+                Some(format) => {
+                    info!("Encoding 10-bit ({})", format);
+                    color_caps.push_str(&format!(",format={}", format));
+                    bit_depth = 10;
+                }
+                None => warn!(
+                    "{} ({:?}) has no 10-bit input, encoding 8-bit",
+                    encoder_name, config.codec
+                ),
+            }
+        }
+        let color_caps = color_caps
+            .parse::<gst::Caps>()
+            .map_err(|e| GstError::PipelineFailed(format!("Invalid caps: {}", e)))?;
+        let color_filter = gst::ElementFactory::make("capsfilter")
+            .name("colorfilter")
+            .property("caps", &color_caps)
+            .build()
+            .map_err(|e| GstError::PipelineFailed(format!("Failed to create capsfilter: {}", e)))?;
+        debug!(
+            "Encoding {} range {} ({})",
+            color_range.as_str(), config.color_matrix.as_str(), colorimetry
+        );
+
         let payloader = Self::create_payloader(config.codec)?;
 
+        // Optional downscale between convert and encoder
+        let scaler = if config.is_scaled() {
+            let (enc_w, enc_h) = config.encoded_size();
+            let scale = gst::ElementFactory::make("videoscale")
+                .build()
+                .map_err(|e| GstError::PipelineFailed(format!("Failed to create videoscale: {}", e)))?;
+            let scale_caps = format!("video/x-raw,width={},height={}", enc_w, enc_h)
+                .parse::<gst::Caps>()
+                .map_err(|e| GstError::PipelineFailed(format!("Invalid caps: {}", e)))?;
+            let filter = gst::ElementFactory::make("capsfilter")
+                .property("caps", &scale_caps)
+                .build()
+                .map_err(|e| GstError::PipelineFailed(format!("Failed to create capsfilter: {}", e)))?;
+            info!("Encoding downscaled to {}x{}", enc_w, enc_h);
+            Some((scale, filter))
+        } else {
+            None
+        };
+
         let appsink = gst_app::AppSink::builder()
             .name("rtpsink")
             .sync(false)
@@ -143,16 +322,24 @@ impl VideoPipeline {
         pipeline.add_many([
             appsrc.upcast_ref(),
             &convert,
+            &color_filter,
             &encoder,
             &payloader,
             appsink.upcast_ref(),
         ]).map_err(|e| GstError::PipelineFailed(format!("Failed to add elements: {}", e)))?;
 
-        // Link: appsrc -> convert -> encoder -> payloader -> appsink
-        appsrc.upcast_ref::<gst::Element>().link(&convert)
+        // Link: appsrc -> convert -> colorfilter -> [videoscale -> capsfilter] -> encoder -> payloader -> appsink
+        gst::Element::link_many([appsrc.upcast_ref::<gst::Element>(), &convert, &color_filter])
             .map_err(|e| GstError::LinkFailed(format!("appsrc->convert: {}", e)))?;
-        convert.link(&encoder)
-            .map_err(|e| GstError::LinkFailed(format!("convert->encoder: {}", e)))?;
+        if let Some((ref scale, ref filter)) = scaler {
+            pipeline.add_many([scale, filter])
+                .map_err(|e| GstError::PipelineFailed(format!("Failed to add scaler: {}", e)))?;
+            gst::Element::link_many([&color_filter, scale, filter, &encoder])
+                .map_err(|e| GstError::LinkFailed(format!("convert->scale->encoder: {}", e)))?;
+        } else {
+            color_filter.link(&encoder)
+                .map_err(|e| GstError::LinkFailed(format!("convert->encoder: {}", e)))?;
+        }
         encoder.link(&payloader)
             .map_err(|e| GstError::LinkFailed(format!("encoder->payloader: {}", e)))?;
         payloader.link(appsink.upcast_ref::<gst::Element>())
@@ -160,6 +347,45 @@ impl VideoPipeline {
 
         pipeline.set_latency(gst::ClockTime::from_mseconds(config.latency_ms as u64));
 
+        // Streaming threads announce themselves with a stream-status message
+        // posted from the new thread, so pinning or re-prioritising there
+        // affects only that thread
+        if !config.encode_cpus.is_empty() || config.realtime.is_some() {
+            let cpus = config.encode_cpus.clone();
+            let realtime = config.realtime;
+            let realtime_logged = AtomicBool::new(false);
+            if let Some(bus) = pipeline.bus() {
+                bus.set_sync_handler(move |_, msg| {
+                    if let gst::MessageView::StreamStatus(status) = msg.view() {
+                        if status.get().0 == gst::StreamStatusType::Enter {
+                            if !cpus.is_empty() {
+                                if let Err(e) = crate::cpu_affinity::pin_current_thread(&cpus) {
+                                    warn!("Failed to pin streaming thread to CPUs {:?}: {}", cpus, e);
+                                }
+                            }
+                            if let Some((policy, priority)) = realtime {
+                                let result = crate::cpu_affinity::set_current_thread_realtime(policy, priority);
+                                // Report the first thread only; the rest behave the same
+                                if !realtime_logged.swap(true, Ordering::Relaxed) {
+                                    match result {
+                                        Ok(()) => info!(
+                                            "Encode threads use {} priority {}",
+                                            policy.as_str(), priority
+                                        ),
+                                        Err(e) => warn!(
+                                            "Encode threads keep normal scheduling: {}{}",
+                                            e, crate::cpu_affinity::realtime_hint(&e)
+                                        ),
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    gst::BusSyncReply::Pass
+                });
+            }
+        }
+
         Ok(Self {
             pipeline,
             appsrc,
@@ -168,9 +394,20 @@ impl VideoPipeline {
             state: Arc::new(AtomicBool::new(false)),
             frame_count: Arc::new(AtomicU64::new(0)),
             encoder_element: encoder_name,
+            bit_depth,
         })
     }
 
+    /// 10-bit format `encoder` accepts, if `codec` has a 10-bit profile
+    /// browsers decode (VP9 profile 2, AV1 main)
+    fn ten_bit_input(encoder: &gst::Element, codec: VideoCodec) -> Option<&'static str> {
+        if !matches!(codec, VideoCodec::VP9 | VideoCodec::AV1) {
+            return None;
+        }
+        let sink = encoder.static_pad("sink")?;
+        ten_bit_format(&sink.pad_template_caps().to_string())
+    }
+
     /// Create RTP payloader for the specified codec
     fn create_payloader(codec: VideoCodec) -> Result<gst::Element, GstError> {
         let (element_name, pt) = match codec {
@@ -180,8 +417,13 @@ impl VideoPipeline {
             VideoCodec::AV1 => ("rtpav1pay", 99),
         };
 
+        // RTP timestamps are the buffer running time at 90 kHz, with no
+        // random offset, so frames can be matched to the time they were
+        // pushed
         let mut builder = gst::ElementFactory::make(element_name)
-            .property("pt", pt as u32);
+            .property("pt", pt as u32)
+            .property("timestamp-offset", 0u32)
+            .property("perfect-rtptime", false);
 
         // For H264, ensure SPS/PPS are sent regularly for browser decoders.
         if matches!(codec, VideoCodec::H264) {
@@ -218,12 +460,14 @@ impl VideoPipeline {
         Ok(())
     }
 
-    /// Push a raw frame (XRGB8888 / BGRx) into the pipeline via appsrc
-    pub fn push_frame(&self, data: &[u8]) -> Result<(), GstError> {
+    /// Push a raw frame (in `config.pixel_format`) into the pipeline via
+    /// appsrc, stamped with running time `pts` (None: on arrival)
+    pub fn push_frame(&self, data: &[u8], pts: Option<gst::ClockTime>) -> Result<(), GstError> {
         let mut buffer = gst::Buffer::with_size(data.len())
             .map_err(|e| GstError::PipelineFailed(format!("Buffer alloc failed: {}", e)))?;
         {
             let buffer_ref = buffer.get_mut().unwrap();
+            buffer_ref.set_pts(pts);
             let mut map = buffer_ref.map_writable()
                 .map_err(|e| GstError::PipelineFailed(format!("Buffer map failed: {}", e)))?;
             map.copy_from_slice(data);
@@ -234,6 +478,21 @@ impl VideoPipeline {
         Ok(())
     }
 
+    /// Current running time of the pipeline, to stamp a frame with before
+    /// pushing it. The RTP timestamp of the frame follows from it (see
+    /// [`crate::webrtc::media_clock::video_rtp_timestamp`]).
+    pub fn running_time(&self) -> Option<gst::ClockTime> {
+        self.pipeline.current_running_time()
+    }
+
+    /// Handle for pushing frames from another thread
+    pub fn frame_pusher(&self) -> FramePusher {
+        FramePusher {
+            appsrc: self.appsrc.clone(),
+            frame_count: self.frame_count.clone(),
+        }
+    }
+
     /// Pause the pipeline
     pub fn pause(&self) -> Result<(), GstError> {
         self.pipeline
@@ -338,11 +597,27 @@ impl VideoPipeline {
         }
     }
 
+    /// Raw format appsrc advertises to the pipeline, as set in its caps
+    pub fn input_format(&self) -> Option<String> {
+        let caps = self.appsrc.caps()?;
+        let structure = caps.structure(0)?;
+        structure.get::<String>("format").ok()
+    }
+
     /// Get frame count
     pub fn frame_count(&self) -> u64 {
         self.frame_count.load(Ordering::Relaxed)
     }
 
+    /// Frames pushed into appsrc that the encoder has not consumed yet
+    pub fn queued_frames(&self) -> u64 {
+        let frame_bytes = self.config.width as u64 * self.config.height as u64 * 4;
+        if frame_bytes == 0 {
+            return 0;
+        }
+        self.appsrc.current_level_bytes() / frame_bytes
+    }
+
     /// Get pipeline configuration
     pub fn config(&self) -> &PipelineConfig {
         &self.config
@@ -352,6 +627,12 @@ impl VideoPipeline {
     pub fn encoder_name(&self) -> &str {
         &self.encoder_element
     }
+
+    /// Bit depth actually encoded; 8 when 10-bit was configured but the
+    /// encoder has no 10-bit input
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
 }
 
 impl Drop for VideoPipeline {
@@ -370,5 +651,38 @@ mod tests {
         assert_eq!(config.framerate, 30);
         assert_eq!(config.bitrate, 4000);
         assert_eq!(config.codec, VideoCodec::H264);
+        assert_eq!(config.color_range, ColorRange::Full);
+    }
+
+    #[test]
+    fn test_encoded_size_applies_render_scale() {
+        let mut config = PipelineConfig { width: 1920, height: 1080, ..Default::default() };
+        assert!(!config.is_scaled());
+        assert_eq!(config.encoded_size(), (1920, 1080));
+        config.render_scale = 0.5;
+        assert!(config.is_scaled());
+        assert_eq!(config.encoded_size(), (960, 540));
+        // The divisor stacks on top, and odd results round down to even
+        config.scale_divisor = 2;
+        assert_eq!(config.encoded_size(), (480, 270));
+        config.width = 1366;
+        config.scale_divisor = 1;
+        assert_eq!(config.encoded_size(), (682, 540));
+    }
+
+    #[test]
+    fn test_ten_bit_format() {
+        let vp9enc = "video/x-raw, format=(string){ I420, YV12, Y42B, Y444, I420_10LE, I422_10LE }";
+        assert_eq!(ten_bit_format(vp9enc), Some("I420_10LE"));
+        assert_eq!(ten_bit_format("video/x-raw, format=(string){ NV12, P010_10LE }"), Some("P010_10LE"));
+        assert_eq!(ten_bit_format("video/x-raw, format=(string){ I420, NV12 }"), None);
+    }
+
+    #[test]
+    fn test_encoder_colorimetry() {
+        // Same values as GStreamer's named "bt709" and "bt601"
+        assert_eq!(encoder_colorimetry(ColorRange::Limited, ColorMatrix::Bt709), "2:3:5:1");
+        assert_eq!(encoder_colorimetry(ColorRange::Limited, ColorMatrix::Bt601), "2:4:5:4");
+        assert_eq!(encoder_colorimetry(ColorRange::Full, ColorMatrix::Bt709), "1:3:5:1");
     }
 }
@@ -0,0 +1,32 @@
+//! Session and input hooks for embedders
+//!
+//! A [`Hooks`] implementation is stored on `SharedState` and consulted by the
+//! WebRTC session driver and the compositor input loop. Every method has a
+//! no-op default, so implementors only override what they need.
+
+use std::net::SocketAddr;
+
+use crate::input::InputEventData;
+
+/// Observe or veto sessions and input events
+pub trait Hooks: Send + Sync {
+    /// A WebRTC session is about to start driving. Return false to refuse it;
+    /// the connection is dropped and `on_session_closed` is not called.
+    fn on_session_connected(&self, _session_id: &str, _peer: SocketAddr) -> bool {
+        true
+    }
+
+    /// A previously accepted session has ended
+    fn on_session_closed(&self, _session_id: &str) {}
+
+    /// An input event is about to be injected into the compositor.
+    /// Return false to drop it. Runs on the compositor thread, so keep it cheap.
+    fn on_input(&self, _event: &InputEventData) -> bool {
+        true
+    }
+}
+
+/// Default hooks: accept everything
+pub struct NoopHooks;
+
+impl Hooks for NoopHooks {}
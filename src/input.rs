@@ -3,23 +3,48 @@
 //! Defines the input event data structures used by the data channel
 //! and compositor input injection.
 
+use crate::config::PointerAcceleration;
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Lower bound for `mouse_sensitivity`, in config and at runtime
+pub const MIN_MOUSE_SENSITIVITY: f64 = 0.1;
+/// Upper bound for `mouse_sensitivity`, in config and at runtime
+pub const MAX_MOUSE_SENSITIVITY: f64 = 10.0;
+/// Highest evdev keycode the default xkb keymap covers (xkb keycode 255)
+pub const MAX_EVDEV_KEYCODE: u32 = 247;
+/// Per-event speed (px) above which the quadratic curve starts to accelerate
+const ACCEL_KNEE: f64 = 8.0;
+
 /// Input event types
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum InputEvent {
     MouseMove,
     MouseButton,
     MouseWheel,
     Keyboard,
+    /// Raw evdev keycode in `keycode`, bypassing the keysym table (no
+    /// server-side key repeat)
+    KeyboardKeycode,
     KeyboardReset,
+    /// Release every held mouse button (input control changed hands)
+    ButtonReset,
     TextInput,
     Clipboard,
     Ping,
     WindowFocus,
     WindowClose,
+    /// The session ended: release its seat and close its input recording
+    SessionClosed,
 }
 
 /// Input event data passed from WebRTC data channel to compositor
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct InputEventData {
     pub event_type: InputEvent,
     pub mouse_x: i32,
@@ -29,11 +54,19 @@ pub struct InputEventData {
     pub wheel_delta_x: i16,
     pub wheel_delta_y: i16,
     pub keysym: u32,
+    /// Evdev keycode for `KeyboardKeycode`
+    pub keycode: u32,
     pub key_pressed: bool,
     pub button_mask: u32,
     pub text: String,
     pub timestamp: u64,
     pub window_id: u32,
+    /// Session the event came from (None for MCP and other local sources)
+    #[serde(skip)]
+    pub session_id: Option<Arc<str>>,
+    /// Re-injected from a recording, so not recorded again
+    #[serde(skip)]
+    pub replayed: bool,
 }
 
 impl Default for InputEventData {
@@ -47,11 +80,289 @@ impl Default for InputEventData {
             wheel_delta_x: 0,
             wheel_delta_y: 0,
             keysym: 0,
+            keycode: 0,
             key_pressed: false,
             button_mask: 0,
             text: String::new(),
             timestamp: 0,
             window_id: 0,
+            session_id: None,
+            replayed: false,
+        }
+    }
+}
+
+/// Scale one relative motion event by the sensitivity and acceleration curve.
+///
+/// With `Quadratic`, events moving less than `ACCEL_KNEE` pixels keep the
+/// linear gain so precise aiming is unaffected; faster ones get an extra
+/// gain of `speed / ACCEL_KNEE`, making distance grow with speed squared.
+pub fn accelerate(dx: f64, dy: f64, sensitivity: f64, curve: PointerAcceleration) -> (f64, f64) {
+    let mut gain = sensitivity;
+    if curve == PointerAcceleration::Quadratic {
+        let speed = dx.hypot(dy);
+        if speed > ACCEL_KNEE {
+            gain *= speed / ACCEL_KNEE;
+        }
+    }
+    (dx * gain, dy * gain)
+}
+
+/// Map a pointer position from the encoded stream, which is what the browser
+/// sees, back to the display. Identity when the stream is not scaled.
+pub fn stream_to_display(x: i32, y: i32, stream: (u32, u32), display: (u32, u32)) -> (i32, i32) {
+    if stream == display || stream.0 == 0 || stream.1 == 0 {
+        return (x, y);
+    }
+    let map = |v: i32, from: u32, to: u32| (v as f64 * to as f64 / from as f64).round() as i32;
+    (map(x, stream.0, display.0), map(y, stream.1, display.1))
+}
+
+/// Check if a character requires Shift to type on a US keyboard.
+pub fn char_needs_shift(c: char) -> bool {
+    matches!(c,
+        '~' | '!' | '@' | '#' | '$' | '%' | '^' | '&' | '*' | '(' | ')' |
+        '_' | '+' | '{' | '}' | '|' | ':' | '"' | '<' | '>' | '?' |
+        'A'..='Z'
+    )
+}
+
+/// Get the unshifted base character for a shifted character on US keyboard.
+pub fn get_unshifted_char(c: char) -> char {
+    match c {
+        '~' => '`', '!' => '1', '@' => '2', '#' => '3', '$' => '4',
+        '%' => '5', '^' => '6', '&' => '7', '*' => '8', '(' => '9',
+        ')' => '0', '_' => '-', '+' => '=', '{' => '[', '}' => ']',
+        '|' => '\\', ':' => ';', '"' => '\'', '<' => ',', '>' => '.',
+        '?' => '/',
+        c if c.is_ascii_uppercase() => c.to_ascii_lowercase(),
+        other => other,
+    }
+}
+
+/// Convert a single character to its X11 keysym.
+pub fn char_to_keysym(c: char) -> u32 {
+    match c {
+        // ASCII printable range maps directly
+        ' '..='~' => c as u32,
+        // For Unicode characters outside ASCII, use Unicode keysym encoding
+        _ => 0x01000000 | (c as u32),
+    }
+}
+
+/// Text longer than this is slow to type (~80 ms per character); callers
+/// log a warning and should prefer the clipboard path.
+pub const KEYSTROKE_WARN_CHARS: usize = 500;
+
+/// One step of typing text as keystrokes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Keystroke {
+    /// Press and release `keysym`, holding Shift around it if needed
+    Key { keysym: u32, shift: bool },
+    /// Characters with no key on a US layout, committed via text input
+    Text(String),
+}
+
+/// Split text into key presses for US-layout ASCII plus text-input runs
+/// for everything else. Newlines become Return and tabs Tab; `\r` is dropped.
+pub fn plan_keystrokes(text: &str) -> Vec<Keystroke> {
+    let mut steps = Vec::new();
+    for c in text.chars() {
+        let key = match c {
+            '\n' => Some((0xff0d, false)),
+            '\t' => Some((0xff09, false)),
+            '\r' => continue,
+            ' '..='~' => {
+                let shift = char_needs_shift(c);
+                let base = if shift { get_unshifted_char(c) } else { c };
+                Some((char_to_keysym(base), shift))
+            }
+            _ => None,
+        };
+        match (key, steps.last_mut()) {
+            (Some((keysym, shift)), _) => steps.push(Keystroke::Key { keysym, shift }),
+            (None, Some(Keystroke::Text(run))) => run.push(c),
+            (None, _) => steps.push(Keystroke::Text(c.to_string())),
+        }
+    }
+    steps
+}
+
+/// Type `text` as individual key events through the compositor input queue.
+/// Uses the same timing as the MCP `keyboard_type` tool so apps that debounce
+/// input keep up. Typing for a `session` (`ck,`) is tagged with it and stops
+/// once the session no longer has input control; local (MCP) typing passes
+/// None.
+pub async fn type_keystrokes(state: &crate::web::SharedState, session: Option<&Arc<str>>, text: &str) {
+    let send = |event_type: InputEvent, keysym: u32, pressed: bool, text: String| {
+        let _ = state.input_sender.send(InputEventData {
+            event_type,
+            keysym,
+            key_pressed: pressed,
+            text,
+            session_id: session.cloned(),
+            ..Default::default()
+        });
+    };
+    let send_key = |keysym: u32, pressed: bool| send(InputEvent::Keyboard, keysym, pressed, String::new());
+    for step in plan_keystrokes(text) {
+        if session.is_some_and(|id| !state.input_allowed(id)) {
+            return;
+        }
+        match step {
+            Keystroke::Key { keysym, shift } => {
+                if shift { send_key(0xffe1, true); }
+                send_key(keysym, true);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                send_key(keysym, false);
+                if shift { send_key(0xffe1, false); }
+                tokio::time::sleep(Duration::from_millis(30)).await;
+            }
+            Keystroke::Text(run) => {
+                send(InputEvent::TextInput, 0, false, run);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
+
+/// Server-side key auto-repeat.
+///
+/// Remote input arrives as discrete press/release events, so the held key is
+/// tracked here and `due` reports how many repeat presses to synthesize.
+/// Only the most recently pressed key repeats, matching desktop behaviour.
+pub struct KeyRepeater {
+    delay: Duration,
+    interval: Duration,
+    /// (keysym, time of the next repeat)
+    held: Option<(u32, Instant)>,
+}
+
+impl KeyRepeater {
+    pub fn new(delay_ms: u32, rate: u32) -> Self {
+        Self {
+            delay: Duration::from_millis(delay_ms as u64),
+            interval: Duration::from_secs(1) / rate.max(1),
+            held: None,
         }
     }
+
+    pub fn press(&mut self, keysym: u32, now: Instant) {
+        if is_modifier_keysym(keysym) {
+            return;
+        }
+        self.held = Some((keysym, now + self.delay));
+    }
+
+    pub fn release(&mut self, keysym: u32) {
+        if self.held.is_some_and(|(held, _)| held == keysym) {
+            self.held = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.held = None;
+    }
+
+    /// Held keysym and the number of repeats due by `now`
+    pub fn due(&mut self, now: Instant) -> Option<(u32, u32)> {
+        let (keysym, next) = self.held?;
+        if now < next {
+            return None;
+        }
+        let missed = ((now - next).as_nanos() / self.interval.as_nanos().max(1)) as u32;
+        let count = missed + 1;
+        self.held = Some((keysym, next + self.interval * count));
+        Some((keysym, count))
+    }
+}
+
+/// Shift, Control, Caps/Shift Lock, Meta, Alt, Super and Hyper never repeat
+fn is_modifier_keysym(keysym: u32) -> bool {
+    (0xffe1..=0xffee).contains(&keysym)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_stream_coordinates_to_display() {
+        // Unscaled stream passes through
+        assert_eq!(stream_to_display(640, 360, (1920, 1080), (1920, 1080)), (640, 360));
+        // `s,0.5`: half-size stream, doubled back
+        assert_eq!(stream_to_display(480, 270, (960, 540), (1920, 1080)), (960, 540));
+        assert_eq!(stream_to_display(959, 539, (960, 540), (1920, 1080)), (1918, 1078));
+        // Even rounding of the encoded size leaves a non-integer ratio
+        assert_eq!(stream_to_display(682, 384, (682, 384), (1366, 768)), (1366, 768));
+        assert_eq!(stream_to_display(341, 0, (682, 384), (1366, 768)), (683, 0));
+        // Unknown stream size
+        assert_eq!(stream_to_display(10, 20, (0, 0), (1920, 1080)), (10, 20));
+    }
+
+    #[test]
+    fn plans_shifted_keys_newlines_and_text_runs() {
+        assert_eq!(
+            plan_keystrokes("aB!\r\n"),
+            vec![
+                Keystroke::Key { keysym: 0x61, shift: false },
+                Keystroke::Key { keysym: 0x62, shift: true },
+                Keystroke::Key { keysym: 0x31, shift: true },
+                Keystroke::Key { keysym: 0xff0d, shift: false },
+            ]
+        );
+        assert_eq!(
+            plan_keystrokes("x你好y"),
+            vec![
+                Keystroke::Key { keysym: 0x78, shift: false },
+                Keystroke::Text("你好".into()),
+                Keystroke::Key { keysym: 0x79, shift: false },
+            ]
+        );
+    }
+
+    #[test]
+    fn held_key_repeats_after_delay_until_release() {
+        let start = Instant::now();
+        let mut repeater = KeyRepeater::new(500, 25);
+        repeater.press(0xff54, start); // Down
+        assert_eq!(repeater.due(start + Duration::from_millis(499)), None);
+        assert_eq!(repeater.due(start + Duration::from_millis(500)), Some((0xff54, 1)));
+        assert_eq!(repeater.due(start + Duration::from_millis(520)), None);
+        // 40ms interval: a late poll catches up on the missed repeats
+        assert_eq!(repeater.due(start + Duration::from_millis(625)), Some((0xff54, 3)));
+        repeater.release(0xff54);
+        assert_eq!(repeater.due(start + Duration::from_secs(5)), None);
+    }
+
+    #[test]
+    fn modifiers_and_other_releases_do_not_affect_repeat() {
+        let start = Instant::now();
+        let mut repeater = KeyRepeater::new(100, 10);
+        repeater.press(0x61, start); // a
+        repeater.press(0xffe1, start); // Shift_L
+        repeater.release(0x62);
+        assert_eq!(repeater.due(start + Duration::from_millis(100)), Some((0x61, 1)));
+    }
+
+    #[test]
+    fn linear_applies_sensitivity_only() {
+        assert_eq!(accelerate(10.0, -4.0, 1.0, PointerAcceleration::Linear), (10.0, -4.0));
+        assert_eq!(accelerate(10.0, -4.0, 0.5, PointerAcceleration::Linear), (5.0, -2.0));
+        assert_eq!(accelerate(100.0, 0.0, 2.0, PointerAcceleration::Linear), (200.0, 0.0));
+    }
+
+    #[test]
+    fn quadratic_leaves_slow_moves_alone() {
+        assert_eq!(accelerate(3.0, 4.0, 1.0, PointerAcceleration::Quadratic), (3.0, 4.0));
+        assert_eq!(accelerate(8.0, 0.0, 1.5, PointerAcceleration::Quadratic), (12.0, 0.0));
+    }
+
+    #[test]
+    fn quadratic_boosts_fast_moves_and_keeps_direction() {
+        let (x, y) = accelerate(16.0, 0.0, 1.0, PointerAcceleration::Quadratic);
+        assert_eq!((x, y), (32.0, 0.0));
+        let (x, y) = accelerate(-24.0, 32.0, 1.0, PointerAcceleration::Quadratic);
+        assert_eq!((x, y), (-120.0, 160.0));
+    }
 }
@@ -0,0 +1,338 @@
+//! Input recording and replay
+//!
+//! With `input.record_dir` set, every injected input event is appended to a
+//! JSON Lines file per session (`<session>-<unix time>.jsonl`; MCP and other
+//! local input goes to `local-…`). The first line is a header, each further
+//! line `{"t": ms since the recording started, "event": {...}}`.
+//!
+//! `input.record_redaction` keeps typed secrets out of the files: `text`
+//! drops text input and clipboard contents, `keys` additionally drops
+//! printable key presses unless Control, Alt, Meta or Super is held, so
+//! shortcuts survive but typed passwords do not. Replays of redacted
+//! recordings therefore do not retype text.
+//!
+//! Recordings are replayed against a running server through
+//! `POST /api/input/replay` (enabled with `input.allow_replay`), e.g. with
+//! `ivnc --replay <file>`. Replayed events go through the local input path,
+//! so control arbitration does not filter them; they are not recorded again.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::config::RecordRedaction;
+use crate::input::{InputEvent, InputEventData};
+
+/// Recording format version written in the header
+const FORMAT_VERSION: u32 = 1;
+
+/// First line of a recording
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordingHeader {
+    pub version: u32,
+    pub session: String,
+    pub started_at_ms: u64,
+    pub redaction: RecordRedaction,
+}
+
+/// One recorded event
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// Milliseconds since the recording started
+    pub t: u64,
+    pub event: InputEventData,
+}
+
+struct Recording {
+    out: BufWriter<File>,
+    start: Instant,
+    /// Shortcut modifiers currently held
+    modifiers: HashSet<u32>,
+    /// Keys whose press was recorded, so their release is kept too
+    pressed: HashSet<u32>,
+}
+
+/// Writes injected input to per-session recordings
+pub struct InputRecorder {
+    dir: PathBuf,
+    redaction: RecordRedaction,
+    /// None marks a session whose file could not be created
+    sessions: HashMap<String, Option<Recording>>,
+}
+
+impl InputRecorder {
+    pub fn new(dir: PathBuf, redaction: RecordRedaction) -> Self {
+        Self { dir, redaction, sessions: HashMap::new() }
+    }
+
+    /// Append an injected event to its session's recording
+    pub fn record(&mut self, ev: &InputEventData) {
+        if ev.replayed || matches!(ev.event_type, InputEvent::Ping | InputEvent::SessionClosed) {
+            return;
+        }
+        let session = ev.session_id.as_deref().unwrap_or("local");
+        if !self.sessions.contains_key(session) {
+            let recording = self.open(session);
+            self.sessions.insert(session.to_string(), recording);
+        }
+        let Some(Some(recording)) = self.sessions.get_mut(session) else {
+            return;
+        };
+        let Some(event) = redact(ev, self.redaction, &mut recording.modifiers, &mut recording.pressed) else {
+            return;
+        };
+        let line = RecordedEvent { t: recording.start.elapsed().as_millis() as u64, event };
+        let written = serde_json::to_writer(&mut recording.out, &line)
+            .map_err(std::io::Error::from)
+            .and_then(|_| recording.out.write_all(b"\n"));
+        if let Err(e) = written {
+            warn!("Input recording for {} failed, stopping it: {}", session, e);
+            self.sessions.insert(session.to_string(), None);
+        }
+    }
+
+    /// Flush buffered events to disk
+    pub fn flush(&mut self) {
+        for recording in self.sessions.values_mut().flatten() {
+            let _ = recording.out.flush();
+        }
+    }
+
+    /// Finish a closed session's recording
+    pub fn close(&mut self, session: &str) {
+        if let Some(Some(mut recording)) = self.sessions.remove(session) {
+            let _ = recording.out.flush();
+            info!("Input recording for {} closed", session);
+        }
+    }
+
+    fn open(&self, session: &str) -> Option<Recording> {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        // Session ids come from clients' signaling; keep file names tame
+        let name: String = session
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .collect();
+        let path = self.dir.join(format!("{}-{}.jsonl", name, started.as_secs()));
+        // Recordings may hold typed input: readable by the owner only
+        let opened = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(&path));
+        let mut out = match opened {
+            Ok(file) => BufWriter::new(file),
+            Err(e) => {
+                warn!("Cannot create input recording {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        let header = RecordingHeader {
+            version: FORMAT_VERSION,
+            session: session.to_string(),
+            started_at_ms: started.as_millis() as u64,
+            redaction: self.redaction,
+        };
+        serde_json::to_writer(&mut out, &header).ok()?;
+        out.write_all(b"\n").ok()?;
+        info!("Recording input of {} to {}", session, path.display());
+        Some(Recording {
+            out,
+            start: Instant::now(),
+            modifiers: HashSet::new(),
+            pressed: HashSet::new(),
+        })
+    }
+}
+
+/// Control, Alt, Meta and Super: keys typed while one is held are shortcuts
+fn is_shortcut_modifier(keysym: u32) -> bool {
+    matches!(keysym, 0xffe3 | 0xffe4 | 0xffe7..=0xffec)
+}
+
+/// Keysyms that type a character (Latin-1 and Unicode keysyms)
+fn is_printable_keysym(keysym: u32) -> bool {
+    (0x20..=0xff).contains(&keysym) || keysym >= 0x0100_0000
+}
+
+/// The event as it should be recorded, or None to leave it out
+fn redact(
+    ev: &InputEventData,
+    redaction: RecordRedaction,
+    modifiers: &mut HashSet<u32>,
+    pressed: &mut HashSet<u32>,
+) -> Option<InputEventData> {
+    let mut event = ev.clone();
+    if redaction == RecordRedaction::None {
+        return Some(event);
+    }
+    match event.event_type {
+        InputEvent::TextInput => return None,
+        InputEvent::Clipboard => event.text.clear(),
+        // Raw keycodes can't be told apart from typed text
+        InputEvent::KeyboardKeycode if redaction == RecordRedaction::Keys => return None,
+        InputEvent::Keyboard if redaction == RecordRedaction::Keys => {
+            let keysym = event.keysym;
+            if is_shortcut_modifier(keysym) {
+                if event.key_pressed {
+                    modifiers.insert(keysym);
+                } else {
+                    modifiers.remove(&keysym);
+                }
+            } else if is_printable_keysym(keysym) {
+                if event.key_pressed {
+                    if modifiers.is_empty() {
+                        return None;
+                    }
+                    pressed.insert(keysym);
+                } else if !pressed.remove(&keysym) {
+                    return None;
+                }
+            }
+        }
+        _ => {}
+    }
+    Some(event)
+}
+
+/// Parse a recording; the header line is optional
+pub fn parse_recording(text: &str) -> Result<Vec<RecordedEvent>, String> {
+    let mut events = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if index == 0 {
+            if let Ok(header) = serde_json::from_str::<RecordingHeader>(line) {
+                if header.version > FORMAT_VERSION {
+                    return Err(format!("unsupported recording version {}", header.version));
+                }
+                continue;
+            }
+        }
+        let event = serde_json::from_str(line).map_err(|e| format!("line {}: {}", index + 1, e))?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+/// Re-inject recorded events with their original timing divided by `speed`
+pub async fn replay(sender: UnboundedSender<InputEventData>, events: Vec<RecordedEvent>, speed: f64) {
+    let start = tokio::time::Instant::now();
+    let count = events.len();
+    for mut recorded in events {
+        recorded.event.replayed = true;
+        let at = Duration::from_secs_f64(recorded.t as f64 / 1000.0 / speed);
+        tokio::time::sleep_until(start + at).await;
+        if sender.send(recorded.event).is_err() {
+            return;
+        }
+    }
+    info!("Input replay finished ({} events)", count);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(keysym: u32, pressed: bool) -> InputEventData {
+        InputEventData {
+            event_type: InputEvent::Keyboard,
+            keysym,
+            key_pressed: pressed,
+            ..Default::default()
+        }
+    }
+
+    fn recorded(events: &[InputEventData], redaction: RecordRedaction) -> Vec<(u32, bool)> {
+        let (mut modifiers, mut pressed) = (HashSet::new(), HashSet::new());
+        events
+            .iter()
+            .filter_map(|ev| redact(ev, redaction, &mut modifiers, &mut pressed))
+            .map(|ev| (ev.keysym, ev.key_pressed))
+            .collect()
+    }
+
+    #[test]
+    fn key_redaction_keeps_shortcuts_only() {
+        // "a", then Ctrl+c with Ctrl released before c, then Return
+        let events = [
+            key(0x61, true),
+            key(0x61, false),
+            key(0xffe3, true),
+            key(0x63, true),
+            key(0xffe3, false),
+            key(0x63, false),
+            key(0xff0d, true),
+        ];
+        assert_eq!(
+            recorded(&events, RecordRedaction::Keys),
+            [(0xffe3, true), (0x63, true), (0xffe3, false), (0x63, false), (0xff0d, true)]
+        );
+        assert_eq!(recorded(&events, RecordRedaction::Text).len(), events.len());
+    }
+
+    #[test]
+    fn key_redaction_drops_raw_keycodes() {
+        let keycode = InputEventData {
+            event_type: InputEvent::KeyboardKeycode,
+            keycode: 79,
+            key_pressed: true,
+            ..Default::default()
+        };
+        let (mut modifiers, mut pressed) = (HashSet::new(), HashSet::new());
+        assert!(redact(&keycode, RecordRedaction::Keys, &mut modifiers, &mut pressed).is_none());
+        assert!(redact(&keycode, RecordRedaction::Text, &mut modifiers, &mut pressed).is_some());
+    }
+
+    #[test]
+    fn text_redaction_drops_typed_text() {
+        let text = InputEventData {
+            event_type: InputEvent::TextInput,
+            text: "hunter2".into(),
+            ..Default::default()
+        };
+        let (mut modifiers, mut pressed) = (HashSet::new(), HashSet::new());
+        assert!(redact(&text, RecordRedaction::Text, &mut modifiers, &mut pressed).is_none());
+        let kept = redact(&text, RecordRedaction::None, &mut modifiers, &mut pressed).unwrap();
+        assert_eq!(kept.text, "hunter2");
+    }
+
+    #[test]
+    fn recordings_are_private_and_skip_replays() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("ivnc-rec-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut recorder = InputRecorder::new(dir.clone(), RecordRedaction::None);
+        recorder.record(&InputEventData { replayed: true, ..key(0x61, true) });
+        assert!(!dir.exists());
+        recorder.record(&key(0x61, true));
+        recorder.close("local");
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().path()).collect();
+        assert_eq!(files.len(), 1);
+        let mode = std::fs::metadata(&files[0]).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recordings_round_trip() {
+        let header = r#"{"version":1,"session":"s1","started_at_ms":0,"redaction":"keys"}"#;
+        let line = serde_json::to_string(&RecordedEvent { t: 40, event: key(0xff0d, true) }).unwrap();
+        let events = parse_recording(&format!("{}\n{}\n\n", header, line)).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].t, 40);
+        assert_eq!(events[0].event.event_type, InputEvent::Keyboard);
+        assert_eq!(events[0].event.keysym, 0xff0d);
+        // Missing fields take their defaults
+        let events = parse_recording(r#"{"t":5,"event":{"event_type":"mouse_move","mouse_x":3}}"#).unwrap();
+        assert_eq!(events[0].event.mouse_x, 3);
+        assert!(parse_recording("{\"t\":1}").is_err());
+    }
+}
@@ -0,0 +1,214 @@
+//! Latency percentiles over a rolling window
+//!
+//! [`LatencyHistogram`] is a fixed-size bucketed histogram in the spirit of
+//! HDR histograms: 0.1 ms buckets up to 12.8 ms, then 32 buckets per power of
+//! two (about 3% precision) up to several minutes. Recording is a bucket
+//! increment with no allocation, so it can run per frame.
+//!
+//! [`RollingLatency`] keeps the current and the previous window and reports
+//! percentiles over both, so values always cover between one and two
+//! windows of samples and old spikes age out.
+
+use std::time::{Duration, Instant};
+
+/// Histogram resolution: one unit is 100 µs
+const UNITS_PER_MS: f64 = 10.0;
+/// Values below this (in units) get one bucket each
+const LINEAR_LIMIT: u32 = 128;
+/// Buckets per power of two above the linear range
+const SUB_BUCKETS: u32 = 32;
+/// log2 of `LINEAR_LIMIT`
+const LINEAR_BITS: u32 = 7;
+/// Largest power of two tracked; larger values land in the last bucket
+const MAX_BITS: u32 = 22;
+const BUCKETS: usize = (LINEAR_LIMIT + (MAX_BITS - LINEAR_BITS) * SUB_BUCKETS) as usize;
+
+/// Window after which `RollingLatency` rolls over
+pub const LATENCY_WINDOW: Duration = Duration::from_secs(30);
+
+/// p50/p95/p99 in milliseconds; zero with no samples
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize)]
+pub struct LatencyPercentiles {
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub samples: u64,
+}
+
+#[derive(Clone)]
+pub struct LatencyHistogram {
+    counts: [u32; BUCKETS],
+    total: u64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self { counts: [0; BUCKETS], total: 0 }
+    }
+
+    pub fn record(&mut self, ms: f64) {
+        let units = (ms.max(0.0) * UNITS_PER_MS) as u64;
+        let index = bucket_index(units.min(u32::MAX as u64) as u32);
+        self.counts[index] = self.counts[index].saturating_add(1);
+        self.total += 1;
+    }
+
+    pub fn reset(&mut self) {
+        self.counts = [0; BUCKETS];
+        self.total = 0;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn bucket_index(units: u32) -> usize {
+    if units < LINEAR_LIMIT {
+        return units as usize;
+    }
+    let bits = (31 - units.leading_zeros()).min(MAX_BITS - 1);
+    if bits == MAX_BITS - 1 && units >> (MAX_BITS - 1) > 1 {
+        return BUCKETS - 1;
+    }
+    // Top 6 bits of the value: the leading one plus 5 bits of sub-bucket
+    let sub = (units >> (bits - 5)) - SUB_BUCKETS;
+    (LINEAR_LIMIT + (bits - LINEAR_BITS) * SUB_BUCKETS + sub) as usize
+}
+
+/// Midpoint of a bucket, in milliseconds
+fn bucket_value_ms(index: usize) -> f64 {
+    let index = index as u32;
+    if index < LINEAR_LIMIT {
+        return index as f64 / UNITS_PER_MS;
+    }
+    let bits = (index - LINEAR_LIMIT) / SUB_BUCKETS + LINEAR_BITS;
+    let sub = (index - LINEAR_LIMIT) % SUB_BUCKETS;
+    let width = 1u64 << (bits - 5);
+    let low = (SUB_BUCKETS + sub) as u64 * width;
+    (low as f64 + width as f64 / 2.0) / UNITS_PER_MS
+}
+
+/// Percentile `p` (0-100) over the combined counts of `histograms`
+fn percentile(histograms: &[&LatencyHistogram], total: u64, p: f64) -> f64 {
+    let rank = ((p / 100.0) * total as f64).ceil().max(1.0) as u64;
+    let mut seen = 0u64;
+    for index in 0..BUCKETS {
+        seen += histograms.iter().map(|h| h.counts[index] as u64).sum::<u64>();
+        if seen >= rank {
+            return bucket_value_ms(index);
+        }
+    }
+    0.0
+}
+
+/// Two-window histogram with periodic roll-over
+pub struct RollingLatency {
+    current: LatencyHistogram,
+    previous: LatencyHistogram,
+    window: Duration,
+    window_start: Instant,
+}
+
+impl RollingLatency {
+    pub fn new(window: Duration, now: Instant) -> Self {
+        Self {
+            current: LatencyHistogram::new(),
+            previous: LatencyHistogram::new(),
+            window,
+            window_start: now,
+        }
+    }
+
+    pub fn record(&mut self, ms: f64, now: Instant) {
+        self.roll(now);
+        self.current.record(ms);
+    }
+
+    pub fn percentiles(&mut self, now: Instant) -> LatencyPercentiles {
+        self.roll(now);
+        let total = self.current.count() + self.previous.count();
+        if total == 0 {
+            return LatencyPercentiles::default();
+        }
+        let histograms = [&self.current, &self.previous];
+        LatencyPercentiles {
+            p50: percentile(&histograms, total, 50.0),
+            p95: percentile(&histograms, total, 95.0),
+            p99: percentile(&histograms, total, 99.0),
+            samples: total,
+        }
+    }
+
+    fn roll(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if elapsed < self.window {
+            return;
+        }
+        if elapsed >= self.window * 2 {
+            // Idle for more than a whole window: everything is stale
+            self.previous.reset();
+        } else {
+            std::mem::swap(&mut self.previous, &mut self.current);
+        }
+        self.current.reset();
+        self.window_start = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_are_monotonic_and_precise() {
+        let mut last = 0;
+        for units in (0..5_000_000u32).step_by(7) {
+            let index = bucket_index(units);
+            assert!(index >= last && index < BUCKETS);
+            last = index;
+            if (1000..4_000_000).contains(&units) {
+                let ms = units as f64 / UNITS_PER_MS;
+                let error = (bucket_value_ms(index) - ms).abs() / ms;
+                assert!(error < 0.04, "{} ms -> {}", ms, bucket_value_ms(index));
+            }
+        }
+        assert_eq!(bucket_index(u32::MAX), BUCKETS - 1);
+    }
+
+    #[test]
+    fn percentiles_of_a_skewed_distribution() {
+        let start = Instant::now();
+        let mut latency = RollingLatency::new(Duration::from_secs(10), start);
+        for i in 0..1000 {
+            // 98% around 5 ms, a 2% tail at 200 ms
+            latency.record(if i % 50 == 0 { 200.0 } else { 5.0 }, start);
+        }
+        let p = latency.percentiles(start);
+        assert_eq!(p.samples, 1000);
+        assert!((p.p50 - 5.0).abs() < 0.1);
+        assert!((p.p95 - 5.0).abs() < 0.1);
+        assert!((p.p99 - 200.0).abs() < 8.0);
+    }
+
+    #[test]
+    fn old_samples_roll_out() {
+        let start = Instant::now();
+        let window = Duration::from_secs(10);
+        let mut latency = RollingLatency::new(window, start);
+        latency.record(100.0, start);
+        // Still visible one window later, as the previous window
+        latency.record(1.0, start + window);
+        assert_eq!(latency.percentiles(start + window).samples, 2);
+        // Gone after another window
+        assert_eq!(latency.percentiles(start + window * 2).samples, 1);
+        // And everything after a long idle period
+        assert_eq!(latency.percentiles(start + window * 5), LatencyPercentiles::default());
+    }
+}
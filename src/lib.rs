@@ -1,6 +1,27 @@
 //! iVnc - WebRTC streaming core
 //!
 //! A high-performance WebRTC streaming solution using smithay Wayland compositor and GStreamer.
+//!
+//! # Embedding
+//!
+//! ```no_run
+//! use ivnc::{Config, Server};
+//!
+//! fn main() -> ivnc::Result<()> {
+//!     let server = Server::builder(Config::default())
+//!         .on_stats(|stats| println!("fps={:.1} bandwidth={}bps", stats.fps, stats.bandwidth))
+//!         .build()?;
+//!
+//!     let shutdown = server.shutdown_handle();
+//!     std::thread::spawn(move || {
+//!         std::thread::sleep(std::time::Duration::from_secs(60));
+//!         shutdown.shutdown();
+//!     });
+//!
+//!     // Blocks until shut down; use `run_async().await` from async code
+//!     server.run()
+//! }
+//! ```
 
 pub mod config;
 pub mod audio;
@@ -10,15 +31,33 @@ pub mod file_upload;
 pub mod runtime_settings;
 pub mod transport;
 pub mod input;
+pub mod input_recording;
+pub mod control;
 pub mod web;
 pub mod compositor;
 pub mod gstreamer;
 pub mod webrtc;
 pub mod pake_apps;
+pub mod error;
+pub mod hooks;
+pub mod overlay;
+pub mod frame_clock;
+pub mod latency_histogram;
+pub mod cpu_affinity;
+pub mod watchdog;
+pub mod resource_usage;
+#[cfg(feature = "gpu-metrics")]
+pub mod gpu_usage;
+pub mod server;
+#[cfg(feature = "tls")]
+pub mod cert_store;
 #[cfg(feature = "mcp")]
 pub mod mcp;
 
 // Re-exports
+pub use error::{Error, Result};
+pub use hooks::Hooks;
+pub use server::{Server, ServerBuilder, ShutdownHandle};
 pub use config::{Config, WebRTCConfig, VideoCodec, HardwareEncoder};
 pub use input::{InputEvent, InputEventData};
 pub use gstreamer::{VideoPipeline, PipelineConfig};
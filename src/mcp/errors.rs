@@ -0,0 +1,113 @@
+//! Structured error data for MCP tool failures.
+//!
+//! Every tool error carries `data.code`, a stable machine-readable failure
+//! mode agents can branch on, next to the human-readable message. Extra
+//! context (bounds, limits) is merged into `data` alongside the code.
+
+use rmcp::ErrorData as McpError;
+use serde_json::{json, Value};
+
+/// Failure modes reported in `data.code`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolErrorCode {
+    /// Coordinates or a region fall outside the display
+    OutOfBounds,
+    /// The frame could not be captured or encoded
+    CaptureFailed,
+    /// Input needs a keyboard-focused window and none has focus
+    NotFocused,
+    /// The caller's scope does not allow the tool
+    PermissionDenied,
+    /// A parameter is malformed or out of range
+    InvalidArgument,
+    /// The compositor or a required feature is not available right now
+    Unavailable,
+    /// Anything else
+    Internal,
+}
+
+impl ToolErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ToolErrorCode::OutOfBounds => "out_of_bounds",
+            ToolErrorCode::CaptureFailed => "capture_failed",
+            ToolErrorCode::NotFocused => "not_focused",
+            ToolErrorCode::PermissionDenied => "permission_denied",
+            ToolErrorCode::InvalidArgument => "invalid_argument",
+            ToolErrorCode::Unavailable => "unavailable",
+            ToolErrorCode::Internal => "internal",
+        }
+    }
+}
+
+/// Build a tool error with `data.code` set. The JSON-RPC error code follows
+/// the failure mode: bad input is `invalid_params`, refusals are
+/// `invalid_request`, the rest `internal_error`.
+pub fn tool_error(code: ToolErrorCode, message: impl Into<String>, details: Option<Value>) -> McpError {
+    let mut data = json!({ "code": code.as_str() });
+    if let Some(Value::Object(details)) = details {
+        for (key, value) in details {
+            if key != "code" {
+                data[key] = value;
+            }
+        }
+    }
+    let message = message.into();
+    match code {
+        ToolErrorCode::OutOfBounds | ToolErrorCode::InvalidArgument => McpError::invalid_params(message, Some(data)),
+        ToolErrorCode::NotFocused | ToolErrorCode::PermissionDenied => McpError::invalid_request(message, Some(data)),
+        ToolErrorCode::CaptureFailed | ToolErrorCode::Unavailable | ToolErrorCode::Internal => {
+            McpError::internal_error(message, Some(data))
+        }
+    }
+}
+
+/// `map_err` adapter: wrap a plain error message with `code`
+pub fn with_code<E: std::fmt::Display>(code: ToolErrorCode) -> impl Fn(E) -> McpError {
+    move |e| tool_error(code, e.to_string(), None)
+}
+
+/// Tag an error with the index of the `run_sequence` step that caused it
+pub fn at_step(mut err: McpError, step: usize) -> McpError {
+    if let Some(Value::Object(data)) = err.data.as_mut() {
+        data.insert("step".into(), json!(step));
+    }
+    err.message = format!("step {}: {}", step, err.message).into();
+    err
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_data_carries_code_and_details() {
+        let err = tool_error(
+            ToolErrorCode::OutOfBounds,
+            "coordinates (5000, 10) out of bounds (1920x1080)",
+            Some(json!({ "x": 5000, "y": 10, "code": "ignored" })),
+        );
+        assert_eq!(err.code, McpError::invalid_params("", None).code);
+        assert_eq!(err.message, "coordinates (5000, 10) out of bounds (1920x1080)");
+        let data = err.data.unwrap();
+        assert_eq!(data["code"], "out_of_bounds");
+        assert_eq!(data["x"], 5000);
+    }
+
+    #[test]
+    fn test_failure_modes_map_to_jsonrpc_codes() {
+        let denied = tool_error(ToolErrorCode::PermissionDenied, "no", None);
+        assert_eq!(denied.code, McpError::invalid_request("", None).code);
+        assert_eq!(denied.data.unwrap(), json!({ "code": "permission_denied" }));
+        let capture = with_code::<String>(ToolErrorCode::CaptureFailed)("frame capture timed out (2s)".into());
+        assert_eq!(capture.code, McpError::internal_error("", None).code);
+        assert_eq!(capture.data.unwrap()["code"], "capture_failed");
+    }
+
+    #[test]
+    fn test_step_index_added_to_data() {
+        let err = at_step(tool_error(ToolErrorCode::InvalidArgument, "unknown button: side", None), 3);
+        assert_eq!(err.message, "step 3: unknown button: side");
+        assert_eq!(err.data.unwrap(), json!({ "code": "invalid_argument", "step": 3 }));
+    }
+}
@@ -26,6 +26,12 @@ pub async fn capture_frame(
         .map_err(|_| "compositor dropped frame capture request".to_string())
 }
 
+/// Convert XRGB8888 pixel buffer to raw JPEG bytes at full resolution.
+pub fn xrgb_to_jpeg(width: u32, height: u32, xrgb: &[u8], quality: u8) -> Result<Vec<u8>, String> {
+    let img = xrgb_to_rgb_image(width, height, xrgb)?;
+    encode_jpeg(&img, quality)
+}
+
 /// Convert XRGB8888 pixel buffer to JPEG, returning base64-encoded string.
 /// If the result exceeds `max_bytes`, downscale and re-encode.
 pub fn xrgb_to_jpeg_base64(
@@ -35,18 +41,7 @@ pub fn xrgb_to_jpeg_base64(
     quality: u8,
     max_bytes: usize,
 ) -> Result<String, String> {
-    use image::{ImageBuffer, RgbImage};
-
-    // Convert XRGB8888 → RGB
-    let mut rgb_buf: Vec<u8> = Vec::with_capacity((width * height * 3) as usize);
-    for pixel in xrgb.chunks_exact(4) {
-        rgb_buf.push(pixel[2]); // R  (XRGB8888 LE memory: [B, G, R, X])
-        rgb_buf.push(pixel[1]); // G
-        rgb_buf.push(pixel[0]); // B
-    }
-
-    let img: RgbImage = ImageBuffer::from_raw(width, height, rgb_buf)
-        .ok_or("failed to create image buffer")?;
+    let img = xrgb_to_rgb_image(width, height, xrgb)?;
 
     // First attempt at original resolution
     let jpeg = encode_jpeg(&img, quality)?;
@@ -69,6 +64,91 @@ pub fn xrgb_to_jpeg_base64(
     Ok(base64::engine::general_purpose::STANDARD.encode(&jpeg))
 }
 
+/// Convert an XRGB8888 pixel buffer to an RGB image.
+pub fn xrgb_to_rgb_image(width: u32, height: u32, xrgb: &[u8]) -> Result<image::RgbImage, String> {
+    use image::ImageBuffer;
+
+    let mut rgb_buf: Vec<u8> = Vec::with_capacity((width * height * 3) as usize);
+    for pixel in xrgb.chunks_exact(4) {
+        rgb_buf.push(pixel[2]); // R  (XRGB8888 LE memory: [B, G, R, X])
+        rgb_buf.push(pixel[1]); // G
+        rgb_buf.push(pixel[0]); // B
+    }
+
+    ImageBuffer::from_raw(width, height, rgb_buf)
+        .ok_or_else(|| "failed to create image buffer".to_string())
+}
+
+/// Crop an XRGB8888 frame to `(x, y, w, h)`, clamped to the frame bounds.
+/// Returns (cropped_width, cropped_height, pixels).
+pub fn crop_xrgb(
+    width: u32,
+    height: u32,
+    xrgb: &[u8],
+    region: (u32, u32, u32, u32),
+) -> Result<(u32, u32, Vec<u8>), String> {
+    let (x, y, w, h) = region;
+    let expected = width as usize * height as usize * 4;
+    if xrgb.len() < expected {
+        return Err(format!(
+            "frame buffer is {} bytes, expected {} for {}x{}",
+            xrgb.len(), expected, width, height
+        ));
+    }
+    if x >= width || y >= height {
+        return Err(format!("region origin ({}, {}) out of bounds ({}x{})", x, y, width, height));
+    }
+    let w = w.min(width - x);
+    let h = h.min(height - y);
+    if w == 0 || h == 0 {
+        return Err("region is empty".into());
+    }
+    let stride = width as usize * 4;
+    let mut out = Vec::with_capacity(w as usize * h as usize * 4);
+    for row in y..y + h {
+        let start = row as usize * stride + x as usize * 4;
+        out.extend_from_slice(&xrgb[start..start + w as usize * 4]);
+    }
+    Ok((w, h, out))
+}
+
+/// Convert an XRGB8888 frame to RGB, downscaled to at most `max_width`
+/// pixels wide
+pub fn xrgb_to_rgb_image_max_width(
+    width: u32,
+    height: u32,
+    xrgb: &[u8],
+    max_width: u32,
+) -> Result<image::RgbImage, String> {
+    let img = xrgb_to_rgb_image(width, height, xrgb)?;
+    if width <= max_width {
+        return Ok(img);
+    }
+    let new_h = ((height as u64 * max_width as u64) / width as u64).max(1) as u32;
+    Ok(image::imageops::resize(&img, max_width, new_h, image::imageops::FilterType::Triangle))
+}
+
+/// Encode frames as a looping animated GIF, returning base64. Each frame is
+/// shown for `delay_ms`.
+pub fn frames_to_gif_base64(frames: Vec<image::RgbImage>, delay_ms: u32) -> Result<String, String> {
+    use image::codecs::gif::{GifEncoder, Repeat};
+    use image::{Delay, DynamicImage, Frame};
+
+    let mut buf = Vec::new();
+    {
+        let mut encoder = GifEncoder::new_with_speed(&mut buf, 10);
+        encoder.set_repeat(Repeat::Infinite)
+            .map_err(|e| format!("GIF encode failed: {}", e))?;
+        for img in frames {
+            let rgba = DynamicImage::ImageRgb8(img).into_rgba8();
+            let frame = Frame::from_parts(rgba, 0, 0, Delay::from_numer_denom_ms(delay_ms, 1));
+            encoder.encode_frame(frame)
+                .map_err(|e| format!("GIF encode failed: {}", e))?;
+        }
+    }
+    Ok(base64::engine::general_purpose::STANDARD.encode(&buf))
+}
+
 fn encode_jpeg<P, C>(img: &image::ImageBuffer<P, C>, quality: u8) -> Result<Vec<u8>, String>
 where
     P: image::Pixel<Subpixel = u8> + image::PixelWithColorType + 'static,
@@ -83,3 +163,29 @@ where
         .map_err(|e| format!("JPEG encode failed: {}", e))?;
     Ok(buf.into_inner())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crop_clamps_region_and_checks_buffer_length() {
+        // 3x2 frame, each pixel's first byte is its index
+        let frame: Vec<u8> = (0..6u8).flat_map(|i| [i, 0, 0, 0]).collect();
+        let (w, h, pixels) = crop_xrgb(3, 2, &frame, (1, 0, 5, 5)).unwrap();
+        assert_eq!((w, h), (2, 2));
+        assert_eq!(pixels.chunks(4).map(|p| p[0]).collect::<Vec<_>>(), [1, 2, 4, 5]);
+
+        assert!(crop_xrgb(3, 2, &frame, (3, 0, 1, 1)).is_err());
+        assert!(crop_xrgb(3, 2, &frame[..20], (0, 0, 1, 1)).is_err());
+    }
+
+    #[test]
+    fn frames_are_downscaled_to_max_width() {
+        let frame = vec![0u8; 400 * 100 * 4];
+        let img = xrgb_to_rgb_image_max_width(400, 100, &frame, 160).unwrap();
+        assert_eq!(img.dimensions(), (160, 40));
+        let img = xrgb_to_rgb_image_max_width(400, 100, &frame, 1280).unwrap();
+        assert_eq!(img.dimensions(), (400, 100));
+    }
+}
@@ -1,5 +1,7 @@
 //! Key name → X11 keysym mapping and combo key parsing for MCP keyboard tools.
 
+pub use crate::input::{char_needs_shift, char_to_keysym, get_unshifted_char};
+
 /// Parse a key combination string like "Ctrl+c" or "Alt+F4" into
 /// (modifier_keysyms, main_keysym).
 pub fn parse_key_combo(key: &str) -> Result<(Vec<u32>, u32), String> {
@@ -24,28 +26,6 @@ pub fn parse_key_combo(key: &str) -> Result<(Vec<u32>, u32), String> {
     Ok((modifiers, main_sym))
 }
 
-/// Check if a character requires Shift to type on a US keyboard.
-pub fn char_needs_shift(c: char) -> bool {
-    matches!(c,
-        '~' | '!' | '@' | '#' | '$' | '%' | '^' | '&' | '*' | '(' | ')' |
-        '_' | '+' | '{' | '}' | '|' | ':' | '"' | '<' | '>' | '?' |
-        'A'..='Z'
-    )
-}
-
-/// Get the unshifted base character for a shifted character on US keyboard.
-pub fn get_unshifted_char(c: char) -> char {
-    match c {
-        '~' => '`', '!' => '1', '@' => '2', '#' => '3', '$' => '4',
-        '%' => '5', '^' => '6', '&' => '7', '*' => '8', '(' => '9',
-        ')' => '0', '_' => '-', '+' => '=', '{' => '[', '}' => ']',
-        '|' => '\\', ':' => ';', '"' => '\'', '<' => ',', '>' => '.',
-        '?' => '/',
-        c if c.is_ascii_uppercase() => c.to_ascii_lowercase(),
-        other => other,
-    }
-}
-
 /// Get keysym for a modifier name (case-insensitive).
 fn modifier_keysym(name: &str) -> Option<u32> {
     match name.to_lowercase().as_str() {
@@ -109,13 +89,3 @@ pub fn get_keysym(name: &str) -> Option<u32> {
         _ => None,
     }
 }
-
-/// Convert a single character to its X11 keysym.
-pub fn char_to_keysym(c: char) -> u32 {
-    match c {
-        // ASCII printable range maps directly
-        ' '..='~' => c as u32,
-        // For Unicode characters outside ASCII, use Unicode keysym encoding
-        _ => 0x01000000 | (c as u32),
-    }
-}
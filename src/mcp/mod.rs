@@ -3,9 +3,16 @@
 //! Exposes desktop control tools (screenshot, mouse, keyboard, clipboard,
 //! window management) via the MCP protocol over stdio or Streamable HTTP.
 
+pub mod errors;
 pub mod frame_capture;
 pub mod keyboard;
+pub mod scopes;
+#[cfg(feature = "ocr")]
+pub mod ocr;
+#[cfg(feature = "template-match")]
+pub mod template_match;
 pub mod tools;
+pub mod windows;
 
 use std::sync::Arc;
 use rmcp::{
@@ -16,22 +23,79 @@ use rmcp::{
     handler::server::tool::ToolCallContext,
 };
 use base64::Engine;
+use crate::clipboard::ClipboardContent;
 use crate::web::SharedState;
 use crate::input::{InputEvent, InputEventData};
+use errors::{at_step, tool_error, with_code, ToolErrorCode};
 use tools::*;
 
+/// Text longer than this (in chars) is pasted instead of typed when method is "auto"
+const PASTE_THRESHOLD_CHARS: usize = 200;
+/// Most steps one `run_sequence` call may run
+const MAX_SEQUENCE_STEPS: usize = 50;
+/// Longest single `wait` step, and the cap on all waits in a sequence
+const MAX_SEQUENCE_WAIT_MS: u64 = 10_000;
+const MAX_SEQUENCE_TOTAL_WAIT_MS: u64 = 30_000;
+/// Most characters the `type` steps of a sequence may type in total; typed
+/// as key events they take ~80 ms each
+const MAX_SEQUENCE_TEXT_CHARS: usize = 500;
+
+/// Raw keycodes an MCP session holds down (`keyboard_keycode` with
+/// `action=press`). Shared by every clone of the session's server; the
+/// last one to drop, when the session ends, releases what is still held.
+struct HeldKeycodes {
+    keys: std::sync::Mutex<std::collections::BTreeSet<u32>>,
+    input_sender: tokio::sync::mpsc::UnboundedSender<InputEventData>,
+}
+
+impl HeldKeycodes {
+    fn update(&self, keycode: u32, pressed: bool) {
+        let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        if pressed {
+            keys.insert(keycode);
+        } else {
+            keys.remove(&keycode);
+        }
+    }
+}
+
+impl Drop for HeldKeycodes {
+    fn drop(&mut self) {
+        // Runs while unwinding too: a poisoned set is still worth releasing
+        for keycode in std::mem::take(self.keys.get_mut().unwrap_or_else(|e| e.into_inner())) {
+            let _ = self.input_sender.send(InputEventData {
+                event_type: InputEvent::KeyboardKeycode,
+                keycode,
+                key_pressed: false,
+                ..Default::default()
+            });
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct McpServer {
     pub state: Arc<SharedState>,
+    held_keycodes: Arc<HeldKeycodes>,
     #[allow(dead_code)]
     tool_router: ToolRouter<Self>,
 }
 
 impl McpServer {
     pub fn new(state: Arc<SharedState>) -> Self {
+        let tool_router = Self::tool_router();
+        #[cfg(feature = "ocr")]
+        let tool_router = tool_router + Self::ocr_router();
+        #[cfg(feature = "template-match")]
+        let tool_router = tool_router + Self::template_match_router();
+        let held_keycodes = Arc::new(HeldKeycodes {
+            keys: Default::default(),
+            input_sender: state.input_sender.clone(),
+        });
         Self {
             state,
-            tool_router: Self::tool_router(),
+            held_keycodes,
+            tool_router,
         }
     }
 }
@@ -41,14 +105,21 @@ impl McpServer {
     fn validate_coords(&self, x: i32, y: i32) -> Result<(), McpError> {
         let (w, h) = self.state.display_size();
         if x < 0 || y < 0 || x >= w as i32 || y >= h as i32 {
-            return Err(McpError::invalid_params(
+            return Err(tool_error(
+                ToolErrorCode::OutOfBounds,
                 format!("coordinates ({}, {}) out of bounds ({}x{})", x, y, w, h),
-                None,
+                Some(serde_json::json!({ "x": x, "y": y, "width": w, "height": h })),
             ));
         }
         Ok(())
     }
 
+    /// Hold for the whole event sequence of an input tool so concurrent
+    /// calls can't interleave (e.g. leak a modifier into another combo).
+    async fn input_sequence(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.state.mcp_input_lock.lock().await
+    }
+
     fn send_key(&self, keysym: u32, pressed: bool) {
         let _ = self.state.input_sender.send(InputEventData {
             event_type: InputEvent::Keyboard,
@@ -70,6 +141,62 @@ impl McpServer {
         tokio::time::sleep(std::time::Duration::from_millis(30)).await;
     }
 
+    /// Check every step of a `run_sequence` before any runs, so a bad step
+    /// can't leave the desktop with half a sequence applied. Returns the
+    /// parsed combo of each `key` step.
+    fn validate_sequence(&self, steps: &[SequenceStep]) -> Result<Vec<Option<(Vec<u32>, u32)>>, McpError> {
+        if steps.is_empty() || steps.len() > MAX_SEQUENCE_STEPS {
+            return Err(tool_error(
+                ToolErrorCode::InvalidArgument,
+                format!("sequence has {} steps; expected 1-{}", steps.len(), MAX_SEQUENCE_STEPS),
+                Some(serde_json::json!({ "max_steps": MAX_SEQUENCE_STEPS })),
+            ));
+        }
+        let mut combos = Vec::with_capacity(steps.len());
+        let (mut total_wait, mut total_chars) = (0, 0);
+        for (i, step) in steps.iter().enumerate() {
+            let mut combo = None;
+            match step {
+                SequenceStep::Move { x, y } => self.validate_coords(*x, *y).map_err(|e| at_step(e, i))?,
+                SequenceStep::Click { x, y, button, .. } => {
+                    self.validate_coords(*x, *y).map_err(|e| at_step(e, i))?;
+                    Self::parse_button(button).map_err(|e| at_step(e, i))?;
+                }
+                SequenceStep::Type { text, .. } => total_chars += text.chars().count(),
+                SequenceStep::Key { key } => {
+                    combo = Some(keyboard::parse_key_combo(key)
+                        .map_err(|e| at_step(tool_error(ToolErrorCode::InvalidArgument, e, None), i))?);
+                }
+                SequenceStep::Wait { ms } => {
+                    if *ms > MAX_SEQUENCE_WAIT_MS {
+                        return Err(at_step(tool_error(
+                            ToolErrorCode::InvalidArgument,
+                            format!("wait of {} ms exceeds {} ms", ms, MAX_SEQUENCE_WAIT_MS),
+                            None,
+                        ), i));
+                    }
+                    total_wait += ms;
+                }
+            }
+            combos.push(combo);
+        }
+        if total_wait > MAX_SEQUENCE_TOTAL_WAIT_MS {
+            return Err(tool_error(
+                ToolErrorCode::InvalidArgument,
+                format!("sequence waits {} ms in total; the limit is {} ms", total_wait, MAX_SEQUENCE_TOTAL_WAIT_MS),
+                None,
+            ));
+        }
+        if total_chars > MAX_SEQUENCE_TEXT_CHARS {
+            return Err(tool_error(
+                ToolErrorCode::InvalidArgument,
+                format!("sequence types {} chars in total; the limit is {} (use keyboard_type for long text)", total_chars, MAX_SEQUENCE_TEXT_CHARS),
+                None,
+            ));
+        }
+        Ok(combos)
+    }
+
     fn send_text_input(&self, text: &str) {
         let _ = self.state.input_sender.send(InputEventData {
             event_type: InputEvent::TextInput,
@@ -78,9 +205,100 @@ impl McpServer {
         });
     }
 
+    /// Typed text needs a target; fail with `not_focused` rather than
+    /// sending keystrokes nowhere.
+    async fn require_focus(&self) -> Result<(), McpError> {
+        let (window, _) = windows::query_focus(&self.state).await
+            .map_err(with_code(ToolErrorCode::Unavailable))?;
+        if window.is_none() {
+            return Err(tool_error(ToolErrorCode::NotFocused, "no window has keyboard focus", None));
+        }
+        Ok(())
+    }
+
+    /// Tool scope for the caller, from the `X-MCP-Token` header on HTTP
+    /// (stdio callers have no headers and get the default scope).
+    fn caller_scope(&self, context: &RequestContext<RoleServer>) -> scopes::ToolScope {
+        let token = context.extensions
+            .get::<axum::http::request::Parts>()
+            .and_then(|parts| parts.headers.get(scopes::TOKEN_HEADER))
+            .and_then(|value| value.to_str().ok());
+        scopes::resolve(&self.state.config.mcp, token)
+    }
+
+    fn parse_button(button: &str) -> Result<u8, McpError> {
+        match button {
+            "left" => Ok(0), "middle" => Ok(1), "right" => Ok(2),
+            other => Err(tool_error(ToolErrorCode::InvalidArgument, format!("unknown button: {}", other), None)),
+        }
+    }
+
+    async fn click_at(&self, x: i32, y: i32, button: u8, double: bool) {
+        // Move cursor to click position first — the compositor button handler
+        // uses the pointer's current location, not the event coordinates.
+        let _ = self.state.input_sender.send(InputEventData {
+            event_type: InputEvent::MouseMove, mouse_x: x, mouse_y: y, ..Default::default()
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        let clicks = if double { 2 } else { 1 };
+        for i in 0..clicks {
+            if i > 0 { tokio::time::sleep(std::time::Duration::from_millis(50)).await; }
+            let _ = self.state.input_sender.send(InputEventData {
+                event_type: InputEvent::MouseButton, mouse_x: x, mouse_y: y,
+                mouse_button: button, button_pressed: true, ..Default::default()
+            });
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            let _ = self.state.input_sender.send(InputEventData {
+                event_type: InputEvent::MouseButton, mouse_x: x, mouse_y: y,
+                mouse_button: button, button_pressed: false, ..Default::default()
+            });
+        }
+    }
+
+    /// Type `text` as key events, or via text input (IME) when it has
+    /// characters the keysym table can't produce. Returns the method used.
+    async fn type_text(&self, text: &str) -> &'static str {
+        if Self::text_is_ascii_typeable(text) {
+            for c in text.chars() { self.type_char(c).await; }
+            "key events"
+        } else {
+            self.send_text_input(text);
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            "text input"
+        }
+    }
+
     fn text_is_ascii_typeable(text: &str) -> bool {
         text.chars().all(|c| c.is_ascii() && !c.is_ascii_control())
     }
+
+    async fn press_combo(&self, modifiers: &[u32], main_sym: u32) {
+        for &m in modifiers {
+            self.send_key(m, true);
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        self.send_key(main_sym, true);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        self.send_key(main_sym, false);
+        for &m in modifiers.iter().rev() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            self.send_key(m, false);
+        }
+    }
+
+    /// Set the compositor clipboard and trigger the paste shortcut,
+    /// mirroring the `inject_text` fallback in the compositor loop.
+    async fn paste_text(&self, text: &str, shortcut: &str) -> Result<(), McpError> {
+        let (modifiers, main_sym) = keyboard::parse_key_combo(shortcut)
+            .map_err(with_code(ToolErrorCode::InvalidArgument))?;
+        let _ = self.state.clipboard_incoming_tx.send(ClipboardContent::text(text.to_string()));
+        self.state.clipboard_incoming_dirty.store(true, std::sync::atomic::Ordering::Relaxed);
+        // Give the compositor loop a tick to install the new selection.
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        self.press_combo(&modifiers, main_sym).await;
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        Ok(())
+    }
 }
 
 #[tool_router]
@@ -95,17 +313,91 @@ impl McpServer {
             if delay > 0 { tokio::time::sleep(std::time::Duration::from_millis(delay)).await; }
         }
         let (w, h, pixels) = frame_capture::capture_frame(&self.state).await
-            .map_err(|e| McpError::internal_error(e, None))?;
+            .map_err(with_code(ToolErrorCode::CaptureFailed))?;
         let b64 = frame_capture::xrgb_to_jpeg_base64(w, h, &pixels, 80, 800_000)
-            .map_err(|e| McpError::internal_error(e, None))?;
+            .map_err(with_code(ToolErrorCode::CaptureFailed))?;
         Ok(CallToolResult::success(vec![Content::image(b64, "image/jpeg")]))
     }
 
+    #[tool(description = "Record a short animated GIF of the desktop (default 2s at 5 fps, max 10s / 15 fps). Useful for catching animations, toasts and other transient UI.")]
+    pub async fn capture_clip(
+        &self,
+        Parameters(params): Parameters<CaptureClipParams>,
+    ) -> Result<CallToolResult, McpError> {
+        const MAX_CLIP_BYTES: usize = 8 * 1024 * 1024;
+        // Downscaled RGB frames held until the GIF is encoded
+        const MAX_BUFFERED_BYTES: usize = 128 * 1024 * 1024;
+
+        let duration_ms = params.duration_ms.clamp(100, 10_000);
+        let fps = params.fps.clamp(1, 15);
+        let max_width = params.max_width.clamp(160, 1280);
+        let interval = std::time::Duration::from_millis(1000 / fps as u64);
+        let frame_count = ((duration_ms * fps as u64) / 1000).max(1) as usize;
+
+        let mut frames: Vec<image::RgbImage> = Vec::with_capacity(frame_count);
+        let mut buffered = 0usize;
+        let mut ticker = tokio::time::interval(interval);
+        for _ in 0..frame_count {
+            ticker.tick().await;
+            let (w, h, pixels) = frame_capture::capture_frame(&self.state).await
+                .map_err(with_code(ToolErrorCode::CaptureFailed))?;
+            if pixels.is_empty() {
+                return Err(tool_error(ToolErrorCode::CaptureFailed, "compositor returned an empty frame", None));
+            }
+            // Downscale as frames arrive so only small frames are buffered
+            let frame = tokio::task::spawn_blocking(move || {
+                frame_capture::xrgb_to_rgb_image_max_width(w, h, &pixels, max_width)
+            })
+            .await
+            .map_err(|e| tool_error(ToolErrorCode::Internal, format!("frame scale task failed: {}", e), None))?
+            .map_err(with_code(ToolErrorCode::CaptureFailed))?;
+            // The first frame tells how much the whole clip will hold
+            let frame_bytes = frame.as_raw().len();
+            if frames.is_empty() && frame_bytes.saturating_mul(frame_count) > MAX_BUFFERED_BYTES {
+                return Err(tool_error(
+                    ToolErrorCode::InvalidArgument,
+                    format!(
+                        "{} frames of {}x{} exceed the capture buffer; lower duration_ms, fps or max_width",
+                        frame_count, frame.width(), frame.height()
+                    ),
+                    Some(serde_json::json!({ "bytes": frame_bytes * frame_count, "max_bytes": MAX_BUFFERED_BYTES })),
+                ));
+            }
+            buffered += frame_bytes;
+            if buffered > MAX_BUFFERED_BYTES {
+                return Err(tool_error(
+                    ToolErrorCode::InvalidArgument,
+                    "clip exceeds the capture buffer; lower duration_ms, fps or max_width",
+                    Some(serde_json::json!({ "bytes": buffered, "max_bytes": MAX_BUFFERED_BYTES })),
+                ));
+            }
+            frames.push(frame);
+        }
+
+        let delay_ms = 1000 / fps;
+        let b64 = tokio::task::spawn_blocking(move || frame_capture::frames_to_gif_base64(frames, delay_ms))
+        .await
+        .map_err(|e| tool_error(ToolErrorCode::Internal, format!("GIF encode task failed: {}", e), None))?
+        .map_err(with_code(ToolErrorCode::CaptureFailed))?;
+        if b64.len() > MAX_CLIP_BYTES {
+            return Err(tool_error(
+                ToolErrorCode::InvalidArgument,
+                format!("clip is too large ({} bytes); lower duration_ms, fps or max_width", b64.len()),
+                Some(serde_json::json!({ "bytes": b64.len(), "max_bytes": MAX_CLIP_BYTES })),
+            ));
+        }
+        Ok(CallToolResult::success(vec![
+            Content::image(b64, "image/gif"),
+            Content::text(format!("Captured {} frames at {} fps", frame_count, fps)),
+        ]))
+    }
+
     #[tool(description = "Move the mouse cursor to the specified coordinates.")]
     pub async fn mouse_move(
         &self,
         Parameters(params): Parameters<MouseMoveParams>,
     ) -> Result<CallToolResult, McpError> {
+        let _input = self.input_sequence().await;
         self.validate_coords(params.x, params.y)?;
         let _ = self.state.input_sender.send(InputEventData {
             event_type: InputEvent::MouseMove, mouse_x: params.x, mouse_y: params.y, ..Default::default()
@@ -113,35 +405,58 @@ impl McpServer {
         Ok(CallToolResult::success(vec![Content::text(format!("Moved to ({}, {})", params.x, params.y))]))
     }
 
-    #[tool(description = "Click a mouse button at coordinates. Supports left/right/middle and double-click.")]
-    pub async fn mouse_click(
+    #[tool(description = "Warp the cursor to absolute coordinates without changing which mouse buttons are held. Use to re-anchor the pointer, e.g. in the middle of a drag.")]
+    pub async fn warp_cursor(
         &self,
-        Parameters(params): Parameters<MouseClickParams>,
+        Parameters(params): Parameters<MouseMoveParams>,
     ) -> Result<CallToolResult, McpError> {
+        let _input = self.input_sequence().await;
+        self.validate_coords(params.x, params.y)?;
+        let _ = self.state.input_sender.send(InputEventData {
+            event_type: InputEvent::MouseMove, mouse_x: params.x, mouse_y: params.y,
+            text: "warp".to_string(), ..Default::default()
+        });
+        Ok(CallToolResult::success(vec![Content::text(format!("Warped to ({}, {})", params.x, params.y))]))
+    }
+
+    #[tool(description = "Move the pointer without clicking (keyboard focus is unchanged), wait dwell_ms, then report the window under the cursor (app_id, title) and whether a popup such as a tooltip is there. Useful for probing hover effects.")]
+    pub async fn hover(
+        &self,
+        Parameters(params): Parameters<HoverParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let _input = self.input_sequence().await;
         self.validate_coords(params.x, params.y)?;
-        // Move cursor to click position first — the compositor button handler
-        // uses the pointer's current location, not the event coordinates.
         let _ = self.state.input_sender.send(InputEventData {
             event_type: InputEvent::MouseMove, mouse_x: params.x, mouse_y: params.y, ..Default::default()
         });
-        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
-        let button: u8 = match params.button.as_str() {
-            "left" => 0, "middle" => 1, "right" => 2,
-            other => return Err(McpError::invalid_params(format!("unknown button: {}", other), None)),
+        let dwell = params.dwell_ms.min(10_000);
+        if dwell > 0 { tokio::time::sleep(std::time::Duration::from_millis(dwell)).await; }
+        let hit = windows::query_window_at(&self.state, params.x, params.y).await
+            .map_err(with_code(ToolErrorCode::Unavailable))?;
+        let value = match hit {
+            Some((window, on_popup)) => serde_json::json!({
+                "x": params.x, "y": params.y,
+                "window": window,
+                "on_popup": on_popup,
+            }),
+            None => serde_json::json!({
+                "x": params.x, "y": params.y,
+                "window": null,
+                "on_popup": false,
+            }),
         };
-        let clicks = if params.double { 2 } else { 1 };
-        for i in 0..clicks {
-            if i > 0 { tokio::time::sleep(std::time::Duration::from_millis(50)).await; }
-            let _ = self.state.input_sender.send(InputEventData {
-                event_type: InputEvent::MouseButton, mouse_x: params.x, mouse_y: params.y,
-                mouse_button: button, button_pressed: true, ..Default::default()
-            });
-            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
-            let _ = self.state.input_sender.send(InputEventData {
-                event_type: InputEvent::MouseButton, mouse_x: params.x, mouse_y: params.y,
-                mouse_button: button, button_pressed: false, ..Default::default()
-            });
-        }
+        Ok(CallToolResult::structured(value))
+    }
+
+    #[tool(description = "Click a mouse button at coordinates. Supports left/right/middle and double-click.")]
+    pub async fn mouse_click(
+        &self,
+        Parameters(params): Parameters<MouseClickParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let _input = self.input_sequence().await;
+        self.validate_coords(params.x, params.y)?;
+        let button = Self::parse_button(&params.button)?;
+        self.click_at(params.x, params.y, button, params.double).await;
         let action = if params.double { "Double-clicked" } else { "Clicked" };
         Ok(CallToolResult::success(vec![Content::text(format!("{} {} at ({}, {})", action, params.button, params.x, params.y))]))
     }
@@ -151,46 +466,61 @@ impl McpServer {
         &self,
         Parameters(params): Parameters<MouseScrollParams>,
     ) -> Result<CallToolResult, McpError> {
+        let _input = self.input_sequence().await;
         let _ = self.state.input_sender.send(InputEventData {
             event_type: InputEvent::MouseWheel, wheel_delta_x: params.dx, wheel_delta_y: params.dy, ..Default::default()
         });
         Ok(CallToolResult::success(vec![Content::text(format!("Scrolled dx={} dy={}", params.dx, params.dy))]))
     }
 
-    #[tool(description = "Type text using the keyboard. Supports ASCII and non-ASCII (CJK, emoji, etc.) text. Non-ASCII text is sent via IME/text input.")]
+    #[tool(description = "Type text using the keyboard. Supports ASCII and non-ASCII (CJK, emoji, etc.) text. Non-ASCII text is sent via IME/text input. Long text (or method=\"paste\") is pasted via the clipboard. Fails with not_focused when no window has keyboard focus.")]
     pub async fn keyboard_type(
         &self,
         Parameters(params): Parameters<KeyboardTypeParams>,
     ) -> Result<CallToolResult, McpError> {
-        if Self::text_is_ascii_typeable(&params.text) {
-            for c in params.text.chars() { self.type_char(c).await; }
+        let char_count = params.text.chars().count();
+        let clipboard = self.state.clipboard_enabled();
+        let paste = match params.method.as_str() {
+            "auto" => clipboard && char_count > PASTE_THRESHOLD_CHARS,
+            "paste" if !clipboard => {
+                return Err(tool_error(
+                    ToolErrorCode::Unavailable,
+                    "method=paste needs the clipboard (input.enable_clipboard = false)",
+                    None,
+                ))
+            }
+            "paste" => true,
+            "type" => false,
+            other => return Err(tool_error(ToolErrorCode::InvalidArgument, format!("unknown method: {}", other), None)),
+        };
+        let _input = self.input_sequence().await;
+        self.require_focus().await?;
+        let method = if paste {
+            self.paste_text(&params.text, &params.paste_shortcut).await?;
+            "clipboard paste"
         } else {
-            self.send_text_input(&params.text);
-            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-        }
+            self.type_text(&params.text).await
+        };
         if params.enter {
             self.send_key(0xff0d, true);
             tokio::time::sleep(std::time::Duration::from_millis(50)).await;
             self.send_key(0xff0d, false);
         }
         Ok(CallToolResult::success(vec![Content::text(
-            format!("Typed {} chars{}", params.text.chars().count(), if params.enter { " + Enter" } else { "" }),
+            format!("Typed {} chars via {}{}", char_count, method, if params.enter { " + Enter" } else { "" }),
         )]))
     }
 
-    #[tool(description = "Type multiple lines of text. Enter is pressed after each line. Supports non-ASCII (CJK, emoji, etc.) text via IME.")]
+    #[tool(description = "Type multiple lines of text. Enter is pressed after each line. Supports non-ASCII (CJK, emoji, etc.) text via IME. Fails with not_focused when no window has keyboard focus.")]
     pub async fn keyboard_type_multiline(
         &self,
         Parameters(params): Parameters<KeyboardTypeMultilineParams>,
     ) -> Result<CallToolResult, McpError> {
+        let _input = self.input_sequence().await;
+        self.require_focus().await?;
         let count = params.lines.len();
         for (i, line) in params.lines.iter().enumerate() {
-            if Self::text_is_ascii_typeable(line) {
-                for c in line.chars() { self.type_char(c).await; }
-            } else {
-                self.send_text_input(line);
-                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-            }
+            self.type_text(line).await;
             self.send_key(0xff0d, true);
             tokio::time::sleep(std::time::Duration::from_millis(50)).await;
             self.send_key(0xff0d, false);
@@ -204,20 +534,103 @@ impl McpServer {
         &self,
         Parameters(params): Parameters<KeyboardKeyParams>,
     ) -> Result<CallToolResult, McpError> {
+        let _input = self.input_sequence().await;
         let (modifiers, main_sym) = keyboard::parse_key_combo(&params.key)
-            .map_err(|e| McpError::invalid_params(e, None))?;
-        for &m in &modifiers {
-            self.send_key(m, true);
-            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            .map_err(with_code(ToolErrorCode::InvalidArgument))?;
+        self.press_combo(&modifiers, main_sym).await;
+        Ok(CallToolResult::success(vec![Content::text(format!("Pressed {}", params.key))]))
+    }
+
+    #[tool(description = "Press or release a raw evdev keycode (1-247), bypassing the keysym table keyboard_key uses. Use for keys keysyms don't distinguish, e.g. keypad vs top-row digits (79 vs 2) or left vs right modifiers, in games and specialized apps. action: tap (default), press or release.")]
+    pub async fn keyboard_keycode(
+        &self,
+        Parameters(params): Parameters<KeyboardKeycodeParams>,
+    ) -> Result<CallToolResult, McpError> {
+        if params.keycode == 0 || params.keycode > crate::input::MAX_EVDEV_KEYCODE {
+            return Err(tool_error(
+                ToolErrorCode::OutOfBounds,
+                format!("keycode {} out of range (1-{})", params.keycode, crate::input::MAX_EVDEV_KEYCODE),
+                Some(serde_json::json!({ "keycode": params.keycode, "max_keycode": crate::input::MAX_EVDEV_KEYCODE })),
+            ));
         }
-        self.send_key(main_sym, true);
-        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
-        self.send_key(main_sym, false);
-        for &m in modifiers.iter().rev() {
-            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
-            self.send_key(m, false);
+        let states: &[bool] = match params.action.as_str() {
+            "tap" => &[true, false],
+            "press" => &[true],
+            "release" => &[false],
+            other => return Err(tool_error(ToolErrorCode::InvalidArgument, format!("unknown action: {}", other), None)),
+        };
+        let _input = self.input_sequence().await;
+        for (i, &pressed) in states.iter().enumerate() {
+            if i > 0 { tokio::time::sleep(std::time::Duration::from_millis(50)).await; }
+            let _ = self.state.input_sender.send(InputEventData {
+                event_type: InputEvent::KeyboardKeycode,
+                keycode: params.keycode,
+                key_pressed: pressed,
+                ..Default::default()
+            });
+            self.held_keycodes.update(params.keycode, pressed);
         }
-        Ok(CallToolResult::success(vec![Content::text(format!("Pressed {}", params.key))]))
+        Ok(CallToolResult::success(vec![Content::text(
+            format!("Sent keycode {} ({})", params.keycode, params.action),
+        )]))
+    }
+
+    #[tool(description = "Run up to 50 input steps in one call, with no other MCP input tool interleaving (viewers' own input is not held off): move {x,y}, click {x,y,button,double}, type {text,enter}, key {key}, wait {ms}. Each step is an object with an \"action\" field. All steps are validated before any runs; waits total at most 30 s and type steps at most 500 characters. Returns per-step results.")]
+    pub async fn run_sequence(
+        &self,
+        Parameters(params): Parameters<RunSequenceParams>,
+    ) -> Result<CallToolResult, McpError> {
+        // Validate under the input lock, against the display size the
+        // steps will run at
+        let _input = self.input_sequence().await;
+        let combos = self.validate_sequence(&params.steps)?;
+
+        let started = std::time::Instant::now();
+        let mut results = Vec::with_capacity(params.steps.len());
+        for (i, (step, combo)) in params.steps.iter().zip(combos).enumerate() {
+            let (action, result) = match step {
+                SequenceStep::Move { x, y } => {
+                    let _ = self.state.input_sender.send(InputEventData {
+                        event_type: InputEvent::MouseMove, mouse_x: *x, mouse_y: *y, ..Default::default()
+                    });
+                    ("move", format!("Moved to ({}, {})", x, y))
+                }
+                SequenceStep::Click { x, y, button, double } => {
+                    let code = Self::parse_button(button)?;
+                    self.click_at(*x, *y, code, *double).await;
+                    let verb = if *double { "Double-clicked" } else { "Clicked" };
+                    ("click", format!("{} {} at ({}, {})", verb, button, x, y))
+                }
+                SequenceStep::Type { text, enter } => {
+                    // Checked when the step runs: an earlier click may be
+                    // what focuses the target
+                    self.require_focus().await.map_err(|e| at_step(e, i))?;
+                    let method = self.type_text(text).await;
+                    if *enter {
+                        self.send_key(0xff0d, true);
+                        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                        self.send_key(0xff0d, false);
+                    }
+                    let suffix = if *enter { " + Enter" } else { "" };
+                    ("type", format!("Typed {} chars via {}{}", text.chars().count(), method, suffix))
+                }
+                SequenceStep::Key { key } => {
+                    if let Some((modifiers, main_sym)) = combo {
+                        self.press_combo(&modifiers, main_sym).await;
+                    }
+                    ("key", format!("Pressed {}", key))
+                }
+                SequenceStep::Wait { ms } => {
+                    tokio::time::sleep(std::time::Duration::from_millis(*ms)).await;
+                    ("wait", format!("Waited {} ms", ms))
+                }
+            };
+            results.push(serde_json::json!({ "step": i, "action": action, "result": result }));
+        }
+        Ok(CallToolResult::structured(serde_json::json!({
+            "steps": results,
+            "elapsed_ms": started.elapsed().as_millis() as u64,
+        })))
     }
 
     #[tool(description = "Read the current clipboard text content.")]
@@ -226,7 +639,7 @@ impl McpServer {
         match clip {
             Some(b64) => {
                 let decoded = base64::engine::general_purpose::STANDARD.decode(&b64)
-                    .map_err(|e| McpError::internal_error(format!("base64 decode: {}", e), None))?;
+                    .map_err(|e| tool_error(ToolErrorCode::Internal, format!("base64 decode: {}", e), None))?;
                 let text = String::from_utf8_lossy(&decoded).into_owned();
                 Ok(CallToolResult::success(vec![Content::text(text)]))
             }
@@ -239,19 +652,59 @@ impl McpServer {
         &self,
         Parameters(params): Parameters<ClipboardWriteParams>,
     ) -> Result<CallToolResult, McpError> {
-        let b64 = base64::engine::general_purpose::STANDARD.encode(params.text.as_bytes());
-        let _ = self.state.clipboard_incoming_tx.send(b64);
+        // Don't swap the clipboard under an in-flight keyboard_type paste
+        let _input = self.input_sequence().await;
+        let _ = self.state.clipboard_incoming_tx.send(ClipboardContent::text(params.text));
         self.state.clipboard_incoming_dirty.store(true, std::sync::atomic::Ordering::Relaxed);
         Ok(CallToolResult::success(vec![Content::text("Clipboard updated")]))
     }
 
+    #[tool(description = "Paste the current clipboard text into the focused app. method=\"type\" types it as keystrokes instead, for apps that ignore clipboard paste (slow for long text).")]
+    pub async fn clipboard_paste(
+        &self,
+        Parameters(params): Parameters<ClipboardPasteParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let clip = self.state.clipboard.lock().unwrap().clone();
+        let Some(b64) = clip else {
+            return Ok(CallToolResult::success(vec![Content::text("(clipboard empty)")]));
+        };
+        let decoded = base64::engine::general_purpose::STANDARD.decode(&b64)
+            .map_err(|e| tool_error(ToolErrorCode::Internal, format!("base64 decode: {}", e), None))?;
+        let text = String::from_utf8_lossy(&decoded).into_owned();
+        let char_count = text.chars().count();
+
+        let _input = self.input_sequence().await;
+        match params.method.as_str() {
+            "paste" => {
+                let (modifiers, main_sym) = keyboard::parse_key_combo(&params.paste_shortcut)
+                    .map_err(with_code(ToolErrorCode::InvalidArgument))?;
+                self.press_combo(&modifiers, main_sym).await;
+                Ok(CallToolResult::success(vec![Content::text(
+                    format!("Pasted {} chars via {}", char_count, params.paste_shortcut),
+                )]))
+            }
+            "type" => {
+                crate::input::type_keystrokes(&self.state, None, &text).await;
+                let note = if char_count > crate::input::KEYSTROKE_WARN_CHARS {
+                    " (long text; method=\"paste\" is much faster where the app supports it)"
+                } else {
+                    ""
+                };
+                Ok(CallToolResult::success(vec![Content::text(
+                    format!("Typed {} clipboard chars as keystrokes{}", char_count, note),
+                )]))
+            }
+            other => Err(tool_error(ToolErrorCode::InvalidArgument, format!("unknown method: {}", other), None)),
+        }
+    }
+
     #[tool(description = "Get screen dimensions, FPS, bandwidth, and connection statistics.")]
     pub async fn get_screen_info(&self) -> Result<CallToolResult, McpError> {
         let (w, h) = self.state.display_size();
         let stats = self.state.stats.lock().unwrap().clone();
         let sessions = self.state.webrtc_sessions();
         let uptime = self.state.uptime().as_secs();
-        let info = serde_json::json!({
+        let mut info = serde_json::json!({
             "width": w, "height": h,
             "fps": format!("{:.1}", stats.fps),
             "bandwidth_bps": stats.bandwidth,
@@ -260,11 +713,72 @@ impl McpServer {
             "cpu_percent": format!("{:.1}", stats.cpu_percent),
             "mem_bytes": stats.mem_used,
         });
+        info["latency_ms"] = serde_json::json!({
+            "frame": stats.frame_latency,
+            "client": stats.client_latency,
+            "handoff": stats.encode_handoff,
+        });
+        if let Some(gpu) = stats.gpu {
+            info["gpu"] = serde_json::to_value(gpu).unwrap_or_default();
+        }
         Ok(CallToolResult::success(vec![Content::text(
             serde_json::to_string_pretty(&info).unwrap(),
         )]))
     }
 
+    #[tool(description = "Get the current display resolution.")]
+    pub async fn get_resolution(&self) -> Result<CallToolResult, McpError> {
+        let (width, height) = self.state.display_size();
+        Ok(CallToolResult::structured(serde_json::json!({ "width": width, "height": height })))
+    }
+
+    #[tool(description = "Change the display resolution and wait until it takes effect. Sizes above the server maximum (display.max_width x max_height, 7680x4320 by default) are clamped to it, then fitted to display.aspect_ratio when one is locked. Windows are resized to fill the new display. Returns the size in effect, whether the request was adjusted (clamped), and applied=false if the compositor kept the old size.")]
+    pub async fn set_resolution(
+        &self,
+        Parameters(params): Parameters<SetResolutionParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let display = &self.state.config.display;
+        let Some(clamped) = display.clamp_size(params.width, params.height) else {
+            return Err(tool_error(
+                ToolErrorCode::InvalidArgument,
+                format!("resolution {}x{} must be non-zero", params.width, params.height),
+                Some(serde_json::json!({ "max_width": display.max_width, "max_height": display.max_height })),
+            ));
+        };
+        let target = self.state.resize_display(clamped.0, clamped.1);
+        // The compositor loop applies the resize (and rebuilds the encoder)
+        // on its next pass; a failed resize leaves the old size in place
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(5);
+        while self.state.display_size() != target && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        let (width, height) = self.state.display_size();
+        Ok(CallToolResult::structured(serde_json::json!({
+            "width": width,
+            "height": height,
+            "requested": { "width": params.width, "height": params.height },
+            "clamped": target != (params.width, params.height),
+            "applied": (width, height) == target,
+        })))
+    }
+
+    #[tool(description = "Compute statistics now instead of waiting for the periodic update, push them (with the taskbar and cursor) to connected clients, and return the stats JSON. Requests closer than 250 ms apart return the latest stats without a refresh.")]
+    pub async fn refresh_stats(&self) -> Result<CallToolResult, McpError> {
+        let generation = self.state.stats_generation.load(std::sync::atomic::Ordering::Relaxed);
+        let refreshed = self.state.request_stats_now();
+        if refreshed {
+            // The main loop picks the request up within its measurement window
+            let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(1);
+            while self.state.stats_generation.load(std::sync::atomic::Ordering::Relaxed) == generation
+                && tokio::time::Instant::now() < deadline
+            {
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+        }
+        let stats: serde_json::Value = serde_json::from_str(&self.state.stats_json()).unwrap_or_default();
+        Ok(CallToolResult::structured(serde_json::json!({ "refreshed": refreshed, "stats": stats })))
+    }
+
     #[tool(description = "List all open windows with their IDs, titles, and focus state.")]
     pub async fn list_windows(&self) -> Result<CallToolResult, McpError> {
         let json = self.state.last_taskbar_json.lock().unwrap().clone();
@@ -274,11 +788,87 @@ impl McpServer {
         }
     }
 
+    #[tool(description = "Get all windows as structured data: id, title, app_id, display_name, focused, minimized and geometry (x, y, width, height).")]
+    pub async fn get_window_tree(&self) -> Result<CallToolResult, McpError> {
+        let windows = windows::query_window_tree(&self.state).await
+            .map_err(with_code(ToolErrorCode::Unavailable))?;
+        let value = serde_json::json!({ "windows": windows });
+        Ok(CallToolResult::structured(value))
+    }
+
+    #[tool(description = "Report where typed text will go: the keyboard-focused window (id, title, app_id, geometry) and whether its client supports text input (IME). Returns focused=false when nothing has focus.")]
+    pub async fn get_focus(&self) -> Result<CallToolResult, McpError> {
+        let (window, text_input) = windows::query_focus(&self.state).await
+            .map_err(with_code(ToolErrorCode::Unavailable))?;
+        let value = match window {
+            Some(window) => serde_json::json!({
+                "focused": true,
+                "window": window,
+                "text_input": text_input,
+            }),
+            None => serde_json::json!({
+                "focused": false,
+                "window": null,
+                "text_input": text_input,
+                "message": "no window has keyboard focus",
+            }),
+        };
+        Ok(CallToolResult::structured(value))
+    }
+
+    #[tool(description = "List running apps (by app_id) with their windows, topmost first. Each window has id, title, focused, minimized and stack_index (0 = topmost).")]
+    pub async fn list_apps(&self) -> Result<CallToolResult, McpError> {
+        let mut windows = windows::query_window_tree(&self.state).await
+            .map_err(with_code(ToolErrorCode::Unavailable))?;
+        // Topmost first, minimized windows last
+        windows.sort_by_key(|w| w.stack_index.unwrap_or(u32::MAX));
+        let mut apps: Vec<serde_json::Value> = Vec::new();
+        for window in windows {
+            let entry = serde_json::json!({
+                "id": window.id,
+                "title": window.title,
+                "focused": window.focused,
+                "minimized": window.minimized,
+                "stack_index": window.stack_index,
+            });
+            match apps.iter_mut().find(|app| app["app_id"] == window.app_id.as_str()) {
+                Some(app) => app["windows"].as_array_mut().unwrap().push(entry),
+                None => apps.push(serde_json::json!({
+                    "app_id": window.app_id,
+                    "display_name": window.display_name,
+                    "windows": [entry],
+                })),
+            }
+        }
+        Ok(CallToolResult::structured(serde_json::json!({ "apps": apps })))
+    }
+
+    #[tool(description = "Switch to the next (or previous) window in stacking order, like Alt+Tab, optionally only among one app's windows. Minimized windows are skipped. Returns the newly focused window.")]
+    pub async fn switch_window(
+        &self,
+        Parameters(params): Parameters<SwitchWindowParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let reverse = match params.direction.as_str() {
+            "next" => false,
+            "previous" => true,
+            other => return Err(tool_error(ToolErrorCode::InvalidArgument, format!("unknown direction: {}", other), None)),
+        };
+        let _input = self.input_sequence().await;
+        let switch = crate::web::shared::WindowSwitch { reverse, app_id: params.app_id };
+        let window = windows::switch_window(&self.state, switch).await
+            .map_err(with_code(ToolErrorCode::Unavailable))?;
+        match window {
+            Some(window) => Ok(CallToolResult::structured(serde_json::json!({ "window": window }))),
+            None => Err(tool_error(ToolErrorCode::Unavailable, "no visible window to switch to", None)),
+        }
+    }
+
     #[tool(description = "Focus a window by its ID (from list_windows).")]
     pub async fn window_focus(
         &self,
         Parameters(params): Parameters<WindowIdParams>,
     ) -> Result<CallToolResult, McpError> {
+        let _input = self.input_sequence().await;
         let _ = self.state.input_sender.send(InputEventData {
             event_type: InputEvent::WindowFocus,
             window_id: params.window_id,
@@ -294,6 +884,7 @@ impl McpServer {
         &self,
         Parameters(params): Parameters<WindowIdParams>,
     ) -> Result<CallToolResult, McpError> {
+        let _input = self.input_sequence().await;
         let _ = self.state.input_sender.send(InputEventData {
             event_type: InputEvent::WindowClose,
             window_id: params.window_id,
@@ -303,6 +894,100 @@ impl McpServer {
             format!("Closed window {}", params.window_id),
         )]))
     }
+
+    #[tool(description = "Give input control of a shared desktop to a viewer session (input.control_policy), or revoke it when session_id is omitted. Returns the control state: holder and sessions waiting for control.")]
+    pub async fn grant_control(
+        &self,
+        Parameters(params): Parameters<GrantControlParams>,
+    ) -> Result<CallToolResult, McpError> {
+        if self.state.control_message().is_none() {
+            return Err(tool_error(ToolErrorCode::Unavailable, "input control arbitration is off (input.control_policy)", None));
+        }
+        if let Some(session_id) = params.session_id.as_deref() {
+            if !self.state.control.lock().unwrap().knows(session_id) {
+                return Err(tool_error(
+                    ToolErrorCode::InvalidArgument,
+                    format!("no connected session {}", session_id),
+                    None,
+                ));
+            }
+        }
+        self.state.update_control(|control| control.grant(params.session_id.as_deref()));
+        let state = self.state.control.lock().unwrap().to_json();
+        Ok(CallToolResult::structured(state))
+    }
+}
+
+#[cfg(feature = "ocr")]
+#[tool_router(router = ocr_router)]
+impl McpServer {
+    #[tool(description = "Read on-screen text via OCR. Returns recognized words with bounding boxes (display coordinates) so they can be clicked. Use region to limit the scan.")]
+    pub async fn read_screen_text(
+        &self,
+        Parameters(params): Parameters<ReadScreenTextParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let (w, h, pixels) = frame_capture::capture_frame(&self.state).await
+            .map_err(with_code(ToolErrorCode::CaptureFailed))?;
+        let (origin, (w, h, pixels)) = match params.region {
+            Some(region) => {
+                let cropped = frame_capture::crop_xrgb(w, h, &pixels, region.as_tuple())
+                    .map_err(with_code(ToolErrorCode::OutOfBounds))?;
+                ((region.x, region.y), cropped)
+            }
+            None => ((0, 0), (w, h, pixels)),
+        };
+        let words = ocr::recognize(w, h, &pixels, origin, &params.lang, params.min_confidence).await
+            .map_err(with_code(ToolErrorCode::Internal))?;
+        let text = words.iter().map(|word| word.text.as_str()).collect::<Vec<_>>().join(" ");
+        Ok(CallToolResult::structured(serde_json::json!({
+            "text": text,
+            "words": words,
+        })))
+    }
+}
+
+#[cfg(feature = "template-match")]
+#[tool_router(router = template_match_router)]
+impl McpServer {
+    #[tool(description = "Locate a UI element on screen from a base64 PNG/JPEG template image. Returns matches with center coordinates (x, y) usable with mouse_click, bounding boxes and confidence.")]
+    pub async fn find_on_screen(
+        &self,
+        Parameters(params): Parameters<FindOnScreenParams>,
+    ) -> Result<CallToolResult, McpError> {
+        if !(0.0..=1.0).contains(&params.threshold) {
+            return Err(tool_error(ToolErrorCode::InvalidArgument, "threshold must be between 0.0 and 1.0", None));
+        }
+        let template_bytes = base64::engine::general_purpose::STANDARD.decode(params.template.trim())
+            .map_err(|e| tool_error(ToolErrorCode::InvalidArgument, format!("template base64 decode: {}", e), None))?;
+        let template = template_match::Gray::decode(&template_bytes)
+            .map_err(with_code(ToolErrorCode::InvalidArgument))?;
+
+        let (w, h, pixels) = frame_capture::capture_frame(&self.state).await
+            .map_err(with_code(ToolErrorCode::CaptureFailed))?;
+        let (origin, (w, h, pixels)) = match params.region {
+            Some(region) => {
+                let cropped = frame_capture::crop_xrgb(w, h, &pixels, region.as_tuple())
+                    .map_err(with_code(ToolErrorCode::OutOfBounds))?;
+                ((region.x, region.y), cropped)
+            }
+            None => ((0, 0), (w, h, pixels)),
+        };
+
+        let threshold = params.threshold;
+        let max_results = params.max_results.clamp(1, 50);
+        let matches = tokio::task::spawn_blocking(move || {
+            let frame = template_match::Gray::from_xrgb(w, h, &pixels);
+            template_match::find(&frame, &template, threshold, max_results, origin)
+        })
+        .await
+        .map_err(|e| tool_error(ToolErrorCode::Internal, format!("template match task failed: {}", e), None))?
+        .map_err(with_code(ToolErrorCode::InvalidArgument))?;
+
+        Ok(CallToolResult::structured(serde_json::json!({
+            "found": !matches.is_empty(),
+            "matches": matches,
+        })))
+    }
 }
 
 impl ServerHandler for McpServer {
@@ -324,7 +1009,9 @@ impl ServerHandler for McpServer {
             instructions: Some(
                 "iVnc remote desktop MCP server. Use screenshot to see the desktop, \
                  mouse/keyboard tools to interact, clipboard to read/write text, \
-                 and window tools to manage windows.".into(),
+                 and window tools to manage windows. Tool errors carry data.code \
+                 (out_of_bounds, capture_failed, not_focused, permission_denied, \
+                 invalid_argument, unavailable, internal) for branching on failures.".into(),
             ),
         }
     }
@@ -332,9 +1019,14 @@ impl ServerHandler for McpServer {
     async fn list_tools(
         &self,
         _request: Option<PaginatedRequestParams>,
-        _context: RequestContext<RoleServer>,
+        context: RequestContext<RoleServer>,
     ) -> Result<ListToolsResult, McpError> {
-        Ok(ListToolsResult::with_all_items(self.tool_router.list_all()))
+        let scope = self.caller_scope(&context);
+        let tools = self.tool_router.list_all()
+            .into_iter()
+            .filter(|tool| scope.allows(&tool.name) && scopes::tool_enabled(&self.state.config, &tool.name))
+            .collect();
+        Ok(ListToolsResult::with_all_items(tools))
     }
 
     async fn call_tool(
@@ -342,7 +1034,122 @@ impl ServerHandler for McpServer {
         request: CallToolRequestParams,
         context: RequestContext<RoleServer>,
     ) -> Result<CallToolResult, McpError> {
+        if !self.caller_scope(&context).allows(&request.name) {
+            return Err(tool_error(
+                ToolErrorCode::PermissionDenied,
+                format!("tool '{}' is not permitted for this caller", request.name),
+                Some(serde_json::json!({ "tool": request.name })),
+            ));
+        }
+        if !scopes::tool_enabled(&self.state.config, &request.name) {
+            return Err(tool_error(
+                ToolErrorCode::Unavailable,
+                format!("tool '{}' is disabled (input.enable_clipboard = false)", request.name),
+                Some(serde_json::json!({ "tool": request.name })),
+            ));
+        }
         let ctx = ToolCallContext::new(self, request, context);
         self.tool_router.call(ctx).await
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn server() -> (McpServer, tokio::sync::mpsc::UnboundedReceiver<InputEventData>) {
+        server_with(Config::default())
+    }
+
+    fn server_with(config: Config) -> (McpServer, tokio::sync::mpsc::UnboundedReceiver<InputEventData>) {
+        let (state, rx) = SharedState::for_test(config);
+        (McpServer::new(state), rx)
+    }
+
+    fn wait(ms: u64) -> SequenceStep {
+        SequenceStep::Wait { ms }
+    }
+
+    fn step_of(err: &McpError) -> Option<u64> {
+        err.data.as_ref()?.get("step")?.as_u64()
+    }
+
+    #[test]
+    fn sequence_step_count_is_capped() {
+        let (server, _rx) = server();
+        assert!(server.validate_sequence(&[]).is_err());
+        let steps: Vec<_> = (0..MAX_SEQUENCE_STEPS).map(|_| wait(0)).collect();
+        assert_eq!(server.validate_sequence(&steps).unwrap().len(), MAX_SEQUENCE_STEPS);
+        let steps: Vec<_> = (0..=MAX_SEQUENCE_STEPS).map(|_| wait(0)).collect();
+        assert!(server.validate_sequence(&steps).is_err());
+    }
+
+    #[test]
+    fn sequence_waits_and_text_are_capped() {
+        let (server, _rx) = server();
+        // One wait over the per-step cap names its step
+        let err = server.validate_sequence(&[wait(0), wait(MAX_SEQUENCE_WAIT_MS + 1)]).unwrap_err();
+        assert_eq!(step_of(&err), Some(1));
+        // Each under the cap, but too long together
+        let steps = [wait(MAX_SEQUENCE_WAIT_MS), wait(MAX_SEQUENCE_WAIT_MS), wait(MAX_SEQUENCE_WAIT_MS)];
+        assert!(server.validate_sequence(&steps).is_ok());
+        let steps = [wait(MAX_SEQUENCE_WAIT_MS), wait(MAX_SEQUENCE_WAIT_MS), wait(MAX_SEQUENCE_WAIT_MS), wait(1)];
+        assert!(server.validate_sequence(&steps).is_err());
+        let typed = |n| SequenceStep::Type { text: "a".repeat(n), enter: false };
+        assert!(server.validate_sequence(&[typed(MAX_SEQUENCE_TEXT_CHARS)]).is_ok());
+        assert!(server.validate_sequence(&[typed(MAX_SEQUENCE_TEXT_CHARS), typed(1)]).is_err());
+    }
+
+    #[tokio::test]
+    async fn bad_step_rejects_the_sequence_before_any_input() {
+        let (server, mut rx) = server();
+        let steps = vec![
+            SequenceStep::Move { x: 10, y: 10 },
+            SequenceStep::Click { x: 10, y: 10, button: "left".into(), double: false },
+            SequenceStep::Key { key: "Ctrl+NoSuchKey".into() },
+        ];
+        let err = server.run_sequence(Parameters(RunSequenceParams { steps })).await.unwrap_err();
+        assert_eq!(step_of(&err), Some(2));
+        // Out of the display, after valid steps
+        let steps = vec![SequenceStep::Move { x: 10, y: 10 }, SequenceStep::Move { x: 100_000, y: 10 }];
+        let err = server.run_sequence(Parameters(RunSequenceParams { steps })).await.unwrap_err();
+        assert_eq!(step_of(&err), Some(1));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn held_keycodes_are_released_when_the_session_ends() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let held = Arc::new(HeldKeycodes { keys: Default::default(), input_sender: tx });
+        held.update(79, true);
+        held.update(42, true);
+        held.update(30, true);
+        held.update(30, false);
+        // A clone going away (one request handler) releases nothing
+        drop(held.clone());
+        assert!(rx.try_recv().is_err());
+        drop(held);
+        let released: Vec<(u32, bool)> = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|ev| {
+                assert_eq!(ev.event_type, InputEvent::KeyboardKeycode);
+                (ev.keycode, ev.key_pressed)
+            })
+            .collect();
+        assert_eq!(released, [(42, false), (79, false)]);
+    }
+
+    #[tokio::test]
+    async fn paste_typing_is_refused_without_the_clipboard() {
+        let mut config = Config::default();
+        config.input.enable_clipboard = false;
+        let (server, mut rx) = server_with(config);
+        let params: KeyboardTypeParams = serde_json::from_value(serde_json::json!({
+            "text": "secret", "method": "paste",
+        })).unwrap();
+        let err = server.keyboard_type(Parameters(params)).await.unwrap_err();
+        assert_eq!(err.data.as_ref().and_then(|d| d.get("code")), Some(&serde_json::json!("unavailable")));
+        assert!(rx.try_recv().is_err());
+        assert!(server.state.clipboard_incoming_rx.lock().unwrap().try_recv().is_err());
+    }
+}
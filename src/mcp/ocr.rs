@@ -0,0 +1,125 @@
+//! On-screen text recognition for the `read_screen_text` MCP tool.
+//!
+//! Runs the `tesseract` CLI on a PNG of the captured frame and parses its
+//! TSV output into word boxes in display coordinates.
+
+use serde::Serialize;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// A recognized word and its bounding box in display pixels
+#[derive(Debug, Clone, Serialize)]
+pub struct OcrWord {
+    pub text: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub confidence: f32,
+}
+
+/// Recognize text in an XRGB8888 image. `origin` is added to every box so
+/// results are in display coordinates when the image is a cropped region.
+pub async fn recognize(
+    width: u32,
+    height: u32,
+    xrgb: &[u8],
+    origin: (u32, u32),
+    lang: &str,
+    min_confidence: f32,
+) -> Result<Vec<OcrWord>, String> {
+    let png = encode_png(width, height, xrgb)?;
+
+    let mut child = Command::new("tesseract")
+        .args(["stdin", "stdout", "-l", lang, "tsv"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run tesseract (is it installed?): {}", e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(&png).await
+            .map_err(|e| format!("failed to write image to tesseract: {}", e))?;
+    }
+
+    let output = tokio::time::timeout(std::time::Duration::from_secs(30), child.wait_with_output())
+        .await
+        .map_err(|_| "tesseract timed out (30s)".to_string())?
+        .map_err(|e| format!("tesseract failed: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "tesseract exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(parse_tsv(&String::from_utf8_lossy(&output.stdout), origin, min_confidence))
+}
+
+fn encode_png(width: u32, height: u32, xrgb: &[u8]) -> Result<Vec<u8>, String> {
+    use image::codecs::png::PngEncoder;
+    use std::io::Cursor;
+
+    let img = super::frame_capture::xrgb_to_rgb_image(width, height, xrgb)?;
+    let mut buf = Cursor::new(Vec::new());
+    img.write_with_encoder(PngEncoder::new(&mut buf))
+        .map_err(|e| format!("PNG encode failed: {}", e))?;
+    Ok(buf.into_inner())
+}
+
+/// Parse tesseract TSV output, keeping word-level (level 5) rows.
+///
+/// Columns: level page_num block_num par_num line_num word_num left top width height conf text
+fn parse_tsv(tsv: &str, origin: (u32, u32), min_confidence: f32) -> Vec<OcrWord> {
+    let mut words = Vec::new();
+    for line in tsv.lines().skip(1) {
+        let cols: Vec<&str> = line.splitn(12, '\t').collect();
+        if cols.len() < 12 || cols[0] != "5" {
+            continue;
+        }
+        let text = cols[11].trim();
+        if text.is_empty() {
+            continue;
+        }
+        let confidence: f32 = cols[10].parse().unwrap_or(-1.0);
+        if confidence < min_confidence {
+            continue;
+        }
+        let num = |i: usize| cols[i].parse::<i32>().unwrap_or(0);
+        words.push(OcrWord {
+            text: text.to_string(),
+            x: num(6) + origin.0 as i32,
+            y: num(7) + origin.1 as i32,
+            width: num(8),
+            height: num(9),
+            confidence,
+        });
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_tsv_keeps_confident_words_in_display_coordinates() {
+        let tsv = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext\n\
+                   4\t1\t1\t1\t1\t0\t10\t20\t200\t30\t-1\t\n\
+                   5\t1\t1\t1\t1\t1\t10\t20\t50\t30\t96.5\tHello\n\
+                   5\t1\t1\t1\t1\t2\t70\t20\t60\t30\t40\tw0rld\n\
+                   5\t1\t1\t1\t1\t3\t140\t20\t10\t30\t95\t \n\
+                   5\t1\t1\t1\t1\t4\t160\t20\t50\t30\t91\tagain";
+        let words = parse_tsv(tsv, (100, 5), 60.0);
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0].text, "Hello");
+        assert_eq!((words[0].x, words[0].y, words[0].width, words[0].height), (110, 25, 50, 30));
+        assert_eq!(words[0].confidence, 96.5);
+        assert_eq!(words[1].text, "again");
+        // Malformed rows are skipped
+        assert!(parse_tsv("header\n5\t1\t2", (0, 0), 0.0).is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+//! Tool authorization scopes for the MCP server.
+//!
+//! Callers may present a token in the `X-MCP-Token` header to select a
+//! configured scope; callers without one get `mcp.allowed_tools`. Tools
+//! for features the server has turned off are withheld from every scope.
+
+use crate::config::{Config, McpConfig};
+
+/// Header carrying the scope token on the Streamable HTTP transport
+pub const TOKEN_HEADER: &str = "x-mcp-token";
+
+/// Tools that need `input.enable_clipboard`
+const CLIPBOARD_TOOLS: [&str; 3] = ["clipboard_read", "clipboard_write", "clipboard_paste"];
+
+/// Whether the server configuration leaves `tool` usable at all
+pub fn tool_enabled(config: &Config, tool: &str) -> bool {
+    config.input.enable_clipboard || !CLIPBOARD_TOOLS.contains(&tool)
+}
+
+/// The set of tools a caller may use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolScope {
+    All,
+    Only(Vec<String>),
+}
+
+impl ToolScope {
+    fn from_list(tools: &[String]) -> Self {
+        if tools.iter().any(|t| t == "*") {
+            ToolScope::All
+        } else {
+            ToolScope::Only(tools.to_vec())
+        }
+    }
+
+    pub fn allows(&self, tool: &str) -> bool {
+        match self {
+            ToolScope::All => true,
+            ToolScope::Only(tools) => tools.iter().any(|t| t == tool),
+        }
+    }
+}
+
+/// Resolve the scope for a caller. An unknown token gets no tools rather
+/// than falling back to the default scope.
+pub fn resolve(config: &McpConfig, token: Option<&str>) -> ToolScope {
+    match token {
+        Some(token) => config
+            .scopes
+            .iter()
+            .find(|scope| scope.token == token)
+            .map(|scope| ToolScope::from_list(&scope.tools))
+            .unwrap_or(ToolScope::Only(Vec::new())),
+        None => match &config.allowed_tools {
+            Some(tools) => ToolScope::from_list(tools),
+            None => ToolScope::All,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::McpScopeConfig;
+
+    fn config() -> McpConfig {
+        McpConfig {
+            allowed_tools: Some(vec!["screenshot".into(), "list_windows".into()]),
+            scopes: vec![
+                McpScopeConfig { token: "admin".into(), tools: vec!["*".into()] },
+                McpScopeConfig { token: "viewer".into(), tools: vec!["screenshot".into()] },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_default_scope_allows_all_when_unset() {
+        let scope = resolve(&McpConfig::default(), None);
+        assert!(scope.allows("clipboard_write"));
+    }
+
+    #[test]
+    fn test_default_scope_restricted() {
+        let scope = resolve(&config(), None);
+        assert!(scope.allows("screenshot"));
+        assert!(scope.allows("list_windows"));
+        assert!(!scope.allows("clipboard_write"));
+    }
+
+    #[test]
+    fn test_token_scopes() {
+        let cfg = config();
+        assert!(resolve(&cfg, Some("admin")).allows("clipboard_write"));
+        let viewer = resolve(&cfg, Some("viewer"));
+        assert!(viewer.allows("screenshot"));
+        assert!(!viewer.allows("list_windows"));
+        assert!(!resolve(&cfg, Some("bogus")).allows("screenshot"));
+    }
+
+    #[test]
+    fn test_clipboard_tools_need_clipboard() {
+        let mut config = Config::default();
+        assert!(tool_enabled(&config, "clipboard_read"));
+        config.input.enable_clipboard = false;
+        for tool in CLIPBOARD_TOOLS {
+            assert!(!tool_enabled(&config, tool));
+        }
+        assert!(tool_enabled(&config, "keyboard_type"));
+    }
+}
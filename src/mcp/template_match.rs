@@ -0,0 +1,287 @@
+//! Template matching for the `find_on_screen` MCP tool.
+//!
+//! Normalized cross-correlation over grayscale images. A coarse pass on a
+//! box-downscaled copy finds candidates, which are then refined at full
+//! resolution, keeping a full-screen search fast enough for interactive use.
+
+use serde::Serialize;
+
+/// A template match in display pixels
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateMatch {
+    /// Center X (suitable for mouse_click)
+    pub x: i32,
+    /// Center Y (suitable for mouse_click)
+    pub y: i32,
+    pub left: i32,
+    pub top: i32,
+    pub width: i32,
+    pub height: i32,
+    /// Normalized cross-correlation score (-1.0 to 1.0)
+    pub confidence: f32,
+}
+
+/// Grayscale image with f32 luma samples
+pub struct Gray {
+    w: usize,
+    h: usize,
+    data: Vec<f32>,
+}
+
+impl Gray {
+    /// Convert an XRGB8888 buffer (memory order B, G, R, X) to luma
+    pub fn from_xrgb(width: u32, height: u32, xrgb: &[u8]) -> Self {
+        let data = xrgb
+            .chunks_exact(4)
+            .map(|p| 0.114 * p[0] as f32 + 0.587 * p[1] as f32 + 0.299 * p[2] as f32)
+            .collect();
+        Self { w: width as usize, h: height as usize, data }
+    }
+
+    /// Decode an encoded image (PNG/JPEG) to luma
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let img = image::load_from_memory(bytes)
+            .map_err(|e| format!("failed to decode template image: {}", e))?
+            .to_luma8();
+        let (w, h) = img.dimensions();
+        Ok(Self {
+            w: w as usize,
+            h: h as usize,
+            data: img.into_raw().into_iter().map(|v| v as f32).collect(),
+        })
+    }
+
+    fn downscale(&self, factor: usize) -> Self {
+        if factor <= 1 {
+            return Self { w: self.w, h: self.h, data: self.data.clone() };
+        }
+        let w = self.w / factor;
+        let h = self.h / factor;
+        let area = (factor * factor) as f32;
+        let mut data = Vec::with_capacity(w * h);
+        for y in 0..h {
+            for x in 0..w {
+                let mut sum = 0.0;
+                for dy in 0..factor {
+                    let row = (y * factor + dy) * self.w + x * factor;
+                    sum += self.data[row..row + factor].iter().sum::<f32>();
+                }
+                data.push(sum / area);
+            }
+        }
+        Self { w, h, data }
+    }
+}
+
+/// Summed-area tables for O(1) window mean/variance
+struct Integral {
+    stride: usize,
+    sum: Vec<f64>,
+    sq: Vec<f64>,
+}
+
+impl Integral {
+    fn new(img: &Gray) -> Self {
+        let stride = img.w + 1;
+        let mut sum = vec![0.0; stride * (img.h + 1)];
+        let mut sq = vec![0.0; stride * (img.h + 1)];
+        for y in 0..img.h {
+            let mut row_sum = 0.0;
+            let mut row_sq = 0.0;
+            for x in 0..img.w {
+                let v = img.data[y * img.w + x] as f64;
+                row_sum += v;
+                row_sq += v * v;
+                sum[(y + 1) * stride + x + 1] = sum[y * stride + x + 1] + row_sum;
+                sq[(y + 1) * stride + x + 1] = sq[y * stride + x + 1] + row_sq;
+            }
+        }
+        Self { stride, sum, sq }
+    }
+
+    fn window(&self, x: usize, y: usize, w: usize, h: usize) -> (f64, f64) {
+        let s = self.stride;
+        let at = |t: &[f64]| t[(y + h) * s + x + w] - t[y * s + x + w] - t[(y + h) * s + x] + t[y * s + x];
+        (at(&self.sum), at(&self.sq))
+    }
+}
+
+/// Zero-mean template plus its L2 norm
+struct Prepared<'a> {
+    tpl: &'a Gray,
+    zero_mean: Vec<f32>,
+    norm: f64,
+}
+
+impl<'a> Prepared<'a> {
+    fn new(tpl: &'a Gray) -> Self {
+        let mean = tpl.data.iter().map(|&v| v as f64).sum::<f64>() / tpl.data.len() as f64;
+        let zero_mean: Vec<f32> = tpl.data.iter().map(|&v| (v as f64 - mean) as f32).collect();
+        let norm = zero_mean.iter().map(|&v| (v as f64) * (v as f64)).sum::<f64>().sqrt();
+        Self { tpl, zero_mean, norm }
+    }
+
+    fn score(&self, img: &Gray, ii: &Integral, x: usize, y: usize) -> f32 {
+        let (tw, th) = (self.tpl.w, self.tpl.h);
+        let n = (tw * th) as f64;
+        let (sum, sq) = ii.window(x, y, tw, th);
+        let var = sq - sum * sum / n;
+        if var <= 1e-6 {
+            return 0.0;
+        }
+        let mut num = 0.0f64;
+        for ty in 0..th {
+            let row = &img.data[(y + ty) * img.w + x..(y + ty) * img.w + x + tw];
+            let tpl_row = &self.zero_mean[ty * tw..(ty + 1) * tw];
+            num += row.iter().zip(tpl_row).map(|(a, b)| a * b).sum::<f32>() as f64;
+        }
+        (num / (var.sqrt() * self.norm)) as f32
+    }
+}
+
+/// Keep the best-scoring candidates whose centers are at least half a
+/// template apart.
+fn suppress(mut candidates: Vec<(f32, usize, usize)>, tw: usize, th: usize, limit: usize) -> Vec<(f32, usize, usize)> {
+    candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    let mut kept: Vec<(f32, usize, usize)> = Vec::new();
+    for c in candidates {
+        let overlaps = kept.iter().any(|k| {
+            k.1.abs_diff(c.1) < tw.max(2) / 2 && k.2.abs_diff(c.2) < th.max(2) / 2
+        });
+        if !overlaps {
+            kept.push(c);
+            if kept.len() >= limit {
+                break;
+            }
+        }
+    }
+    kept
+}
+
+/// Find up to `max_results` locations of `tpl` in `frame` scoring at least `threshold`.
+/// `origin` is added to all coordinates (for region searches).
+pub fn find(
+    frame: &Gray,
+    tpl: &Gray,
+    threshold: f32,
+    max_results: usize,
+    origin: (u32, u32),
+) -> Result<Vec<TemplateMatch>, String> {
+    if tpl.w == 0 || tpl.h == 0 {
+        return Err("template image is empty".into());
+    }
+    if tpl.w > frame.w || tpl.h > frame.h {
+        return Err(format!(
+            "template ({}x{}) is larger than the search area ({}x{})",
+            tpl.w, tpl.h, frame.w, frame.h
+        ));
+    }
+    let full = Prepared::new(tpl);
+    if full.norm <= 1e-3 {
+        return Err("template has no contrast (solid color)".into());
+    }
+
+    // Coarse pass: shrink so the template is still at least ~8px on its short side.
+    let factor = (tpl.w.min(tpl.h) / 8).clamp(1, 4);
+    let coarse_frame = frame.downscale(factor);
+    let coarse_tpl = tpl.downscale(factor);
+    let coarse = Prepared::new(&coarse_tpl);
+    let coarse_ii = Integral::new(&coarse_frame);
+    let coarse_floor = if factor > 1 { (threshold - 0.25).max(0.0) } else { threshold };
+    let mut candidates = Vec::new();
+    if coarse.norm > 1e-3 {
+        for y in 0..=(coarse_frame.h - coarse_tpl.h) {
+            for x in 0..=(coarse_frame.w - coarse_tpl.w) {
+                let score = coarse.score(&coarse_frame, &coarse_ii, x, y);
+                if score >= coarse_floor {
+                    candidates.push((score, x, y));
+                }
+            }
+        }
+    }
+    let candidates = suppress(candidates, coarse_tpl.w, coarse_tpl.h, max_results * 4);
+
+    // Refine each candidate at full resolution within ±2 coarse pixels.
+    let ii = if factor > 1 { Integral::new(frame) } else { coarse_ii };
+    let max_x = frame.w - tpl.w;
+    let max_y = frame.h - tpl.h;
+    let radius = if factor > 1 { factor * 2 } else { 0 };
+    let mut refined = Vec::new();
+    for (score, cx, cy) in candidates {
+        if factor == 1 {
+            refined.push((score, cx, cy));
+            continue;
+        }
+        let (bx, by) = (cx * factor, cy * factor);
+        let mut best = (f32::MIN, bx.min(max_x), by.min(max_y));
+        for y in by.saturating_sub(radius)..=(by + radius).min(max_y) {
+            for x in bx.saturating_sub(radius)..=(bx + radius).min(max_x) {
+                let s = full.score(frame, &ii, x, y);
+                if s > best.0 {
+                    best = (s, x, y);
+                }
+            }
+        }
+        if best.0 >= threshold {
+            refined.push(best);
+        }
+    }
+
+    Ok(suppress(refined, tpl.w, tpl.h, max_results)
+        .into_iter()
+        .map(|(score, x, y)| {
+            let left = x as i32 + origin.0 as i32;
+            let top = y as i32 + origin.1 as i32;
+            TemplateMatch {
+                x: left + tpl.w as i32 / 2,
+                y: top + tpl.h as i32 / 2,
+                left,
+                top,
+                width: tpl.w as i32,
+                height: tpl.h as i32,
+                confidence: score,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic noise so the template only matches in one place
+    fn noise(w: usize, h: usize, seed: u32) -> Gray {
+        let mut state = seed.wrapping_mul(747_796_405).wrapping_add(1);
+        let data = (0..w * h)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                ((state >> 16) & 0xff) as f32
+            })
+            .collect();
+        Gray { w, h, data }
+    }
+
+    #[test]
+    fn test_find_locates_template() {
+        let frame = noise(200, 120, 0);
+        let (tx, ty, tw, th) = (57, 33, 40, 24);
+        let mut data = Vec::with_capacity(tw * th);
+        for y in ty..ty + th {
+            data.extend_from_slice(&frame.data[y * frame.w + tx..y * frame.w + tx + tw]);
+        }
+        let tpl = Gray { w: tw, h: th, data };
+
+        let matches = find(&frame, &tpl, 0.95, 1, (10, 20)).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].left, tx as i32 + 10);
+        assert_eq!(matches[0].top, ty as i32 + 20);
+        assert!(matches[0].confidence > 0.99);
+    }
+
+    #[test]
+    fn test_find_rejects_flat_template() {
+        let frame = noise(64, 64, 1);
+        let tpl = Gray { w: 8, h: 8, data: vec![42.0; 64] };
+        assert!(find(&frame, &tpl, 0.8, 1, (0, 0)).is_err());
+    }
+}
@@ -12,6 +12,80 @@ pub struct ScreenshotParams {
     pub delay_ms: Option<u64>,
 }
 
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CaptureClipParams {
+    /// Recording duration in milliseconds (100-10000, default: 2000)
+    #[serde(default = "default_clip_duration_ms")]
+    pub duration_ms: u64,
+    /// Frames per second (1-15, default: 5)
+    #[serde(default = "default_clip_fps")]
+    pub fps: u32,
+    /// Maximum output width in pixels (160-1280, default: 640)
+    #[serde(default = "default_clip_max_width")]
+    pub max_width: u32,
+}
+
+fn default_clip_duration_ms() -> u64 { 2000 }
+fn default_clip_fps() -> u32 { 5 }
+fn default_clip_max_width() -> u32 { 640 }
+
+/// Rectangular screen region in display pixels
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema)]
+pub struct ScreenRegion {
+    /// Left edge
+    pub x: u32,
+    /// Top edge
+    pub y: u32,
+    /// Width
+    pub width: u32,
+    /// Height
+    pub height: u32,
+}
+
+impl ScreenRegion {
+    pub fn as_tuple(&self) -> (u32, u32, u32, u32) {
+        (self.x, self.y, self.width, self.height)
+    }
+}
+
+// ── OCR ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReadScreenTextParams {
+    /// Optional region to limit OCR to (default: whole screen)
+    #[serde(default)]
+    pub region: Option<ScreenRegion>,
+    /// Tesseract language code(s), e.g. "eng" or "eng+chi_sim" (default: "eng")
+    #[serde(default = "default_ocr_lang")]
+    pub lang: String,
+    /// Drop words below this confidence (0-100, default: 50)
+    #[serde(default = "default_min_confidence")]
+    pub min_confidence: f32,
+}
+
+fn default_ocr_lang() -> String { "eng".into() }
+fn default_min_confidence() -> f32 { 50.0 }
+
+// ── Template matching ───────────────────────────────────────────────
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FindOnScreenParams {
+    /// Base64-encoded PNG or JPEG of the element to find
+    pub template: String,
+    /// Minimum match confidence (0.0-1.0, default: 0.8)
+    #[serde(default = "default_match_threshold")]
+    pub threshold: f32,
+    /// Maximum number of matches to return (default: 5)
+    #[serde(default = "default_max_matches")]
+    pub max_results: usize,
+    /// Optional region to search (default: whole screen)
+    #[serde(default)]
+    pub region: Option<ScreenRegion>,
+}
+
+fn default_match_threshold() -> f32 { 0.8 }
+fn default_max_matches() -> usize { 5 }
+
 // ── Mouse ───────────────────────────────────────────────────────────
 
 #[derive(Debug, Deserialize, JsonSchema)]
@@ -22,6 +96,19 @@ pub struct MouseMoveParams {
     pub y: i32,
 }
 
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct HoverParams {
+    /// X coordinate
+    pub x: i32,
+    /// Y coordinate
+    pub y: i32,
+    /// Time to wait after moving before probing, in milliseconds (0-10000, default: 500)
+    #[serde(default = "default_dwell_ms")]
+    pub dwell_ms: u64,
+}
+
+fn default_dwell_ms() -> u64 { 500 }
+
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct MouseClickParams {
     /// Mouse button: "left" (default), "right", or "middle"
@@ -56,8 +143,18 @@ pub struct KeyboardTypeParams {
     /// Press Enter after typing (default: false)
     #[serde(default)]
     pub enter: bool,
+    /// Input method: "auto" (default), "type" (key events / IME), or "paste" (clipboard + paste shortcut).
+    /// "auto" pastes text longer than 200 characters.
+    #[serde(default = "default_type_method")]
+    pub method: String,
+    /// Shortcut used by the paste method (default: "Ctrl+Shift+v")
+    #[serde(default = "default_paste_shortcut")]
+    pub paste_shortcut: String,
 }
 
+fn default_type_method() -> String { "auto".into() }
+fn default_paste_shortcut() -> String { "Ctrl+Shift+v".into() }
+
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct KeyboardTypeMultilineParams {
     /// Lines of text to type (Enter is pressed after each line)
@@ -70,6 +167,54 @@ pub struct KeyboardKeyParams {
     pub key: String,
 }
 
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct KeyboardKeycodeParams {
+    /// Evdev keycode (1-247), e.g. 2 for the top-row 1, 79 for keypad 1
+    pub keycode: u32,
+    /// "tap" (default) presses and releases; "press" or "release" sends
+    /// only that half, e.g. to hold a key across other tool calls
+    #[serde(default = "default_keycode_action")]
+    pub action: String,
+}
+
+fn default_keycode_action() -> String { "tap".into() }
+
+// ── Sequence ────────────────────────────────────────────────────────
+
+/// One step of a `run_sequence` call, selected by `action`
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum SequenceStep {
+    /// Move the pointer to (x, y)
+    Move { x: i32, y: i32 },
+    /// Click at (x, y); button is "left" (default), "right" or "middle"
+    Click {
+        x: i32,
+        y: i32,
+        #[serde(default = "default_button")]
+        button: String,
+        #[serde(default)]
+        double: bool,
+    },
+    /// Type text (key events, or IME for non-ASCII); enter presses Enter after.
+    /// Needs a focused window; at most 500 chars across a sequence
+    Type {
+        text: String,
+        #[serde(default)]
+        enter: bool,
+    },
+    /// Press a key or combo, e.g. "Return", "Ctrl+s"
+    Key { key: String },
+    /// Pause for ms milliseconds (0-10000)
+    Wait { ms: u64 },
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RunSequenceParams {
+    /// Steps to run in order (1-50)
+    pub steps: Vec<SequenceStep>,
+}
+
 // ── Clipboard ───────────────────────────────────────────────────────
 
 #[derive(Debug, Deserialize, JsonSchema)]
@@ -78,6 +223,39 @@ pub struct ClipboardWriteParams {
     pub text: String,
 }
 
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ClipboardPasteParams {
+    /// "paste" (default): paste shortcut, the app reads the clipboard.
+    /// "type": type the clipboard text as keystrokes, for apps that ignore
+    /// clipboard paste. Slow for long text (~80 ms per character).
+    #[serde(default = "default_clipboard_paste_method")]
+    pub method: String,
+    /// Shortcut used by the paste method (default: "Ctrl+Shift+v")
+    #[serde(default = "default_paste_shortcut")]
+    pub paste_shortcut: String,
+}
+
+fn default_clipboard_paste_method() -> String { "paste".into() }
+
+// ── Display ─────────────────────────────────────────────────────────
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetResolutionParams {
+    /// Width in pixels; clamped to display.max_width
+    pub width: u32,
+    /// Height in pixels; clamped to display.max_height
+    pub height: u32,
+}
+
+// ── Control ─────────────────────────────────────────────────────────
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GrantControlParams {
+    /// Session to give input control to; omit to revoke control
+    #[serde(default)]
+    pub session_id: Option<String>,
+}
+
 // ── Window ──────────────────────────────────────────────────────────
 
 #[derive(Debug, Deserialize, JsonSchema)]
@@ -85,3 +263,16 @@ pub struct WindowIdParams {
     /// Window ID (index from list_windows)
     pub window_id: u32,
 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SwitchWindowParams {
+    /// "next" (default) brings up the bottom-most window so repeated calls
+    /// visit every window; "previous" goes back the other way
+    #[serde(default = "default_switch_direction")]
+    pub direction: String,
+    /// Only cycle through windows of this app_id (from list_apps)
+    #[serde(default)]
+    pub app_id: Option<String>,
+}
+
+fn default_switch_direction() -> String { "next".into() }
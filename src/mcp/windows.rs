@@ -0,0 +1,73 @@
+//! Window tree queries for MCP tools.
+//!
+//! Window geometry and focus live on the compositor thread, so tools ask the
+//! main loop for a snapshot through a oneshot channel (like frame capture).
+
+use std::sync::Arc;
+use tokio::sync::oneshot;
+
+use crate::web::shared::{WindowInfo, WindowSwitch};
+use crate::web::SharedState;
+
+/// Request a snapshot of all toplevel windows from the compositor main loop.
+pub async fn query_window_tree(state: &Arc<SharedState>) -> Result<Vec<WindowInfo>, String> {
+    let (tx, rx) = oneshot::channel();
+    state
+        .window_tree_tx
+        .send(tx)
+        .map_err(|_| "compositor not running")?;
+
+    tokio::time::timeout(std::time::Duration::from_secs(2), rx)
+        .await
+        .map_err(|_| "window tree query timed out (2s)")?
+        .map_err(|_| "compositor dropped window tree request".to_string())
+}
+
+/// Ask the compositor which window is under a display point. The flag is true
+/// when the hit surface is a popup or subsurface (e.g. a tooltip).
+pub async fn query_window_at(
+    state: &Arc<SharedState>,
+    x: i32,
+    y: i32,
+) -> Result<Option<(WindowInfo, bool)>, String> {
+    let (tx, rx) = oneshot::channel();
+    state
+        .window_at_tx
+        .send(((x, y), tx))
+        .map_err(|_| "compositor not running")?;
+
+    tokio::time::timeout(std::time::Duration::from_secs(2), rx)
+        .await
+        .map_err(|_| "window hit-test timed out (2s)")?
+        .map_err(|_| "compositor dropped window hit-test request".to_string())
+}
+
+/// Ask the compositor for the keyboard-focused window and whether the focused
+/// client has text-input bound.
+pub async fn query_focus(state: &Arc<SharedState>) -> Result<(Option<WindowInfo>, bool), String> {
+    let (tx, rx) = oneshot::channel();
+    state
+        .focus_query_tx
+        .send(tx)
+        .map_err(|_| "compositor not running")?;
+
+    tokio::time::timeout(std::time::Duration::from_secs(2), rx)
+        .await
+        .map_err(|_| "focus query timed out (2s)")?
+        .map_err(|_| "compositor dropped focus request".to_string())
+}
+
+/// Ask the compositor to raise and focus the next window in stacking order.
+/// None when there is no mapped window to switch to.
+pub async fn switch_window(state: &Arc<SharedState>, switch: WindowSwitch) -> Result<Option<WindowInfo>, String> {
+    let (tx, rx) = oneshot::channel();
+    state
+        .window_switch_tx
+        .send((switch, tx))
+        .map_err(|_| "compositor not running")?;
+
+    tokio::time::timeout(std::time::Duration::from_secs(2), rx)
+        .await
+        .map_err(|_| "window switch timed out (2s)")?
+        .map_err(|_| "compositor dropped window switch request".to_string())
+}
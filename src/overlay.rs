@@ -0,0 +1,178 @@
+//! Debug stats overlay burned into the video frame
+//!
+//! Draws a few lines of text onto the XRGB8888 framebuffer before it is
+//! encoded, so recordings and screenshots carry the stream state with them.
+//! Uses a built-in 5x7 bitmap font; lowercase is rendered as uppercase and
+//! characters without a glyph are left blank.
+
+use crate::config::{OverlayConfig, OverlayPosition, OverlayVerbosity};
+use crate::web::shared::RuntimeStats;
+
+const GLYPH_W: usize = 5;
+const GLYPH_H: usize = 7;
+/// Each font pixel is drawn as a SCALE x SCALE block
+const SCALE: usize = 2;
+const PADDING: usize = 4 * SCALE;
+const LINE_GAP: usize = 2 * SCALE;
+const MARGIN: usize = 8;
+
+/// Overlay content, refreshed once per stats interval
+pub struct StatsOverlay {
+    config: OverlayConfig,
+    lines: Vec<String>,
+}
+
+impl StatsOverlay {
+    pub fn new(config: OverlayConfig) -> Self {
+        Self { config, lines: Vec::new() }
+    }
+
+    /// Rebuild the text from the latest stats
+    pub fn update(&mut self, stats: &RuntimeStats, sessions: u64, width: u32, height: u32) {
+        self.lines.clear();
+        self.lines.push(format!("FPS {:.1}  {} KBPS", stats.fps, stats.bandwidth / 1000));
+        if self.config.verbosity == OverlayVerbosity::Full {
+            self.lines.push(format!("KEYFRAMES {}  SESSIONS {}", stats.keyframes_total, sessions));
+            self.lines.push(format!("{}X{}  FRAMES {}", width, height, stats.total_frames));
+            self.lines.push(format!("CPU {:.0}%  RTT {} MS", stats.cpu_percent, stats.client_latency_ms));
+        }
+    }
+
+    /// Draw onto a tightly packed XRGB8888 frame of `width` x `height`
+    pub fn draw(&self, pixels: &mut [u8], width: u32, height: u32) {
+        if self.lines.is_empty() {
+            return;
+        }
+        draw_text_box(pixels, width as usize, height as usize, &self.lines, self.config.position);
+    }
+}
+
+fn draw_text_box(pixels: &mut [u8], width: usize, height: usize, lines: &[String], position: OverlayPosition) {
+    if pixels.len() < width * height * 4 {
+        return;
+    }
+    let cols = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let box_w = cols * (GLYPH_W + 1) * SCALE + 2 * PADDING;
+    let box_h = lines.len() * (GLYPH_H * SCALE + LINE_GAP) - LINE_GAP + 2 * PADDING;
+    if box_w + 2 * MARGIN > width || box_h + 2 * MARGIN > height {
+        return;
+    }
+    let (x0, y0) = match position {
+        OverlayPosition::TopLeft => (MARGIN, MARGIN),
+        OverlayPosition::TopRight => (width - box_w - MARGIN, MARGIN),
+        OverlayPosition::BottomLeft => (MARGIN, height - box_h - MARGIN),
+        OverlayPosition::BottomRight => (width - box_w - MARGIN, height - box_h - MARGIN),
+    };
+
+    // Darken the background so the text is readable on any content
+    for y in y0..y0 + box_h {
+        let row = &mut pixels[(y * width + x0) * 4..(y * width + x0 + box_w) * 4];
+        for px in row.chunks_exact_mut(4) {
+            px[0] /= 4;
+            px[1] /= 4;
+            px[2] /= 4;
+        }
+    }
+
+    for (i, line) in lines.iter().enumerate() {
+        let ty = y0 + PADDING + i * (GLYPH_H * SCALE + LINE_GAP);
+        for (j, ch) in line.chars().enumerate() {
+            let tx = x0 + PADDING + j * (GLYPH_W + 1) * SCALE;
+            let rows = glyph(ch.to_ascii_uppercase());
+            for (gy, bits) in rows.iter().enumerate() {
+                for gx in 0..GLYPH_W {
+                    if bits & (0x10 >> gx) == 0 {
+                        continue;
+                    }
+                    for dy in 0..SCALE {
+                        let y = ty + gy * SCALE + dy;
+                        let start = (y * width + tx + gx * SCALE) * 4;
+                        for px in pixels[start..start + SCALE * 4].chunks_exact_mut(4) {
+                            px[0] = 0xff;
+                            px[1] = 0xff;
+                            px[2] = 0xff;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 5x7 glyph rows, most significant of the low 5 bits is the left column
+fn glyph(ch: char) -> [u8; GLYPH_H] {
+    match ch {
+        '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+        '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+        '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+        '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+        '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+        '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+        '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+        '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+        'A' => [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'B' => [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e],
+        'C' => [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e],
+        'D' => [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c],
+        'E' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f],
+        'F' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10],
+        'G' => [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f],
+        'H' => [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'I' => [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f],
+        'M' => [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'P' => [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+        'Q' => [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d],
+        'R' => [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11],
+        'S' => [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e],
+        'T' => [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a],
+        'X' => [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04],
+        'Z' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c],
+        ':' => [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        _ => [0; GLYPH_H],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white_pixels(pixels: &[u8]) -> usize {
+        pixels.chunks_exact(4).filter(|p| p[0] == 0xff && p[1] == 0xff && p[2] == 0xff).count()
+    }
+
+    #[test]
+    fn draws_in_the_configured_corner() {
+        let (w, h) = (320usize, 240usize);
+        let lines = vec!["FPS 30".to_string()];
+
+        let mut frame = vec![0x80u8; w * h * 4];
+        draw_text_box(&mut frame, w, h, &lines, OverlayPosition::BottomRight);
+        let (top, bottom) = frame.split_at(w * h * 2);
+        assert_eq!(white_pixels(top), 0);
+        assert!(white_pixels(bottom) > 0);
+        // Background around the text is darkened, the rest is untouched
+        assert_eq!(&frame[..4], &[0x80, 0x80, 0x80, 0x80]);
+    }
+
+    #[test]
+    fn skips_frames_too_small_for_the_box() {
+        let mut frame = vec![0u8; 16 * 16 * 4];
+        draw_text_box(&mut frame, 16, 16, &["FPS 30".to_string()], OverlayPosition::TopLeft);
+        assert!(frame.iter().all(|&b| b == 0));
+    }
+}
@@ -0,0 +1,37 @@
+//! Input replay client (`ivnc --replay <file>`)
+//!
+//! Posts an input recording to a running server's `/api/input/replay`
+//! (requires `input.allow_replay` there). Basic auth credentials come from
+//! the config file and CLI flags, as for the server itself.
+
+use ivnc::Config;
+use std::path::Path;
+
+/// Send `file` for replay; returns the server's reply
+pub fn run(config: &Config, file: &Path, url: &str, speed: f64) -> Result<String, String> {
+    let body = std::fs::read_to_string(file).map_err(|e| format!("{}: {}", file.display(), e))?;
+    // Catch malformed files before anything reaches the server
+    let events = ivnc::input_recording::parse_recording(&body)?;
+    if events.is_empty() {
+        return Err(format!("{}: no events", file.display()));
+    }
+
+    let endpoint = format!("{}/api/input/replay?speed={}", url.trim_end_matches('/'), speed);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string())?;
+    runtime.block_on(async {
+        let mut request = reqwest::Client::new().post(&endpoint).body(body);
+        if config.http.basic_auth_enabled {
+            request = request.basic_auth(&config.http.basic_auth_user, Some(&config.http.basic_auth_password));
+        }
+        let response = request.send().await.map_err(|e| format!("{}: {}", endpoint, e))?;
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        if !status.is_success() {
+            return Err(format!("{}: {} {}", endpoint, status, text));
+        }
+        Ok(text)
+    })
+}
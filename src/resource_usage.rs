@@ -0,0 +1,235 @@
+//! Process CPU and memory sampling
+//!
+//! Reads `/proc/self/stat` and `/proc/self/status` once per interval and
+//! publishes the result through [`SharedState::update_resource_usage`], which
+//! feeds the stats messages, `/metrics` and MCP. CPU percent is relative to
+//! one core, like `top`, so it can exceed 100 on multi-core machines.
+//!
+//! Optionally (`http.thread_cpu_metrics`) it also walks `/proc/self/task`
+//! and attributes CPU to thread names: `audio-capture`, the tokio workers
+//! (`tokio-runtime-w`), GStreamer streaming threads, and the compositor loop,
+//! which runs on the main thread and carries the process name.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::web::SharedState;
+
+/// How often resource usage is sampled
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// CPU time consumed by the process, derived from successive samples
+pub struct CpuSampler {
+    ticks_per_sec: f64,
+    last: Option<(u64, Instant)>,
+}
+
+impl CpuSampler {
+    pub fn new() -> Self {
+        let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        Self {
+            ticks_per_sec: if ticks > 0 { ticks as f64 } else { 100.0 },
+            last: None,
+        }
+    }
+
+    /// CPU percent since the previous call; None on the first call or if
+    /// `/proc/self/stat` cannot be read
+    pub fn sample(&mut self) -> Option<f64> {
+        let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+        let ticks = parse_cpu_ticks(&stat)?;
+        self.record(ticks, Instant::now())
+    }
+
+    fn record(&mut self, ticks: u64, now: Instant) -> Option<f64> {
+        let previous = self.last.replace((ticks, now));
+        let (last_ticks, last_at) = previous?;
+        let elapsed = now.duration_since(last_at).as_secs_f64();
+        if elapsed <= 0.0 {
+            return None;
+        }
+        let used = ticks.saturating_sub(last_ticks) as f64 / self.ticks_per_sec;
+        Some(used / elapsed * 100.0)
+    }
+}
+
+/// Per-thread CPU, summed by thread name
+pub struct ThreadCpuSampler {
+    ticks_per_sec: f64,
+    /// tid -> ticks at the previous sample
+    last_ticks: HashMap<u32, u64>,
+    last_at: Option<Instant>,
+}
+
+impl ThreadCpuSampler {
+    pub fn new() -> Self {
+        Self {
+            ticks_per_sec: CpuSampler::new().ticks_per_sec,
+            last_ticks: HashMap::new(),
+            last_at: None,
+        }
+    }
+
+    /// CPU percent per thread name since the previous call, sorted by name;
+    /// empty on the first call
+    pub fn sample(&mut self) -> Vec<(String, f64)> {
+        let Ok(dir) = std::fs::read_dir("/proc/self/task") else {
+            return Vec::new();
+        };
+        let threads: Vec<(u32, String, u64)> = dir
+            .flatten()
+            .filter_map(|entry| {
+                let tid: u32 = entry.file_name().to_str()?.parse().ok()?;
+                let stat = std::fs::read_to_string(entry.path().join("stat")).ok()?;
+                Some((tid, parse_comm(&stat)?.to_string(), parse_cpu_ticks(&stat)?))
+            })
+            .collect();
+        self.record(threads, Instant::now())
+    }
+
+    fn record(&mut self, threads: Vec<(u32, String, u64)>, now: Instant) -> Vec<(String, f64)> {
+        let elapsed = self.last_at.replace(now).map(|at| now.duration_since(at).as_secs_f64());
+        let mut previous = std::mem::take(&mut self.last_ticks);
+        let mut by_name: BTreeMap<String, f64> = BTreeMap::new();
+        for (tid, name, ticks) in threads {
+            // Threads started since the last sample count from zero
+            let delta = ticks.saturating_sub(previous.remove(&tid).unwrap_or(0));
+            self.last_ticks.insert(tid, ticks);
+            if let Some(elapsed) = elapsed.filter(|e| *e > 0.0) {
+                *by_name.entry(name).or_default() += delta as f64 / self.ticks_per_sec / elapsed * 100.0;
+            }
+        }
+        by_name.into_iter().collect()
+    }
+}
+
+impl Default for ThreadCpuSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for CpuSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// utime + stime from a `/proc/<pid>/stat` line
+pub fn parse_cpu_ticks(stat: &str) -> Option<u64> {
+    // comm may contain spaces and parentheses; the fields start after the last ')'
+    let (_, rest) = stat.rsplit_once(')')?;
+    let mut fields = rest.split_whitespace().skip(11);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    Some(utime + stime)
+}
+
+/// Thread or process name (the parenthesised comm) from a stat line
+pub fn parse_comm(stat: &str) -> Option<&str> {
+    let start = stat.find('(')?;
+    let end = stat.rfind(')')?;
+    stat.get(start + 1..end)
+}
+
+/// Resident set size in bytes from `/proc/<pid>/status`
+pub fn parse_rss_bytes(status: &str) -> Option<u64> {
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: u64 = line["VmRSS:".len()..].trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kb * 1024)
+}
+
+fn read_rss_bytes() -> Option<u64> {
+    parse_rss_bytes(&std::fs::read_to_string("/proc/self/status").ok()?)
+}
+
+/// Sample CPU and RSS every `SAMPLE_INTERVAL` until the runtime shuts down,
+/// plus per-thread CPU when `per_thread` is set
+pub async fn run_sampler(shared: Arc<SharedState>, per_thread: bool) {
+    let mut cpu = CpuSampler::new();
+    let mut threads = per_thread.then(ThreadCpuSampler::new);
+    let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        if let Some(ref mut threads) = threads {
+            shared.update_thread_cpu(threads.sample());
+        }
+        let Some(cpu_percent) = cpu.sample() else {
+            continue;
+        };
+        let Some(mem_used) = read_rss_bytes() else {
+            debug!("Could not read VmRSS from /proc/self/status");
+            continue;
+        };
+        shared.update_resource_usage(cpu_percent, mem_used);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_stat_with_awkward_comm() {
+        let stat = "1234 (ivnc (main) x) S 1 1234 1234 0 -1 4194560 500 0 0 0 250 50 0 0 20 0 8 0 100 0 0";
+        assert_eq!(parse_cpu_ticks(stat), Some(300));
+        assert_eq!(parse_cpu_ticks("garbage"), None);
+    }
+
+    #[test]
+    fn parses_rss_from_status() {
+        let status = "Name:\tivnc\nVmPeak:\t  90000 kB\nVmRSS:\t   51200 kB\nThreads:\t8\n";
+        assert_eq!(parse_rss_bytes(status), Some(51200 * 1024));
+        assert_eq!(parse_rss_bytes("Name:\tivnc\n"), None);
+    }
+
+    #[test]
+    fn cpu_percent_is_the_tick_delta_over_the_interval() {
+        let start = Instant::now();
+        let mut sampler = CpuSampler { ticks_per_sec: 100.0, last: None };
+        assert_eq!(sampler.record(1000, start), None);
+        let percent = sampler.record(1050, start + Duration::from_secs(1)).unwrap();
+        assert!((percent - 50.0).abs() < 1e-9);
+        // Two cores fully busy
+        let percent = sampler.record(1250, start + Duration::from_secs(2)).unwrap();
+        assert!((percent - 200.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn thread_cpu_is_summed_by_name() {
+        let start = Instant::now();
+        let mut sampler = ThreadCpuSampler {
+            ticks_per_sec: 100.0,
+            last_ticks: HashMap::new(),
+            last_at: None,
+        };
+        let first = vec![
+            (1, "ivnc".to_string(), 100),
+            (2, "tokio-runtime-w".to_string(), 10),
+            (3, "tokio-runtime-w".to_string(), 20),
+        ];
+        assert!(sampler.record(first, start).is_empty());
+        let second = vec![
+            (1, "ivnc".to_string(), 150),
+            (2, "tokio-runtime-w".to_string(), 20),
+            (3, "tokio-runtime-w".to_string(), 30),
+            (4, "audio-capture".to_string(), 5),
+        ];
+        let usage = sampler.record(second, start + Duration::from_secs(1));
+        let names: Vec<&str> = usage.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["audio-capture", "ivnc", "tokio-runtime-w"]);
+        assert!((usage[0].1 - 5.0).abs() < 1e-9);
+        assert!((usage[1].1 - 50.0).abs() < 1e-9);
+        assert!((usage[2].1 - 20.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn comm_keeps_inner_parentheses() {
+        assert_eq!(parse_comm("12 (ivnc (main)) S 1"), Some("ivnc (main)"));
+        assert_eq!(parse_comm("12 S 1"), None);
+    }
+}
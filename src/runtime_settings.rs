@@ -1,9 +1,17 @@
 //! Runtime-adjustable settings derived from client SETTINGS messages.
 
 use crate::config::Config;
+use crate::input::{MAX_MOUSE_SENSITIVITY, MIN_MOUSE_SENSITIVITY};
 use log::debug;
 use serde_json::Value;
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+/// Smallest render scale a client may request with `s,`
+pub const MIN_RENDER_SCALE: f64 = 0.25;
+/// Render scale is a bandwidth control; upscaling past the display is refused
+pub const MAX_RENDER_SCALE: f64 = 1.0;
+/// Upper bound for `display.max_output_scale`
+pub const MAX_OUTPUT_SCALE: f64 = 4.0;
 
 pub struct RuntimeSettings {
     target_fps: AtomicU32,
@@ -14,6 +22,17 @@ pub struct RuntimeSettings {
     keyframe_interval: AtomicU32,
     keyframe_request: AtomicBool,
     audio_bitrate_dirty: AtomicBool,
+    /// f64 bits of the relative-motion sensitivity
+    mouse_sensitivity: AtomicU64,
+    /// Cursor blended into the video instead of drawn by the browser
+    /// (`SET_NATIVE_CURSOR_RENDERING,1`)
+    server_cursor: AtomicBool,
+    /// f64 bits of the encode scale relative to the display (`s,`)
+    render_scale: AtomicU64,
+    /// f64 bits of the Wayland output scale (`scale,`)
+    output_scale: AtomicU64,
+    /// `display.max_output_scale`; 1.0 refuses client scaling
+    max_output_scale: f64,
 }
 
 impl RuntimeSettings {
@@ -27,6 +46,11 @@ impl RuntimeSettings {
             keyframe_interval: AtomicU32::new(config.webrtc.keyframe_interval.max(1)),
             keyframe_request: AtomicBool::new(false),
             audio_bitrate_dirty: AtomicBool::new(false),
+            mouse_sensitivity: AtomicU64::new(config.input.mouse_sensitivity.to_bits()),
+            server_cursor: AtomicBool::new(false),
+            render_scale: AtomicU64::new(MAX_RENDER_SCALE.to_bits()),
+            output_scale: AtomicU64::new(1.0f64.to_bits()),
+            max_output_scale: config.display.max_output_scale.max(1.0).min(MAX_OUTPUT_SCALE),
         }
     }
 
@@ -52,6 +76,52 @@ impl RuntimeSettings {
         self.keyframe_interval.load(Ordering::Relaxed)
     }
 
+    pub fn mouse_sensitivity(&self) -> f64 {
+        f64::from_bits(self.mouse_sensitivity.load(Ordering::Relaxed))
+    }
+
+    pub fn set_mouse_sensitivity(&self, sensitivity: f64) {
+        if !sensitivity.is_finite() {
+            return;
+        }
+        let clamped = sensitivity.clamp(MIN_MOUSE_SENSITIVITY, MAX_MOUSE_SENSITIVITY);
+        self.mouse_sensitivity.store(clamped.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn server_cursor(&self) -> bool {
+        self.server_cursor.load(Ordering::Relaxed)
+    }
+
+    pub fn set_server_cursor(&self, enabled: bool) {
+        self.server_cursor.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn render_scale(&self) -> f64 {
+        f64::from_bits(self.render_scale.load(Ordering::Relaxed))
+    }
+
+    pub fn set_render_scale(&self, scale: f64) {
+        if !scale.is_finite() {
+            return;
+        }
+        let clamped = scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+        self.render_scale.store(clamped.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn output_scale(&self) -> f64 {
+        f64::from_bits(self.output_scale.load(Ordering::Relaxed))
+    }
+
+    /// Clamped to `display.max_output_scale` and rounded to the 1/120 steps
+    /// wp_fractional_scale can express
+    pub fn set_output_scale(&self, scale: f64) {
+        if !scale.is_finite() {
+            return;
+        }
+        let clamped = (scale.clamp(1.0, self.max_output_scale) * 120.0).round() / 120.0;
+        self.output_scale.store(clamped.to_bits(), Ordering::Relaxed);
+    }
+
     pub fn take_keyframe_request(&self) -> bool {
         self.keyframe_request.swap(false, Ordering::Relaxed)
     }
@@ -107,6 +177,28 @@ impl RuntimeSettings {
             }
             return true;
         }
+        if let Some(payload) = message.strip_prefix("SET_NATIVE_CURSOR_RENDERING,") {
+            match payload {
+                "1" | "true" => self.set_server_cursor(true),
+                "0" | "false" => self.set_server_cursor(false),
+                _ => debug!("Invalid SET_NATIVE_CURSOR_RENDERING value: {}", payload),
+            }
+            return true;
+        }
+        if let Some(payload) = message.strip_prefix("s,") {
+            match payload.parse::<f64>() {
+                Ok(scale) => self.set_render_scale(scale),
+                Err(_) => debug!("Invalid render scale: {}", payload),
+            }
+            return true;
+        }
+        if let Some(payload) = message.strip_prefix("scale,") {
+            match payload.parse::<f64>() {
+                Ok(scale) => self.set_output_scale(scale),
+                Err(_) => debug!("Invalid output scale: {}", payload),
+            }
+            return true;
+        }
         if message.starts_with("ab,") {
             let payload = message.trim_start_matches("ab,");
             if let Ok(bitrate) = payload.parse::<u32>() {
@@ -145,5 +237,49 @@ impl RuntimeSettings {
         if let Some(interval) = value.get("keyframe_interval").and_then(|v| v.as_u64()) {
             self.set_keyframe_interval(interval as u32);
         }
+
+        if let Some(sensitivity) = value.get("mouse_sensitivity").and_then(|v| v.as_f64()) {
+            self.set_mouse_sensitivity(sensitivity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings_with_max_scale(max_output_scale: f64) -> RuntimeSettings {
+        let mut config = Config::default();
+        config.display.max_output_scale = max_output_scale;
+        RuntimeSettings::new(&config)
+    }
+
+    #[test]
+    fn output_scale_is_rounded_to_120ths_and_clamped() {
+        let settings = settings_with_max_scale(2.0);
+        assert!(settings.handle_simple_message("scale,1.5"));
+        assert_eq!(settings.output_scale(), 1.5);
+        // 1.333 is 159.96/120: stored as the nearest step, 160/120
+        settings.handle_simple_message("scale,1.333");
+        assert_eq!(settings.output_scale(), 160.0 / 120.0);
+        settings.handle_simple_message("scale,1.004");
+        assert_eq!(settings.output_scale(), 1.0);
+        settings.handle_simple_message("scale,3");
+        assert_eq!(settings.output_scale(), 2.0);
+        settings.handle_simple_message("scale,0.5");
+        assert_eq!(settings.output_scale(), 1.0);
+        // Unparseable or non-finite values leave the scale alone
+        settings.handle_simple_message("scale,1.25");
+        for bad in ["scale,big", "scale,NaN", "scale,inf"] {
+            assert!(settings.handle_simple_message(bad));
+            assert_eq!(settings.output_scale(), 1.25);
+        }
+    }
+
+    #[test]
+    fn output_scale_is_refused_by_default() {
+        let settings = settings_with_max_scale(1.0);
+        settings.handle_simple_message("scale,2");
+        assert_eq!(settings.output_scale(), 1.0);
     }
 }
@@ -0,0 +1,268 @@
+//! Preflight self-test (`ivnc --selftest`)
+//!
+//! Checks runtime libraries and GStreamer plugins, then builds a short-lived
+//! encoder pipeline, pushes a few synthetic frames and waits for RTP output.
+//! Nothing else is started (no compositor, no HTTP server).
+
+use ::gstreamer as gst;
+use ivnc::gstreamer::encoder::{required_elements, ElementRequirement};
+use ivnc::gstreamer::{PipelineConfig, VideoPipeline};
+use ivnc::Config;
+use std::time::{Duration, Instant};
+
+const TEST_WIDTH: u32 = 320;
+const TEST_HEIGHT: u32 = 240;
+const TEST_FRAMES: u32 = 30;
+const RTP_WAIT: Duration = Duration::from_secs(3);
+
+/// Shared libraries loaded at runtime: (soname, Debian package)
+const CORE_LIBS: &[(&str, &str)] = &[
+    ("libgstreamer-1.0.so.0", "libgstreamer1.0-0"),
+    ("libgstapp-1.0.so.0", "libgstreamer-plugins-base1.0-0"),
+    ("libpixman-1.so.0", "libpixman-1-0"),
+    ("libxkbcommon.so.0", "libxkbcommon0"),
+];
+
+/// Libraries needed only when audio capture is enabled. Audio is encoded
+/// with libopus directly, not through GStreamer's opusenc/rtpopuspay.
+const AUDIO_LIBS: &[(&str, &str)] = &[
+    #[cfg(feature = "pulseaudio")]
+    ("libpulse-simple.so.0", "libpulse0"),
+    #[cfg(any(feature = "pulseaudio", feature = "audio"))]
+    ("libopus.so.0", "libopus0"),
+];
+
+/// GStreamer elements that are nice to have but not needed by the
+/// configured pipeline: (element, Debian package)
+const OPTIONAL_GST_PLUGINS: &[(&str, &str)] = &[
+    ("rtph264pay", "gstreamer1.0-plugins-good"),
+    ("rtpvp8pay", "gstreamer1.0-plugins-good"),
+    ("openh264enc", "gstreamer1.0-plugins-bad"),
+    ("ximagesrc", "gstreamer1.0-x"),
+];
+
+/// Runtime libraries that cannot be dlopen'ed
+pub fn missing_runtime_libs(audio_enabled: bool) -> Vec<(&'static str, &'static str)> {
+    let audio: &[(&str, &str)] = if audio_enabled { AUDIO_LIBS } else { &[] };
+    let mut missing = Vec::new();
+    for &(soname, pkg) in CORE_LIBS.iter().chain(audio) {
+        let cstr = std::ffi::CString::new(soname).unwrap();
+        let handle = unsafe { libc::dlopen(cstr.as_ptr(), libc::RTLD_LAZY) };
+        if handle.is_null() {
+            missing.push((soname, pkg));
+        } else {
+            unsafe { libc::dlclose(handle); }
+        }
+    }
+    missing
+}
+
+/// Pipeline elements the configured codec/encoder cannot run without and
+/// that are not registered (requires `gst::init`)
+pub fn missing_required_plugins(config: &Config) -> Vec<ElementRequirement> {
+    required_elements(config.webrtc.video_codec, config.webrtc.hardware_encoder)
+        .into_iter()
+        .filter(|req| !req.satisfied())
+        .collect()
+}
+
+/// Optional elements with no registered factory (requires `gst::init`)
+pub fn missing_optional_plugins(config: &Config) -> Vec<(&'static str, &'static str)> {
+    let required = required_elements(config.webrtc.video_codec, config.webrtc.hardware_encoder);
+    OPTIONAL_GST_PLUGINS
+        .iter()
+        .copied()
+        .filter(|(element, _)| !required.iter().any(|r| r.any_of.contains(element)))
+        .filter(|(element, _)| gst::ElementFactory::find(element).is_none())
+        .collect()
+}
+
+/// One-line description of an unmet requirement, with an install hint
+pub fn describe_requirement(req: &ElementRequirement) -> String {
+    if req.any_of.is_empty() {
+        format!("{}: no such encoder exists (change webrtc.hardware_encoder or video_codec)", req.role)
+    } else {
+        format!(
+            "{}: none of [{}] found (package: {})",
+            req.role,
+            req.any_of.join(", "),
+            req.packages().join(" or ")
+        )
+    }
+}
+
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn pass(&self, what: &str, detail: &str) {
+        println!("[PASS] {}: {}", what, detail);
+    }
+
+    fn fail(&mut self, what: &str, detail: &str) {
+        self.failures += 1;
+        println!("[FAIL] {}: {}", what, detail);
+    }
+
+    fn warn(&self, what: &str, detail: &str) {
+        println!("[WARN] {}: {}", what, detail);
+    }
+}
+
+/// Run all checks and print a report. Returns true if everything passed.
+pub fn run(config: Result<Config, ivnc::Error>) -> bool {
+    let mut report = Report { failures: 0 };
+    println!("ivnc v{} self-test", env!("CARGO_PKG_VERSION"));
+
+    let config = match config {
+        Ok(c) => match c.validate() {
+            Ok(()) => {
+                report.pass("configuration", "valid");
+                c
+            }
+            Err(e) => {
+                report.fail("configuration", &e.to_string());
+                c
+            }
+        },
+        Err(e) => {
+            report.fail("configuration", &format!("{} (checking against defaults)", e));
+            Config::default()
+        }
+    };
+
+    let missing = missing_runtime_libs(config.audio.enabled);
+    if missing.is_empty() {
+        report.pass("runtime libraries", "all found");
+    } else {
+        for (soname, pkg) in &missing {
+            report.fail("runtime library", &format!("{} missing (package: {})", soname, pkg));
+        }
+    }
+
+    if let Err(e) = gst::init() {
+        report.fail("GStreamer init", &e.to_string());
+        return finish(report);
+    }
+    report.pass("GStreamer init", &gst::version_string());
+
+    let missing = missing_required_plugins(&config);
+    if missing.is_empty() {
+        report.pass("GStreamer plugins", "all required elements found");
+    } else {
+        for req in &missing {
+            report.fail("GStreamer plugin", &describe_requirement(req));
+        }
+    }
+    for (element, pkg) in missing_optional_plugins(&config) {
+        report.warn("GStreamer plugin", &format!("optional {} missing (package: {})", element, pkg));
+    }
+
+    encode_test(&config, &mut report);
+    finish(report)
+}
+
+fn finish(report: Report) -> bool {
+    if report.failures == 0 {
+        println!("\nSelf-test passed");
+        true
+    } else {
+        println!("\nSelf-test failed ({} check(s))", report.failures);
+        false
+    }
+}
+
+/// Build the configured encoder at a small size and verify it emits RTP
+fn encode_test(config: &Config, report: &mut Report) {
+    let pipeline_config = PipelineConfig {
+        width: TEST_WIDTH,
+        height: TEST_HEIGHT,
+        framerate: 30,
+        codec: config.webrtc.video_codec,
+        bitrate: 1000,
+        hardware_encoder: config.webrtc.hardware_encoder,
+        keyframe_interval: TEST_FRAMES,
+        latency_ms: config.webrtc.pipeline_latency_ms,
+        max_latency_ms: config.webrtc.max_latency_ms,
+        queue_policy: config.encoding.appsrc_queue,
+        queue_max_bytes: config.encoding.appsrc_max_bytes,
+        scale_divisor: 1,
+        render_scale: 1.0,
+        encode_cpus: Vec::new(),
+        realtime: None,
+        color_range: config.webrtc.color_range,
+        color_matrix: config.webrtc.color_matrix,
+        bit_depth: config.webrtc.bit_depth,
+        pixel_format: config.display.pixel_format,
+    };
+
+    let pipeline = match VideoPipeline::new(pipeline_config) {
+        Ok(p) => p,
+        Err(e) => {
+            report.fail("encoder pipeline", &e.to_string());
+            return;
+        }
+    };
+    if let Err(e) = pipeline.start() {
+        report.fail("encoder pipeline", &e.to_string());
+        return;
+    }
+    report.pass(
+        "encoder pipeline",
+        &format!("{:?} via {}", config.webrtc.video_codec, pipeline.encoder_name()),
+    );
+
+    let frame_len = (TEST_WIDTH * TEST_HEIGHT * 4) as usize;
+    let mut rtp = RtpTally::default();
+    for i in 0..TEST_FRAMES {
+        let shade = (i * 8) as u8;
+        let frame: Vec<u8> = (0..frame_len)
+            .map(|b| if b % 4 == 3 { 0 } else { shade.wrapping_add((b / 4 % 256) as u8) })
+            .collect();
+        if let Err(e) = pipeline.push_frame(&frame, None) {
+            report.fail("frame push", &e.to_string());
+            let _ = pipeline.stop();
+            return;
+        }
+        rtp.drain(&pipeline, 0);
+        std::thread::sleep(Duration::from_millis(33));
+    }
+
+    let deadline = Instant::now() + RTP_WAIT;
+    while rtp.packets == 0 && Instant::now() < deadline {
+        rtp.drain(&pipeline, 100);
+    }
+    let _ = pipeline.stop();
+
+    if rtp.packets == 0 {
+        report.fail("RTP output", &format!("no packets within {:?} of {} frames", RTP_WAIT, TEST_FRAMES));
+    } else if rtp.bad_header {
+        report.fail("RTP output", "packets without a valid RTP header");
+    } else {
+        report.pass("RTP output", &format!("{} packets, {} bytes", rtp.packets, rtp.bytes));
+    }
+}
+
+#[derive(Default)]
+struct RtpTally {
+    packets: u64,
+    bytes: u64,
+    bad_header: bool,
+}
+
+impl RtpTally {
+    fn drain(&mut self, pipeline: &VideoPipeline, timeout_ms: u64) {
+        while let Some(sample) = pipeline.try_pull_sample_timeout(timeout_ms) {
+            let Some(buffer) = sample.buffer() else { continue };
+            let Ok(map) = buffer.map_readable() else { continue };
+            let data = map.as_slice();
+            // RTP version 2 in the top two bits
+            if data.len() < 12 || data[0] >> 6 != 2 {
+                self.bad_header = true;
+            }
+            self.packets += 1;
+            self.bytes += data.len() as u64;
+        }
+    }
+}
@@ -0,0 +1,2314 @@
+//! Embeddable streaming server
+//!
+//! [`ServerBuilder`] wires a [`Config`] into a [`Server`] that owns the
+//! compositor loop, GStreamer pipeline and HTTP/WebRTC services. The `ivnc`
+//! binary is a thin wrapper around it.
+
+use base64::Engine;
+use log::{debug, info, error, warn};
+use smithay::reexports::wayland_server::Resource;
+use std::env;
+use std::io::Read;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::clipboard::ClipboardContent;
+use crate::audio::{resolve_backend, run_audio_capture, validate_source, AudioConfig as RuntimeAudioConfig, AudioStatus};
+use crate::compositor::{Compositor, DisplayError, HeadlessBackend};
+use crate::config::{self, AudioBackend, Config};
+use crate::error::{Error, Result};
+use crate::hooks::Hooks;
+use crate::gstreamer::{self, PipelineConfig};
+use crate::input::{InputEvent, InputEventData};
+use crate::web::shared::RuntimeStats;
+use crate::overlay::StatsOverlay;
+use crate::frame_clock::{FrameClock, LatencyBudget};
+use crate::watchdog::{Heartbeat, LoopStage};
+use crate::webrtc::{self, SessionManager};
+use crate::{cpu_affinity, file_upload, runtime_settings, web};
+
+/// Frames tracked between push and RTP output for frame latency; more in
+/// flight means the encoder dropped some
+const MAX_FRAMES_IN_FLIGHT: usize = 32;
+
+/// Shortest measurement window for stats requested on demand (`getstats`)
+const ON_DEMAND_STATS_MIN_WINDOW: Duration = Duration::from_millis(100);
+
+/// Called with `(width, height, pixels)` for every rendered frame; pixels are
+/// in `display.pixel_format` (XRGB8888 by default)
+pub type FrameCallback = Box<dyn FnMut(u32, u32, &[u8]) + Send>;
+
+/// Called with the aggregated stats once per second
+pub type StatsCallback = Box<dyn FnMut(&RuntimeStats) + Send>;
+
+/// Builder for [`Server`]
+pub struct ServerBuilder {
+    config: Config,
+    on_frame: Option<FrameCallback>,
+    on_stats: Option<StatsCallback>,
+    hooks: Option<Arc<dyn Hooks>>,
+    #[cfg(feature = "mcp")]
+    mcp_stdio: bool,
+}
+
+impl ServerBuilder {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            on_frame: None,
+            on_stats: None,
+            hooks: None,
+            #[cfg(feature = "mcp")]
+            mcp_stdio: false,
+        }
+    }
+
+    /// Observe every rendered frame before it is pushed to the encoder.
+    /// Runs on the compositor thread, so keep it cheap.
+    pub fn on_frame<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(u32, u32, &[u8]) + Send + 'static,
+    {
+        self.on_frame = Some(Box::new(f));
+        self
+    }
+
+    /// Observe the stats snapshot that is broadcast to clients each stats
+    /// interval (`server.stats_interval_ms`)
+    pub fn on_stats<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&RuntimeStats) + Send + 'static,
+    {
+        self.on_stats = Some(Box::new(f));
+        self
+    }
+
+    /// Install session/input hooks (see [`Hooks`])
+    pub fn hooks<H: Hooks + 'static>(mut self, hooks: H) -> Self {
+        self.hooks = Some(Arc::new(hooks));
+        self
+    }
+
+    /// Also serve MCP on stdin/stdout
+    #[cfg(feature = "mcp")]
+    pub fn mcp_stdio(mut self, enabled: bool) -> Self {
+        self.mcp_stdio = enabled;
+        self
+    }
+
+    /// Validate the config and set up the shared state
+    pub fn build(self) -> Result<Server> {
+        self.config.validate()?;
+
+        let runtime_settings = Arc::new(runtime_settings::RuntimeSettings::new(&self.config));
+        let (input_tx, input_rx) = mpsc::unbounded_channel::<InputEventData>();
+        let ui_config = config::ui::UiConfig::from_env(&self.config);
+        let mut shared_state = web::SharedState::new(
+            self.config.clone(), ui_config, input_tx, runtime_settings.clone(),
+        );
+        if let Some(hooks) = self.hooks {
+            shared_state.hooks = hooks;
+        }
+        let shared_state = Arc::new(shared_state);
+
+        Ok(Server {
+            config: self.config,
+            shared_state,
+            runtime_settings,
+            input_rx,
+            running: Arc::new(AtomicBool::new(true)),
+            on_frame: self.on_frame,
+            on_stats: self.on_stats,
+            #[cfg(feature = "mcp")]
+            mcp_stdio: self.mcp_stdio,
+        })
+    }
+}
+
+/// A configured streaming server, ready to run
+pub struct Server {
+    config: Config,
+    shared_state: Arc<web::SharedState>,
+    runtime_settings: Arc<runtime_settings::RuntimeSettings>,
+    input_rx: mpsc::UnboundedReceiver<InputEventData>,
+    running: Arc<AtomicBool>,
+    on_frame: Option<FrameCallback>,
+    on_stats: Option<StatsCallback>,
+    #[cfg(feature = "mcp")]
+    mcp_stdio: bool,
+}
+
+/// Stops a running [`Server`] from another thread
+#[derive(Clone)]
+pub struct ShutdownHandle(Arc<AtomicBool>);
+
+impl ShutdownHandle {
+    /// Ask the compositor loop to exit after the current iteration
+    pub fn shutdown(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Server {
+    pub fn builder(config: Config) -> ServerBuilder {
+        ServerBuilder::new(config)
+    }
+
+    /// State shared with the web/WebRTC/MCP layers (input sender, broadcasts, stats)
+    pub fn shared_state(&self) -> &Arc<web::SharedState> {
+        &self.shared_state
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.running.clone())
+    }
+
+    /// Run the compositor loop on the calling thread until shut down.
+    ///
+    /// Sets `WAYLAND_DISPLAY`, `XDG_RUNTIME_DIR` and GTK variables for the
+    /// process, and owns its own tokio runtime for the HTTP/WebRTC services,
+    /// so it must not be called from inside an async context.
+    pub fn run(self) -> Result<()> {
+        run_server(self)
+    }
+
+    /// Run the server on a dedicated thread and wait for it to finish.
+    ///
+    /// Startup modifies the process environment (see [`Server::run`]) while
+    /// the caller's runtime threads are live. Avoid anything that reads the
+    /// environment (`std::env`, `getaddrinfo`, spawning processes) on other
+    /// threads until the server is up.
+    pub async fn run_async(self) -> Result<()> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        std::thread::Builder::new().name("ivnc-server".into()).spawn(move || {
+            let _ = tx.send(self.run());
+        })?;
+        rx.await.unwrap_or_else(|_| {
+            Err(Error::Io(std::io::Error::other("server thread exited without a result")))
+        })
+    }
+}
+
+fn run_server(server: Server) -> Result<()> {
+    let Server {
+        mut config,
+        shared_state,
+        runtime_settings,
+        mut input_rx,
+        running,
+        mut on_frame,
+        mut on_stats,
+        #[cfg(feature = "mcp")]
+        mcp_stdio,
+    } = server;
+    // The startup size follows `display.aspect_ratio` like any resize
+    let (width, height) = config.display.fit_aspect(config.display.width, config.display.height);
+    if (width, height) != (config.display.width, config.display.height) {
+        info!(
+            "Display {}x{} adjusted to {}x{} for aspect ratio {}",
+            config.display.width, config.display.height, width, height,
+            config.display.aspect_ratio.as_deref().unwrap_or_default()
+        );
+        shared_state.set_display_size(width, height);
+    }
+
+    use smithay::reexports::calloop::EventLoop;
+    use smithay::reexports::wayland_server::Display;
+
+    // set_var is only sound while no other thread reads the environment.
+    // The calls below run before this function starts its own threads (the
+    // tokio runtime is created later). Under the ivnc binary, `Server::run`
+    // is called from main with no other threads, so that is enough. An
+    // embedder's threads are outside our control: `run_async` callers must
+    // not read the environment until the server is up (see its docs).
+
+    // Ensure XDG_RUNTIME_DIR is set (required for Wayland socket)
+    if env::var("XDG_RUNTIME_DIR").is_err() {
+        let dir = format!("/run/user/{}", unsafe { libc::getuid() });
+        std::fs::create_dir_all(&dir).ok();
+        env::set_var("XDG_RUNTIME_DIR", &dir);
+        info!("Set XDG_RUNTIME_DIR={}", dir);
+    }
+
+    // Clean up wayland sockets: kill non-ivnc processes occupying them
+    if let Ok(xdg_dir) = env::var("XDG_RUNTIME_DIR") {
+        for i in 0..3 {
+            let sock = format!("{}/wayland-{}", xdg_dir, i);
+            let lock = format!("{}.lock", sock);
+            if !std::path::Path::new(&sock).exists() {
+                continue;
+            }
+            if std::os::unix::net::UnixStream::connect(&sock).is_err() {
+                // Stale socket, just remove
+                std::fs::remove_file(&sock).ok();
+                std::fs::remove_file(&lock).ok();
+                continue;
+            }
+            // Socket is alive — find and kill the non-ivnc listener
+            if let Ok(output) = std::process::Command::new("fuser").arg(&sock).output() {
+                let pids_str = String::from_utf8_lossy(&output.stdout);
+                for token in pids_str.split_whitespace() {
+                    let pid: i32 = match token.trim().parse() {
+                        Ok(p) if p > 1 => p,
+                        _ => continue,
+                    };
+                    // Check if it's our own process
+                    if pid == std::process::id() as i32 {
+                        continue;
+                    }
+                    // Read process name
+                    let comm = std::fs::read_to_string(format!("/proc/{}/comm", pid))
+                        .unwrap_or_default();
+                    if comm.trim() == "ivnc" {
+                        continue;
+                    }
+                    warn!("Killing non-ivnc process {} ({}) occupying {}", pid, comm.trim(), sock);
+                    unsafe { libc::kill(pid, libc::SIGKILL); }
+                }
+                std::thread::sleep(Duration::from_millis(200));
+                std::fs::remove_file(&sock).ok();
+                std::fs::remove_file(&lock).ok();
+            }
+        }
+    }
+
+    let mut event_loop: EventLoop<Compositor> = EventLoop::try_new()
+        .map_err(|e| DisplayError::InitFailed(e.to_string()))?;
+    let display: Display<Compositor> = Display::new()
+        .map_err(|e| DisplayError::InitFailed(e.to_string()))?;
+    let mut comp = Compositor::new(&mut event_loop, display);
+    comp.focus_new_windows = config.input.focus_new_windows;
+
+    let mut backend = HeadlessBackend::new(width, height, config.display.pixel_format)?;
+    let _output_global = backend.output().create_global::<Compositor>(&comp.display_handle);
+    comp.space.map_output(backend.output(), (0, 0));
+
+    let socket_name = comp.socket_name.clone();
+    env::set_var("WAYLAND_DISPLAY", &socket_name);
+    // Disable GTK3 CSD via gtk3-nocsd (process-level env only)
+    env::set_var("GTK_CSD", "0");
+    let nocsd_lib = "/lib/x86_64-linux-gnu/libgtk3-nocsd.so.0";
+    if std::path::Path::new(nocsd_lib).exists() {
+        let existing = env::var("LD_PRELOAD").unwrap_or_default();
+        if existing.is_empty() {
+            env::set_var("LD_PRELOAD", nocsd_lib);
+        } else {
+            env::set_var("LD_PRELOAD", format!("{}:{}", existing, nocsd_lib));
+        }
+        info!("gtk3-nocsd enabled via LD_PRELOAD");
+    }
+    // Set up GTK CSS to hide headerbars on fullscreen windows only.
+    // Dialogs are excluded so their controls (Open/Cancel buttons) stay visible.
+    setup_gtk_css_env();
+    info!("Wayland socket: {:?}", socket_name);
+
+    // GStreamer pipeline
+    let pipeline_config = pipeline_config_for(&config, width, height, 1, 1.0);
+    let mut pipeline = gstreamer::VideoPipeline::new(pipeline_config)?;
+    // A renderer/appsrc layout mismatch would swap red and blue silently.
+    // The backend reports the layout its readback actually delivers.
+    let capture_format = backend.pixel_format();
+    let input_format = pipeline.input_format();
+    if input_format.as_deref() != Some(capture_format.gst_format()) {
+        return Err(Error::Config(format!(
+            "renderer produces {} ({}) but the encoder pipeline expects {}",
+            capture_format.as_str(),
+            capture_format.gst_format(),
+            input_format.as_deref().unwrap_or("an unknown format")
+        )));
+    }
+    pipeline.start()?;
+    info!("GStreamer pipeline started (encoder: {})", pipeline.encoder_name());
+    let (stream_w, stream_h) = pipeline.config().encoded_size();
+    shared_state.set_stream_size(stream_w, stream_h);
+    shared_state.video_bit_depth.store(pipeline.bit_depth(), Ordering::Relaxed);
+    // Frames go to a dedicated encode thread unless pushed synchronously
+    let encode_queue = match config.encoding.encode_queue_depth {
+        0 => None,
+        depth => {
+            info!("Encoding on a separate thread (queue depth {})", depth);
+            Some(gstreamer::EncodeQueue::new(depth)?)
+        }
+    };
+
+    // Tokio runtime for async services
+    let tokio_rt = tokio::runtime::Runtime::new()?;
+    #[cfg(feature = "gpu-metrics")]
+    if let Some(sampler) = crate::gpu_usage::GpuSampler::for_encoder(pipeline.encoder_name()) {
+        tokio_rt.spawn(crate::gpu_usage::run_sampler(shared_state.clone(), sampler));
+    }
+    {
+        let st = shared_state.clone();
+        let r = running.clone();
+        let c = config.clone();
+        let rs = runtime_settings.clone();
+        tokio_rt.spawn(async move {
+            #[cfg(feature = "mcp")]
+            let result = run_async_services(c, st, rs, r, mcp_stdio).await;
+            #[cfg(not(feature = "mcp"))]
+            let result = run_async_services(c, st, rs, r).await;
+            if let Err(e) = result {
+                error!("Async services error: {}", e);
+            }
+        });
+    }
+
+    // Ensure PulseAudio is running (needed for audio capture)
+    #[cfg(feature = "pulseaudio")]
+    if config.audio.enabled && matches!(config.audio.backend, AudioBackend::Auto | AudioBackend::Pulse) {
+        ensure_pulseaudio();
+    }
+
+    // Audio capture thread
+    if config.audio.enabled {
+        let backend = resolve_backend(config.audio.backend);
+        info!("Starting audio capture thread (backend={}{} rate={} ch={} bitrate={})",
+            backend.as_str(),
+            if config.audio.backend == AudioBackend::Auto { " (auto)" } else { "" },
+            config.audio.sample_rate, config.audio.channels, config.audio.bitrate);
+        shared_state.stats.lock().unwrap().audio_backend = Some(backend.as_str());
+        let source = config.audio.source.as_deref().and_then(|s| validate_source(backend, s));
+        if config.audio.app_id.is_some() && backend != AudioBackend::Pulse {
+            warn!("audio.app_id needs the PulseAudio backend; capturing the full mix");
+        }
+        // Opus only codes FEC in SILK/hybrid mode, which high bitrates skip
+        if config.audio.fec && config.audio.bitrate > 32_000 * config.audio.channels as u32 {
+            warn!("audio.fec has little effect at {} bps; Opus adds FEC at lower bitrates", config.audio.bitrate);
+        }
+        let r = running.clone();
+        let ac = config.audio.clone();
+        let (audio_tx, mut audio_rx) = mpsc::unbounded_channel();
+        let st = shared_state.clone();
+        tokio_rt.spawn(async move {
+            while let Some(pkt) = audio_rx.recv().await {
+                st.broadcast_audio(pkt);
+            }
+        });
+        let status_state = shared_state.clone();
+        std::thread::Builder::new().name("audio-capture".into()).spawn(move || {
+            info!("Audio capture thread started");
+            let rt_audio = RuntimeAudioConfig {
+                backend,
+                source,
+                sample_rate: ac.sample_rate, channels: ac.channels, bitrate: ac.bitrate,
+                max_retries: ac.max_retries,
+                app_id: ac.app_id.clone(),
+                fec_loss_percent: ac.fec.then_some(ac.fec_loss_percent),
+                dtx: ac.dtx,
+            };
+            // Video is unaffected; clients are only told the audio state
+            let on_status = |status: AudioStatus| status_state.send_text(status.message().to_string());
+            match run_audio_capture(rt_audio, audio_tx, r, on_status) {
+                Ok(()) => info!("Audio capture thread exited normally"),
+                Err(e) => warn!("Audio capture ended with error: {}", e),
+            }
+        })?;
+    } else {
+        info!("Audio capture disabled in config");
+    }
+
+    // Pin the compositor loop. Streaming threads of later pipeline rebuilds
+    // are started from this thread and would inherit its mask, so without
+    // encode_cpu they get the original one back.
+    if !config.server.compositor_cpu.is_empty() {
+        if config.server.encode_cpu.is_empty() {
+            config.server.encode_cpu = cpu_affinity::allowed_cpus().unwrap_or_default();
+        }
+        match cpu_affinity::pin_current_thread(&config.server.compositor_cpu) {
+            Ok(()) => info!("Compositor loop pinned to CPUs {:?}", config.server.compositor_cpu),
+            Err(e) => warn!("Failed to pin compositor loop to CPUs {:?}: {}", config.server.compositor_cpu, e),
+        }
+    }
+    if config.server.realtime != config::RealtimePolicy::Off {
+        match cpu_affinity::set_current_thread_realtime(config.server.realtime, config.server.realtime_priority) {
+            Ok(()) => info!(
+                "Compositor loop uses {} priority {}",
+                config.server.realtime.as_str(), config.server.realtime_priority
+            ),
+            Err(e) => warn!(
+                "Compositor loop keeps normal scheduling: {}{}",
+                e, cpu_affinity::realtime_hint(&e)
+            ),
+        }
+    }
+
+    // Main compositor loop
+    let target_fps = shared_state.config.encoding.target_fps.max(1);
+    let mut frame_clock = FrameClock::new(target_fps, Instant::now());
+    let mut frames_skipped: u64 = 0;
+    let mut latency_budget = (config.encoding.latency_budget_ms > 0).then(|| {
+        LatencyBudget::new(config.encoding.latency_budget_ms, config.encoding.cpu_budget_percent)
+    });
+    let mut frames_dropped: u64 = 0;
+    let mut frames_queue_dropped: u64 = 0;
+    // Refreshed once per stats interval for the latency budget. The sampled
+    // usage is per core (400% = four busy cores); the budget is a share of
+    // every core the process may run on
+    let mut cpu_percent = 0.0;
+    let cpu_cores = std::thread::available_parallelism().map_or(1, |n| n.get()) as f64;
+    let mut last_stats = Instant::now();
+    let stats_interval = Duration::from_millis(config.server.stats_interval_ms);
+    // Whatever the pipeline leaves of the latency budget
+    let rtp_flush_after = Duration::from_millis(
+        config.webrtc.max_latency_ms.saturating_sub(config.webrtc.pipeline_latency_ms).max(10) as u64,
+    );
+    let mut overlay = config.overlay.enabled.then(|| StatsOverlay::new(config.overlay.clone()));
+    let mut frame_count: u64 = 0;
+    let mut byte_count: u64 = 0;
+
+    let mut render_frames: u64 = 0;
+    let mut rtp_packets: u64 = 0;
+    let mut prev_window_count: usize = 0;
+    let mut keyframe = webrtc::media_track::KeyframeAssembler::default();
+    let mut rtp_frame = RtpFrameBuffer::default();
+    // Push times of frames not yet out of the encoder, for frame latency
+    let mut frame_push_times = webrtc::media_clock::PushTimes::new(MAX_FRAMES_IN_FLIGHT);
+    // Encode scale last applied from the client's `s,` request
+    let mut render_scale = pipeline.config().render_scale;
+    let mut last_render = Instant::now();
+    let mut had_sessions = false;
+    let mut prev_button_mask: u32 = 0;
+    let mut key_repeater = config.input.key_repeat.then(|| {
+        // Repeats are synthesized here, so tell clients not to run their own timers
+        comp.seat.get_keyboard().unwrap().change_repeat_info(0, 0);
+        comp.server_key_repeat = Some((config.input.key_repeat_delay_ms, config.input.key_repeat_rate));
+        info!(
+            "Server-side key repeat: {} ms delay, {}/s",
+            config.input.key_repeat_delay_ms, config.input.key_repeat_rate
+        );
+        crate::input::KeyRepeater::new(config.input.key_repeat_delay_ms, config.input.key_repeat_rate)
+    });
+    let mut input_recorder = config.input.record_dir.as_ref().map(|dir| {
+        crate::input_recording::InputRecorder::new(dir.into(), config.input.record_redaction)
+    });
+    let (disp_w, disp_h) = shared_state.display_size();
+    let mut prev_cursor_pos: (f64, f64) = (disp_w as f64 / 2.0, disp_h as f64 / 2.0);
+    let mut prev_cursor_serial: Option<u64> = None;
+    let mut cursor_png_cache = crate::compositor::cursor::CursorPngCache::default();
+    let mut prev_cursor_msg = cursor_message(&comp.cursor_status, &mut cursor_png_cache);
+    shared_state.update_cursor_message(prev_cursor_msg.clone());
+    // Server-side cursor (`SET_NATIVE_CURSOR_RENDERING,1`): the image blended
+    // into frames and where it was last drawn
+    let mut server_cursor = false;
+    let mut cursor_bitmap: Option<crate::compositor::cursor::CursorBitmap> = None;
+    let mut drawn_cursor_pos: Option<(i32, i32)> = None;
+    // Throttled pointer position broadcast (`input.cursor_position_hz`)
+    let cursor_pos_interval = (config.input.cursor_position_hz > 0)
+        .then(|| Duration::from_secs_f64(1.0 / config.input.cursor_position_hz as f64));
+    let mut sent_cursor_pos: Option<(i32, i32)> = None;
+    let mut last_cursor_pos_sent = Instant::now();
+    let mut prev_taskbar_json: String = String::new();
+    // Non-blocking clipboard pipe read state
+    let mut clipboard_pipe: Option<std::fs::File> = None;
+    let mut clipboard_pipe_buf: Vec<u8> = Vec::new();
+    let mut clipboard_pipe_total: usize = 0;
+    // MIME type requested for `comp.clipboard_read_fd` and for the open pipe
+    let mut clipboard_read_mime = String::new();
+    let mut clipboard_pipe_mime = String::new();
+    let max_clipboard_bytes = shared_state.config.input.max_clipboard_bytes;
+
+    let heartbeat = Arc::new(Heartbeat::new());
+    heartbeat.enter(LoopStage::Dispatch);
+    if config.server.watchdog_timeout_secs > 0 {
+        crate::watchdog::spawn(
+            heartbeat.clone(),
+            Duration::from_secs(config.server.watchdog_timeout_secs),
+            config.server.watchdog_action,
+            shared_state.clone(),
+            running.clone(),
+        )?;
+    }
+
+    info!("Compositor loop starting at {} fps", target_fps);
+
+    while running.load(Ordering::Relaxed) {
+        heartbeat.enter(LoopStage::Dispatch);
+        event_loop.dispatch(Some(Duration::from_millis(1)), &mut comp)
+            .map_err(|e| DisplayError::DispatchFailed(e.to_string()))?;
+        comp.space.refresh();
+        comp.popups.cleanup();
+        comp.display_handle.flush_clients().ok();
+
+        heartbeat.enter(LoopStage::Clipboard);
+        // Deferred clipboard read: new_selection saved the mime type but couldn't
+        // call request_data_device_client_selection because smithay hadn't updated
+        // the seat's selection yet. Now after dispatch() it's safe to request.
+        // With the clipboard disabled, remote selections are never read.
+        if let Some(mime) = shared_state.deferred_clipboard_read(comp.clipboard_pending_mime.take()) {
+            use std::os::fd::{AsRawFd, FromRawFd};
+            use smithay::wayland::selection::data_device::request_data_device_client_selection;
+
+            let mut fds = [0i32; 2];
+            if unsafe { libc::pipe(fds.as_mut_ptr()) } == 0 {
+                let read_fd = unsafe { std::os::fd::OwnedFd::from_raw_fd(fds[0]) };
+                let write_fd = unsafe { std::os::fd::OwnedFd::from_raw_fd(fds[1]) };
+                // Set read end to non-blocking
+                unsafe {
+                    let flags = libc::fcntl(read_fd.as_raw_fd(), libc::F_GETFL);
+                    if flags >= 0 {
+                        libc::fcntl(read_fd.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK);
+                    }
+                }
+                info!("Deferred clipboard: requesting client data for mime={}", mime);
+                if request_data_device_client_selection::<Compositor>(&comp.seat, mime.clone(), write_fd).is_ok() {
+                    comp.clipboard_read_fd = Some(read_fd);
+                    clipboard_read_mime = mime;
+                    // Flush immediately so the client receives the fd and can write data
+                    comp.display_handle.flush_clients().ok();
+                } else {
+                    warn!("Deferred clipboard: request_data_device_client_selection failed");
+                }
+            } else {
+                warn!("Deferred clipboard: pipe() failed");
+            }
+        }
+
+        // Browser clipboard → remote compositor (drain all pending items).
+        // Process BEFORE input events so that when Ctrl+V arrives, the
+        // clipboard selection is already set and the app can read it.
+        {
+            let mut rx = shared_state.clipboard_incoming_rx.lock().unwrap();
+            while let Ok(content) = rx.try_recv() {
+                info!(
+                    "Clipboard from browser: {} bytes{}",
+                    content.text.len(),
+                    if content.html.is_some() { " (HTML)" } else { "" }
+                );
+                offer_clipboard(&mut comp, content, &config.input.clipboard_mime_types);
+                // Suppress client clipboard re-assertions for a short window.
+                // The focused client (e.g. Chromium) will re-assert its own
+                // wl_data_source with stale content in response to our selection change.
+                comp.clipboard_suppress_until = Some(Instant::now() + Duration::from_millis(500));
+            }
+        }
+        comp.display_handle.flush_clients().ok();
+
+        heartbeat.enter(LoopStage::Input);
+        drain_input_events(
+            &mut input_rx,
+            &mut comp,
+            &shared_state,
+            &mut prev_button_mask,
+            &mut prev_cursor_pos,
+            key_repeater.as_mut(),
+            input_recorder.as_mut(),
+        );
+        if let Some(ref mut repeater) = key_repeater {
+            let now = Instant::now();
+            if let Some((keysym, count)) = repeater.due(now) {
+                inject_key_repeats(&mut comp, keysym, count);
+            }
+            // Multi-seat: each session's held key repeats on its own seat
+            for (session, keysym, count) in comp.session_seats.due_repeats(now) {
+                if let Some(entry) = comp.session_seats.swap_in(&mut comp.seat, &session) {
+                    inject_key_repeats(&mut comp, keysym, count);
+                    comp.put_back_session_seat(session, entry);
+                }
+            }
+        }
+        comp.display_handle.flush_clients().ok(); // flush injected input events immediately
+        heartbeat.enter(LoopStage::Clipboard);
+
+        // Read clipboard from Wayland client (remote → browser).
+        // The pipe read fd is non-blocking so we accumulate data across
+        // loop iterations without deadlocking the compositor.
+        if let Some(fd) = comp.clipboard_read_fd.take() {
+            clipboard_pipe_buf.clear();
+            clipboard_pipe_total = 0;
+            clipboard_pipe = Some(std::fs::File::from(fd));
+            clipboard_pipe_mime = std::mem::take(&mut clipboard_read_mime);
+        }
+        if let Some(ref mut file) = clipboard_pipe {
+            let mut tmp = [0u8; 4096];
+            loop {
+                match file.read(&mut tmp) {
+                    Ok(0) => {
+                        // EOF — client closed write end, data is complete
+                        if !clipboard_pipe_buf.is_empty() {
+                            let data = std::mem::take(&mut clipboard_pipe_buf);
+                            shared_state.publish_remote_clipboard(&clipboard_pipe_mime, data, clipboard_pipe_total);
+                        }
+                        clipboard_pipe_buf.clear();
+                        clipboard_pipe = None;
+                        // Rich-text apps offer HTML too; fetch it once the text is in
+                        if comp.clipboard_pending_html && clipboard_pipe_mime != "text/html" {
+                            comp.clipboard_pending_html = false;
+                            if comp.clipboard_pending_mime.is_none() {
+                                comp.clipboard_pending_mime = Some("text/html".to_string());
+                            }
+                        }
+                        break;
+                    }
+                    Ok(n) => {
+                        // Keep draining the pipe past the limit, but stop buffering.
+                        clipboard_pipe_total += n;
+                        let keep = max_clipboard_bytes.saturating_sub(clipboard_pipe_buf.len()).min(n);
+                        clipboard_pipe_buf.extend_from_slice(&tmp[..keep]);
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        // No more data available yet, try again next iteration
+                        break;
+                    }
+                    Err(_) => {
+                        // Pipe error, discard
+                        clipboard_pipe_buf.clear();
+                        clipboard_pipe = None;
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Multi-seat: tell clients where the other sessions' cursors are
+        if let Some(json) = comp.session_seats.take_changes() {
+            shared_state.send_text(format!("seats,{}", json));
+        }
+
+        // Switch between browser- and server-drawn cursor. The browser
+        // hides its own cursor while the server draws one.
+        if runtime_settings.server_cursor() != server_cursor {
+            server_cursor = !server_cursor;
+            info!("Cursor rendering: {}", if server_cursor { "server" } else { "client" });
+            let msg = if server_cursor { HIDDEN_CURSOR_MESSAGE.to_string() } else { prev_cursor_msg.clone() };
+            shared_state.send_text(shared_state.cursor_mode_message());
+            shared_state.send_text(msg.clone());
+            shared_state.update_cursor_message(msg);
+            cursor_bitmap = server_cursor.then(|| server_cursor_bitmap(&comp.cursor_status, comp.output_scale)).flatten();
+            drawn_cursor_pos = None;
+            comp.needs_redraw = true;
+        }
+
+        // Broadcast cursor changes to frontend
+        if prev_cursor_serial != Some(comp.cursor_serial) {
+            prev_cursor_serial = Some(comp.cursor_serial);
+            if server_cursor {
+                cursor_bitmap = server_cursor_bitmap(&comp.cursor_status, comp.output_scale);
+                comp.needs_redraw = true;
+            }
+            let msg = cursor_message(&comp.cursor_status, &mut cursor_png_cache);
+            if msg != prev_cursor_msg {
+                debug!("Cursor changed: {}", &msg[..msg.len().min(80)]);
+                if !server_cursor {
+                    shared_state.send_text(msg.clone());
+                    // Part of the state bundle new sessions get on connect
+                    shared_state.update_cursor_message(msg.clone());
+                }
+                prev_cursor_msg = msg;
+            }
+        }
+        // A server-drawn cursor needs a new frame whenever the pointer moves
+        if server_cursor {
+            if let Some(pos) = comp.pointer_display_location() {
+                if drawn_cursor_pos != Some(pos) {
+                    comp.needs_redraw = true;
+                }
+            }
+        }
+        if let Some(interval) = cursor_pos_interval {
+            if last_cursor_pos_sent.elapsed() >= interval {
+                if let Some(pos) = comp.pointer_display_location() {
+                    if sent_cursor_pos != Some(pos) {
+                        shared_state.send_text(format!("cursorpos,{},{}", pos.0, pos.1));
+                        sent_cursor_pos = Some(pos);
+                        last_cursor_pos_sent = Instant::now();
+                    }
+                }
+            }
+        }
+
+        // Detect window changes and request keyframe so browsers can decode the new content
+        let cur_window_count = comp.space.elements().count();
+        heartbeat.set_windows(cur_window_count);
+        if cur_window_count != prev_window_count {
+            info!("Window count changed: {} -> {}", prev_window_count, cur_window_count);
+            prev_window_count = cur_window_count;
+            backend.reset_damage();
+            pipeline.request_keyframe();
+            comp.needs_redraw = true;
+            comp.taskbar_dirty = true;
+        }
+
+        // Broadcast taskbar window list to frontend when dirty
+        if comp.taskbar_dirty {
+            comp.taskbar_dirty = false;
+            let mut windows_json = Vec::new();
+            for window in collect_window_tree(&comp) {
+                // Minimized windows are unmapped from the space; keep them off the taskbar
+                if window.minimized {
+                    continue;
+                }
+                if window.focused {
+                    comp.focused_surface_id = Some(window.id);
+                }
+                windows_json.push(serde_json::json!({
+                    "id": window.id,
+                    "title": window.title,
+                    "app_id": window.app_id,
+                    "display_name": window.display_name,
+                    "focused": window.focused,
+                    "attention": comp.window_registry.get(window.id as usize)
+                        .is_some_and(|wl| comp.attention.contains(&wl.id())),
+                }));
+            }
+            let json = serde_json::json!({ "windows": windows_json }).to_string();
+            if json != prev_taskbar_json {
+                prev_taskbar_json = json.clone();
+                let msg = format!("taskbar,{}", json);
+                info!("Taskbar broadcast: {}", msg);
+                shared_state.send_text(msg);
+                // Cache for MCP list_windows tool and new sessions
+                *shared_state.last_taskbar_json.lock().unwrap() = Some(json);
+            }
+        }
+        // After the taskbar, so clients already know the window
+        for window in comp.attention.take_pending() {
+            if let Some(id) = comp.window_registry.iter().position(|wl| wl.id() == window) {
+                shared_state.send_text(format!("attention,{}", id));
+            }
+        }
+
+        heartbeat.enter(LoopStage::Resize);
+        if let Some((w, h)) = shared_state.take_pending_resize() {
+            if let Err(e) = backend.resize(w, h) {
+                warn!("Resize failed: {}", e);
+            } else {
+                shared_state.set_display_size(w, h);
+                shared_state.send_text(shared_state.display_size_message());
+                shared_state.emit_event("resolution_changed", serde_json::json!({ "width": w, "height": h }));
+                // The cached (and any half-collected) keyframe has the old size
+                shared_state.clear_keyframe_cache();
+                keyframe.reset();
+
+                // Re-configure all non-dialog toplevel windows to the new output size
+                comp.configure_toplevels(w, h);
+                if config.display.keep_windows_in_bounds {
+                    comp.keep_windows_in_bounds();
+                }
+
+                // Rebuild pipeline with new dimensions
+                let divisor = pipeline.config().scale_divisor;
+                rebuild_pipeline(
+                    &mut pipeline,
+                    &shared_state,
+                    pipeline_config_for(&config, w, h, divisor, render_scale),
+                );
+                frame_push_times.clear();
+            }
+        }
+
+        apply_runtime_settings(&runtime_settings, &pipeline);
+
+        // Client render scale (`s,`): the display keeps its size, only the
+        // encoded stream shrinks
+        let requested_scale = runtime_settings.render_scale();
+        if requested_scale != render_scale {
+            render_scale = requested_scale;
+            let (w, h) = shared_state.display_size();
+            let divisor = pipeline.config().scale_divisor;
+            info!("Render scale set to {:.2}", render_scale);
+            rebuild_pipeline(
+                &mut pipeline,
+                &shared_state,
+                pipeline_config_for(&config, w, h, divisor, render_scale),
+            );
+            frame_push_times.clear();
+            // Cached keyframe has the old encoded size
+            shared_state.clear_keyframe_cache();
+            keyframe.reset();
+            comp.needs_redraw = true;
+            shared_state.emit_event(
+                "encode_scale_changed",
+                serde_json::json!({ "scale_divisor": divisor, "render_scale": render_scale }),
+            );
+        }
+
+        // Client output scale (`scale,`): same pixels, fewer logical ones,
+        // so apps re-layout and render at the higher density
+        let requested_output_scale = runtime_settings.output_scale();
+        if requested_output_scale != comp.output_scale {
+            backend.set_scale(requested_output_scale);
+            comp.set_output_scale(requested_output_scale);
+            let (w, h) = shared_state.display_size();
+            comp.configure_toplevels(w, h);
+            if config.display.keep_windows_in_bounds {
+                comp.keep_windows_in_bounds();
+            }
+            // Keep the pointer on the same display pixel
+            let location = comp.display_to_logical(prev_cursor_pos.0, prev_cursor_pos.1);
+            let under = comp.surface_under(location);
+            let pointer = comp.seat.get_pointer().unwrap();
+            let serial = smithay::utils::SERIAL_COUNTER.next_serial();
+            let time = (comp.start_time.elapsed().as_millis() & 0xFFFFFFFF) as u32;
+            pointer.motion(&mut comp, under, &smithay::input::pointer::MotionEvent { location, serial, time });
+            pointer.frame(&mut comp);
+            // A server-drawn cursor is blended in display pixels
+            if server_cursor {
+                cursor_bitmap = server_cursor_bitmap(&comp.cursor_status, comp.output_scale);
+            }
+            pipeline.request_keyframe();
+            comp.needs_redraw = true;
+            shared_state.send_text(shared_state.display_size_message());
+            shared_state.emit_event("output_scale_changed", serde_json::json!({ "scale": requested_output_scale }));
+        }
+
+        heartbeat.enter(LoopStage::FrameCallbacks);
+        // Send frame callbacks BEFORE sleep so clients have the full
+        // frame period to prepare and commit their next buffer.
+        backend.send_frame_callbacks(&comp);
+        comp.display_handle.flush_clients().ok();
+
+        heartbeat.enter(LoopStage::Sleep);
+        // Frame timing — clients are working in parallel during this sleep.
+        // Overrunning the budget skips slots rather than queueing frames.
+        frames_skipped += frame_clock.wait();
+
+        // Quick dispatch to pick up commits that arrived during sleep
+        heartbeat.enter(LoopStage::Dispatch);
+        event_loop.dispatch(Some(Duration::ZERO), &mut comp)
+            .map_err(|e| DisplayError::DispatchFailed(e.to_string()))?;
+        comp.display_handle.flush_clients().ok();
+
+        heartbeat.enter(LoopStage::Render);
+        // Render + encode if any client committed new content
+        // Also force periodic renders when sessions are active to ensure
+        // the browser always has decodable video frames.
+        let has_sessions = shared_state.rtp_receiver_count() > 0;
+        if !comp.needs_redraw && has_sessions && last_render.elapsed() >= Duration::from_secs(1) {
+            comp.needs_redraw = true;
+        }
+        // First viewer on an idle desktop: render now so the keyframe forced
+        // by the subscription is encoded immediately, not on the next commit
+        if has_sessions && !had_sessions {
+            comp.needs_redraw = true;
+        }
+        // No viewers (and no frame callback wanting pixels): pause the
+        // encoder instead of feeding it, and resume with a keyframe for the
+        // next viewer
+        if on_frame.is_none() && has_sessions != had_sessions {
+            if has_sessions {
+                info!("Viewer connected: resuming the encoder");
+                if let Err(e) = pipeline.resume() {
+                    warn!("Failed to resume pipeline: {}", e);
+                }
+                shared_state.request_keyframe();
+            } else {
+                info!("No viewers: pausing the encoder");
+                if let Err(e) = pipeline.pause() {
+                    warn!("Failed to pause pipeline: {}", e);
+                }
+            }
+        }
+        had_sessions = has_sessions;
+        // Apply keyframe requests before pushing the frame so it is the one
+        // encoded as an IDR
+        if shared_state.take_keyframe_request() {
+            pipeline.request_keyframe();
+        }
+        // No viewers, or every viewer paused video (and no frame callback
+        // wants pixels): let the encoder idle, keeping the redraw pending
+        // for a resume
+        let encoder_idle = on_frame.is_none() && (!has_sessions || shared_state.all_video_paused());
+        // Encoder backlog over the latency budget: drop this frame and keep
+        // the redraw pending so the next slot renders the latest content
+        let over_budget = comp.needs_redraw && !encoder_idle && latency_budget.as_mut().is_some_and(|budget| {
+            let handed_off = encode_queue.as_ref().map_or(0, |q| q.pending() as u64);
+            budget.should_drop(pipeline.queued_frames() + handed_off, frame_clock.period(), cpu_percent)
+        });
+        if over_budget {
+            frames_dropped += 1;
+        }
+        if comp.needs_redraw && !encoder_idle && !over_budget {
+            comp.needs_redraw = false;
+            match backend.render_frame(&mut comp) {
+                Some(mut pixels) => {
+                    render_frames += 1;
+                    last_render = Instant::now();
+                    if let Some(ref overlay) = overlay {
+                        let (w, h) = shared_state.display_size();
+                        overlay.draw(&mut pixels, w, h);
+                    }
+                    if server_cursor {
+                        if let Some(pos) = comp.pointer_display_location() {
+                            if let Some(ref bitmap) = cursor_bitmap {
+                                let (w, h) = shared_state.display_size();
+                                bitmap.blend(&mut pixels, w as usize, h as usize, pos, backend.pixel_format().is_bgr());
+                            }
+                            drawn_cursor_pos = Some(pos);
+                        }
+                    }
+                    if let Some(cb) = on_frame.as_mut() {
+                        let (w, h) = shared_state.display_size();
+                        cb(w, h, &pixels);
+                    }
+                    heartbeat.enter(LoopStage::Encode);
+                    let frame_bytes = pixels.len() as u64;
+                    let handoff_start = Instant::now();
+                    // Stamped here, so the frame's RTP timestamp is known
+                    let pts = pipeline.running_time();
+                    let handed_off = match encode_queue {
+                        Some(ref queue) => queue.submit((pipeline.frame_pusher(), pixels, pts)),
+                        None => {
+                            if let Err(e) = pipeline.push_frame(&pixels, pts) {
+                                warn!("Failed to push frame: {}", e);
+                                continue;
+                            }
+                            true
+                        }
+                    };
+                    if !handed_off {
+                        // Encode thread is behind: drop the frame and render
+                        // the latest content again next slot
+                        frames_queue_dropped += 1;
+                        comp.needs_redraw = true;
+                    } else {
+                        shared_state.record_handoff_latency(handoff_start.elapsed().as_secs_f64() * 1000.0);
+                        if let Some(pts) = pts {
+                            let rtp = webrtc::media_clock::video_rtp_timestamp(pts.nseconds());
+                            // The pipeline's first frame anchors the capture
+                            // clock sessions stamp their sender reports from
+                            if frame_push_times.push(rtp, handoff_start) {
+                                *shared_state.video_clock.lock().unwrap() = frame_push_times.clock();
+                            }
+                        }
+                        frame_count += 1;
+                        byte_count += frame_bytes;
+                    }
+                }
+                None => {
+                    warn!("render_frame returned None (windows={})", comp.space.elements().count());
+                }
+            }
+        }
+
+        // MCP window tree: answer with a live snapshot of the registry
+        #[cfg(feature = "mcp")]
+        {
+            let mut wt_rx = shared_state.window_tree_rx.lock().unwrap();
+            while let Ok(sender) = wt_rx.try_recv() {
+                let _ = sender.send(collect_window_tree(&comp));
+            }
+        }
+
+        // MCP hit-test: report the window under a point
+        #[cfg(feature = "mcp")]
+        {
+            let mut wa_rx = shared_state.window_at_rx.lock().unwrap();
+            while let Ok(((x, y), sender)) = wa_rx.try_recv() {
+                let _ = sender.send(window_at(&comp, x, y));
+            }
+        }
+
+        // MCP focus query: report the keyboard-focused window
+        #[cfg(feature = "mcp")]
+        {
+            let mut fq_rx = shared_state.focus_query_rx.lock().unwrap();
+            while let Ok(sender) = fq_rx.try_recv() {
+                let _ = sender.send(focused_window(&comp));
+            }
+        }
+
+        // MCP window switch: raise and focus the next window in the stack
+        #[cfg(feature = "mcp")]
+        {
+            let mut ws_rx = shared_state.window_switch_rx.lock().unwrap();
+            while let Ok((switch, sender)) = ws_rx.try_recv() {
+                let _ = sender.send(switch_window(&mut comp, &switch));
+            }
+        }
+
+        // MCP frame capture: drain pending requests and respond with current frame
+        #[cfg(feature = "mcp")]
+        {
+            let mut fc_rx = shared_state.frame_capture_rx.lock().unwrap();
+            while let Ok(sender) = fc_rx.try_recv() {
+                match backend.render_frame(&mut comp) {
+                    Some(mut pixels) => {
+                        // Screenshots work on XRGB8888
+                        if backend.pixel_format().is_bgr() {
+                            pixels.chunks_exact_mut(4).for_each(|px| px.swap(0, 2));
+                        }
+                        let (w, h) = shared_state.display_size();
+                        let _ = sender.send((w, h, pixels));
+                    }
+                    None => {
+                        let _ = sender.send((0, 0, Vec::new()));
+                    }
+                }
+            }
+        }
+
+        heartbeat.enter(LoopStage::Rtp);
+        pull_and_broadcast_rtp(
+            &pipeline,
+            &shared_state,
+            &mut rtp_packets,
+            &mut keyframe,
+            &mut rtp_frame,
+            &mut frame_push_times,
+            rtp_flush_after,
+        );
+
+        let stats_window = web::shared::StatsWindow {
+            frames: frame_count,
+            bytes: byte_count,
+            skipped: frames_skipped,
+            dropped: frames_dropped,
+            queue_dropped: frames_queue_dropped,
+        };
+        let periodic_stats = last_stats.elapsed() >= stats_interval;
+        // An on-demand request waits for a short window so rates are not
+        // computed over a few milliseconds. It gets a snapshot of the window
+        // so far; the periodic window keeps running for everyone else.
+        if !periodic_stats
+            && last_stats.elapsed() >= ON_DEMAND_STATS_MIN_WINDOW
+            && shared_state.take_stats_request()
+        {
+            let mut snapshot = shared_state.stats.lock().unwrap().clone();
+            snapshot.apply_window(&stats_window, last_stats.elapsed().as_secs_f64());
+            shared_state.send_text(format!("stats,{}", shared_state.stats_json_of(&snapshot)));
+            send_on_demand_state(&shared_state);
+            shared_state.stats_generation.fetch_add(1, Ordering::Relaxed);
+        }
+        if periodic_stats {
+            heartbeat.enter(LoopStage::Stats);
+            // A rebuild (resize) while idle starts the new pipeline playing
+            if on_frame.is_none() && !had_sessions && pipeline.state() == gstreamer::pipeline::PipelineState::Running {
+                if let Err(e) = pipeline.pause() {
+                    warn!("Failed to pause pipeline: {}", e);
+                }
+            }
+            let secs = last_stats.elapsed().as_secs_f64();
+            let windows = comp.space.elements().count();
+            info!(
+                "Loop stats: windows={}, rendered={}, pushed={}, rtp_pkts={}, secs={:.1}",
+                windows, render_frames, frame_count, rtp_packets, secs
+            );
+            shared_state.refresh_latency_percentiles();
+            {
+                let mut stats = shared_state.stats.lock().unwrap();
+                stats.apply_window(&stats_window, secs);
+                cpu_percent = stats.cpu_percent / cpu_cores;
+            }
+            // Single encoder: downscale only when every viewer is constrained
+            if config.webrtc.congestion_downscale {
+                let divisor = if shared_state.all_sessions_congested() { 2 } else { 1 };
+                if divisor != pipeline.config().scale_divisor {
+                    let (w, h) = shared_state.display_size();
+                    info!(
+                        "{} encoded resolution for congested viewers",
+                        if divisor > 1 { "Halving" } else { "Restoring" }
+                    );
+                    rebuild_pipeline(
+                        &mut pipeline,
+                        &shared_state,
+                        pipeline_config_for(&config, w, h, divisor, render_scale),
+                    );
+                    frame_push_times.clear();
+                    // Cached keyframe has the old encoded size
+                    shared_state.clear_keyframe_cache();
+                    keyframe.reset();
+                    shared_state.emit_event(
+                        "encode_scale_changed",
+                        serde_json::json!({ "scale_divisor": divisor, "render_scale": render_scale }),
+                    );
+                }
+            }
+            if frames_skipped > 0 {
+                debug!(
+                    "Frame budget ({:?}) overrun: skipped {} slots",
+                    frame_clock.period(), frames_skipped
+                );
+            }
+            if let Some(dropped) = latency_budget.as_mut().and_then(|b| b.take_log(Instant::now())) {
+                warn!(
+                    "Encoder over its {} ms latency budget: dropped {} frames",
+                    config.encoding.latency_budget_ms, dropped
+                );
+            }
+            if let Some(cb) = on_stats.as_mut() {
+                let snapshot = shared_state.stats.lock().unwrap().clone();
+                cb(&snapshot);
+            }
+            if let Some(ref mut overlay) = overlay {
+                let (w, h) = shared_state.display_size();
+                let sessions = shared_state.webrtc_session_count.load(Ordering::Relaxed);
+                overlay.update(&shared_state.stats.lock().unwrap(), sessions, w, h);
+            }
+            let stats_json = shared_state.stats_json();
+            if shared_state.event_sender.receiver_count() > 0 {
+                if let Ok(stats) = serde_json::from_str::<serde_json::Value>(&stats_json) {
+                    shared_state.emit_event("stats", serde_json::json!({ "stats": stats }));
+                }
+            }
+            shared_state.send_text(format!("stats,{}", stats_json));
+            // A request due now is answered by this broadcast
+            if shared_state.take_stats_request() {
+                send_on_demand_state(&shared_state);
+            }
+            shared_state.stats_generation.fetch_add(1, Ordering::Relaxed);
+            render_frames = 0;
+            frame_count = 0;
+            byte_count = 0;
+            frames_skipped = 0;
+            frames_dropped = 0;
+            frames_queue_dropped = 0;
+            rtp_packets = 0;
+            last_stats = Instant::now();
+        }
+    }
+
+    info!("Shutting down...");
+    running.store(false, Ordering::SeqCst);
+    let _ = pipeline.stop();
+    tokio_rt.shutdown_timeout(Duration::from_secs(3));
+    info!("ivnc stopped");
+    Ok(())
+}
+
+/// Synthesize `count` repeat presses of `keysym` on `comp.seat`
+fn inject_key_repeats(comp: &mut Compositor, keysym: u32, count: u32) {
+    let ev = InputEventData {
+        event_type: InputEvent::Keyboard,
+        keysym,
+        key_pressed: true,
+        ..Default::default()
+    };
+    for _ in 0..count {
+        let serial = smithay::utils::SERIAL_COUNTER.next_serial();
+        let time = (comp.start_time.elapsed().as_millis() & 0xFFFFFFFF) as u32;
+        inject_key(comp, &ev, serial, time);
+    }
+}
+
+fn drain_input_events(
+    input_rx: &mut mpsc::UnboundedReceiver<InputEventData>,
+    state: &mut Compositor,
+    shared: &Arc<web::SharedState>,
+    prev_button_mask: &mut u32,
+    prev_cursor_pos: &mut (f64, f64),
+    mut key_repeater: Option<&mut crate::input::KeyRepeater>,
+    mut recorder: Option<&mut crate::input_recording::InputRecorder>,
+) {
+    use smithay::utils::SERIAL_COUNTER;
+
+    while let Ok(ev) = input_rx.try_recv() {
+        if !shared.hooks.on_input(&ev) {
+            continue;
+        }
+        if let Some(recorder) = recorder.as_deref_mut() {
+            match (&ev.session_id, ev.event_type) {
+                (Some(id), InputEvent::SessionClosed) => recorder.close(id),
+                _ => recorder.record(&ev),
+            }
+        }
+        let serial = SERIAL_COUNTER.next_serial();
+        // Use monotonic clock for Wayland event timestamps (milliseconds).
+        // The frontend doesn't send timestamps for keyboard events, so
+        // ev.timestamp is 0 — Chromium may discard events with time=0.
+        let time = (state.start_time.elapsed().as_millis() & 0xFFFFFFFF) as u32;
+
+        // Multi-seat: run the event against the session's own seat and
+        // pointer state, swapped in for the duration of the match
+        let session_seat = match &ev.session_id {
+            Some(id) if shared.config.input.multi_seat && ev.event_type != InputEvent::SessionClosed => {
+                let mut entry = state.swap_in_session_seat(id, *prev_cursor_pos);
+                std::mem::swap(prev_cursor_pos, &mut entry.cursor_pos);
+                std::mem::swap(prev_button_mask, &mut entry.button_mask);
+                if let (Some(main), Some(own)) = (key_repeater.as_deref_mut(), entry.key_repeater.as_mut()) {
+                    std::mem::swap(main, own);
+                }
+                Some((id.clone(), entry))
+            }
+            _ => None,
+        };
+
+        match ev.event_type {
+            InputEvent::MouseMove => {
+                // A warp re-anchors relative motion (e.g. when pointer lock is
+                // toggled) without touching the button state
+                let warp = ev.text == "warp";
+                let (mut x, mut y) = if ev.text == "relative" {
+                    let (dx, dy) = crate::input::accelerate(
+                        ev.mouse_x as f64,
+                        ev.mouse_y as f64,
+                        shared.runtime_settings.mouse_sensitivity(),
+                        shared.config.input.pointer_acceleration,
+                    );
+                    (prev_cursor_pos.0 + dx, prev_cursor_pos.1 + dy)
+                } else {
+                    (ev.mouse_x as f64, ev.mouse_y as f64)
+                };
+                let (disp_w, disp_h) = shared.display_size();
+                x = x.clamp(0.0, disp_w.saturating_sub(1) as f64);
+                y = y.clamp(0.0, disp_h.saturating_sub(1) as f64);
+                *prev_cursor_pos = (x, y);
+                let pos = state.display_to_logical(x, y);
+                let under = state.surface_under(pos);
+                let ptr = state.seat.get_pointer().unwrap();
+                ptr.motion(
+                    state, under.clone(),
+                    &smithay::input::pointer::MotionEvent { location: pos, serial, time },
+                );
+                ptr.frame(state);
+
+                // Re-send keyboard focus after the first pointer enter.
+                // Chromium's Ozone/Wayland layer ignores keyboard events received
+                // before wl_pointer.enter, so we re-send wl_keyboard.enter once
+                // the pointer has entered the surface.
+                if state.kbd_focus_needs_reenter && under.is_some() {
+                    let keyboard = state.seat.get_keyboard().unwrap();
+                    if let Some(focus) = keyboard.current_focus() {
+                        let reenter_serial = SERIAL_COUNTER.next_serial();
+                        info!("Re-sending keyboard focus after first pointer enter");
+                        keyboard.set_focus(state, None, reenter_serial);
+                        let reenter_serial2 = SERIAL_COUNTER.next_serial();
+                        keyboard.set_focus(state, Some(focus), reenter_serial2);
+                    }
+                    state.kbd_focus_needs_reenter = false;
+                }
+
+                // Synthesize button events from buttonMask changes.
+                // The frontend sends m,x,y,buttonMask,0 — button state is
+                // encoded in the mask, not as separate b,button,pressed messages.
+                let new_mask = ev.button_mask;
+                if !warp && new_mask != *prev_button_mask {
+                    info!("ButtonMask changed: {} -> {} at ({},{})", *prev_button_mask, new_mask, ev.mouse_x, ev.mouse_y);
+                    let changed = new_mask ^ *prev_button_mask;
+                    for bit in 0..5u8 {
+                        if changed & (1 << bit) != 0 {
+                            let pressed = new_mask & (1 << bit) != 0;
+                            let synth = InputEventData {
+                                event_type: InputEvent::MouseButton,
+                                mouse_x: x as i32,
+                                mouse_y: y as i32,
+                                mouse_button: bit,
+                                button_pressed: pressed,
+                                ..Default::default()
+                            };
+                            let btn_serial = SERIAL_COUNTER.next_serial();
+                            inject_button(state, &synth, btn_serial, time);
+                        }
+                    }
+                    *prev_button_mask = new_mask;
+                }
+            }
+            InputEvent::MouseButton => {
+                inject_button(state, &ev, serial, time);
+            }
+            InputEvent::MouseWheel => {
+                inject_scroll(state, &ev, time);
+            }
+            InputEvent::Keyboard => {
+                if let Some(repeater) = key_repeater.as_deref_mut() {
+                    if ev.key_pressed {
+                        repeater.press(ev.keysym, Instant::now());
+                    } else {
+                        repeater.release(ev.keysym);
+                    }
+                }
+                inject_key(state, &ev, serial, time);
+            }
+            InputEvent::KeyboardKeycode => {
+                // A release for a key a reset already let go is dropped
+                let held = if ev.key_pressed {
+                    state.held_keycodes.insert(ev.keycode);
+                    true
+                } else {
+                    state.held_keycodes.remove(&ev.keycode)
+                };
+                if held {
+                    inject_keycode(state, &ev, serial, time);
+                }
+            }
+            InputEvent::KeyboardReset => {
+                if let Some(repeater) = key_repeater.as_deref_mut() {
+                    repeater.clear();
+                }
+                // Release all modifier keys to clear stuck state
+                let keyboard = state.seat.get_keyboard().unwrap();
+                let modifier_keycodes: &[u32] = &[
+                    50, 62,   // Shift L/R
+                    37, 105,  // Control L/R
+                    64, 108,  // Alt L/R
+                    133, 134, // Super L/R
+                ];
+                for &kc in modifier_keycodes {
+                    let s = smithay::utils::SERIAL_COUNTER.next_serial();
+                    keyboard.input::<(), _>(
+                        state,
+                        smithay::input::keyboard::Keycode::from(kc),
+                        smithay::backend::input::KeyState::Released,
+                        s, time,
+                        |_, _, _| smithay::input::keyboard::FilterResult::Forward,
+                    );
+                }
+                // and raw keycodes an MCP client left pressed (those are on
+                // the main seat, not a session's own)
+                if session_seat.is_none() {
+                    for keycode in std::mem::take(&mut state.held_keycodes) {
+                        let release = InputEventData {
+                            event_type: InputEvent::KeyboardKeycode,
+                            keycode,
+                            ..Default::default()
+                        };
+                        inject_keycode(state, &release, SERIAL_COUNTER.next_serial(), time);
+                    }
+                }
+                info!("Keyboard reset: released all modifier and held keys");
+            }
+            InputEvent::ButtonReset => {
+                for bit in (0..5u8).filter(|bit| *prev_button_mask & (1 << bit) != 0) {
+                    let release = InputEventData {
+                        event_type: InputEvent::MouseButton,
+                        mouse_x: prev_cursor_pos.0 as i32,
+                        mouse_y: prev_cursor_pos.1 as i32,
+                        mouse_button: bit,
+                        button_pressed: false,
+                        ..Default::default()
+                    };
+                    inject_button(state, &release, SERIAL_COUNTER.next_serial(), time);
+                }
+                *prev_button_mask = 0;
+            }
+            InputEvent::Ping => {
+                shared.send_text("pong".to_string());
+            }
+            InputEvent::SessionClosed => {
+                if let Some(id) = &ev.session_id {
+                    state.release_session_seat(id);
+                }
+            }
+            InputEvent::TextInput => {
+                inject_text(state, &ev, &shared.config.input.clipboard_mime_types);
+            }
+            InputEvent::WindowFocus => {
+                let target_idx = ev.window_id as usize;
+                let wl_surface = state.window_registry.get(target_idx).cloned();
+                if let Some(wl_surface) = wl_surface {
+                    let window = state.space.elements()
+                        .find(|w| w.toplevel().unwrap().wl_surface() == &wl_surface)
+                        .cloned();
+                    if let Some(window) = window {
+                        state.space.raise_element(&window, true);
+                        let keyboard = state.seat.get_keyboard().unwrap();
+                        keyboard.set_focus(state, Some(wl_surface), serial);
+                        state.focused_surface_id = Some(ev.window_id);
+                        state.taskbar_dirty = true;
+                        state.needs_redraw = true;
+                        info!("WindowFocus: switched to window index {}", target_idx);
+                    }
+                }
+            }
+            InputEvent::WindowClose => {
+                let target_idx = ev.window_id as usize;
+                let wl_surface = state.window_registry.get(target_idx).cloned();
+                if let Some(wl_surface) = wl_surface {
+                    let window = state.space.elements()
+                        .find(|w| w.toplevel().unwrap().wl_surface() == &wl_surface)
+                        .cloned();
+                    if let Some(window) = window {
+                        window.toplevel().unwrap().send_close();
+                        info!("WindowClose: sent close to window index {}", target_idx);
+                        // After close, focus the last window in registry
+                        // (the most recently created one that isn't being closed)
+                        let last_surface = state.window_registry.iter().enumerate().rev()
+                            .find(|(i, _)| *i != target_idx)
+                            .map(|(i, s)| (i, s.clone()));
+                        if let Some((idx, wl_s)) = last_surface {
+                            let next_win = state.space.elements()
+                                .find(|w| w.toplevel().unwrap().wl_surface() == &wl_s)
+                                .cloned();
+                            if let Some(next_win) = next_win {
+                                state.space.raise_element(&next_win, true);
+                                let keyboard = state.seat.get_keyboard().unwrap();
+                                keyboard.set_focus(state, Some(wl_s), serial);
+                                state.focused_surface_id = Some(idx as u32);
+                                state.needs_redraw = true;
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        if let Some((id, mut entry)) = session_seat {
+            std::mem::swap(prev_cursor_pos, &mut entry.cursor_pos);
+            std::mem::swap(prev_button_mask, &mut entry.button_mask);
+            if let (Some(main), Some(own)) = (key_repeater.as_deref_mut(), entry.key_repeater.as_mut()) {
+                std::mem::swap(main, own);
+            }
+            if ev.event_type == InputEvent::MouseMove {
+                state.session_seats.mark_changed();
+            }
+            state.put_back_session_seat(id, entry);
+        }
+    }
+    if let Some(recorder) = recorder {
+        recorder.flush();
+    }
+}
+
+fn inject_button(state: &mut Compositor, ev: &InputEventData, serial: smithay::utils::Serial, time: u32) {
+    let button = match ev.mouse_button {
+        0 => 0x110u32,
+        1 => 0x112,
+        2 => 0x111,
+        b => 0x110 + b as u32,
+    };
+    let btn_state = if ev.button_pressed {
+        smithay::backend::input::ButtonState::Pressed
+    } else {
+        smithay::backend::input::ButtonState::Released
+    };
+
+    // On press, focus the toplevel window under the pointer so it receives keyboard input.
+    // We must use the toplevel's wl_surface (not a subsurface from surface_under()),
+    // because Chromium routes keyboard events based on which wl_surface has keyboard focus.
+    // Using a subsurface would cause Chromium to ignore key events entirely.
+    if ev.button_pressed {
+        let pos = state.display_to_logical(ev.mouse_x as f64, ev.mouse_y as f64);
+        if let Some((window, _)) = state.space.element_under(pos) {
+            if let Some(toplevel) = window.toplevel() {
+                let wl_surface = toplevel.wl_surface().clone();
+                let keyboard = state.seat.get_keyboard().unwrap();
+                keyboard.set_focus(state, Some(wl_surface), serial);
+            }
+        }
+    }
+
+    let ptr = state.seat.get_pointer().unwrap();
+    ptr.button(
+        state,
+        &smithay::input::pointer::ButtonEvent { button, state: btn_state, serial, time },
+    );
+    ptr.frame(state);
+}
+
+fn inject_scroll(state: &mut Compositor, ev: &InputEventData, time: u32) {
+    use smithay::backend::input::Axis;
+    use smithay::input::pointer::AxisFrame;
+    let ptr = state.seat.get_pointer().unwrap();
+    let mut frame = AxisFrame::new(time);
+    if ev.wheel_delta_y != 0 {
+        frame = frame.value(Axis::Vertical, ev.wheel_delta_y as f64);
+    }
+    if ev.wheel_delta_x != 0 {
+        frame = frame.value(Axis::Horizontal, ev.wheel_delta_x as f64);
+    }
+    ptr.axis(state, frame);
+    ptr.frame(state);
+}
+
+fn inject_key(state: &mut Compositor, ev: &InputEventData, serial: smithay::utils::Serial, time: u32) {
+    use smithay::input::keyboard::{FilterResult, Keycode};
+    let keyboard = state.seat.get_keyboard().unwrap();
+    let key_state = if ev.key_pressed {
+        smithay::backend::input::KeyState::Pressed
+    } else {
+        smithay::backend::input::KeyState::Released
+    };
+
+    // Frontend sends X11 keysyms; smithay expects xkb keycodes (evdev + 8).
+    // Use a lookup table for the most common keysyms.
+    let keycode = match keysym_to_keycode(ev.keysym) {
+        Some(code) => code,
+        None => {
+            warn!("Unknown keysym 0x{:x}; dropping key event", ev.keysym);
+            return;
+        }
+    };
+    let has_focus = keyboard.current_focus().is_some();
+    info!("inject_key: keysym=0x{:x} keycode={} pressed={} has_focus={}", ev.keysym, keycode, ev.key_pressed, has_focus);
+    keyboard.input::<(), _>(
+        state, Keycode::from(keycode), key_state, serial, time,
+        |_, _, _| FilterResult::Forward,
+    );
+}
+
+/// Inject a raw evdev keycode, for keys keysyms don't tell apart (e.g.
+/// keypad vs top-row digits)
+fn inject_keycode(state: &mut Compositor, ev: &InputEventData, serial: smithay::utils::Serial, time: u32) {
+    use smithay::input::keyboard::{FilterResult, Keycode};
+    if ev.keycode == 0 || ev.keycode > crate::input::MAX_EVDEV_KEYCODE {
+        warn!("Keycode {} out of range; dropping key event", ev.keycode);
+        return;
+    }
+    let keyboard = state.seat.get_keyboard().unwrap();
+    let key_state = if ev.key_pressed {
+        smithay::backend::input::KeyState::Pressed
+    } else {
+        smithay::backend::input::KeyState::Released
+    };
+    debug!("inject_keycode: evdev={} pressed={}", ev.keycode, ev.key_pressed);
+    // xkb keycodes are evdev + 8
+    keyboard.input::<(), _>(
+        state, Keycode::from(ev.keycode + 8), key_state, serial, time,
+        |_, _, _| FilterResult::Forward,
+    );
+}
+
+/// Snapshot every registered toplevel with its title, focus and geometry.
+/// Windows that are registered but not mapped in the space are reported as minimized.
+fn collect_window_tree(comp: &Compositor) -> Vec<web::shared::WindowInfo> {
+    let focused_wl = comp.seat.get_keyboard()
+        .and_then(|kb| kb.current_focus());
+    let mapped_count = comp.space.elements().count();
+    let mut windows = Vec::new();
+    for (idx, wl_surface) in comp.window_registry.iter().enumerate() {
+        // Space elements run bottom to top
+        let stack_pos = comp.space.elements()
+            .position(|w| w.toplevel().unwrap().wl_surface() == wl_surface);
+        let mapped = comp.space.elements()
+            .find(|w| w.toplevel().unwrap().wl_surface() == wl_surface);
+        // In display pixels, like the coordinates input tools take
+        let geometry = mapped
+            .and_then(|w| comp.space.element_geometry(w))
+            .map(|g| g.to_f64().to_physical(comp.output_scale).to_i32_round::<i32>());
+        let is_focused = focused_wl.as_ref()
+            .map(|f| f.id() == wl_surface.id())
+            .unwrap_or(false);
+        let (title, app_id) = smithay::wayland::compositor::with_states(wl_surface, |states| {
+            let data = states.data_map
+                .get::<smithay::wayland::shell::xdg::XdgToplevelSurfaceData>()
+                .unwrap()
+                .lock()
+                .unwrap();
+            (
+                data.title.clone().unwrap_or_default(),
+                data.app_id.clone().unwrap_or_default(),
+            )
+        });
+        windows.push(web::shared::WindowInfo {
+            id: idx as u32,
+            display_name: resolve_display_name(&app_id, &title),
+            title,
+            app_id,
+            focused: is_focused,
+            minimized: mapped.is_none(),
+            stack_index: stack_pos.map(|pos| (mapped_count - 1 - pos) as u32),
+            x: geometry.map(|g| g.loc.x).unwrap_or(0),
+            y: geometry.map(|g| g.loc.y).unwrap_or(0),
+            width: geometry.map(|g| g.size.w).unwrap_or(0),
+            height: geometry.map(|g| g.size.h).unwrap_or(0),
+        });
+    }
+    windows
+}
+
+/// Cycle keyboard focus through the mapped windows in stacking order.
+/// Forward raises the bottom-most window, so repeated switches visit every
+/// window; reverse sends the top window to the bottom. Returns the newly
+/// focused window.
+#[cfg_attr(not(feature = "mcp"), allow(dead_code))]
+fn switch_window(comp: &mut Compositor, switch: &web::shared::WindowSwitch) -> Option<web::shared::WindowInfo> {
+    let tree = collect_window_tree(comp);
+    // Bottom to top, optionally limited to one app
+    let stack: Vec<smithay::desktop::Window> = comp.space.elements()
+        .filter(|w| {
+            let Some(app_id) = switch.app_id.as_deref() else { return true };
+            let wl_surface = w.toplevel().unwrap().wl_surface();
+            comp.window_registry.iter()
+                .position(|wl| wl.id() == wl_surface.id())
+                .and_then(|idx| tree.get(idx))
+                .is_some_and(|info| info.app_id == app_id)
+        })
+        .cloned()
+        .collect();
+    let raise = if switch.reverse {
+        &stack[..stack.len().saturating_sub(1)]
+    } else {
+        &stack[..stack.len().min(1)]
+    };
+    // A single window (or reverse with one) just gets focus
+    let top = raise.last().or(stack.last())?.clone();
+    for window in raise {
+        comp.space.raise_element(window, true);
+    }
+    let wl_surface = top.toplevel().unwrap().wl_surface().clone();
+    let idx = comp.window_registry.iter().position(|wl| wl.id() == wl_surface.id())? as u32;
+    let keyboard = comp.seat.get_keyboard().unwrap();
+    keyboard.set_focus(comp, Some(wl_surface), smithay::utils::SERIAL_COUNTER.next_serial());
+    comp.focused_surface_id = Some(idx);
+    comp.taskbar_dirty = true;
+    comp.needs_redraw = true;
+    info!("Window switch: focused window index {}", idx);
+    collect_window_tree(comp).into_iter().find(|w| w.id == idx)
+}
+
+/// Find the toplevel under a display point. The flag is true when the hit
+/// surface is a popup or subsurface (e.g. a tooltip) rather than the toplevel itself.
+#[cfg_attr(not(feature = "mcp"), allow(dead_code))]
+fn window_at(comp: &Compositor, x: i32, y: i32) -> Option<(web::shared::WindowInfo, bool)> {
+    let pos = comp.display_to_logical(x as f64, y as f64);
+    let (window, _) = comp.space.element_under(pos)?;
+    let toplevel = window.toplevel()?.wl_surface().clone();
+    let on_popup = comp.surface_under(pos)
+        .map(|(surface, _)| surface.id() != toplevel.id())
+        .unwrap_or(false);
+    let idx = comp.window_registry.iter().position(|wl| wl.id() == toplevel.id())? as u32;
+    collect_window_tree(comp)
+        .into_iter()
+        .find(|w| w.id == idx)
+        .map(|w| (w, on_popup))
+}
+
+/// The keyboard-focused toplevel (None if focus is empty or on an unregistered
+/// surface) and whether the focused client has a text-input instance bound.
+#[cfg_attr(not(feature = "mcp"), allow(dead_code))]
+fn focused_window(comp: &Compositor) -> (Option<web::shared::WindowInfo>, bool) {
+    use smithay::wayland::text_input::TextInputSeat;
+
+    let window = collect_window_tree(comp).into_iter().find(|w| w.focused);
+    let mut text_input = false;
+    comp.seat.text_input().with_focused_text_input(|_, _| {
+        text_input = true;
+    });
+    (window, text_input)
+}
+
+/// Make `content` the compositor-owned clipboard selection, offered under
+/// the configured MIME types (plus text/html for HTML)
+fn offer_clipboard(state: &mut Compositor, content: ClipboardContent, mime_types: &[String]) {
+    use smithay::wayland::selection::data_device::set_data_device_selection;
+
+    let offered = content.mime_types(mime_types);
+    state.pending_paste = Some(content);
+    state.pending_paste_mimes = offered.clone();
+    let dh = state.display_handle.clone();
+    let seat = state.seat.clone();
+    set_data_device_selection(&dh, &seat, offered, ());
+}
+
+/// Inject committed text from IME into the focused Wayland client.
+/// Uses zwp_text_input_v3 commit_string if the client supports it.
+fn inject_text(state: &mut Compositor, ev: &InputEventData, mime_types: &[String]) {
+    use smithay::wayland::text_input::TextInputSeat;
+
+    if ev.text.is_empty() {
+        return;
+    }
+
+    let text_input = state.seat.text_input().clone();
+    let mut sent = false;
+
+    text_input.with_focused_text_input(|ti, _surface| {
+        ti.commit_string(Some(ev.text.clone()));
+        ti.done(0);
+        sent = true;
+    });
+
+    if sent {
+        info!("Injected text via text_input protocol: {:?}", ev.text);
+    } else {
+        // Fallback: set compositor-side clipboard selection, then simulate Ctrl+Shift+V
+        info!("No text_input client, using clipboard paste for: {:?}", ev.text);
+        offer_clipboard(state, ClipboardContent::text(ev.text.clone()), mime_types);
+
+        // Simulate Ctrl+Shift+V (terminal paste shortcut).
+        // Use known evdev keycodes directly to avoid keysym mapping gaps.
+        let keyboard = state.seat.get_keyboard().unwrap();
+        let ctrl_code: u32 = 37;  // Control_L
+        let shift_code: u32 = 50; // Shift_L
+        let v_code: u32 = 55;     // v
+
+        let s = smithay::utils::SERIAL_COUNTER.next_serial();
+        keyboard.input::<(), _>(state, smithay::input::keyboard::Keycode::from(ctrl_code),
+            smithay::backend::input::KeyState::Pressed, s, 0, |_, _, _| smithay::input::keyboard::FilterResult::Forward);
+        let s = smithay::utils::SERIAL_COUNTER.next_serial();
+        keyboard.input::<(), _>(state, smithay::input::keyboard::Keycode::from(shift_code),
+            smithay::backend::input::KeyState::Pressed, s, 0, |_, _, _| smithay::input::keyboard::FilterResult::Forward);
+        let s = smithay::utils::SERIAL_COUNTER.next_serial();
+        keyboard.input::<(), _>(state, smithay::input::keyboard::Keycode::from(v_code),
+            smithay::backend::input::KeyState::Pressed, s, 0, |_, _, _| smithay::input::keyboard::FilterResult::Forward);
+        let s = smithay::utils::SERIAL_COUNTER.next_serial();
+        keyboard.input::<(), _>(state, smithay::input::keyboard::Keycode::from(v_code),
+            smithay::backend::input::KeyState::Released, s, 0, |_, _, _| smithay::input::keyboard::FilterResult::Forward);
+        let s = smithay::utils::SERIAL_COUNTER.next_serial();
+        keyboard.input::<(), _>(state, smithay::input::keyboard::Keycode::from(shift_code),
+            smithay::backend::input::KeyState::Released, s, 0, |_, _, _| smithay::input::keyboard::FilterResult::Forward);
+        let s = smithay::utils::SERIAL_COUNTER.next_serial();
+        keyboard.input::<(), _>(state, smithay::input::keyboard::Keycode::from(ctrl_code),
+            smithay::backend::input::KeyState::Released, s, 0, |_, _, _| smithay::input::keyboard::FilterResult::Forward);
+    }
+}
+
+/// Convert X11 keysym to xkb keycode (evdev keycode + 8).
+fn keysym_to_keycode(keysym: u32) -> Option<u32> {
+    match keysym {
+        // Letters (a-z / A-Z)
+        0x61 | 0x41 => 38, 0x62 | 0x42 => 56, 0x63 | 0x43 => 54,
+        0x64 | 0x44 => 40, 0x65 | 0x45 => 26, 0x66 | 0x46 => 41,
+        0x67 | 0x47 => 42, 0x68 | 0x48 => 43, 0x69 | 0x49 => 31,
+        0x6a | 0x4a => 44, 0x6b | 0x4b => 45, 0x6c | 0x4c => 46,
+        0x6d | 0x4d => 58, 0x6e | 0x4e => 57, 0x6f | 0x4f => 32,
+        0x70 | 0x50 => 33, 0x71 | 0x51 => 24, 0x72 | 0x52 => 27,
+        0x73 | 0x53 => 39, 0x74 | 0x54 => 28, 0x75 | 0x55 => 30,
+        0x76 | 0x56 => 55, 0x77 | 0x57 => 25, 0x78 | 0x58 => 53,
+        0x79 | 0x59 => 29, 0x7a | 0x5a => 52,
+        // Digits 0-9 and shifted symbols on same keys
+        0x30 | 0x29 => 19, 0x31 | 0x21 => 10, 0x32 | 0x40 => 11,
+        0x33 | 0x23 => 12, 0x34 | 0x24 => 13, 0x35 | 0x25 => 14,
+        0x36 | 0x5e => 15, 0x37 | 0x26 => 16, 0x38 | 0x2a => 17,
+        0x39 | 0x28 => 18,
+        // Function keys F1-F12
+        0xffbe => 67, 0xffbf => 68, 0xffc0 => 69, 0xffc1 => 70,
+        0xffc2 => 71, 0xffc3 => 72, 0xffc4 => 73, 0xffc5 => 74,
+        0xffc6 => 75, 0xffc7 => 76, 0xffc8 => 95, 0xffc9 => 96,
+        // Modifiers
+        0xffe1 => 50, 0xffe2 => 62,   // Shift L/R
+        0xffe3 => 37, 0xffe4 => 105,  // Control L/R
+        0xffe9 => 64, 0xffea => 108,  // Alt L/R
+        0xffeb => 133, 0xffec => 134, // Super L/R
+        0xffe5 => 66,                 // Caps_Lock
+        // Navigation
+        0xff0d => 36, 0xff1b => 9, 0xff08 => 22, 0xff09 => 23,
+        0x20 => 65, 0xffff => 119, 0xff63 => 118,
+        0xff50 => 110, 0xff57 => 115, 0xff55 => 112, 0xff56 => 117,
+        // Arrows
+        0xff51 => 113, 0xff52 => 111, 0xff53 => 114, 0xff54 => 116,
+        // Symbols (key and shifted variant grouped)
+        0x2d | 0x5f => 20,  // minus / underscore
+        0x3d | 0x2b => 21,  // equal / plus
+        0x5b | 0x7b => 34,  // bracketleft / braceleft
+        0x5d | 0x7d => 35,  // bracketright / braceright
+        0x5c | 0x7c => 51,  // backslash / bar
+        0x3b | 0x3a => 47,  // semicolon / colon
+        0x27 | 0x22 => 48,  // apostrophe / quotedbl
+        0x60 | 0x7e => 49,  // grave / tilde
+        0x2c | 0x3c => 59,  // comma / less
+        0x2e | 0x3e => 60,  // period / greater
+        0x2f | 0x3f => 61,  // slash / question
+        // Misc
+        0xff13 => 127, 0xff14 => 78, 0xff61 => 107, 0xff7f => 77,
+        _ => {
+            log::debug!("Unknown keysym 0x{:x}, no keycode mapping", keysym);
+            return None;
+        }
+    }
+    .into()
+}
+
+/// Check if an RTP packet contains an H.264 keyframe NAL unit.
+fn is_h264_keyframe_packet(data: &[u8]) -> bool {
+    let hdr_len = webrtc::media_track::rtp_util::header_length(data).unwrap_or(12);
+    if data.len() <= hdr_len { return false; }
+    let nal_type = data[hdr_len] & 0x1F;
+    match nal_type {
+        5 | 7 | 8 => true,
+        24 => true,
+        28 if data.len() > hdr_len + 1 => (data[hdr_len + 1] & 0x1F) == 5,
+        _ => false,
+    }
+}
+
+/// Packets of the frame currently coming out of the payloader
+#[derive(Default)]
+struct RtpFrameBuffer {
+    packets: Vec<Vec<u8>>,
+    /// Timestamp of the last packet pulled
+    prev_ts: Option<u32>,
+    /// When the last packet was pulled
+    last_sample: Option<Instant>,
+    /// Part of the current frame already went out on the timeout
+    partial_sent: bool,
+}
+
+fn pull_and_broadcast_rtp(
+    pipeline: &gstreamer::VideoPipeline,
+    shared: &Arc<web::SharedState>,
+    rtp_count: &mut u64,
+    keyframe: &mut webrtc::media_track::KeyframeAssembler,
+    frame: &mut RtpFrameBuffer,
+    push_times: &mut webrtc::media_clock::PushTimes,
+    flush_after: Duration,
+) {
+    // Output size this pipeline encodes; a resize may already have changed
+    // the display before its last frames drain
+    let size = (pipeline.config().width, pipeline.config().height);
+    while let Some(sample) = pipeline.try_pull_sample() {
+        if let Some(buffer) = sample.buffer() {
+            let map = buffer.map_readable().unwrap();
+            let data = map.as_slice().to_vec();
+
+            let Some(ts) = webrtc::media_track::rtp_util::get_timestamp(&data) else {
+                continue;
+            };
+
+            // When timestamp changes, the previous frame is complete —
+            // set marker bit on its last packet and flush. Inequality is
+            // all that matters here, so the 32-bit wrap needs no care.
+            if frame.prev_ts.is_some_and(|prev| ts != prev) {
+                if !frame.packets.is_empty() {
+                    flush_frame(frame, shared, rtp_count, keyframe, push_times, size, true);
+                }
+                frame.partial_sent = false;
+            }
+            frame.prev_ts = Some(ts);
+            frame.last_sample = Some(Instant::now());
+            let has_marker = webrtc::media_track::rtp_util::is_marker_set(&data);
+            frame.packets.push(data);
+            if has_marker {
+                flush_frame(frame, shared, rtp_count, keyframe, push_times, size, true);
+                frame.partial_sent = false;
+            }
+        }
+    }
+
+    // If no new packets arrived for a short window, send what there is so
+    // a frame missing its marker bit does not stall. It may also be a slow
+    // frame cut short, so it is not marked complete: the marker is left
+    // alone and a keyframe is only cached once its real end goes out.
+    if !frame.packets.is_empty() && frame.last_sample.is_some_and(|at| at.elapsed() >= flush_after) {
+        flush_frame(frame, shared, rtp_count, keyframe, push_times, size, false);
+        frame.partial_sent = true;
+    }
+}
+
+/// Broadcast the buffered packets. For a `complete` frame the marker bit is
+/// set on its last packet first. Keyframes are cached tagged with `size`,
+/// the output size of the pipeline that produced them.
+fn flush_frame(
+    frame: &mut RtpFrameBuffer,
+    shared: &Arc<web::SharedState>,
+    rtp_count: &mut u64,
+    keyframe: &mut webrtc::media_track::KeyframeAssembler,
+    push_times: &mut webrtc::media_clock::PushTimes,
+    size: (u32, u32),
+    complete: bool,
+) {
+    // Match the frame to its push by RTP timestamp (unless its start
+    // already went out)
+    let ts = frame.packets.first().and_then(|pkt| webrtc::media_track::rtp_util::get_timestamp(pkt));
+    if let Some(pushed) = ts.filter(|_| !frame.partial_sent).and_then(|ts| push_times.take(ts)) {
+        shared.record_frame_latency(pushed.elapsed().as_secs_f64() * 1000.0);
+    }
+
+    if complete {
+        if let Some(last) = frame.packets.last_mut() {
+            if last.len() >= 2 {
+                last[1] |= 0x80;
+            }
+        }
+    }
+
+    for data in frame.packets.drain(..) {
+        if let Some(packets) = keyframe.push(&data, is_h264_keyframe_packet(&data)) {
+            log::info!("Cached keyframe: {} pkts, {} bytes",
+                packets.len(),
+                packets.iter().map(|p| p.len()).sum::<usize>());
+            shared.set_keyframe_cache(packets, size.0, size.1);
+            shared.stats.lock().unwrap().keyframes_total += 1;
+        }
+
+        *rtp_count += 1;
+        if *rtp_count <= 3 || *rtp_count % 500 == 0 {
+            log::info!("broadcast_rtp #{} receivers={}", *rtp_count, shared.rtp_receiver_count());
+        }
+        shared.broadcast_rtp(data);
+    }
+}
+
+/// Encoder pipeline settings for the given output size; the stream is
+/// encoded at `render_scale` of it, further divided by `scale_divisor`
+pub fn pipeline_config_for(
+    config: &Config,
+    width: u32,
+    height: u32,
+    scale_divisor: u32,
+    render_scale: f64,
+) -> PipelineConfig {
+    PipelineConfig {
+        width, height,
+        framerate: config.encoding.target_fps,
+        codec: config.webrtc.video_codec,
+        bitrate: config.webrtc.video_bitrate,
+        hardware_encoder: config.webrtc.hardware_encoder,
+        keyframe_interval: config.webrtc.keyframe_interval,
+        latency_ms: config.webrtc.pipeline_latency_ms,
+        max_latency_ms: config.webrtc.max_latency_ms,
+        queue_policy: config.encoding.appsrc_queue,
+        queue_max_bytes: config.encoding.appsrc_max_bytes,
+        scale_divisor,
+        render_scale,
+        encode_cpus: config.server.encode_cpu.clone(),
+        realtime: (config.server.realtime != config::RealtimePolicy::Off)
+            .then_some((config.server.realtime, config.server.realtime_priority)),
+        color_range: config.webrtc.color_range,
+        color_matrix: config.webrtc.color_matrix,
+        bit_depth: config.webrtc.bit_depth,
+        pixel_format: config.display.pixel_format,
+    }
+}
+
+/// Taskbar and cursor that come with an on-demand (`getstats`) stats reply
+fn send_on_demand_state(shared: &web::SharedState) {
+    if let Some(json) = shared.last_taskbar_json.lock().unwrap().clone() {
+        shared.send_text(format!("taskbar,{}", json));
+    }
+    if let Some(msg) = shared.last_cursor_message() {
+        shared.send_text(msg);
+    }
+}
+
+/// Sent instead of cursor updates while the server draws the cursor
+const HIDDEN_CURSOR_MESSAGE: &str = r#"cursor,{"override":"none"}"#;
+
+/// Image to blend into frames for the cursor status, sized for the output
+/// scale; named cursors use the built-in arrow, as does a client-drawn one
+/// that can't be read
+fn server_cursor_bitmap(
+    status: &smithay::input::pointer::CursorImageStatus,
+    output_scale: f64,
+) -> Option<crate::compositor::cursor::CursorBitmap> {
+    use crate::compositor::cursor::{surface_cursor_bitmap, CursorBitmap};
+    use smithay::input::pointer::CursorImageStatus;
+    match status {
+        CursorImageStatus::Hidden => None,
+        CursorImageStatus::Named(_) => Some(CursorBitmap::arrow().at_scale(output_scale)),
+        CursorImageStatus::Surface(surface) => Some(
+            surface_cursor_bitmap(surface).unwrap_or_else(CursorBitmap::arrow).at_scale(output_scale),
+        ),
+    }
+}
+
+/// `cursor,{json}` for the cursor status: a CSS cursor name, or for
+/// client-drawn cursors the PNG image with its hotspot
+fn cursor_message(
+    status: &smithay::input::pointer::CursorImageStatus,
+    png_cache: &mut crate::compositor::cursor::CursorPngCache,
+) -> String {
+    use smithay::input::pointer::CursorImageStatus;
+    let name = match status {
+        CursorImageStatus::Hidden => "none",
+        CursorImageStatus::Named(icon) => icon.name(),
+        CursorImageStatus::Surface(surface) => match crate::compositor::cursor::surface_cursor(surface, png_cache) {
+            Some(image) => {
+                let json = serde_json::json!({
+                    "curdata": base64::engine::general_purpose::STANDARD.encode(&image.png),
+                    "hotx": image.hotspot.0,
+                    "hoty": image.hotspot.1,
+                    "handle": surface.id().protocol_id(),
+                });
+                return format!("cursor,{}", json);
+            }
+            // Not readable (no SHM buffer yet): fall back to the default
+            None => "default",
+        },
+    };
+    format!("cursor,{{\"override\":\"{}\"}}", name)
+}
+
+/// Replace the running pipeline; keeps the old one if the new one fails
+fn rebuild_pipeline(
+    pipeline: &mut gstreamer::VideoPipeline,
+    shared: &web::SharedState,
+    new_config: PipelineConfig,
+) {
+    let (w, h) = (new_config.width, new_config.height);
+    info!("Rebuilding GStreamer pipeline for {}x{}", w, h);
+    let _ = pipeline.stop();
+    match gstreamer::VideoPipeline::new(new_config) {
+        Ok(new_pipeline) => {
+            if let Err(e) = new_pipeline.start() {
+                error!("Failed to start new pipeline: {}", e);
+            } else {
+                *pipeline = new_pipeline;
+                info!("Pipeline rebuilt for {}x{}", w, h);
+                // Browser pointer positions follow the new video size
+                let (stream_w, stream_h) = pipeline.config().encoded_size();
+                shared.set_stream_size(stream_w, stream_h);
+                shared.video_bit_depth.store(pipeline.bit_depth(), Ordering::Relaxed);
+            }
+        }
+        Err(e) => error!("Failed to create new pipeline: {}", e),
+    }
+}
+
+fn apply_runtime_settings(
+    rs: &Arc<runtime_settings::RuntimeSettings>,
+    pipeline: &gstreamer::VideoPipeline,
+) {
+    if rs.take_keyframe_request() {
+        pipeline.request_keyframe();
+    }
+    let new_bitrate = rs.video_bitrate_kbps();
+    if new_bitrate != pipeline.config().bitrate {
+        pipeline.set_bitrate(new_bitrate);
+    }
+    let new_ki = rs.keyframe_interval();
+    if new_ki != pipeline.config().keyframe_interval {
+        pipeline.set_keyframe_interval(new_ki);
+    }
+}
+
+async fn run_async_services(
+    config: Config,
+    shared: Arc<web::SharedState>,
+    runtime_settings: Arc<runtime_settings::RuntimeSettings>,
+    _running: Arc<AtomicBool>,
+    #[cfg(feature = "mcp")] mcp_stdio: bool,
+) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let upload_settings = file_upload::FileUploadSettings::from_config(&config);
+
+    // CPU / RSS for stats, /metrics and MCP
+    tokio::spawn(crate::resource_usage::run_sampler(shared.clone(), config.http.thread_cpu_metrics));
+
+    // Session manager (WebRTC)
+    let session_manager = if config.webrtc.enabled {
+        // Resolve a routable IP for the ICE-TCP candidate.
+        // 0.0.0.0 is not valid in SDP — the browser needs an actual address.
+        let bind_ip: std::net::IpAddr = config.http.host.parse().unwrap_or(
+            std::net::IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED),
+        );
+        let candidate_ip = if bind_ip.is_unspecified() {
+            std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST)
+        } else {
+            bind_ip
+        };
+        let listen_addr = std::net::SocketAddr::new(candidate_ip, config.http.port);
+        info!("ICE-TCP candidate address: {}", listen_addr);
+        #[allow(unused_mut)]
+        let mut sm = SessionManager::new(
+            config.webrtc.clone(),
+            shared.input_sender.clone(),
+            upload_settings,
+            runtime_settings.clone(),
+            shared.clone(),
+            16,
+            listen_addr,
+        );
+        if let Some(ref dir) = config.webrtc.dtls_cert_dir {
+            #[cfg(feature = "tls")]
+            {
+                let stored = crate::cert_store::load_or_generate(std::path::Path::new(dir), "dtls", &["ivnc"])?;
+                sm.set_dtls_cert(str0m::config::DtlsCert {
+                    certificate: stored.cert_der,
+                    private_key: stored.key_der,
+                });
+            }
+            #[cfg(not(feature = "tls"))]
+            warn!("webrtc.dtls_cert_dir ({}) ignored: built without the tls feature", dir);
+        }
+        Some(Arc::new(sm))
+    } else {
+        None
+    };
+
+    // Experimental WebTransport (QUIC over UDP, separate from the TCP port)
+    if config.webtransport.enabled {
+        #[cfg(feature = "webtransport")]
+        {
+            let wt_port = config.webtransport.port.unwrap_or(config.http.port);
+            if let Err(e) = crate::transport::webtransport::start(wt_port, shared.clone()) {
+                warn!("WebTransport server failed to start: {}", e);
+            }
+        }
+        #[cfg(not(feature = "webtransport"))]
+        warn!("webtransport.enabled ignored: built without the webtransport feature");
+    }
+
+    // MCP stdio mode: run MCP server on stdin/stdout alongside HTTP
+    #[cfg(feature = "mcp")]
+    if mcp_stdio {
+        info!("Starting MCP server on stdio (HTTP server will also start)");
+        let mcp_server = crate::mcp::McpServer::new(shared.clone());
+        let transport = rmcp::transport::io::stdio();
+        use rmcp::ServiceExt;
+        let service = mcp_server.serve(transport).await
+            .map_err(|e| -> Box<dyn std::error::Error + Send + Sync> {
+                format!("MCP stdio error: {}", e).into()
+            })?;
+        tokio::spawn(async move {
+            if let Err(e) = service.waiting().await {
+                log::error!("MCP stdio session ended with error: {}", e);
+            } else {
+                log::info!("MCP stdio session ended");
+            }
+        });
+    }
+
+    // Pake apps manager
+    let pake_state = match crate::pake_apps::api::PakeState::new() {
+        Ok(ps) => {
+            info!("Pake apps manager initialized");
+            let ps_arc = std::sync::Arc::new(ps);
+
+            // Restore previously running apps after update
+            let ps_clone = ps_arc.clone();
+            tokio::spawn(async move {
+                // Wait a bit for the system to stabilize
+                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+                if let Err(e) = ps_clone.restore_running_state().await {
+                    log::warn!("Failed to restore running apps: {}", e);
+                } else {
+                    log::info!("Running apps restoration completed");
+                }
+            });
+
+            Some(ps_arc)
+        }
+        Err(e) => {
+            warn!("Failed to init Pake apps manager: {}", e);
+            None
+        }
+    };
+
+    // HTTP server
+    let port = config.http.port;
+    info!("Starting HTTP server on port {}", port);
+    let tls = config.http.tls.then(|| web::TlsOptions::from_config(&config.http));
+    web::run_http_server_with_webrtc(port, shared.clone(), session_manager, tls, pake_state)
+        .await
+        .map_err(|e| -> Box<dyn std::error::Error + Send + Sync> {
+            format!("HTTP server error: {}", e).into()
+        })?;
+
+    Ok(())
+}
+
+/// Look up a .desktop file whose StartupWMClass matches the given app_id,
+/// and return its Name= value. Returns None if no match found.
+/// For Pake apps (ivnc-pake-*), extract the app name from the window title.
+fn resolve_display_name(app_id: &str, title: &str) -> Option<String> {
+    if app_id.is_empty() {
+        return None;
+    }
+
+    // Special handling for Pake apps: extract name from window title
+    // Chrome window titles are typically: "Page Title - Google Chrome" or "Page Title - Chromium"
+    if app_id == "ivnc-pake-windowed" || app_id == "ivnc-pake-app" {
+        // Try to extract the page title before " - Google Chrome" or " - Chromium"
+        if let Some(pos) = title.rfind(" - ") {
+            let page_title = &title[..pos];
+            // Skip generic titles like "Untitled"
+            if !page_title.is_empty() && page_title != "Untitled" {
+                return Some(page_title.to_string());
+            }
+        }
+        // Fallback: return the full title if we can't parse it
+        if !title.is_empty() && title != "Untitled" {
+            return Some(title.to_string());
+        }
+        return None;
+    }
+
+    let data_home = env::var("XDG_DATA_HOME")
+        .ok()
+        .map(std::path::PathBuf::from)
+        .or_else(|| env::var("HOME").ok().map(|h| std::path::PathBuf::from(h).join(".local/share")))?;
+    let apps_dir = data_home.join("applications");
+    let entries = std::fs::read_dir(&apps_dir).ok()?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("desktop") {
+            continue;
+        }
+        let content = std::fs::read_to_string(&path).ok()?;
+        let mut name = None;
+        let mut wm_class = None;
+        for line in content.lines() {
+            if let Some(v) = line.strip_prefix("Name=") {
+                name = Some(v.to_string());
+            } else if let Some(v) = line.strip_prefix("StartupWMClass=") {
+                wm_class = Some(v.to_string());
+            }
+        }
+        if wm_class.as_deref() == Some(app_id) {
+            return name;
+        }
+    }
+    None
+}
+
+/// Ensure PulseAudio daemon is running so audio capture can connect.
+/// If not running, starts it with `--exit-idle-time=-1` to keep it alive.
+#[cfg(feature = "pulseaudio")]
+fn ensure_pulseaudio() {
+    use std::process::Command;
+
+    // Check if already running
+    let status = Command::new("pulseaudio")
+        .arg("--check")
+        .status();
+    if let Ok(s) = status {
+        if s.success() {
+            info!("PulseAudio already running");
+            return;
+        }
+    }
+
+    info!("PulseAudio not running, starting daemon...");
+    match Command::new("pulseaudio")
+        .args(["--start", "--exit-idle-time=-1"])
+        .status()
+    {
+        Ok(s) if s.success() => info!("PulseAudio daemon started"),
+        Ok(s) => warn!("PulseAudio start exited with: {}", s),
+        Err(e) => warn!("Failed to start PulseAudio: {}", e),
+    }
+}
+
+/// Set up GTK CSS to hide headerbars on fullscreen windows.
+/// Dialogs are excluded so their controls stay visible.
+fn setup_gtk_css_env() {
+    // Only hide headerbar on fullscreen windows, exclude dialogs
+    let css = "\
+window.fullscreen:not(.dialog):not(.messagedialog) headerbar {\n\
+  min-height: 0;\n\
+  padding: 0;\n\
+  margin: 0 0 -100px 0;\n\
+  border: none;\n\
+  background: none;\n\
+  box-shadow: none;\n\
+  opacity: 0;\n\
+}\n\
+window.fullscreen:not(.dialog):not(.messagedialog) headerbar * {\n\
+  min-height: 0;\n\
+  min-width: 0;\n\
+  padding: 0;\n\
+  margin: 0;\n\
+}\n\
+window.fullscreen:not(.dialog):not(.messagedialog) .titlebar {\n\
+  min-height: 0;\n\
+  padding: 0;\n\
+  margin: 0 0 -100px 0;\n\
+  border: none;\n\
+  background: none;\n\
+  box-shadow: none;\n\
+  opacity: 0;\n\
+}\n\
+window.fullscreen:not(.dialog):not(.messagedialog) .titlebar * {\n\
+  min-height: 0;\n\
+  min-width: 0;\n\
+  padding: 0;\n\
+  margin: 0;\n\
+}\n\
+/* Ensure dialog action areas stay visible */\n\
+.dialog actionbar,\n\
+.messagedialog actionbar {\n\
+  min-height: 40px;\n\
+}\n";
+
+    // Write CSS to ivnc-specific directory
+    let runtime_dir = env::var("XDG_RUNTIME_DIR")
+        .unwrap_or_else(|_| format!("/run/user/{}", unsafe { libc::getuid() }));
+    let ivnc_dir = format!("{}/ivnc", runtime_dir);
+    if std::fs::create_dir_all(&ivnc_dir).is_err() {
+        warn!("Failed to create ivnc runtime dir {}", ivnc_dir);
+        return;
+    }
+
+    let css_path = format!("{}/gtk-headerbar.css", ivnc_dir);
+    if std::fs::write(&css_path, css).is_err() {
+        warn!("Failed to write GTK CSS to {}", css_path);
+        return;
+    }
+    info!("Wrote GTK CSS to {}", css_path);
+
+    // GTK_CSS tells GTK to load this CSS file in addition to the theme's CSS.
+    env::set_var("GTK_CSS", &css_path);
+    info!("Set GTK_CSS={}", css_path);
+}
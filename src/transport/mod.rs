@@ -1,7 +1,10 @@
 //! Transport layer for iVnc streaming
 //!
-//! Handles WebRTC signaling over WebSocket.
+//! Handles WebRTC signaling over WebSocket, plus the experimental
+//! WebTransport media path.
 
 pub mod signaling_server;
+#[cfg(feature = "webtransport")]
+pub mod webtransport;
 
 pub use signaling_server::handle_signaling_connection;
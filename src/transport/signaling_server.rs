@@ -10,16 +10,39 @@
 #![allow(dead_code)]
 
 use crate::webrtc::{SignalingMessage, SessionManager};
-use crate::webrtc::signaling::SignalingParser;
+use crate::webrtc::signaling::{parse_ice_candidate, SignalingParser};
 use crate::web::SharedState;
 use axum::extract::ws::{Message, WebSocket};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
 use futures::{SinkExt, StreamExt};
 use log::{info, warn, debug, error};
 use serde_json::Value;
 use serde_json::json;
+use std::io::{Read, Write};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
+/// Outbound messages buffered per signaling connection
+const OUTBOUND_QUEUE_CAPACITY: usize = 64;
+
+/// How long the outbound queue may stay full before the client is dropped
+const SLOW_CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// WebSocket subprotocol a client offers to have large signaling messages
+/// deflate-compressed. axum's WebSocket stack does not implement the
+/// permessage-deflate extension, so compression is negotiated here instead.
+pub const DEFLATE_SUBPROTOCOL: &str = "ivnc-deflate";
+
+/// Messages shorter than this are sent as plain text even when compression
+/// was negotiated; only SDP blobs are worth the effort
+const COMPRESS_MIN_BYTES: usize = 512;
+
+/// Upper bound on an inflated client message, against deflate bombs
+const MAX_INFLATED_BYTES: u64 = 1 << 20;
+
 /// Signaling server configuration
 #[derive(Debug, Clone)]
 pub struct SignalingConfig {
@@ -41,23 +64,103 @@ impl Default for SignalingConfig {
     }
 }
 
-/// Handle a WebRTC signaling WebSocket connection
+/// Bounded outbound queue for a signaling connection.
+///
+/// Messages are dropped while the queue is full; once it has stayed full for
+/// longer than `max_full` the queue is marked overflowed and the connection
+/// should be closed.
+struct OutboundQueue {
+    tx: mpsc::Sender<String>,
+    max_full: Duration,
+    full_since: Option<Instant>,
+    dropped: u64,
+    overflowed: bool,
+}
+
+impl OutboundQueue {
+    fn new(tx: mpsc::Sender<String>, max_full: Duration) -> Self {
+        Self { tx, max_full, full_since: None, dropped: 0, overflowed: false }
+    }
+
+    /// Queue a message; returns false if it was dropped.
+    fn push(&mut self, msg: String) -> bool {
+        if self.overflowed {
+            return false;
+        }
+        match self.tx.try_send(msg) {
+            Ok(()) => {
+                self.full_since = None;
+                true
+            }
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                self.dropped += 1;
+                let since = *self.full_since.get_or_insert_with(Instant::now);
+                if since.elapsed() >= self.max_full {
+                    self.overflowed = true;
+                }
+                false
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        }
+    }
+
+    fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+}
+
+/// Raw deflate (as produced by `CompressionStream("deflate-raw")`)
+fn deflate(text: &str) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(text.as_bytes()).expect("writing to a Vec cannot fail");
+    encoder.finish().expect("writing to a Vec cannot fail")
+}
+
+/// Inverse of [`deflate`], refusing output larger than `MAX_INFLATED_BYTES`
+fn inflate(data: &[u8]) -> std::io::Result<String> {
+    let mut text = String::new();
+    DeflateDecoder::new(data)
+        .take(MAX_INFLATED_BYTES + 1)
+        .read_to_string(&mut text)?;
+    if text.len() as u64 > MAX_INFLATED_BYTES {
+        return Err(std::io::Error::other("inflated message too large"));
+    }
+    Ok(text)
+}
+
+/// Frame an outbound message: large ones become binary deflate frames when
+/// the client negotiated `DEFLATE_SUBPROTOCOL`
+fn outbound_frame(msg: String, compress: bool) -> Message {
+    if compress && msg.len() >= COMPRESS_MIN_BYTES {
+        Message::Binary(deflate(&msg).into())
+    } else {
+        Message::Text(msg.into())
+    }
+}
+
+/// Handle a WebRTC signaling WebSocket connection. `compress` is set when
+/// the client negotiated `DEFLATE_SUBPROTOCOL`.
 pub async fn handle_signaling_connection(
     socket: WebSocket,
     state: Arc<SharedState>,
     session_manager: Arc<SessionManager>,
     client_host: Option<String>,
+    compress: bool,
 ) {
-    info!("New signaling WebSocket connection established (host: {:?})", client_host);
+    info!(
+        "New signaling WebSocket connection established (host: {:?}, compression: {})",
+        client_host, compress
+    );
     let (mut ws_sender, mut ws_receiver) = socket.split();
 
-    // Create a channel for sending messages
-    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    // Bounded channel for sending messages; a client that stops reading is dropped
+    let (tx, mut rx) = mpsc::channel::<String>(OUTBOUND_QUEUE_CAPACITY);
+    let mut tx = OutboundQueue::new(tx, SLOW_CLIENT_TIMEOUT);
 
     // Spawn task to forward messages to WebSocket
     let send_task = tokio::spawn(async move {
         while let Some(msg) = rx.recv().await {
-            if ws_sender.send(Message::Text(msg.into())).await.is_err() {
+            if ws_sender.send(outbound_frame(msg, compress)).await.is_err() {
                 break;
             }
         }
@@ -69,63 +172,30 @@ pub async fn handle_signaling_connection(
 
     // Process incoming messages
     while let Some(result) = ws_receiver.next().await {
-        match result {
-            Ok(Message::Text(text)) => {
-                let text_str: &str = text.as_ref();
-
-                if let Some(reply) = handle_gstreamer_control_message(text_str, &mut wire_format) {
-                    let _ = tx.send(reply);
-                    continue;
-                }
+        if tx.overflowed() {
+            warn!(
+                "Signaling client not reading (queue full for {:?}, {} messages dropped); closing connection (session: {:?})",
+                SLOW_CLIENT_TIMEOUT, tx.dropped, session_id
+            );
+            break;
+        }
 
-                if let Some(msg) = parse_gstreamer_json_message(text_str) {
-                    wire_format = WireFormat::GStreamer;
-                    if let Some(response) = handle_signaling_message(
-                        msg,
-                        &mut session_id,
-                        &state,
-                        &session_manager,
-                        &tx,
-                        wire_format,
-                        client_host.as_deref(),
-                    ).await {
-                        let _ = tx.send(response);
-                    }
+        let text = match result {
+            Ok(Message::Text(text)) => text.to_string(),
+            Ok(Message::Binary(data)) if compress => match inflate(&data) {
+                Ok(text) => text,
+                Err(e) => {
+                    warn!("Undecodable compressed signaling message: {}", e);
                     continue;
                 }
-
-                match SignalingParser::parse(text_str) {
-                    Ok(msg) => {
-                        if let Some(response) = handle_signaling_message(
-                            msg,
-                            &mut session_id,
-                            &state,
-                            &session_manager,
-                            &tx,
-                            wire_format,
-                            client_host.as_deref(),
-                        ).await {
-                            let _ = tx.send(response);
-                        }
-                    }
-                    Err(e) => {
-                        warn!("Invalid signaling message: {}", e);
-                        let error = SignalingMessage::error(
-                            "PARSE_ERROR",
-                            &e.to_string(),
-                            session_id.clone(),
-                        );
-                        if let Some(msg) = format_signaling_message(&error, wire_format) {
-                            let _ = tx.send(msg);
-                        }
-                    }
-                }
-            }
+            },
             Ok(Message::Binary(_)) => {
                 debug!("Received binary message on signaling channel");
+                continue;
             }
             Ok(Message::Ping(_data)) => {
                 debug!("Received ping on signaling channel");
+                continue;
             }
             Ok(Message::Close(_)) => {
                 info!("Signaling connection closed");
@@ -135,7 +205,56 @@ pub async fn handle_signaling_connection(
                 error!("WebSocket error: {}", e);
                 break;
             }
-            _ => {}
+            _ => continue,
+        };
+        let text_str: &str = &text;
+
+        if let Some(reply) = handle_gstreamer_control_message(text_str, &mut wire_format) {
+            tx.push(reply);
+            continue;
+        }
+
+        if let Some(msg) = parse_gstreamer_json_message(text_str) {
+            wire_format = WireFormat::GStreamer;
+            if let Some(response) = handle_signaling_message(
+                msg,
+                &mut session_id,
+                &state,
+                &session_manager,
+                &mut tx,
+                wire_format,
+                client_host.as_deref(),
+            ).await {
+                tx.push(response);
+            }
+            continue;
+        }
+
+        match SignalingParser::parse(text_str) {
+            Ok(msg) => {
+                if let Some(response) = handle_signaling_message(
+                    msg,
+                    &mut session_id,
+                    &state,
+                    &session_manager,
+                    &mut tx,
+                    wire_format,
+                    client_host.as_deref(),
+                ).await {
+                    tx.push(response);
+                }
+            }
+            Err(e) => {
+                warn!("Invalid signaling message: {}", e);
+                let error = SignalingMessage::error(
+                    "PARSE_ERROR",
+                    &e.to_string(),
+                    session_id.clone(),
+                );
+                if let Some(msg) = format_signaling_message(&error, wire_format) {
+                    tx.push(msg);
+                }
+            }
         }
     }
 
@@ -162,7 +281,7 @@ async fn handle_signaling_message(
     session_id: &mut Option<String>,
     state: &Arc<SharedState>,
     session_manager: &Arc<SessionManager>,
-    tx: &mpsc::UnboundedSender<String>,
+    tx: &mut OutboundQueue,
     wire_format: WireFormat,
     client_host: Option<&str>,
 ) -> Option<String> {
@@ -182,7 +301,7 @@ async fn handle_signaling_message(
                             "input",
                         );
                         if let Some(payload) = format_signaling_message(&ready, wire_format) {
-                            let _ = tx.send(payload);
+                            tx.push(payload);
                         }
                     }
 
@@ -192,7 +311,7 @@ async fn handle_signaling_message(
                             session_id: sid.clone(),
                         };
                         if let Some(payload) = format_signaling_message(&complete, wire_format) {
-                            let _ = tx.send(payload);
+                            tx.push(payload);
                         }
                     }
 
@@ -220,8 +339,20 @@ async fn handle_signaling_message(
 
         SignalingMessage::IceCandidate { candidate, sdp_mid: _, sdp_mline_index: _, session_id: _ } => {
             // With ICE-lite, we don't need remote candidates from the browser.
-            // The browser will connect to our TCP passive candidate directly.
-            debug!("Received browser ICE candidate (ignored in ICE-lite mode): {}", &candidate[..candidate.len().min(80)]);
+            // The browser will connect to our TCP passive candidate directly,
+            // so mDNS-obfuscated (.local) host candidates don't block anything.
+            // Only candidates that parse are counted (end-of-candidates is empty)
+            let info = parse_ice_candidate(&candidate);
+            if let Some(ref info) = info {
+                state.record_ice_candidate(info);
+            }
+            // Client-supplied: truncate by chars, not bytes
+            let shown: String = candidate.chars().take(80).collect();
+            if info.as_ref().is_some_and(|c| c.is_mdns()) {
+                debug!("Received browser mDNS candidate (not resolved, ICE-lite): {}", shown);
+            } else {
+                debug!("Received browser ICE candidate (ignored in ICE-lite mode): {}", shown);
+            }
             None
         }
 
@@ -355,4 +486,58 @@ mod tests {
         assert_eq!(config.path, "/webrtc");
         assert_eq!(config.ping_interval_secs, 30);
     }
+
+    #[test]
+    fn compression_roundtrips_and_skips_small_messages() {
+        let sdp = "v=0\r\na=candidate:1 1 tcp 2130706431 10.0.0.1 8080 typ host\r\n".repeat(40);
+        let Message::Binary(frame) = outbound_frame(sdp.clone(), true) else {
+            panic!("large message should be compressed");
+        };
+        assert!(frame.len() < sdp.len() / 4);
+        assert_eq!(inflate(&frame).unwrap(), sdp);
+
+        assert!(matches!(outbound_frame("HELLO".into(), true), Message::Text(_)));
+        assert!(matches!(outbound_frame(sdp, false), Message::Text(_)));
+    }
+
+    #[test]
+    fn inflate_rejects_oversized_output() {
+        let bomb = deflate(&"a".repeat(MAX_INFLATED_BYTES as usize + 1));
+        assert!(inflate(&bomb).is_err());
+        assert!(inflate(b"not deflate").is_err());
+    }
+
+    #[test]
+    fn outbound_queue_overflows_when_client_stops_reading() {
+        let (tx, mut rx) = mpsc::channel::<String>(2);
+        let mut queue = OutboundQueue::new(tx, Duration::from_millis(20));
+
+        assert!(queue.push("a".into()));
+        assert!(queue.push("b".into()));
+        // Full, but still within the grace period
+        assert!(!queue.push("c".into()));
+        assert!(!queue.overflowed());
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(!queue.push("d".into()));
+        assert!(queue.overflowed());
+        assert_eq!(queue.dropped, 2);
+
+        // Once overflowed, nothing else is queued even if space frees up
+        assert_eq!(rx.try_recv().unwrap(), "a");
+        assert!(!queue.push("e".into()));
+    }
+
+    #[test]
+    fn outbound_queue_recovers_when_client_drains() {
+        let (tx, mut rx) = mpsc::channel::<String>(1);
+        let mut queue = OutboundQueue::new(tx, Duration::from_millis(20));
+
+        assert!(queue.push("a".into()));
+        assert!(!queue.push("b".into()));
+        assert_eq!(rx.try_recv().unwrap(), "a");
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(queue.push("c".into()));
+        assert!(!queue.overflowed());
+    }
 }
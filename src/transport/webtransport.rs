@@ -0,0 +1,312 @@
+//! Experimental WebTransport (HTTP/3) transport
+//!
+//! An alternative to ICE-TCP for networks where WebRTC connectivity fails.
+//! A browser opens `https://<host>:<port>/wt?token=<token>` (details from
+//! `/wt-config`) and then:
+//!
+//! - receives media as unreliable datagrams: one kind byte followed by the
+//!   payload. `0x01` = video RTP packet as produced by the GStreamer
+//!   payloader, `0x02` = one Opus frame (20 ms, 48 kHz).
+//! - opens one bidirectional stream for control. Both directions carry
+//!   newline-terminated text messages in the DataChannel format (input
+//!   events, `r,WxH`, `SETTINGS,...` in; cursor/clipboard/stats out).
+//!
+//! The QUIC certificate is self-signed and pinned by the browser through
+//! `serverCertificateHashes`, which only accepts certificates valid for at
+//! most 14 days, so the server must be restarted at least that often.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use wtransport::endpoint::IncomingSession;
+use wtransport::{Connection, Endpoint, Identity, ServerConfig};
+
+use crate::input::{InputEvent, InputEventData};
+use crate::web::SharedState;
+use crate::webrtc::data_channel::InputDataChannel;
+
+/// Datagram kind byte for video RTP packets
+pub const DATAGRAM_VIDEO: u8 = 0x01;
+/// Datagram kind byte for Opus audio frames
+pub const DATAGRAM_AUDIO: u8 = 0x02;
+/// Longest control line accepted from a peer, newline included
+const MAX_CONTROL_LINE: usize = 64 * 1024;
+
+/// Bind the QUIC endpoint, publish the connection details for `/wt-config`
+/// and spawn the accept loop.
+pub fn start(port: u16, shared: Arc<SharedState>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let identity = Identity::self_signed(["localhost", "127.0.0.1", "::1"])?;
+    let cert_hash: Vec<u8> = {
+        let digest = identity.certificate_chain().as_slice()[0].hash();
+        let bytes: &[u8; 32] = digest.as_ref();
+        bytes.to_vec()
+    };
+
+    let config = ServerConfig::builder()
+        .with_bind_default(port)
+        .with_identity(identity)
+        .keep_alive_interval(Some(Duration::from_secs(3)))
+        .build();
+    let endpoint = Endpoint::server(config)?;
+
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    let details = serde_json::json!({
+        "port": port,
+        "path": "/wt",
+        "token": token,
+        "cert_hash": cert_hash,
+        "cert_hash_algorithm": "sha-256",
+    });
+    *shared.webtransport_config.lock().unwrap() = Some(details.to_string());
+    info!("WebTransport (experimental) listening on udp/{}", port);
+
+    tokio::spawn(async move {
+        loop {
+            let incoming = endpoint.accept().await;
+            tokio::spawn(handle_incoming(incoming, token.clone(), shared.clone()));
+        }
+    });
+    Ok(())
+}
+
+async fn handle_incoming(incoming: IncomingSession, token: String, shared: Arc<SharedState>) {
+    let request = match incoming.await {
+        Ok(r) => r,
+        Err(e) => {
+            debug!("WebTransport handshake failed: {}", e);
+            return;
+        }
+    };
+    if !path_has_token(request.path(), &token) {
+        warn!("WebTransport session rejected: bad or missing token");
+        request.forbidden().await;
+        return;
+    }
+    // The CONNECT :authority plays the part of the HTTP Host header
+    let host = request.authority().to_string();
+    match request.accept().await {
+        Ok(connection) => drive_session(connection, host, shared).await,
+        Err(e) => debug!("WebTransport accept failed: {}", e),
+    }
+}
+
+/// `/wt?token=<token>` with the expected token
+fn path_has_token(path: &str, token: &str) -> bool {
+    let Some((route, query)) = path.split_once('?') else {
+        return false;
+    };
+    route == "/wt" && query.split('&').any(|kv| kv.strip_prefix("token=") == Some(token))
+}
+
+async fn drive_session(connection: Connection, host: String, shared: Arc<SharedState>) {
+    let session_id: Arc<str> = Arc::from(uuid::Uuid::new_v4().to_string());
+    let peer = connection.remote_address();
+    if !shared.hooks.on_session_connected(&session_id, peer) {
+        info!("WebTransport session {} from {} refused by hook", session_id, peer);
+        return;
+    }
+    info!("WebTransport session {} connected from {}", session_id, peer);
+    shared.emit_event(
+        "session_connected",
+        serde_json::json!({ "session_id": &*session_id, "peer": peer.to_string(), "transport": "webtransport" }),
+    );
+
+    let (mut control_tx, control_rx) = match connection.accept_bi().await {
+        Ok(streams) => streams,
+        Err(e) => {
+            debug!("WebTransport session {} closed before control stream: {}", session_id, e);
+            close_session(&session_id, &shared);
+            return;
+        }
+    };
+    let mut control_rx = BufReader::new(control_rx);
+    let mut control_line = Vec::new();
+    // Joining changes nothing clients see, so there is nothing to broadcast
+    shared.update_control(|control| {
+        control.join(&session_id);
+        false
+    });
+
+    let mut rtp_rx = shared.subscribe_rtp_mpsc();
+    let mut audio_rx = shared.subscribe_audio_mpsc();
+    let mut text_rx = shared.subscribe_text_mpsc();
+    shared.request_keyframe();
+    for msg in shared.session_state_bundle(Some(&host)) {
+        let _ = control_tx.write_all(format!("{}\n", msg).as_bytes()).await;
+    }
+
+    let mut dropped: u64 = 0;
+    loop {
+        tokio::select! {
+            read = read_control_line(&mut control_rx, &mut control_line) => match read {
+                Ok(true) => {
+                    let text = String::from_utf8_lossy(&control_line).into_owned();
+                    control_line.clear();
+                    if let Some(reply) = handle_control_message(text.trim_end(), &session_id, &shared) {
+                        if control_tx.write_all(format!("{}\n", reply).as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Ok(false) => break,
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    warn!("WebTransport session {} closed: {}", session_id, e);
+                    break;
+                }
+                Err(e) => {
+                    debug!("WebTransport session {} control read error: {}", session_id, e);
+                    break;
+                }
+            },
+            Some(packet) = rtp_rx.recv() => {
+                if !send_datagram(&connection, DATAGRAM_VIDEO, &packet) {
+                    dropped += 1;
+                }
+            }
+            Some(audio) = audio_rx.recv() => {
+                if !send_datagram(&connection, DATAGRAM_AUDIO, &audio.data) {
+                    dropped += 1;
+                }
+            }
+            Some(text) = text_rx.recv() => {
+                let line = format!("{}\n", text);
+                if control_tx.write_all(line.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+            _ = connection.closed() => break,
+        }
+    }
+
+    if dropped > 0 {
+        info!("WebTransport session {} dropped {} oversized datagrams", session_id, dropped);
+    }
+    shared.update_control(|control| control.leave(&session_id));
+    if shared.config.input.multi_seat || shared.config.input.record_dir.is_some() {
+        let _ = shared.input_sender.send(InputEventData {
+            event_type: InputEvent::SessionClosed,
+            session_id: Some(session_id.clone()),
+            ..Default::default()
+        });
+    }
+    close_session(&session_id, &shared);
+}
+
+/// Read the next newline-terminated control line into `line`; false at
+/// the end of the stream. Lines longer than `MAX_CONTROL_LINE` fail with
+/// `InvalidData` instead of buffering without bound. Cancel safe: a partial
+/// line stays in `line` and the next call continues it.
+async fn read_control_line<R: AsyncBufRead + Unpin>(reader: &mut R, line: &mut Vec<u8>) -> std::io::Result<bool> {
+    let limit = (MAX_CONTROL_LINE + 1).saturating_sub(line.len()) as u64;
+    (&mut *reader).take(limit).read_until(b'\n', line).await?;
+    if line.last() == Some(&b'\n') {
+        return Ok(true);
+    }
+    if line.len() > MAX_CONTROL_LINE {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("control line longer than {} bytes", MAX_CONTROL_LINE),
+        ));
+    }
+    Ok(false)
+}
+
+fn close_session(session_id: &str, shared: &SharedState) {
+    info!("WebTransport session {} closed", session_id);
+    shared.hooks.on_session_closed(session_id);
+    shared.emit_event("session_closed", serde_json::json!({ "session_id": session_id }));
+}
+
+/// Send one media datagram; false if it did not fit or the send failed
+fn send_datagram(connection: &Connection, kind: u8, payload: &[u8]) -> bool {
+    if connection.max_datagram_size().is_some_and(|max| payload.len() + 1 > max) {
+        return false;
+    }
+    let mut datagram = Vec::with_capacity(payload.len() + 1);
+    datagram.push(kind);
+    datagram.extend_from_slice(payload);
+    connection.send_datagram(datagram).is_ok()
+}
+
+/// Control messages accepted on the bidirectional stream: a subset of the
+/// DataChannel protocol (settings, resize, keyboard reset, input control,
+/// input events). Input is subject to `input.control_policy` like WebRTC
+/// input. Returns a reply line for this session, if any.
+fn handle_control_message(text: &str, session_id: &Arc<str>, shared: &SharedState) -> Option<String> {
+    if text.is_empty() || text.starts_with("pong") {
+        return None;
+    }
+    if let Some(payload) = text.strip_prefix("SETTINGS,") {
+        shared.runtime_settings.apply_settings_json(payload);
+        return None;
+    }
+    if shared.runtime_settings.handle_simple_message(text) {
+        return None;
+    }
+    if text == "getstats" {
+        shared.request_stats_now();
+        return None;
+    }
+    if crate::control::is_control_request(text) {
+        return shared.handle_control_request(session_id, text);
+    }
+    if let Some(payload) = text.strip_prefix("r,") {
+        return Some(shared.handle_resize_request(session_id, payload));
+    }
+    let event = if text == "kr" {
+        Ok(InputEventData { event_type: InputEvent::KeyboardReset, ..Default::default() })
+    } else {
+        InputDataChannel::parse_input_text(text)
+    };
+    match event {
+        Ok(_) if !shared.input_allowed(session_id) => {}
+        Ok(mut event) => {
+            event.session_id = Some(session_id.clone());
+            shared.map_stream_input(&mut event);
+            let _ = shared.input_sender.send(event);
+        }
+        Err(e) => debug!("WebTransport control parse error: {}", e),
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_must_match() {
+        assert!(path_has_token("/wt?token=abc", "abc"));
+        assert!(path_has_token("/wt?x=1&token=abc", "abc"));
+        assert!(!path_has_token("/wt?token=abcd", "abc"));
+        assert!(!path_has_token("/wt", "abc"));
+        assert!(!path_has_token("/other?token=abc", "abc"));
+    }
+
+    #[tokio::test]
+    async fn control_lines_are_capped() {
+        let mut line = Vec::new();
+        let mut reader: &[u8] = b"kr\nr,1280x720";
+        assert!(read_control_line(&mut reader, &mut line).await.unwrap());
+        assert_eq!(line, b"kr\n");
+        line.clear();
+        // End of stream in the middle of a line
+        assert!(!read_control_line(&mut reader, &mut line).await.unwrap());
+
+        let mut line = Vec::new();
+        let long = vec![b'a'; MAX_CONTROL_LINE + 10];
+        let mut reader: &[u8] = &long;
+        let err = read_control_line(&mut reader, &mut line).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(line.len(), MAX_CONTROL_LINE + 1);
+
+        // Exactly at the cap, newline included, is fine
+        let mut exact = vec![b'a'; MAX_CONTROL_LINE - 1];
+        exact.push(b'\n');
+        let mut line = Vec::new();
+        let mut reader: &[u8] = &exact;
+        assert!(read_control_line(&mut reader, &mut line).await.unwrap());
+    }
+}
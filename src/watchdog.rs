@@ -0,0 +1,209 @@
+//! Compositor loop watchdog
+//!
+//! The compositor loop records a heartbeat each time it moves to another
+//! stage of an iteration. A separate thread checks the heartbeat; when it
+//! has not moved for `server.watchdog_timeout_secs` the stall is logged with
+//! the stage the loop is stuck in and the window count, and a
+//! `compositor_stalled` event is emitted. With `watchdog_action = "abort"`
+//! the process is then aborted so a supervisor (systemd, Kubernetes) can
+//! restart it.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{error, info};
+
+use crate::config::WatchdogAction;
+use crate::web::SharedState;
+
+/// Part of a compositor loop iteration, for stall diagnostics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum LoopStage {
+    Dispatch = 0,
+    Input,
+    Clipboard,
+    Resize,
+    FrameCallbacks,
+    Sleep,
+    Render,
+    Encode,
+    Rtp,
+    Stats,
+}
+
+impl LoopStage {
+    const ALL: [LoopStage; 10] = [
+        LoopStage::Dispatch,
+        LoopStage::Input,
+        LoopStage::Clipboard,
+        LoopStage::Resize,
+        LoopStage::FrameCallbacks,
+        LoopStage::Sleep,
+        LoopStage::Render,
+        LoopStage::Encode,
+        LoopStage::Rtp,
+        LoopStage::Stats,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoopStage::Dispatch => "wayland dispatch",
+            LoopStage::Input => "input injection",
+            LoopStage::Clipboard => "clipboard transfer",
+            LoopStage::Resize => "resize / pipeline rebuild",
+            LoopStage::FrameCallbacks => "frame callbacks / client flush",
+            LoopStage::Sleep => "frame sleep",
+            LoopStage::Render => "render",
+            LoopStage::Encode => "encoder push",
+            LoopStage::Rtp => "RTP pull / broadcast",
+            LoopStage::Stats => "stats",
+        }
+    }
+}
+
+/// Progress marker shared between the compositor loop and the watchdog
+pub struct Heartbeat {
+    start: Instant,
+    /// Milliseconds since `start` at the last stage change
+    last_beat_ms: AtomicU64,
+    stage: AtomicU8,
+    windows: AtomicUsize,
+}
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            last_beat_ms: AtomicU64::new(0),
+            stage: AtomicU8::new(LoopStage::Dispatch as u8),
+            windows: AtomicUsize::new(0),
+        }
+    }
+
+    /// Record progress into `stage`
+    pub fn enter(&self, stage: LoopStage) {
+        self.stage.store(stage as u8, Ordering::Relaxed);
+        self.last_beat_ms.store(self.start.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn set_windows(&self, windows: usize) {
+        self.windows.store(windows, Ordering::Relaxed);
+    }
+
+    pub fn stage(&self) -> LoopStage {
+        LoopStage::ALL[self.stage.load(Ordering::Relaxed) as usize]
+    }
+
+    /// Time since the last stage change
+    pub fn since_last_beat(&self) -> Duration {
+        let last = Duration::from_millis(self.last_beat_ms.load(Ordering::Relaxed));
+        self.start.elapsed().saturating_sub(last)
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Transition {
+    Stalled,
+    Recovered,
+}
+
+/// Reports each stall once and its recovery once
+#[derive(Default)]
+struct StallTracker {
+    stalled: bool,
+}
+
+impl StallTracker {
+    fn observe(&mut self, since_beat: Duration, timeout: Duration) -> Option<Transition> {
+        let stalled = since_beat >= timeout;
+        if stalled == self.stalled {
+            return None;
+        }
+        self.stalled = stalled;
+        Some(if stalled { Transition::Stalled } else { Transition::Recovered })
+    }
+}
+
+/// Start the watchdog thread; it exits when `running` is cleared
+pub fn spawn(
+    heartbeat: Arc<Heartbeat>,
+    timeout: Duration,
+    action: WatchdogAction,
+    shared: Arc<SharedState>,
+    running: Arc<AtomicBool>,
+) -> std::io::Result<()> {
+    let check_every = (timeout / 4).max(Duration::from_millis(100));
+    std::thread::Builder::new().name("watchdog".into()).spawn(move || {
+        let mut tracker = StallTracker::default();
+        let mut stall_start = Instant::now();
+        while running.load(Ordering::Relaxed) {
+            std::thread::sleep(check_every);
+            let since_beat = heartbeat.since_last_beat();
+            match tracker.observe(since_beat, timeout) {
+                Some(Transition::Stalled) => {
+                    stall_start = Instant::now().checked_sub(since_beat).unwrap_or_else(Instant::now);
+                    let stage = heartbeat.stage();
+                    let windows = heartbeat.windows.load(Ordering::Relaxed);
+                    error!(
+                        "Compositor loop stalled for {:.1}s in {} (windows={}, sessions={})",
+                        since_beat.as_secs_f64(),
+                        stage.as_str(),
+                        windows,
+                        shared.rtp_receiver_count()
+                    );
+                    shared.emit_event(
+                        "compositor_stalled",
+                        serde_json::json!({
+                            "stage": stage.as_str(),
+                            "stalled_ms": since_beat.as_millis() as u64,
+                            "windows": windows,
+                        }),
+                    );
+                    if action == WatchdogAction::Abort {
+                        error!("Aborting so the supervisor can restart the server");
+                        std::process::abort();
+                    }
+                }
+                Some(Transition::Recovered) => {
+                    info!("Compositor loop recovered after {:.1}s", stall_start.elapsed().as_secs_f64());
+                }
+                None => {}
+            }
+        }
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stalls_and_recoveries_are_reported_once() {
+        let timeout = Duration::from_secs(5);
+        let mut tracker = StallTracker::default();
+        assert_eq!(tracker.observe(Duration::from_secs(1), timeout), None);
+        assert_eq!(tracker.observe(Duration::from_secs(5), timeout), Some(Transition::Stalled));
+        assert_eq!(tracker.observe(Duration::from_secs(9), timeout), None);
+        assert_eq!(tracker.observe(Duration::from_millis(10), timeout), Some(Transition::Recovered));
+        assert_eq!(tracker.observe(Duration::from_millis(20), timeout), None);
+    }
+
+    #[test]
+    fn heartbeat_tracks_the_current_stage() {
+        let heartbeat = Heartbeat::new();
+        heartbeat.enter(LoopStage::Render);
+        assert_eq!(heartbeat.stage(), LoopStage::Render);
+        assert!(heartbeat.since_last_beat() < Duration::from_secs(1));
+        heartbeat.enter(LoopStage::Stats);
+        assert_eq!(heartbeat.stage(), LoopStage::Stats);
+    }
+}
@@ -5,7 +5,7 @@
 
 use axum::{
     body::Body,
-    http::{header, StatusCode},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
     response::Response,
 };
 use rust_embed::RustEmbed;
@@ -15,21 +15,51 @@ use rust_embed::RustEmbed;
 #[folder = "web/ivnc/dist"]
 pub struct WebAssets;
 
-/// Get an embedded file and return it as an Axum response
-pub fn get_embedded_file(path: &str) -> Response {
+/// Get an embedded file and return it as an Axum response.
+///
+/// If the bundle has a `.br`/`.gz` sibling and the client's
+/// `Accept-Encoding` allows it, that variant is served with the matching
+/// `Content-Encoding` (the compression layer leaves such responses alone).
+/// Cacheable files carry a strong ETag (content hash) and `Last-Modified`;
+/// a request whose `If-None-Match` matches gets an empty 304.
+pub fn get_embedded_file(path: &str, req_headers: &HeaderMap) -> Response {
     // Normalize path: remove leading slash, default to index.html
     let path = path.trim_start_matches('/');
     let path = if path.is_empty() { "index.html" } else { path };
 
-    match WebAssets::get(path) {
+    let accept = req_headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    let variant = PRECOMPRESSED
+        .iter()
+        .filter(|(encoding, _)| accepts_encoding(accept, encoding))
+        .find_map(|(encoding, ext)| {
+            WebAssets::get(&format!("{}.{}", path, ext)).map(|file| (*encoding, file))
+        });
+    let (encoding, content) = match variant {
+        Some((encoding, file)) => (Some(encoding), Some(file)),
+        None => (None, WebAssets::get(path)),
+    };
+
+    match content {
         Some(content) => {
-            let mime = mime_guess::from_path(path).first_or_octet_stream();
-            Response::builder()
-                .status(StatusCode::OK)
-                .header(header::CONTENT_TYPE, mime.as_ref())
-                .header(header::CACHE_CONTROL, cache_control_for_path(path))
-                .body(Body::from(content.data.into_owned()))
-                .unwrap()
+            let etag = format!("\"{}\"", hex(&content.metadata.sha256_hash()));
+            let last_modified = content.metadata.last_modified();
+            let mut resp = asset_response(
+                path,
+                content.data.into_owned(),
+                &etag,
+                last_modified,
+                req_headers.get(header::IF_NONE_MATCH),
+            );
+            if let Some(encoding) = encoding {
+                resp.headers_mut().insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+            }
+            if is_compressible(path) {
+                resp.headers_mut().insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+            }
+            resp
         }
         None => Response::builder()
             .status(StatusCode::NOT_FOUND)
@@ -38,6 +68,77 @@ pub fn get_embedded_file(path: &str) -> Response {
     }
 }
 
+/// Precompressed variants in preference order: (Content-Encoding, file suffix)
+const PRECOMPRESSED: &[(&str, &str)] = &[("br", "br"), ("gzip", "gz")];
+
+/// Whether `Accept-Encoding` lists `encoding` (or `*`) without `q=0`
+fn accepts_encoding(accept: &str, encoding: &str) -> bool {
+    accept.split(',').any(|item| {
+        let mut parts = item.split(';').map(str::trim);
+        let name = parts.next().unwrap_or("");
+        let rejected = parts.any(|p| {
+            p.strip_prefix("q=")
+                .and_then(|q| q.parse::<f32>().ok())
+                .is_some_and(|q| q == 0.0)
+        });
+        (name.eq_ignore_ascii_case(encoding) || name == "*") && !rejected
+    })
+}
+
+/// Text assets that build.sh precompresses
+fn is_compressible(path: &str) -> bool {
+    [".js", ".css", ".html", ".json", ".svg"].iter().any(|ext| path.ends_with(ext))
+}
+
+fn asset_response(
+    path: &str,
+    data: Vec<u8>,
+    etag: &str,
+    last_modified: Option<u64>,
+    if_none_match: Option<&HeaderValue>,
+) -> Response {
+    let cache_control = cache_control_for_path(path);
+    // no-store files (index.html, sw.js) are never revalidated, so skip validators
+    if cache_control.starts_with("no-store") {
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, mime_guess::from_path(path).first_or_octet_stream().as_ref())
+            .header(header::CACHE_CONTROL, cache_control)
+            .body(Body::from(data))
+            .unwrap();
+    }
+
+    let not_modified = if_none_match
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| etag_matches(v, etag));
+    let mut builder = Response::builder()
+        .header(header::ETAG, etag)
+        .header(header::CACHE_CONTROL, cache_control);
+    if let Some(secs) = last_modified {
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        builder = builder.header(header::LAST_MODIFIED, httpdate::fmt_http_date(time));
+    }
+    if not_modified {
+        return builder.status(StatusCode::NOT_MODIFIED).body(Body::empty()).unwrap();
+    }
+    builder
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, mime_guess::from_path(path).first_or_octet_stream().as_ref())
+        .body(Body::from(data))
+        .unwrap()
+}
+
+/// `If-None-Match` is a comma-separated list of (possibly weak) tags or `*`
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').map(str::trim).any(|tag| {
+        tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
 /// Check if embedded assets are available
 pub fn has_embedded_assets() -> bool {
     WebAssets::get("index.html").is_some()
@@ -65,3 +166,41 @@ fn cache_control_for_path(path: &str) -> &'static str {
         "public, max-age=3600"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_if_none_match_yields_304() {
+        let etag = "\"abc123\"";
+        let hit = HeaderValue::from_static("\"zzz\", W/\"abc123\"");
+        let resp = asset_response("assets/app.js", b"x".to_vec(), etag, Some(0), Some(&hit));
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.headers()[header::ETAG], etag);
+
+        let miss = HeaderValue::from_static("\"other\"");
+        let resp = asset_response("assets/app.js", b"x".to_vec(), etag, Some(0), Some(&miss));
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::LAST_MODIFIED], "Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    fn accept_encoding_parsing() {
+        assert!(accepts_encoding("gzip, deflate, br", "br"));
+        assert!(accepts_encoding("gzip;q=0.8", "gzip"));
+        assert!(!accepts_encoding("br;q=0, gzip", "br"));
+        assert!(accepts_encoding("*", "gzip"));
+        assert!(!accepts_encoding("", "gzip"));
+        assert!(!accepts_encoding("identity", "br"));
+    }
+
+    #[test]
+    fn index_html_stays_no_store() {
+        let hit = HeaderValue::from_static("\"abc123\"");
+        let resp = asset_response("index.html", b"x".to_vec(), "\"abc123\"", None, Some(&hit));
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CACHE_CONTROL], "no-store, max-age=0");
+        assert!(resp.headers().get(header::ETAG).is_none());
+    }
+}
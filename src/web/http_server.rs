@@ -9,12 +9,14 @@
 #![allow(dead_code)]
 
 use crate::web::embedded_assets::{get_embedded_file, has_embedded_assets};
-use crate::web::shared::SharedState;
+use crate::latency_histogram::LatencyPercentiles;
+use crate::web::shared::{GpuUsage, SharedState};
 use axum::{
     body::Body,
     extract::{Query, State, WebSocketUpgrade},
-    http::{header, Request, StatusCode, Uri},
+    http::{header, HeaderMap, Request, StatusCode, Uri},
     middleware,
+    response::sse::{Event as SseEvent, KeepAlive, Sse},
     response::Response,
     routing::{get, post},
     Router,
@@ -30,6 +32,8 @@ use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
 use tokio::net::TcpListener;
 use tower::Service;
+use tower_http::compression::predicate::{DefaultPredicate, NotForContentType, Predicate};
+use tower_http::compression::CompressionLayer;
 use tower_http::services::{ServeDir, ServeFile};
 use base64::Engine;
 use serde::{Deserialize, Serialize};
@@ -38,45 +42,87 @@ use serde_json::json;
 use crate::webrtc::SessionManager;
 use crate::pake_apps::api::PakeState;
 
-/// Classify a TCP connection by its first bytes.
-fn classify_first_bytes(buf: &[u8]) -> ConnectionType {
-    if buf.is_empty() {
-        return ConnectionType::Unknown;
+const HTTP_METHODS: [&[u8]; 9] = [
+    b"GET", b"POST", b"HEAD", b"PUT", b"PATCH", b"DELETE", b"OPTIONS", b"CONNECT", b"TRACE",
+];
+/// Enough to decide every case: the longest HTTP method is 7 bytes
+const PEEK_BYTES: usize = 8;
+/// `peek` returns immediately while data is buffered, so growth of a
+/// partial prefix is polled at this interval
+const PEEK_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Classify a TCP connection by its first bytes, or `None` if the bytes so
+/// far are a prefix that more data could still resolve either way.
+fn classify_first_bytes(buf: &[u8]) -> Option<ConnectionType> {
+    let &b0 = buf.first()?;
+    if HTTP_METHODS.iter().any(|m| buf.starts_with(m)) {
+        return Some(ConnectionType::Http);
     }
-    if looks_like_http(buf) {
-        return ConnectionType::Http;
+    if HTTP_METHODS.iter().any(|m| m.starts_with(buf)) {
+        return None; // partial method, e.g. "G" or "PO"
     }
-    let b0 = buf[0];
     // DTLS/TLS handshake record type (0x16) needs version disambiguation.
     if b0 == 0x16 {
-        if buf.len() >= 2 {
-            let b1 = buf[1];
-            if b1 == 0x03 {
-                return ConnectionType::Tls; // TLS record
-            }
-            if b1 == 0xFE {
-                return ConnectionType::IceTcp; // DTLS record
-            }
-        }
-        // Ambiguous: default to ICE/DTLS to avoid misrouting DTLS to HTTPS.
-        return ConnectionType::IceTcp;
+        return match buf.get(1)? {
+            0x03 => Some(ConnectionType::Tls), // TLS record
+            _ => Some(ConnectionType::IceTcp), // DTLS record (0xFE) or ICE framing
+        };
     }
     // ICE/DTLS record types (ChangeCipherSpec, Alert, Handshake, ApplicationData)
     if (0x00..=0x03).contains(&b0) || (0x14..=0x17).contains(&b0) {
-        return ConnectionType::IceTcp;
+        return Some(ConnectionType::IceTcp);
     }
-    ConnectionType::Unknown
+    Some(ConnectionType::Unknown)
 }
 
-fn looks_like_http(buf: &[u8]) -> bool {
-    const METHODS: [&[u8]; 9] = [
-        b"GET", b"POST", b"HEAD", b"PUT", b"PATCH", b"DELETE", b"OPTIONS", b"CONNECT", b"TRACE",
-    ];
-    if METHODS.iter().any(|m| buf.starts_with(m)) {
-        return true;
+/// Best guess for a prefix that never became decisive: a lone 0x16 goes to
+/// ICE/DTLS to avoid misrouting DTLS to HTTPS; a partial method is HTTP.
+fn guess_from_partial(buf: &[u8]) -> ConnectionType {
+    match buf.first() {
+        Some(0x16) => ConnectionType::IceTcp,
+        Some(b) if b.is_ascii_alphabetic() => ConnectionType::Http,
+        _ => ConnectionType::Unknown,
+    }
+}
+
+enum PeekError {
+    /// Nothing arrived within the classification timeout
+    Timeout,
+    /// Peer closed before sending anything
+    Closed,
+    Io(std::io::Error),
+}
+
+/// Peek until the first bytes identify the protocol. Waits up to
+/// `first_timeout` for any data, then up to `partial_timeout` more for an
+/// ambiguous prefix to grow before settling on a best guess.
+async fn peek_classify(
+    stream: &tokio::net::TcpStream,
+    first_timeout: Duration,
+    partial_timeout: Duration,
+) -> std::result::Result<(ConnectionType, Vec<u8>), PeekError> {
+    let mut buf = [0u8; PEEK_BYTES];
+    let mut n = match tokio::time::timeout(first_timeout, stream.peek(&mut buf)).await {
+        Err(_) => return Err(PeekError::Timeout),
+        Ok(Ok(0)) => return Err(PeekError::Closed),
+        Ok(Ok(n)) => n,
+        Ok(Err(e)) => return Err(PeekError::Io(e)),
+    };
+    let deadline = tokio::time::Instant::now() + partial_timeout;
+    loop {
+        if let Some(kind) = classify_first_bytes(&buf[..n]) {
+            return Ok((kind, buf[..n].to_vec()));
+        }
+        if n == PEEK_BYTES || tokio::time::Instant::now() >= deadline {
+            return Ok((guess_from_partial(&buf[..n]), buf[..n].to_vec()));
+        }
+        tokio::time::sleep(PEEK_POLL_INTERVAL).await;
+        n = match stream.peek(&mut buf).await {
+            Ok(0) => return Err(PeekError::Closed),
+            Ok(m) => m,
+            Err(e) => return Err(PeekError::Io(e)),
+        };
     }
-    // If we only have a few bytes, treat an initial ASCII letter as a partial HTTP method.
-    buf.len() < 3 && buf.first().is_some_and(|b| b.is_ascii_alphabetic())
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -87,12 +133,34 @@ enum ConnectionType {
     Unknown,
 }
 
-/// Run the HTTP server with WebRTC signaling support and same-port ICE-TCP
+/// Where the HTTPS certificate comes from
+#[derive(Debug, Clone, Default)]
+pub struct TlsOptions {
+    /// User-provided PEM certificate chain and private key. When unset a
+    /// self-signed pair is used.
+    pub cert_file: Option<PathBuf>,
+    pub key_file: Option<PathBuf>,
+    /// Persist the self-signed pair here across restarts
+    pub cert_dir: Option<PathBuf>,
+}
+
+impl TlsOptions {
+    pub fn from_config(http: &crate::config::HttpConfig) -> Self {
+        Self {
+            cert_file: http.tls_cert.as_ref().map(PathBuf::from),
+            key_file: http.tls_key.as_ref().map(PathBuf::from),
+            cert_dir: http.tls_cert_dir.as_ref().map(PathBuf::from),
+        }
+    }
+}
+
+/// Run the HTTP server with WebRTC signaling support and same-port ICE-TCP.
+/// `tls` enables HTTPS on the same port.
 pub async fn run_http_server_with_webrtc(
     port: u16,
     state: Arc<SharedState>,
     session_manager: Option<Arc<SessionManager>>,
-    enable_tls: bool,
+    tls: Option<TlsOptions>,
     pake_state: Option<Arc<PakeState>>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let addr = format!("0.0.0.0:{}", port);
@@ -122,9 +190,11 @@ pub async fn run_http_server_with_webrtc(
         .route("/index.html", get(index_handler))
         .route("/health", get(health_handler))
         .route("/metrics", get(metrics_handler))
+        .route("/events", get(events_handler))
         .route("/clients", get(clients_handler))
         .route("/ui-config", get(ui_config_handler))
         .route("/ws-config", get(ws_config_handler))
+        .route("/wt-config", get(wt_config_handler))
         .route("/api/change-password", post(change_password_handler))
         .route("/api/version", get(get_version_handler))
         .route("/api/upgrade/ws", get(upgrade_ws_handler))
@@ -145,8 +215,15 @@ pub async fn run_http_server_with_webrtc(
                 .and_then(|v| v.to_str().ok())
                 .map(|s| s.to_string());
             async move {
+                // Clients that don't offer the subprotocol keep plain text
+                let ws = if state.config.http.signaling_compression {
+                    ws.protocols([crate::transport::signaling_server::DEFLATE_SUBPROTOCOL])
+                } else {
+                    ws
+                };
+                let compress = ws.selected_protocol().is_some();
                 ws.on_upgrade(move |socket| async move {
-                    crate::transport::handle_signaling_connection(socket, state, manager, host_str).await;
+                    crate::transport::handle_signaling_connection(socket, state, manager, host_str, compress).await;
                 })
             }
         };
@@ -158,9 +235,16 @@ pub async fn run_http_server_with_webrtc(
             .route("/{app}/signaling/", get(signaling_handler));
     }
 
-    // MCP Streamable HTTP endpoint
+    // MCP Streamable HTTP endpoint (behind basic auth like every other route)
     #[cfg(feature = "mcp")]
-    {
+    if !state.config.mcp.http_enabled {
+        info!("MCP Streamable HTTP endpoint disabled by config");
+    } else if !state.config.http.basic_auth_enabled && !state.config.mcp.allow_unauthenticated {
+        warn!(
+            "MCP Streamable HTTP endpoint not mounted: basic auth is disabled \
+             (set mcp.allow_unauthenticated = true to serve it anyway)"
+        );
+    } else {
         let mcp_state = state.clone();
         let mcp_session_mgr = Arc::new(
             rmcp::transport::streamable_http_server::session::local::LocalSessionManager::default(),
@@ -174,8 +258,28 @@ pub async fn run_http_server_with_webrtc(
             mcp_session_mgr,
             mcp_config,
         );
-        app = app.route_service("/mcp", mcp_service);
-        info!("MCP Streamable HTTP endpoint enabled at /mcp");
+        let mcp_path = state.config.mcp.http_path.clone();
+        app = app.route_service(&mcp_path, mcp_service);
+        info!("MCP Streamable HTTP endpoint enabled at {}", mcp_path);
+    }
+
+    // JPEG snapshot / MJPEG stream for viewers without WebRTC
+    #[cfg(feature = "mcp")]
+    if state.config.http.snapshot_enabled {
+        app = app
+            .route("/snapshot", get(snapshot_handler))
+            .route("/stream.mjpeg", get(mjpeg_handler));
+        info!("Snapshot endpoints enabled at /snapshot and /stream.mjpeg");
+    }
+    #[cfg(not(feature = "mcp"))]
+    if state.config.http.snapshot_enabled {
+        warn!("http.snapshot_enabled is set but this build lacks the mcp feature; /snapshot not mounted");
+    }
+
+    // Replay of input recordings (input.record_dir)
+    if state.config.input.allow_replay {
+        app = app.route("/api/input/replay", post(input_replay_handler));
+        info!("Input replay enabled at /api/input/replay");
     }
 
     // Pake apps management routes
@@ -204,24 +308,45 @@ pub async fn run_http_server_with_webrtc(
         app = app.merge(crate::pake_apps::api::router(pake.clone()));
     }
 
+    // Compress text responses. Media, archives and file downloads are already
+    // compressed or large; SSE streams are excluded by the default predicate.
+    if auth_state.config.http.compression {
+        let predicate = DefaultPredicate::new()
+            .and(NotForContentType::const_new("application/octet-stream"))
+            .and(NotForContentType::const_new("application/zip"))
+            .and(NotForContentType::const_new("application/gzip"))
+            .and(NotForContentType::const_new("font/woff"))
+            .and(NotForContentType::const_new("video/"))
+            .and(NotForContentType::const_new("audio/"))
+            .and(NotForContentType::const_new("multipart/x-mixed-replace"));
+        app = app.layer(CompressionLayer::new().compress_when(predicate));
+    }
+
     let app = app.layer(middleware::from_fn_with_state(auth_state, basic_auth_middleware));
 
     let listener = TcpListener::bind(&addr).await?;
     let local_addr = listener.local_addr()?;
 
-    // TLS setup
+    // TLS setup. The acceptor sits behind a lock so SIGHUP can swap in a
+    // renewed certificate without dropping existing connections.
     #[cfg(feature = "tls")]
-    let tls_acceptor = if enable_tls {
-        let acceptor = create_tls_acceptor()?;
+    let tls_acceptor = if let Some(opts) = tls {
+        let acceptor = create_tls_acceptor(&opts)?;
         info!("HTTPS+ICE-TCP server listening on https://{}", local_addr);
-        Some(acceptor)
+        let slot = Arc::new(std::sync::RwLock::new(acceptor));
+        if opts.cert_file.is_some() {
+            tokio::spawn(reload_tls_on_sighup(slot.clone(), opts));
+        }
+        Some(slot)
     } else {
         info!("HTTP+ICE-TCP server listening on http://{}", local_addr);
         None
     };
     #[cfg(not(feature = "tls"))]
     {
-        let _ = enable_tls;
+        if tls.is_some() {
+            warn!("TLS requested but ivnc was built without the tls feature");
+        }
         info!("HTTP+ICE-TCP server listening on http://{}", local_addr);
     }
 
@@ -229,6 +354,14 @@ pub async fn run_http_server_with_webrtc(
         info!("Same-port ICE-TCP multiplexing enabled on :{}", port);
     }
 
+    // Bounds connections that have not been classified (or finished the TLS
+    // handshake) yet, so a flood of idle sockets can't pile up tasks
+    let max_pending = metrics_state.config.http.max_pending_connections;
+    let pending = Arc::new(tokio::sync::Semaphore::new(max_pending));
+    let mut saturated = false;
+    let classify_timeout = Duration::from_millis(metrics_state.config.http.classify_timeout_ms);
+    let partial_peek = Duration::from_millis(metrics_state.config.http.partial_peek_ms);
+
     // Accept loop with first-byte protocol splitting
     loop {
         let (tcp_stream, peer_addr) = match listener.accept().await {
@@ -239,43 +372,56 @@ pub async fn run_http_server_with_webrtc(
             }
         };
 
+        let permit = match pending.clone().try_acquire_owned() {
+            Ok(permit) => {
+                if saturated {
+                    info!("Pending connection count back under the limit");
+                    saturated = false;
+                }
+                permit
+            }
+            Err(_) => {
+                if !saturated {
+                    warn!(
+                        "{} connections pending classification (max_pending_connections); \
+                         closing new connections until some complete",
+                        max_pending
+                    );
+                    saturated = true;
+                }
+                debug!("Rejecting connection from {}: pending limit reached", peer_addr);
+                metrics_state.stats.lock().unwrap().connections_rejected += 1;
+                drop(tcp_stream);
+                continue;
+            }
+        };
+
         let app = app.clone();
         let sm = session_manager.clone();
         let conn_state = metrics_state.clone();
         #[cfg(feature = "tls")]
-        let tls_acceptor = tls_acceptor.clone();
+        let tls_acceptor = tls_acceptor.as_ref().map(|slot| slot.read().unwrap().clone());
 
         tokio::spawn(async move {
-            let mut first_bytes = vec![0u8; 8];
-            let peek_result = tokio::time::timeout(
-                std::time::Duration::from_secs(10),
-                tcp_stream.peek(&mut first_bytes),
-            ).await;
-            let n = match peek_result {
-                Ok(Ok(0)) | Err(_) => return,
-                Ok(Ok(n)) => n,
-                Ok(Err(e)) => {
+            let (kind, first_bytes) = match peek_classify(&tcp_stream, classify_timeout, partial_peek).await {
+                Ok(classified) => classified,
+                Err(PeekError::Closed) => return,
+                Err(PeekError::Timeout) => {
+                    debug!("Connection from {} sent nothing within {:?}, closing", peer_addr, classify_timeout);
+                    conn_state.record_protocol_classification("timeout");
+                    return;
+                }
+                Err(PeekError::Io(e)) => {
                     debug!("Peek error from {}: {}", peer_addr, e);
                     return;
                 }
             };
-            first_bytes.truncate(n);
-            // If we only got the first byte and it's a TLS/DTLS handshake marker,
-            // try a quick re-peek to disambiguate version (0x03 vs 0xFE).
-            if first_bytes.len() < 2 && first_bytes.first() == Some(&0x16) {
-                let mut retry_buf = vec![0u8; 8];
-                if let Ok(Ok(n2)) = tokio::time::timeout(
-                    std::time::Duration::from_millis(50),
-                    tcp_stream.peek(&mut retry_buf),
-                ).await {
-                    if n2 > 0 {
-                        retry_buf.truncate(n2);
-                        first_bytes = retry_buf;
-                    }
-                }
-            }
-            let kind = classify_first_bytes(&first_bytes);
             debug!("Connection from {} classified as {:?} (first_bytes={:02x?})", peer_addr, kind, &first_bytes);
+            // TLS keeps its slot until the handshake is done
+            #[cfg(feature = "tls")]
+            let permit = (kind == ConnectionType::Tls && tls_acceptor.is_some()).then_some(permit);
+            #[cfg(not(feature = "tls"))]
+            drop(permit);
 
             // Record protocol classification metric
             conn_state.record_protocol_classification(match kind {
@@ -289,14 +435,20 @@ pub async fn run_http_server_with_webrtc(
             #[cfg(feature = "tls")]
             if let Some(ref acceptor) = tls_acceptor {
                 if kind == ConnectionType::Tls {
-                    // TLS handshake
-                    match acceptor.accept(tcp_stream).await {
-                        Ok(tls_stream) => {
+                    // TLS handshake, on the same budget as classification
+                    // so a stalled client can't keep its pending slot
+                    let handshake = tokio::time::timeout(classify_timeout, acceptor.accept(tcp_stream)).await;
+                    drop(permit);
+                    match handshake {
+                        Ok(Ok(tls_stream)) => {
                             serve_http(TokioIo::new(tls_stream), app).await;
                         }
-                        Err(e) => {
+                        Ok(Err(e)) => {
                             debug!("TLS handshake error from {}: {}", peer_addr, e);
                         }
+                        Err(_) => {
+                            debug!("TLS handshake from {} not done within {:?}, closing", peer_addr, classify_timeout);
+                        }
                     }
                     return;
                 }
@@ -328,6 +480,7 @@ pub async fn run_http_server_with_webrtc(
                 }
                 ConnectionType::Unknown => {
                     warn!("Unrecognized protocol from {} (first_bytes={:02x?}), closing", peer_addr, &first_bytes);
+                    conn_state.record_protocol_classification("unknown_closed");
                 }
             }
         });
@@ -343,6 +496,7 @@ where
         let mut app = app.clone();
         async move { app.call(req).await }
     });
+    // HTTP/1.1, or HTTP/2 when negotiated via ALPN "h2" on TLS connections
     let _ = hyper_util::server::conn::auto::Builder::new(
         hyper_util::rt::TokioExecutor::new(),
     )
@@ -381,27 +535,87 @@ async fn handle_ice_connection(
 }
 
 #[cfg(feature = "tls")]
-fn create_tls_acceptor() -> Result<tokio_rustls::TlsAcceptor, Box<dyn std::error::Error>> {
+fn create_tls_acceptor(opts: &TlsOptions) -> Result<tokio_rustls::TlsAcceptor, Box<dyn std::error::Error>> {
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
     use rustls::ServerConfig;
     use std::sync::Arc as StdArc;
 
-    let cert = rcgen::generate_simple_self_signed(vec![
-        "localhost".to_string(),
-        "ivnc.local".to_string(),
-    ])?;
-    let cert_der = rustls::pki_types::CertificateDer::from(cert.cert);
-    let key_der = rustls::pki_types::PrivateKeyDer::try_from(cert.key_pair.serialize_der())
-        .map_err(|e| format!("TLS key error: {}", e))?;
+    let (certs, key, source) = match (&opts.cert_file, &opts.key_file) {
+        (Some(cert_path), Some(key_path)) => {
+            let (certs, key) = load_pem_pair(cert_path, key_path)?;
+            (certs, key, format!("certificate {}", cert_path.display()))
+        }
+        _ => {
+            const SANS: &[&str] = &["localhost", "ivnc.local"];
+            let cert = match opts.cert_dir {
+                Some(ref dir) => crate::cert_store::load_or_generate(dir, "https", SANS)?,
+                None => crate::cert_store::generate(SANS)?,
+            };
+            let key = PrivateKeyDer::try_from(cert.key_der)
+                .map_err(|e| format!("TLS key error: {}", e))?;
+            (vec![CertificateDer::from(cert.cert_der)], key, "self-signed certificate".to_string())
+        }
+    };
 
-    let config = ServerConfig::builder_with_provider(StdArc::new(rustls::crypto::ring::default_provider()))
+    let mut config = ServerConfig::builder_with_provider(StdArc::new(rustls::crypto::ring::default_provider()))
         .with_safe_default_protocol_versions()?
         .with_no_client_auth()
-        .with_single_cert(vec![cert_der], key_der)?;
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("TLS certificate/key rejected: {}", e))?;
+    // Offer HTTP/2 via ALPN; the auto connection builder serves either
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
 
-    info!("TLS enabled with self-signed certificate");
+    info!("TLS enabled with {}", source);
     Ok(tokio_rustls::TlsAcceptor::from(StdArc::new(config)))
 }
 
+/// Read a PEM certificate chain and private key
+#[cfg(feature = "tls")]
+fn load_pem_pair(
+    cert_path: &std::path::Path,
+    key_path: &std::path::Path,
+) -> Result<(Vec<rustls::pki_types::CertificateDer<'static>>, rustls::pki_types::PrivateKeyDer<'static>), String> {
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read TLS certificate {}: {}", cert_path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("No PEM certificates found in {}", cert_path.display()));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| format!("Failed to read TLS private key {}: {}", key_path.display(), e))?;
+    Ok((certs, key))
+}
+
+/// Reload the user-provided certificate on SIGHUP (e.g. after renewal).
+/// A bad file is logged and the current certificate stays in use.
+#[cfg(feature = "tls")]
+async fn reload_tls_on_sighup(
+    slot: Arc<std::sync::RwLock<tokio_rustls::TlsAcceptor>>,
+    opts: TlsOptions,
+) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Cannot listen for SIGHUP, TLS reload disabled: {}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        match create_tls_acceptor(&opts) {
+            Ok(acceptor) => {
+                *slot.write().unwrap() = acceptor;
+                info!("Reloaded TLS certificate on SIGHUP");
+            }
+            Err(e) => warn!("TLS reload failed, keeping current certificate: {}", e),
+        }
+    }
+}
+
 /// Health check handler
 async fn health_handler(State(state): State<Arc<SharedState>>) -> String {
     let uptime = state.uptime();
@@ -420,13 +634,38 @@ async fn health_handler(State(state): State<Arc<SharedState>>) -> String {
     )
 }
 
+/// Server-Sent Events stream of session lifecycle and stats events.
+/// Each event's data is a JSON object with a `type` field; comment
+/// heartbeats keep idle proxies from closing the connection.
+async fn events_handler(
+    State(state): State<Arc<SharedState>>,
+) -> Sse<impl futures::Stream<Item = Result<SseEvent, std::convert::Infallible>>> {
+    let rx = state.subscribe_events();
+    let stream = futures::stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(json) => return Some((Ok(SseEvent::default().data(json)), rx)),
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                    debug!("SSE subscriber lagged, dropped {} events", n);
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("heartbeat"),
+    )
+}
+
 /// Metrics handler (Prometheus format)
 async fn metrics_handler(State(state): State<Arc<SharedState>>) -> String {
     let uptime = state.uptime().as_secs_f64();
     let clients = state.connection_count();
     let stats = state.stats.lock().unwrap().clone();
 
-    format!(
+    let mut body = format!(
         r#"# HELP ivnc_uptime_seconds Server uptime in seconds
 # TYPE ivnc_uptime_seconds counter
 ivnc_uptime_seconds {}
@@ -445,12 +684,28 @@ ivnc_client_latency_ms {}
 # HELP ivnc_client_fps Client-reported FPS
 # TYPE ivnc_client_fps gauge
 ivnc_client_fps {}
+# HELP ivnc_connections_rejected_total Connections closed because max_pending_connections was reached
+# TYPE ivnc_connections_rejected_total counter
+ivnc_connections_rejected_total {}
+# HELP ivnc_frames_skipped_total Frame slots skipped because the loop overran its budget
+# TYPE ivnc_frames_skipped_total counter
+ivnc_frames_skipped_total {}
+# HELP ivnc_frames_dropped_total Frames dropped to keep the encoder within its latency budget
+# TYPE ivnc_frames_dropped_total counter
+ivnc_frames_dropped_total {}
+# HELP ivnc_encode_queue_dropped_total Rendered frames dropped because the encode queue was full
+# TYPE ivnc_encode_queue_dropped_total counter
+ivnc_encode_queue_dropped_total {}
 # HELP ivnc_proto_connections_total Protocol classification counters
 # TYPE ivnc_proto_connections_total counter
 ivnc_proto_connections_total{{protocol="http"}} {}
 ivnc_proto_connections_total{{protocol="ice_tcp"}} {}
 ivnc_proto_connections_total{{protocol="tls"}} {}
 ivnc_proto_connections_total{{protocol="unknown"}} {}
+# HELP ivnc_proto_classification_failures_total Connections closed during protocol classification
+# TYPE ivnc_proto_classification_failures_total counter
+ivnc_proto_classification_failures_total{{reason="timeout"}} {}
+ivnc_proto_classification_failures_total{{reason="unknown_protocol"}} {}
 "#,
         uptime,
         clients,
@@ -458,11 +713,80 @@ ivnc_proto_connections_total{{protocol="unknown"}} {}
         stats.mem_used,
         stats.client_latency_ms,
         stats.client_fps,
+        stats.connections_rejected,
+        stats.frames_skipped,
+        stats.frames_dropped,
+        stats.frames_queue_dropped,
         stats.proto_http,
         stats.proto_ice_tcp,
         stats.proto_tls,
-        stats.proto_unknown
-    )
+        stats.proto_unknown,
+        stats.proto_timeout,
+        stats.proto_unknown_closed
+    );
+    if !stats.thread_cpu.is_empty() {
+        body.push_str("# HELP ivnc_thread_cpu_percent CPU usage percent by thread name\n");
+        body.push_str("# TYPE ivnc_thread_cpu_percent gauge\n");
+        for (name, percent) in &stats.thread_cpu {
+            body.push_str(&format!(
+                "ivnc_thread_cpu_percent{{thread=\"{}\"}} {:.1}\n",
+                escape_label_value(name),
+                percent
+            ));
+        }
+    }
+    body.push_str(&latency_metrics(
+        "ivnc_frame_latency_ms",
+        "Frame push to RTP output latency in ms",
+        &stats.frame_latency,
+    ));
+    body.push_str(&latency_metrics(
+        "ivnc_client_latency_percentile_ms",
+        "Client-reported latency in ms",
+        &stats.client_latency,
+    ));
+    body.push_str(&latency_metrics(
+        "ivnc_encode_handoff_ms",
+        "Time the main loop spends pushing or queueing a frame in ms",
+        &stats.encode_handoff,
+    ));
+    if let Some(ref gpu) = stats.gpu {
+        body.push_str(&gpu_metrics(gpu));
+    }
+    body
+}
+
+/// Summary-style quantile series over the rolling latency window
+fn latency_metrics(name: &str, help: &str, p: &LatencyPercentiles) -> String {
+    let mut out = format!("# HELP {name} {help}\n# TYPE {name} summary\n");
+    for (quantile, value) in [("0.5", p.p50), ("0.95", p.p95), ("0.99", p.p99)] {
+        out.push_str(&format!("{name}{{quantile=\"{quantile}\"}} {value:.1}\n"));
+    }
+    out.push_str(&format!("{name}_count {}\n", p.samples));
+    out
+}
+
+/// GPU series; fields the GPU does not report are left out
+fn gpu_metrics(gpu: &GpuUsage) -> String {
+    let series = [
+        ("ivnc_gpu_utilization_percent", "GPU utilization percent", Some(gpu.utilization_percent as u64)),
+        ("ivnc_gpu_encoder_utilization_percent", "Hardware encoder utilization percent", gpu.encoder_percent.map(u64::from)),
+        ("ivnc_gpu_encoder_sessions", "Active hardware encoder sessions on the GPU", gpu.encoder_sessions.map(u64::from)),
+        ("ivnc_gpu_memory_used_bytes", "GPU memory in use", gpu.memory_used),
+        ("ivnc_gpu_memory_total_bytes", "GPU memory total", gpu.memory_total),
+    ];
+    let mut out = String::new();
+    for (name, help, value) in series {
+        if let Some(value) = value {
+            out.push_str(&format!("# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}\n"));
+        }
+    }
+    out
+}
+
+/// Escape a Prometheus label value (backslash, quote, newline)
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
 }
 
 async fn basic_auth_middleware(
@@ -536,9 +860,10 @@ async fn clients_handler(State(state): State<Arc<SharedState>>) -> String {
     )
 }
 
-/// UI configuration handler
-async fn ui_config_handler(State(state): State<Arc<SharedState>>) -> String {
-    state.ui_config_json()
+/// UI config for the requested Host (`[[ui.hosts]]`), else the default
+async fn ui_config_handler(State(state): State<Arc<SharedState>>, headers: HeaderMap) -> String {
+    let host = headers.get(header::HOST).and_then(|v| v.to_str().ok());
+    state.ui_config_json(host)
 }
 
 /// WebSocket configuration handler
@@ -554,12 +879,96 @@ async fn ws_config_handler(State(state): State<Arc<SharedState>>) -> Response {
     .unwrap()
 }
 
-async fn index_handler(State(_state): State<Arc<SharedState>>) -> Response {
+/// WebTransport connection details; 404 unless the experimental server runs
+async fn wt_config_handler(State(state): State<Arc<SharedState>>) -> Response {
+    match state.webtransport_config.lock().unwrap().clone() {
+        Some(payload) => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CACHE_CONTROL, "no-store")
+            .body(Body::from(payload))
+            .unwrap(),
+        None => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("WebTransport not enabled"))
+            .unwrap(),
+    }
+}
+
+/// Capture the current frame and encode it as JPEG
+#[cfg(feature = "mcp")]
+async fn capture_jpeg(state: &Arc<SharedState>) -> std::result::Result<Vec<u8>, String> {
+    use crate::mcp::frame_capture;
+
+    let (w, h, pixels) = frame_capture::capture_frame(state).await?;
+    if pixels.is_empty() {
+        return Err("no frame available".into());
+    }
+    let quality = state.config.http.snapshot_quality;
+    tokio::task::spawn_blocking(move || frame_capture::xrgb_to_jpeg(w, h, &pixels, quality))
+        .await
+        .map_err(|e| format!("JPEG encoder task failed: {}", e))?
+}
+
+/// Single JPEG of the current screen
+#[cfg(feature = "mcp")]
+async fn snapshot_handler(State(state): State<Arc<SharedState>>) -> Response {
+    match capture_jpeg(&state).await {
+        Ok(jpeg) => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "image/jpeg")
+            .header(header::CACHE_CONTROL, "no-store")
+            .body(Body::from(jpeg))
+            .unwrap(),
+        Err(e) => Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body(Body::from(e))
+            .unwrap(),
+    }
+}
+
+/// `multipart/x-mixed-replace` MJPEG stream, capped at `http.mjpeg_fps`.
+/// Ends when the client disconnects or the compositor stops answering.
+#[cfg(feature = "mcp")]
+async fn mjpeg_handler(State(state): State<Arc<SharedState>>) -> Response {
+    let period = Duration::from_secs(1) / state.config.http.mjpeg_fps.max(1);
+    let mut ticker = tokio::time::interval(period);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    let stream = futures::stream::unfold((state, ticker), |(state, mut ticker)| async move {
+        ticker.tick().await;
+        match capture_jpeg(&state).await {
+            Ok(jpeg) => {
+                let mut part = format!(
+                    "--frame\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                    jpeg.len()
+                )
+                .into_bytes();
+                part.extend_from_slice(&jpeg);
+                part.extend_from_slice(b"\r\n");
+                Some((Ok::<_, std::convert::Infallible>(part), (state, ticker)))
+            }
+            Err(e) => {
+                debug!("MJPEG stream ended: {}", e);
+                None
+            }
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "multipart/x-mixed-replace; boundary=frame")
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Body::from_stream(stream))
+        .unwrap()
+}
+
+async fn index_handler(State(_state): State<Arc<SharedState>>, headers: HeaderMap) -> Response {
     // Check for embedded assets first, then fall back to filesystem
     let use_embedded = has_embedded_assets() && std::env::var("IVNC_WEB_ROOT").is_err();
 
     if use_embedded {
-        return get_embedded_file("index.html");
+        return get_embedded_file("index.html", &headers);
     }
 
     // Fallback to filesystem
@@ -583,8 +992,8 @@ async fn index_handler(State(_state): State<Arc<SharedState>>) -> Response {
 }
 
 /// Handler for serving embedded static files
-async fn embedded_fallback_handler(uri: Uri) -> Response {
-    get_embedded_file(uri.path())
+async fn embedded_fallback_handler(uri: Uri, headers: HeaderMap) -> Response {
+    get_embedded_file(uri.path(), &headers)
 }
 
 /// Change password handler
@@ -622,6 +1031,48 @@ async fn change_password_handler(
         .unwrap()
 }
 
+#[derive(Debug, Deserialize)]
+struct ReplayQuery {
+    /// Playback speed multiplier (default 1.0)
+    speed: Option<f64>,
+}
+
+/// Re-inject a recording from `input.record_dir`. Replies once the events
+/// are queued; playback keeps its original timing (divided by `speed`).
+async fn input_replay_handler(
+    State(state): State<Arc<SharedState>>,
+    Query(query): Query<ReplayQuery>,
+    body: String,
+) -> Response {
+    let speed = query.speed.unwrap_or(1.0);
+    if !(speed > 0.0 && speed <= 100.0) {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"error":"speed must be in (0, 100]"}"#))
+            .unwrap();
+    }
+    let events = match crate::input_recording::parse_recording(&body) {
+        Ok(events) => events,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json!({ "error": e }).to_string()))
+                .unwrap();
+        }
+    };
+    let count = events.len();
+    let duration_ms = events.last().map(|e| (e.t as f64 / speed) as u64).unwrap_or(0);
+    info!("Replaying {} recorded input events at {}x", count, speed);
+    tokio::spawn(crate::input_recording::replay(state.input_sender.clone(), events, speed));
+    Response::builder()
+        .status(StatusCode::ACCEPTED)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({ "events": count, "duration_ms": duration_ms }).to_string()))
+        .unwrap()
+}
+
 /// Console page handler - serves the Pake apps management UI
 async fn console_handler() -> Response {
     let html = include_str!("../../web/console/index.html");
@@ -1122,3 +1573,53 @@ async fn cleanup_old_backups(exe_path: &std::path::Path) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `bytes` one at a time; returns the first decision and how many
+    /// bytes it took
+    fn classify_incrementally(bytes: &[u8]) -> Option<(ConnectionType, usize)> {
+        (1..=bytes.len()).find_map(|n| classify_first_bytes(&bytes[..n]).map(|kind| (kind, n)))
+    }
+
+    #[test]
+    fn slow_http_methods_wait_for_the_full_token() {
+        assert_eq!(classify_first_bytes(b"G"), None);
+        assert_eq!(classify_first_bytes(b"GE"), None);
+        assert_eq!(classify_incrementally(b"GET / HTTP/1.1"), Some((ConnectionType::Http, 3)));
+        assert_eq!(classify_incrementally(b"POST /api"), Some((ConnectionType::Http, 4)));
+        assert_eq!(classify_incrementally(b"PATCH /x"), Some((ConnectionType::Http, 5)));
+        assert_eq!(classify_incrementally(b"OPTIONS *"), Some((ConnectionType::Http, 7)));
+        // Letters that stop matching any method are not HTTP
+        assert_eq!(classify_incrementally(b"GIF89a"), Some((ConnectionType::Unknown, 2)));
+    }
+
+    #[test]
+    fn tls_and_dtls_need_the_version_byte() {
+        assert_eq!(classify_first_bytes(&[0x16]), None);
+        assert_eq!(classify_incrementally(&[0x16, 0x03, 0x01]), Some((ConnectionType::Tls, 2)));
+        assert_eq!(classify_incrementally(&[0x16, 0xfe, 0xfd]), Some((ConnectionType::IceTcp, 2)));
+    }
+
+    #[test]
+    fn ice_framing_is_decided_from_one_byte() {
+        assert_eq!(classify_incrementally(&[0x00, 0x64, 0x00, 0x01]), Some((ConnectionType::IceTcp, 1)));
+        assert_eq!(classify_incrementally(&[0xff, 0xff]), Some((ConnectionType::Unknown, 1)));
+        assert_eq!(classify_first_bytes(&[]), None);
+    }
+
+    #[test]
+    fn undecided_prefixes_fall_back_to_the_old_guesses() {
+        assert_eq!(guess_from_partial(b"G"), ConnectionType::Http);
+        assert_eq!(guess_from_partial(&[0x16]), ConnectionType::IceTcp);
+        assert_eq!(guess_from_partial(&[]), ConnectionType::Unknown);
+    }
+
+    #[test]
+    fn thread_names_are_escaped_as_label_values() {
+        assert_eq!(escape_label_value("tokio-runtime-w"), "tokio-runtime-w");
+        assert_eq!(escape_label_value("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
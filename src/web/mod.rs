@@ -8,4 +8,4 @@ pub use shared::SharedState;
 pub mod embedded_assets;
 
 pub mod http_server;
-pub use http_server::run_http_server_with_webrtc;
+pub use http_server::{run_http_server_with_webrtc, TlsOptions};
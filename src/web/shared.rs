@@ -4,16 +4,22 @@
 
 #![allow(dead_code)]
 
-use crate::config::Config;
+use crate::config::{ClipboardOverflowPolicy, Config};
 use crate::config::ui::UiConfig;
 use crate::audio::AudioPacket;
+use crate::clipboard::ClipboardContent;
+use crate::control::ControlArbiter;
+use crate::latency_histogram::{LatencyPercentiles, RollingLatency, LATENCY_WINDOW};
+use crate::hooks::{Hooks, NoopHooks};
 use xxhash_rust::xxh64::xxh64;
-use crate::input::InputEventData;
+use crate::input::{InputEvent, InputEventData};
 use crate::runtime_settings::RuntimeSettings;
+use crate::webrtc::media_clock::RtpClock;
 use base64::Engine;
-use log::{info, warn};
+use log::{debug, info, warn};
+use std::collections::HashMap;
 use std::process::Command;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use tokio::sync::broadcast;
 use tokio::sync::mpsc;
@@ -35,12 +41,19 @@ pub struct SharedState {
     /// Text broadcast sender (clipboard, stats, system messages)
     pub text_sender: broadcast::Sender<String>,
 
+    /// Session lifecycle / stats events as JSON, streamed by `GET /events`
+    pub event_sender: broadcast::Sender<String>,
+
     /// Input event sender
     pub input_sender: mpsc::UnboundedSender<InputEventData>,
 
     /// Display dimensions
     pub display_size: Arc<Mutex<(u32, u32)>>,
 
+    /// Encoded video dimensions; browsers send pointer positions in this
+    /// space, which differs from the display when the stream is scaled
+    pub stream_size: Arc<Mutex<(u32, u32)>>,
+
     /// Clipboard content (base64 text)
     pub clipboard: Arc<Mutex<Option<String>>>,
 
@@ -50,14 +63,25 @@ pub struct SharedState {
     /// Request pipeline rebuild (after display resize)
     pub pipeline_rebuild: Arc<AtomicBool>,
 
-    /// Pending display resize target (width, height); pipeline thread will apply it
-    pub pending_resize: Arc<Mutex<Option<(u32, u32)>>>,
+    /// Pending display resize target; pipeline thread will apply it once
+    /// requests pause for `display.resize_debounce_ms`
+    pub pending_resize: Arc<Mutex<PendingResize>>,
 
     /// Runtime stats
     pub stats: Arc<Mutex<RuntimeStats>>,
 
+    /// A client asked for stats now (`getstats`); the main loop computes
+    /// and broadcasts them on its next pass
+    pub stats_requested: Arc<AtomicBool>,
+    /// When the last on-demand stats request was accepted, for throttling
+    last_stats_request: Arc<Mutex<Option<std::time::Instant>>>,
+    /// Bumped each time the main loop publishes stats
+    pub stats_generation: Arc<AtomicU64>,
+
     /// UI configuration
     pub ui_config: Arc<UiConfig>,
+    /// Per-host UI configs (`[[ui.hosts]]`), keyed by normalized host
+    pub ui_host_configs: Arc<HashMap<String, UiConfig>>,
 
     /// Server start time
     pub start_time: std::time::Instant,
@@ -68,7 +92,7 @@ pub struct SharedState {
     /// WebRTC session count
     pub webrtc_session_count: Arc<AtomicU64>,
 
-    /// Bumped each time a DataChannel opens (used to trigger taskbar resend)
+    /// Bumped each time a DataChannel opens
     pub datachannel_open_count: Arc<AtomicU64>,
 
     /// Runtime settings updated from client
@@ -85,11 +109,35 @@ pub struct SharedState {
     pub clipboard_incoming_dirty: Arc<AtomicBool>,
 
     /// Channel for browser→compositor clipboard content (replaces dirty flag for new data)
-    pub clipboard_incoming_tx: mpsc::UnboundedSender<String>,
-    pub clipboard_incoming_rx: Arc<Mutex<mpsc::UnboundedReceiver<String>>>,
+    pub clipboard_incoming_tx: mpsc::UnboundedSender<ClipboardContent>,
+    pub clipboard_incoming_rx: Arc<Mutex<mpsc::UnboundedReceiver<ClipboardContent>>>,
+
+    /// Congestion state per WebRTC session id (true = bandwidth-constrained)
+    pub congested_sessions: Arc<Mutex<HashMap<String, bool>>>,
+
+    /// WebRTC sessions that turned their video off (`video,off`)
+    pub video_paused_sessions: Arc<AtomicUsize>,
+
+    /// Frame push to RTP latency of the encoder, for percentiles
+    pub frame_latency: Arc<Mutex<RollingLatency>>,
+
+    /// Client-reported latency, for percentiles
+    pub client_latency: Arc<Mutex<RollingLatency>>,
+
+    /// Time the main loop spends handing a frame to the encoder
+    pub encode_handoff: Arc<Mutex<RollingLatency>>,
+
+    /// Input control arbitration (`input.control_policy`)
+    pub control: Arc<Mutex<ControlArbiter>>,
 
     /// Cached keyframe RTP packets for new session replay
-    pub keyframe_cache: Arc<Mutex<Vec<Vec<u8>>>>,
+    pub keyframe_cache: Arc<Mutex<Option<CachedKeyframe>>>,
+
+    /// Capture clock of the running encoder pipeline, for sender reports
+    pub video_clock: Arc<Mutex<Option<RtpClock>>>,
+
+    /// Bit depth the running encoder produces (set by the compositor thread)
+    pub video_bit_depth: Arc<AtomicU8>,
 
     /// Per-session mpsc senders for RTP (reliable cross-thread wakeup)
     pub rtp_subscribers: Arc<Mutex<Vec<mpsc::UnboundedSender<Vec<u8>>>>>,
@@ -108,8 +156,107 @@ pub struct SharedState {
     #[cfg(feature = "mcp")]
     pub frame_capture_rx: Arc<Mutex<mpsc::UnboundedReceiver<tokio::sync::oneshot::Sender<(u32, u32, Vec<u8>)>>>>,
 
+    /// Serializes clipboard-as-keystrokes typing (`ck,` messages) so two
+    /// requests don't interleave their characters
+    pub keystroke_typing_lock: Arc<tokio::sync::Mutex<()>>,
+
+    /// Serializes MCP input sequences (clicks, key combos, typing) across all
+    /// MCP sessions; tokio's mutex is fair, so waiting tools run in FIFO order
+    #[cfg(feature = "mcp")]
+    pub mcp_input_lock: Arc<tokio::sync::Mutex<()>>,
+
+    /// MCP window tree channel: main loop answers with a live snapshot of all toplevels
+    #[cfg(feature = "mcp")]
+    pub window_tree_tx: mpsc::UnboundedSender<tokio::sync::oneshot::Sender<Vec<WindowInfo>>>,
+    #[cfg(feature = "mcp")]
+    pub window_tree_rx: Arc<Mutex<mpsc::UnboundedReceiver<tokio::sync::oneshot::Sender<Vec<WindowInfo>>>>>,
+
+    /// MCP hit-test channel: main loop answers with the window at a point
+    /// (and whether the hit surface is a popup/subsurface rather than the toplevel)
+    #[cfg(feature = "mcp")]
+    pub window_at_tx: mpsc::UnboundedSender<WindowAtRequest>,
+    #[cfg(feature = "mcp")]
+    pub window_at_rx: Arc<Mutex<mpsc::UnboundedReceiver<WindowAtRequest>>>,
+
+    /// MCP focus channel: main loop answers with the keyboard-focused window
+    /// (if any) and whether the focused client has text-input bound
+    #[cfg(feature = "mcp")]
+    pub focus_query_tx: mpsc::UnboundedSender<tokio::sync::oneshot::Sender<(Option<WindowInfo>, bool)>>,
+    #[cfg(feature = "mcp")]
+    pub focus_query_rx: Arc<Mutex<mpsc::UnboundedReceiver<tokio::sync::oneshot::Sender<(Option<WindowInfo>, bool)>>>>,
+
+    /// MCP window switch channel: main loop raises and focuses the next
+    /// window in stacking order and answers with it
+    #[cfg(feature = "mcp")]
+    pub window_switch_tx: mpsc::UnboundedSender<WindowSwitchRequest>,
+    #[cfg(feature = "mcp")]
+    pub window_switch_rx: Arc<Mutex<mpsc::UnboundedReceiver<WindowSwitchRequest>>>,
+
     /// Cached latest taskbar JSON for MCP list_windows tool
     pub last_taskbar_json: Arc<Mutex<Option<String>>>,
+
+    /// Connection details served at `/wt-config` while the WebTransport
+    /// server is running (JSON: port, cert hash, token)
+    pub webtransport_config: Arc<Mutex<Option<String>>>,
+
+    /// Embedder hooks for session and input events (no-op by default)
+    pub hooks: Arc<dyn Hooks>,
+}
+
+pub use crate::config::{MAX_DISPLAY_HEIGHT, MAX_DISPLAY_WIDTH};
+
+/// On-demand stats requests closer together than this are ignored
+pub const STATS_REQUEST_MIN_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Keyframes older than this are not replayed to new sessions
+pub const KEYFRAME_CACHE_MAX_AGE: std::time::Duration = std::time::Duration::from_secs(10);
+/// Keyframes larger than this are not cached
+pub const KEYFRAME_CACHE_MAX_BYTES: usize = 4 * 1024 * 1024;
+
+/// Last encoded keyframe, as RTP packets, with the output it was encoded for
+#[derive(Debug, Clone)]
+pub struct CachedKeyframe {
+    pub packets: Vec<Vec<u8>>,
+    pub width: u32,
+    pub height: u32,
+    pub captured_at: std::time::Instant,
+}
+
+impl CachedKeyframe {
+    /// Matches the current output size and is recent enough to replay
+    pub fn is_replayable(&self, width: u32, height: u32) -> bool {
+        self.width == width
+            && self.height == height
+            && self.captured_at.elapsed() <= KEYFRAME_CACHE_MAX_AGE
+    }
+}
+
+/// Latest display resize request. Dragging a browser window edge sends many
+/// sizes per second; only the last one, once no newer request arrived for
+/// the debounce interval, is applied, so the pipeline is rebuilt once.
+#[derive(Debug, Default)]
+pub struct PendingResize {
+    request: Option<((u32, u32), std::time::Instant)>,
+}
+
+impl PendingResize {
+    pub fn set(&mut self, size: (u32, u32), now: std::time::Instant) {
+        self.request = Some((size, now));
+    }
+
+    pub fn clear(&mut self) {
+        self.request = None;
+    }
+
+    /// The requested size, once it has been the latest for `quiet`
+    pub fn take(&mut self, now: std::time::Instant, quiet: std::time::Duration) -> Option<(u32, u32)> {
+        let (size, at) = self.request?;
+        if now.saturating_duration_since(at) < quiet {
+            return None;
+        }
+        self.request = None;
+        Some(size)
+    }
 }
 
 impl std::fmt::Debug for SharedState {
@@ -133,24 +280,40 @@ impl SharedState {
         let (rtp_sender, _) = broadcast::channel(2000);
         let (audio_sender, _) = broadcast::channel(500);
         let (text_sender, _) = broadcast::channel(256);
+        let (event_sender, _) = broadcast::channel(256);
         let (clipboard_incoming_tx, clipboard_incoming_rx) = mpsc::unbounded_channel();
         #[cfg(feature = "mcp")]
         let (frame_capture_tx, frame_capture_rx) = mpsc::unbounded_channel();
+        #[cfg(feature = "mcp")]
+        let (window_tree_tx, window_tree_rx) = mpsc::unbounded_channel();
+        #[cfg(feature = "mcp")]
+        let (window_at_tx, window_at_rx) = mpsc::unbounded_channel();
+        #[cfg(feature = "mcp")]
+        let (focus_query_tx, focus_query_rx) = mpsc::unbounded_channel();
+        #[cfg(feature = "mcp")]
+        let (window_switch_tx, window_switch_rx) = mpsc::unbounded_channel();
         let display_size = Arc::new(Mutex::new((config.display.width, config.display.height)));
+        let ui_host_configs = ui_config.host_overrides(&config.ui.hosts);
 
         Self {
             config: Arc::new(config),
+            ui_host_configs: Arc::new(ui_host_configs),
             ui_config: Arc::new(ui_config),
             rtp_sender,
             audio_sender,
             text_sender,
+            event_sender,
             input_sender,
+            stream_size: Arc::new(Mutex::new(*display_size.lock().unwrap())),
             display_size,
             clipboard: Arc::new(Mutex::new(None)),
             force_keyframe: Arc::new(AtomicBool::new(false)),
             pipeline_rebuild: Arc::new(AtomicBool::new(false)),
-            pending_resize: Arc::new(Mutex::new(None)),
+            pending_resize: Arc::new(Mutex::new(PendingResize::default())),
             stats: Arc::new(Mutex::new(RuntimeStats::default())),
+            stats_requested: Arc::new(AtomicBool::new(false)),
+            last_stats_request: Arc::new(Mutex::new(None)),
+            stats_generation: Arc::new(AtomicU64::new(0)),
             start_time: std::time::Instant::now(),
             last_cursor_message: Arc::new(Mutex::new(None)),
             webrtc_session_count: Arc::new(AtomicU64::new(0)),
@@ -162,7 +325,15 @@ impl SharedState {
             clipboard_incoming_dirty: Arc::new(AtomicBool::new(false)),
             clipboard_incoming_tx,
             clipboard_incoming_rx: Arc::new(Mutex::new(clipboard_incoming_rx)),
-            keyframe_cache: Arc::new(Mutex::new(Vec::new())),
+            congested_sessions: Arc::new(Mutex::new(HashMap::new())),
+            video_paused_sessions: Arc::new(AtomicUsize::new(0)),
+            frame_latency: Arc::new(Mutex::new(RollingLatency::new(LATENCY_WINDOW, std::time::Instant::now()))),
+            client_latency: Arc::new(Mutex::new(RollingLatency::new(LATENCY_WINDOW, std::time::Instant::now()))),
+            encode_handoff: Arc::new(Mutex::new(RollingLatency::new(LATENCY_WINDOW, std::time::Instant::now()))),
+            control: Arc::new(Mutex::new(ControlArbiter::new(config.input.control_policy))),
+            keyframe_cache: Arc::new(Mutex::new(None)),
+            video_clock: Arc::new(Mutex::new(None)),
+            video_bit_depth: Arc::new(AtomicU8::new(8)),
             rtp_subscribers: Arc::new(Mutex::new(Vec::new())),
             audio_subscribers: Arc::new(Mutex::new(Vec::new())),
             text_subscribers: Arc::new(Mutex::new(Vec::new())),
@@ -171,10 +342,40 @@ impl SharedState {
             frame_capture_tx,
             #[cfg(feature = "mcp")]
             frame_capture_rx: Arc::new(Mutex::new(frame_capture_rx)),
+            keystroke_typing_lock: Arc::new(tokio::sync::Mutex::new(())),
+            #[cfg(feature = "mcp")]
+            mcp_input_lock: Arc::new(tokio::sync::Mutex::new(())),
+            #[cfg(feature = "mcp")]
+            window_tree_tx,
+            #[cfg(feature = "mcp")]
+            window_tree_rx: Arc::new(Mutex::new(window_tree_rx)),
+            #[cfg(feature = "mcp")]
+            window_at_tx,
+            #[cfg(feature = "mcp")]
+            window_at_rx: Arc::new(Mutex::new(window_at_rx)),
+            #[cfg(feature = "mcp")]
+            focus_query_tx,
+            #[cfg(feature = "mcp")]
+            focus_query_rx: Arc::new(Mutex::new(focus_query_rx)),
+            #[cfg(feature = "mcp")]
+            window_switch_tx,
+            #[cfg(feature = "mcp")]
+            window_switch_rx: Arc::new(Mutex::new(window_switch_rx)),
             last_taskbar_json: Arc::new(Mutex::new(None)),
+            webtransport_config: Arc::new(Mutex::new(None)),
+            hooks: Arc::new(NoopHooks),
         }
     }
 
+    /// State for unit tests, with the receiving end of its input channel
+    #[cfg(test)]
+    pub(crate) fn for_test(config: Config) -> (Arc<SharedState>, mpsc::UnboundedReceiver<InputEventData>) {
+        let (input_tx, input_rx) = mpsc::unbounded_channel();
+        let ui_config = UiConfig::from_env(&config);
+        let runtime_settings = Arc::new(RuntimeSettings::new(&config));
+        (Arc::new(Self::new(config, ui_config, input_tx, runtime_settings)), input_rx)
+    }
+
     pub fn update_webrtc_stats(&self, kind: &str, payload: &str) {
         match kind {
             "video" => {
@@ -239,11 +440,64 @@ impl SharedState {
         let _ = self.text_sender.send(msg);
     }
 
+    /// Whether clipboard content may pass between browsers, MCP and remote
+    /// apps (`input.enable_clipboard`)
+    pub fn clipboard_enabled(&self) -> bool {
+        self.config.input.enable_clipboard
+    }
+
+    /// Mime type of the deferred remote-selection read to start now, if
+    /// one is pending. With the clipboard disabled it is dropped unread.
+    pub fn deferred_clipboard_read(&self, pending: Option<String>) -> Option<String> {
+        pending.filter(|_| self.clipboard_enabled())
+    }
+
+    /// Broadcast a selection read from a remote app; `total_size` is its
+    /// size before `data` was capped. Returns whether anything was sent.
+    pub fn publish_remote_clipboard(&self, mime_type: &str, data: Vec<u8>, total_size: usize) -> bool {
+        if !self.clipboard_enabled() {
+            return false;
+        }
+        let Some(data) = self.limit_clipboard(data, total_size, true) else {
+            return false;
+        };
+        if mime_type == "text/html" {
+            info!("Clipboard HTML from remote app: {} bytes", data.len());
+            self.set_clipboard_html(&data);
+            return true;
+        }
+        match String::from_utf8(data) {
+            Ok(text) => {
+                info!("Clipboard from remote app: {} bytes", text.len());
+                let encoded = base64::engine::general_purpose::STANDARD.encode(&text);
+                self.send_text(format!("clipboard,{}", encoded));
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
     /// Store clipboard and broadcast to clients
     pub fn set_clipboard(&self, base64_text: String) {
+        let mut base64_text = base64_text;
+        let decoded = base64::engine::general_purpose::STANDARD.decode(&base64_text).ok();
+        let decoded = match decoded {
+            Some(bytes) => {
+                let total_size = bytes.len();
+                let Some(bytes) = self.limit_clipboard(bytes, total_size, true) else {
+                    return;
+                };
+                if bytes.len() != total_size {
+                    base64_text = base64::engine::general_purpose::STANDARD.encode(&bytes);
+                }
+                Some(bytes)
+            }
+            None => None,
+        };
+
         let mut clipboard = self.clipboard.lock().unwrap();
         *clipboard = Some(base64_text.clone());
-        if let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(&base64_text) {
+        if let Some(decoded) = decoded {
             let total_size = decoded.len();
             if total_size > 8192 {
                 self.send_text(format!("clipboard_start,text/plain,{}", total_size));
@@ -259,8 +513,29 @@ impl SharedState {
         self.send_text(format!("clipboard,{}", base64_text));
     }
 
+    /// Broadcast HTML clipboard content, chunked like text when large. Sent
+    /// after the plain text of the same selection so clients can put both
+    /// representations on the system clipboard.
+    pub fn set_clipboard_html(&self, html: &[u8]) {
+        if html.len() > 8192 {
+            self.send_text(format!("clipboard_start,text/html,{}", html.len()));
+            for chunk in html.chunks(4096) {
+                let encoded = base64::engine::general_purpose::STANDARD.encode(chunk);
+                self.send_text(format!("clipboard_data,{}", encoded));
+            }
+            self.send_text("clipboard_finish".to_string());
+            return;
+        }
+        let encoded = base64::engine::general_purpose::STANDARD.encode(html);
+        self.send_text(format!("clipboard_html,{}", encoded));
+    }
+
     /// Store binary clipboard and broadcast to clients
     pub fn set_clipboard_binary(&self, mime_type: String, data: Vec<u8>) {
+        let total_size = data.len();
+        let Some(data) = self.limit_clipboard(data, total_size, false) else {
+            return;
+        };
         if data.len() > 8192 {
             self.send_text(format!("clipboard_start,{},{}", mime_type, data.len()));
             for chunk in data.chunks(4096) {
@@ -275,6 +550,42 @@ impl SharedState {
         self.send_text(format!("clipboard_binary,{},{}", mime_type, encoded));
     }
 
+    /// Apply the configured clipboard size limit.
+    ///
+    /// `total_size` is the original payload size, which may exceed `data.len()`
+    /// when the caller already stopped buffering at the limit. Returns `None`
+    /// when the payload is rejected; clients are notified either way.
+    pub fn limit_clipboard(&self, mut data: Vec<u8>, total_size: usize, is_text: bool) -> Option<Vec<u8>> {
+        let limit = self.config.input.max_clipboard_bytes;
+        if total_size <= limit {
+            return Some(data);
+        }
+        match self.config.input.clipboard_overflow {
+            ClipboardOverflowPolicy::Reject => {
+                warn!("Clipboard payload of {} bytes exceeds limit {}; rejecting", total_size, limit);
+                self.notify_clipboard_too_large(total_size);
+                None
+            }
+            ClipboardOverflowPolicy::Truncate => {
+                data.truncate(limit);
+                if is_text {
+                    crate::clipboard::trim_partial_utf8(&mut data);
+                }
+                warn!("Clipboard payload of {} bytes truncated to {}", total_size, data.len());
+                self.send_text(format!("clipboard_truncated,{},{}", total_size, data.len()));
+                Some(data)
+            }
+        }
+    }
+
+    /// Tell clients a clipboard payload was dropped for exceeding the size limit
+    pub fn notify_clipboard_too_large(&self, total_size: usize) {
+        self.send_text(format!(
+            "clipboard_too_large,{},{}",
+            total_size, self.config.input.max_clipboard_bytes
+        ));
+    }
+
     pub fn mark_clipboard_written(&self, mime_type: &str, data: &[u8]) {
         let mut hash = xxh64(mime_type.as_bytes(), 0);
         hash = xxh64(data, hash);
@@ -297,19 +608,98 @@ impl SharedState {
         *self.display_size.lock().unwrap()
     }
 
-    /// Request display resize
-    pub fn resize_display(&self, width: u32, height: u32) {
+    /// Record the encoded video size (called by compositor thread)
+    pub fn set_stream_size(&self, width: u32, height: u32) {
+        *self.stream_size.lock().unwrap() = (width, height);
+    }
+
+    /// Rewrite a browser's absolute pointer position from stream to display
+    /// coordinates; relative motion and local (MCP) input are left alone
+    pub fn map_stream_input(&self, event: &mut InputEventData) {
+        if event.event_type != InputEvent::MouseMove || event.text == "relative" {
+            return;
+        }
+        let stream = *self.stream_size.lock().unwrap();
+        let (x, y) = crate::input::stream_to_display(event.mouse_x, event.mouse_y, stream, self.display_size());
+        event.mouse_x = x;
+        event.mouse_y = y;
+    }
+
+    /// Apply a client's `r,{width}x{height}`, clamped to
+    /// `display.max_width`/`max_height`, and build the `resize,{json}` reply
+    /// telling it the size it gets (or why it gets none). Under input
+    /// control arbitration only the holder may resize.
+    pub fn handle_resize_request(&self, session_id: &str, payload: &str) -> String {
+        let display = &self.config.display;
+        let max = serde_json::json!({ "width": display.max_width, "height": display.max_height });
+        if !self.input_allowed(session_id) {
+            let error = "another session has input control";
+            return format!("resize,{}", serde_json::json!({ "error": error, "max": max }));
+        }
+        let requested = payload
+            .split_once('x')
+            .and_then(|(w, h)| Some((w.parse::<u32>().ok()?, h.parse::<u32>().ok()?)));
+        let Some((width, height)) = requested else {
+            let error = format!("invalid size: {}", payload);
+            return format!("resize,{}", serde_json::json!({ "error": error, "max": max }));
+        };
+        let Some(applied) = display.clamp_size(width, height) else {
+            let error = "width and height must be non-zero";
+            return format!("resize,{}", serde_json::json!({ "error": error, "max": max }));
+        };
+        if applied != (width, height) {
+            info!("Clamping resize request {}x{} to {}x{}", width, height, applied.0, applied.1);
+        }
+        let applied = self.resize_display(applied.0, applied.1);
+        let clamped = applied != (width, height);
+        format!(
+            "resize,{}",
+            serde_json::json!({
+                "width": applied.0,
+                "height": applied.1,
+                "requested": { "width": width, "height": height },
+                "clamped": clamped,
+            })
+        )
+    }
+
+    /// Request display resize, fitted to `display.aspect_ratio` when one is
+    /// locked. Returns the size that will be applied.
+    pub fn resize_display(&self, width: u32, height: u32) -> (u32, u32) {
+        let (width, height) = self.config.display.fit_aspect(width, height);
         let current = self.display_size();
+        let mut pending = self.pending_resize.lock().unwrap();
         if current == (width, height) {
-            return;
+            // Dragged back to the current size: drop any size queued meanwhile
+            pending.clear();
+            return current;
         }
-        info!("Queuing display resize to {}x{}", width, height);
-        *self.pending_resize.lock().unwrap() = Some((width, height));
+        debug!("Queuing display resize to {}x{}", width, height);
+        pending.set((width, height), std::time::Instant::now());
+        (width, height)
+    }
+
+    /// `display_size,{json}`: the display size, any locked aspect ratio (for
+    /// clients to letterbox the video) and the output scale
+    pub fn display_size_message(&self) -> String {
+        let (width, height) = self.display_size();
+        let aspect_ratio = self.config.display.locked_aspect_ratio().map(|(w, h)| format!("{}:{}", w, h));
+        format!(
+            "display_size,{}",
+            serde_json::json!({
+                "width": width,
+                "height": height,
+                "aspect_ratio": aspect_ratio,
+                "scale": self.runtime_settings.output_scale(),
+            })
+        )
     }
 
-    /// Take pending resize request (called by compositor thread)
+    /// Take the pending resize request once it has settled (called by
+    /// compositor thread on every pass, so the final size is always applied)
     pub fn take_pending_resize(&self) -> Option<(u32, u32)> {
-        self.pending_resize.lock().unwrap().take()
+        let quiet = std::time::Duration::from_millis(self.config.display.resize_debounce_ms);
+        self.pending_resize.lock().unwrap().take(std::time::Instant::now(), quiet)
     }
 
     /// Update resource usage stats
@@ -319,6 +709,16 @@ impl SharedState {
         stats.mem_used = mem_used;
     }
 
+    /// Update per-thread CPU usage (name, percent)
+    pub fn update_thread_cpu(&self, usage: Vec<(String, f64)>) {
+        self.stats.lock().unwrap().thread_cpu = usage;
+    }
+
+    /// Update the GPU sample
+    pub fn update_gpu_usage(&self, usage: Option<GpuUsage>) {
+        self.stats.lock().unwrap().gpu = usage;
+    }
+
     /// Update latency metric (ms)
     pub fn update_latency(&self, latency_ms: u64) {
         let mut stats = self.stats.lock().unwrap();
@@ -326,8 +726,33 @@ impl SharedState {
     }
 
 
+    /// Record how long a frame took from push to its last RTP packet
+    pub fn record_frame_latency(&self, latency_ms: f64) {
+        self.frame_latency.lock().unwrap().record(latency_ms, std::time::Instant::now());
+    }
+
+    /// Record how long pushing or queueing a frame held up the main loop
+    pub fn record_handoff_latency(&self, latency_ms: f64) {
+        self.encode_handoff.lock().unwrap().record(latency_ms, std::time::Instant::now());
+    }
+
+    /// Copy the current latency percentiles into the stats; the median
+    /// frame latency doubles as `latency`
+    pub fn refresh_latency_percentiles(&self) {
+        let now = std::time::Instant::now();
+        let frame = self.frame_latency.lock().unwrap().percentiles(now);
+        let client = self.client_latency.lock().unwrap().percentiles(now);
+        let handoff = self.encode_handoff.lock().unwrap().percentiles(now);
+        let mut stats = self.stats.lock().unwrap();
+        stats.latency_ms = frame.p50.round() as u64;
+        stats.frame_latency = frame;
+        stats.client_latency = client;
+        stats.encode_handoff = handoff;
+    }
+
     /// Update client-reported latency metric (ms)
     pub fn update_client_latency(&self, latency_ms: u64) {
+        self.client_latency.lock().unwrap().record(latency_ms as f64, std::time::Instant::now());
         let mut stats = self.stats.lock().unwrap();
         stats.client_latency_ms = latency_ms;
     }
@@ -345,40 +770,61 @@ impl SharedState {
             "http" => stats.proto_http += 1,
             "ice_tcp" => stats.proto_ice_tcp += 1,
             "tls" => stats.proto_tls += 1,
+            "timeout" => stats.proto_timeout += 1,
+            "unknown_closed" => stats.proto_unknown_closed += 1,
             _ => stats.proto_unknown += 1,
         }
     }
 
-    /// Record an ICE candidate (TCP-only keeps a minimal counter)
-    pub fn record_ice_candidate(&self, transport: Option<&str>) {
+    /// Count a remote (browser) ICE candidate by transport and type
+    pub fn record_ice_candidate(&self, candidate: &crate::webrtc::signaling::IceCandidateInfo) {
         let mut stats = self.stats.lock().unwrap();
         stats.ice_candidates_total += 1;
-        if transport == Some("tcp") {
+        if candidate.transport == "tcp" {
             stats.ice_candidates_tcp += 1;
         }
+        match candidate.candidate_type.as_str() {
+            "host" => stats.ice_candidates_host += 1,
+            "srflx" => stats.ice_candidates_srflx += 1,
+            "prflx" => stats.ice_candidates_prflx += 1,
+            "relay" => stats.ice_candidates_relay += 1,
+            _ => {}
+        }
     }
 
     /// Build stats JSON payload
     pub fn stats_json(&self) -> String {
         let stats = self.stats.lock().unwrap().clone();
+        self.stats_json_of(&stats)
+    }
+
+    /// `stats_json` for a snapshot other than the published stats
+    pub fn stats_json_of(&self, stats: &RuntimeStats) -> String {
         format!(
-            r#"{{"fps":{:.2},"bandwidth":{},"latency":{},"client_latency":{},"client_fps":{},"clients":{},"cpu_percent":{:.1},"mem_used":{},"ice_candidates_total":{},"ice_candidates_tcp":{}}}"#,
+            r#"{{"fps":{:.2},"bandwidth":{},"latency":{},"client_latency":{},"latency_percentiles":{{"frame":{},"client":{},"handoff":{}}},"client_fps":{},"clients":{},"cpu_percent":{:.1},"mem_used":{},"ice_candidates_total":{},"ice_candidates_tcp":{},"ice_candidates":{},"audio_backend":{}}}"#,
             stats.fps,
             stats.bandwidth,
             stats.latency_ms,
             stats.client_latency_ms,
+            percentiles_json(&stats.frame_latency),
+            percentiles_json(&stats.client_latency),
+            percentiles_json(&stats.encode_handoff),
             stats.client_fps,
             self.connection_count(),
             stats.cpu_percent,
             stats.mem_used,
             stats.ice_candidates_total,
-            stats.ice_candidates_tcp
+            stats.ice_candidates_tcp,
+            ice_candidates_json(stats),
+            audio_backend_json(stats.audio_backend)
         )
     }
 
     /// Build UI configuration JSON payload
-    pub fn ui_config_json(&self) -> String {
-        self.ui_config.to_json()
+    pub fn ui_config_json(&self, host: Option<&str>) -> String {
+        host.and_then(|host| self.ui_host_configs.get(&crate::config::ui::normalize_host(host)))
+            .unwrap_or(&self.ui_config)
+            .to_json()
     }
 
     /// Get server uptime
@@ -401,6 +847,7 @@ impl SharedState {
     /// Request a keyframe from the encoder
     pub fn request_keyframe(&self) {
         self.force_keyframe.store(true, Ordering::Relaxed);
+        self.emit_event("keyframe_requested", serde_json::json!({}));
     }
 
     /// Consume keyframe request flag
@@ -424,10 +871,21 @@ impl SharedState {
         self.rtp_subscribers.lock().unwrap().len()
     }
 
-    /// Subscribe to RTP packets via mpsc (reliable cross-thread wakeup)
+    /// Subscribe to RTP packets via mpsc (reliable cross-thread wakeup).
+    /// The first subscriber to an idle pipeline forces a keyframe so it
+    /// doesn't wait a full keyframe interval for something decodable.
     pub fn subscribe_rtp_mpsc(&self) -> mpsc::UnboundedReceiver<Vec<u8>> {
         let (tx, rx) = mpsc::unbounded_channel();
-        self.rtp_subscribers.lock().unwrap().push(tx);
+        let first = {
+            let mut subs = self.rtp_subscribers.lock().unwrap();
+            subs.retain(|tx| !tx.is_closed());
+            subs.push(tx);
+            subs.len() == 1
+        };
+        if first {
+            info!("First RTP subscriber; forcing keyframe");
+            self.request_keyframe();
+        }
         rx
     }
 
@@ -457,16 +915,189 @@ impl SharedState {
         subs.retain(|tx| tx.send(msg.clone()).is_ok());
     }
 
-    /// Update the keyframe cache with a new set of RTP packets
-    pub fn set_keyframe_cache(&self, packets: Vec<Vec<u8>>) {
+    /// Update the keyframe cache with a new set of RTP packets, tagged with
+    /// the output size of the pipeline that encoded them. Oversized
+    /// keyframes are not cached.
+    pub fn set_keyframe_cache(&self, packets: Vec<Vec<u8>>, width: u32, height: u32) {
+        let bytes: usize = packets.iter().map(|p| p.len()).sum();
+        if let Ok(mut cache) = self.keyframe_cache.lock() {
+            *cache = (bytes <= KEYFRAME_CACHE_MAX_BYTES).then(|| CachedKeyframe {
+                packets,
+                width,
+                height,
+                captured_at: std::time::Instant::now(),
+            });
+        }
+    }
+
+    /// Latest video RTP timestamp ↔ capture time mapping
+    pub fn video_clock(&self) -> Option<RtpClock> {
+        *self.video_clock.lock().unwrap()
+    }
+
+    /// Record a session's congestion state; `None` forgets the session
+    pub fn set_session_congested(&self, session_id: &str, congested: Option<bool>) {
+        let mut sessions = self.congested_sessions.lock().unwrap();
+        match congested {
+            Some(c) => {
+                sessions.insert(session_id.to_string(), c);
+            }
+            None => {
+                sessions.remove(session_id);
+            }
+        }
+    }
+
+    /// Whether input from `session_id` may be injected under the control policy
+    pub fn input_allowed(&self, session_id: &str) -> bool {
+        self.control.lock().unwrap().allows(session_id)
+    }
+
+    /// Apply a change to the control arbiter and broadcast the new state
+    /// if it changed. When control leaves a session, its held keys and
+    /// mouse buttons are released.
+    pub fn update_control(&self, change: impl FnOnce(&mut ControlArbiter) -> bool) -> bool {
+        let (json, previous) = {
+            let mut control = self.control.lock().unwrap();
+            let previous = control.holder().map(str::to_string);
+            if !change(&mut control) {
+                return false;
+            }
+            (control.to_json(), previous.filter(|p| control.holder() != Some(p.as_str())))
+        };
+        if let Some(previous) = previous {
+            let session: Arc<str> = Arc::from(previous.as_str());
+            for event_type in [InputEvent::KeyboardReset, InputEvent::ButtonReset] {
+                let _ = self.input_sender.send(InputEventData {
+                    event_type,
+                    session_id: Some(session.clone()),
+                    ..Default::default()
+                });
+            }
+        }
+        info!("Input control: {}", json);
+        self.send_text(format!("control,{}", json));
+        self.emit_event("control_changed", json);
+        true
+    }
+
+    /// Handle a control message (`crate::control::is_control_request`)
+    /// from `session_id`. Returns a reply for the sender: the current state
+    /// when a request or hand-off was refused.
+    pub fn handle_control_request(&self, session_id: &str, text: &str) -> Option<String> {
+        let refused = if text == "release_control" {
+            self.update_control(|control| control.release(session_id));
+            false
+        } else if let Some(target) = text.strip_prefix("grant_control,") {
+            // Hand-off: only the current holder may pass control on
+            !self.update_control(|control| control.holder() == Some(session_id) && control.grant(Some(target)))
+        } else {
+            !self.update_control(|control| control.request(session_id))
+        };
+        if refused {
+            self.control_message()
+        } else {
+            None
+        }
+    }
+
+    /// Current control state as a `control,` message, or None when
+    /// arbitration is off
+    pub fn control_message(&self) -> Option<String> {
+        let control = self.control.lock().unwrap();
+        control.enabled().then(|| format!("control,{}", control.to_json()))
+    }
+
+    /// Current UI state for a session that just connected: UI config (for
+    /// the Host it signaled through), taskbar, cursor, control and stats.
+    /// Broadcasts only carry changes, so without this a late joiner would
+    /// miss them until the next change.
+    pub fn session_state_bundle(&self, host: Option<&str>) -> Vec<String> {
+        let taskbar = self
+            .last_taskbar_json
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(|| r#"{"windows":[]}"#.to_string());
+        let mut messages = vec![
+            format!("ui_config,{}", self.ui_config_json(host)),
+            format!("taskbar,{}", taskbar),
+        ];
+        messages.extend(self.last_cursor_message());
+        messages.push(self.cursor_mode_message());
+        messages.push(self.display_size_message());
+        messages.extend(self.control_message());
+        messages.push(format!("stats,{}", self.stats_json()));
+        messages
+    }
+
+    /// `cursor_mode,server|client`: who draws the cursor
+    pub fn cursor_mode_message(&self) -> String {
+        let mode = if self.runtime_settings.server_cursor() { "server" } else { "client" };
+        format!("cursor_mode,{}", mode)
+    }
+
+    /// Ask the main loop for an immediate stats broadcast (plus taskbar and
+    /// cursor). False if throttled by `STATS_REQUEST_MIN_INTERVAL`.
+    pub fn request_stats_now(&self) -> bool {
+        let now = std::time::Instant::now();
+        let mut last = self.last_stats_request.lock().unwrap();
+        if last.is_some_and(|at| now.duration_since(at) < STATS_REQUEST_MIN_INTERVAL) {
+            return false;
+        }
+        *last = Some(now);
+        self.stats_requested.store(true, Ordering::Relaxed);
+        true
+    }
+
+    /// Consume a pending on-demand stats request
+    pub fn take_stats_request(&self) -> bool {
+        self.stats_requested.swap(false, Ordering::Relaxed)
+    }
+
+    /// True when there is at least one session and every one is constrained.
+    /// With a single encoder, downscaling is only worth it in that case.
+    pub fn all_sessions_congested(&self) -> bool {
+        let sessions = self.congested_sessions.lock().unwrap();
+        !sessions.is_empty() && sessions.values().all(|&c| c)
+    }
+
+    /// Count a session pausing (`true`) or resuming (`false`) its video
+    pub fn set_session_video_paused(&self, paused: bool) {
+        if paused {
+            self.video_paused_sessions.fetch_add(1, Ordering::Relaxed);
+        } else {
+            let _ = self.video_paused_sessions.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+        }
+    }
+
+    /// True when there are video subscribers but every one has paused
+    /// video, so the encoder can idle
+    pub fn all_video_paused(&self) -> bool {
+        let subscribers = self.rtp_receiver_count();
+        subscribers > 0 && self.video_paused_sessions.load(Ordering::Relaxed) >= subscribers
+    }
+
+    /// Drop the cached keyframe (resize, pipeline rebuild)
+    pub fn clear_keyframe_cache(&self) {
         if let Ok(mut cache) = self.keyframe_cache.lock() {
-            *cache = packets;
+            *cache = None;
         }
     }
 
-    /// Get a clone of the cached keyframe packets
+    /// Get a clone of the cached keyframe packets, or nothing if the cache is
+    /// empty, was encoded at another resolution or is too old to replay
     pub fn get_keyframe_cache(&self) -> Vec<Vec<u8>> {
-        self.keyframe_cache.lock().map(|c| c.clone()).unwrap_or_default()
+        let (width, height) = self.display_size();
+        self.keyframe_cache
+            .lock()
+            .ok()
+            .and_then(|c| {
+                c.as_ref()
+                    .filter(|kf| kf.is_replayable(width, height))
+                    .map(|kf| kf.packets.clone())
+            })
+            .unwrap_or_default()
     }
 
     /// Subscribe to RTP packets
@@ -484,6 +1115,24 @@ impl SharedState {
         self.audio_sender.subscribe()
     }
 
+    /// Publish an event to `/events` subscribers. `fields` must be a JSON
+    /// object; a `type` key is added. No-op when nobody is listening.
+    pub fn emit_event(&self, kind: &str, fields: serde_json::Value) {
+        if self.event_sender.receiver_count() == 0 {
+            return;
+        }
+        let mut event = fields;
+        if let Some(obj) = event.as_object_mut() {
+            obj.insert("type".into(), kind.into());
+        }
+        let _ = self.event_sender.send(event.to_string());
+    }
+
+    /// Subscribe to session lifecycle / stats events
+    pub fn subscribe_events(&self) -> broadcast::Receiver<String> {
+        self.event_sender.subscribe()
+    }
+
     /// Increment WebRTC session count
     pub fn increment_webrtc_sessions(&self) {
         self.webrtc_session_count.fetch_add(1, Ordering::Relaxed);
@@ -525,7 +1174,7 @@ impl SharedState {
         let webrtc_sessions = self.webrtc_sessions();
 
         format!(
-            r#"{{"fps":{:.2},"bandwidth":{},"latency":{},"client_latency":{},"client_fps":{},"clients":{},"cpu_percent":{:.1},"mem_used":{},"webrtc_sessions":{},"ice_candidates_total":{},"ice_candidates_tcp":{}}}"#,
+            r#"{{"fps":{:.2},"bandwidth":{},"latency":{},"client_latency":{},"client_fps":{},"clients":{},"cpu_percent":{:.1},"mem_used":{},"webrtc_sessions":{},"ice_candidates_total":{},"ice_candidates_tcp":{},"ice_candidates":{},"audio_backend":{}}}"#,
             stats.fps,
             stats.bandwidth,
             stats.latency_ms,
@@ -536,11 +1185,76 @@ impl SharedState {
             stats.mem_used,
             webrtc_sessions,
             stats.ice_candidates_total,
-            stats.ice_candidates_tcp
+            stats.ice_candidates_tcp,
+            ice_candidates_json(&stats),
+            audio_backend_json(stats.audio_backend)
         )
     }
 }
 
+/// `{"host":..,"srflx":..,"prflx":..,"relay":..}`: remote candidates by type
+fn ice_candidates_json(stats: &RuntimeStats) -> String {
+    format!(
+        r#"{{"host":{},"srflx":{},"prflx":{},"relay":{}}}"#,
+        stats.ice_candidates_host, stats.ice_candidates_srflx, stats.ice_candidates_prflx, stats.ice_candidates_relay
+    )
+}
+
+/// `{"p50":..,"p95":..,"p99":..,"samples":..}` for the stats payloads
+fn percentiles_json(p: &LatencyPercentiles) -> String {
+    format!(r#"{{"p50":{:.1},"p95":{:.1},"p99":{:.1},"samples":{}}}"#, p.p50, p.p95, p.p99, p.samples)
+}
+
+/// `"pulse"` or `null` for the stats payloads
+fn audio_backend_json(backend: Option<&str>) -> String {
+    backend.map_or_else(|| "null".to_string(), |b| format!("\"{}\"", b))
+}
+
+/// Hit-test request: display point and reply channel
+pub type WindowAtRequest = ((i32, i32), tokio::sync::oneshot::Sender<Option<(WindowInfo, bool)>>);
+
+/// Window switch request: cycle direction, optional app filter and reply channel
+pub type WindowSwitchRequest = (WindowSwitch, tokio::sync::oneshot::Sender<Option<WindowInfo>>);
+
+/// Cycle through mapped windows in stacking order, like Alt+Tab
+#[cfg_attr(not(feature = "mcp"), allow(dead_code))]
+#[derive(Debug, Clone, Default)]
+pub struct WindowSwitch {
+    /// Go back to the window that was on top before the last switch
+    pub reverse: bool,
+    /// Only cycle through windows of this app
+    pub app_id: Option<String>,
+}
+
+/// Snapshot of a toplevel window (taskbar entry plus geometry)
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WindowInfo {
+    /// Window ID (index in the compositor's window registry)
+    pub id: u32,
+    pub title: String,
+    pub app_id: String,
+    pub display_name: Option<String>,
+    pub focused: bool,
+    /// Unmapped from the space (minimized); geometry is zero
+    pub minimized: bool,
+    /// Position in the stacking order, 0 = topmost; None when minimized
+    pub stack_index: Option<u32>,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// GPU load behind the hardware encoder (`gpu-metrics` feature)
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GpuUsage {
+    pub utilization_percent: u32,
+    pub encoder_percent: Option<u32>,
+    pub encoder_sessions: Option<u32>,
+    pub memory_used: Option<u64>,
+    pub memory_total: Option<u64>,
+}
+
 /// Runtime stats snapshot
 #[derive(Debug, Clone)]
 pub struct RuntimeStats {
@@ -548,18 +1262,74 @@ pub struct RuntimeStats {
     pub bandwidth: u64,
     pub latency_ms: u64,
     pub client_latency_ms: u64,
+    /// Frame push to RTP latency percentiles over the rolling window
+    pub frame_latency: LatencyPercentiles,
+    /// Client-reported latency percentiles over the rolling window
+    pub client_latency: LatencyPercentiles,
+    /// Main-loop frame hand-off (push or enqueue) percentiles
+    pub encode_handoff: LatencyPercentiles,
     pub client_fps: u32,
     pub total_frames: u64,
     pub total_bytes: u64,
+    /// Keyframes produced by the encoder since start
+    pub keyframes_total: u64,
+    /// Frame slots skipped because render/encode overran the frame budget
+    pub frames_skipped: u64,
+    /// Frames dropped before rendering to stay within the latency budget
+    pub frames_dropped: u64,
+    /// Rendered frames dropped because the encode queue was full
+    pub frames_queue_dropped: u64,
     pub cpu_percent: f64,
     pub mem_used: u64,
+    /// Remote (browser) ICE candidates that parsed, from offers and trickle
     pub ice_candidates_total: u64,
     pub ice_candidates_tcp: u64,
+    /// Remote candidates by type
+    pub ice_candidates_host: u64,
+    pub ice_candidates_srflx: u64,
+    pub ice_candidates_prflx: u64,
+    pub ice_candidates_relay: u64,
     /// Protocol classification counters
     pub proto_http: u64,
     pub proto_ice_tcp: u64,
     pub proto_tls: u64,
     pub proto_unknown: u64,
+    /// Connections closed without sending anything within `classify_timeout_ms`
+    pub proto_timeout: u64,
+    /// Connections closed because their first bytes matched no protocol
+    pub proto_unknown_closed: u64,
+    /// Connections closed because `max_pending_connections` was reached
+    pub connections_rejected: u64,
+    /// Resolved audio capture backend (None while audio is disabled)
+    pub audio_backend: Option<&'static str>,
+    /// CPU percent per thread name, when `http.thread_cpu_metrics` is on
+    pub thread_cpu: Vec<(String, f64)>,
+    /// Latest GPU sample; None without a hardware encoder or GPU statistics
+    pub gpu: Option<GpuUsage>,
+}
+
+/// Main-loop counters since stats were last published
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StatsWindow {
+    pub frames: u64,
+    pub bytes: u64,
+    pub skipped: u64,
+    pub dropped: u64,
+    pub queue_dropped: u64,
+}
+
+impl RuntimeStats {
+    /// Fold in a window lasting `secs`: rates over the window, totals
+    /// including it
+    pub fn apply_window(&mut self, window: &StatsWindow, secs: f64) {
+        self.fps = window.frames as f64 / secs;
+        self.bandwidth = (window.bytes as f64 * 8.0 / secs) as u64;
+        self.total_frames += window.frames;
+        self.total_bytes += window.bytes;
+        self.frames_skipped += window.skipped;
+        self.frames_dropped += window.dropped;
+        self.frames_queue_dropped += window.queue_dropped;
+    }
 }
 
 impl Default for RuntimeStats {
@@ -569,17 +1339,184 @@ impl Default for RuntimeStats {
             bandwidth: 0,
             latency_ms: 0,
             client_latency_ms: 0,
+            frame_latency: LatencyPercentiles::default(),
+            client_latency: LatencyPercentiles::default(),
+            encode_handoff: LatencyPercentiles::default(),
             client_fps: 0,
             total_frames: 0,
             total_bytes: 0,
+            keyframes_total: 0,
+            frames_skipped: 0,
+            frames_dropped: 0,
+            frames_queue_dropped: 0,
             cpu_percent: 0.0,
             mem_used: 0,
             ice_candidates_total: 0,
             ice_candidates_tcp: 0,
+            ice_candidates_host: 0,
+            ice_candidates_srflx: 0,
+            ice_candidates_prflx: 0,
+            ice_candidates_relay: 0,
             proto_http: 0,
             proto_ice_tcp: 0,
             proto_tls: 0,
             proto_unknown: 0,
+            proto_timeout: 0,
+            proto_unknown_closed: 0,
+            connections_rejected: 0,
+            audio_backend: None,
+            thread_cpu: Vec::new(),
+            gpu: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn resize_requests_apply_after_quiet_period() {
+        let quiet = Duration::from_millis(200);
+        let start = Instant::now();
+        let mut pending = PendingResize::default();
+        assert_eq!(pending.take(start, quiet), None);
+
+        // A drag: each request restarts the quiet period
+        pending.set((1200, 800), start);
+        pending.set((1250, 820), start + Duration::from_millis(50));
+        pending.set((1300, 850), start + Duration::from_millis(100));
+        assert_eq!(pending.take(start + Duration::from_millis(250), quiet), None);
+        // Requests stopped: the last size is applied once
+        assert_eq!(pending.take(start + Duration::from_millis(300), quiet), Some((1300, 850)));
+        assert_eq!(pending.take(start + Duration::from_millis(400), quiet), None);
+
+        // No debounce applies immediately
+        pending.set((640, 480), start);
+        assert_eq!(pending.take(start, Duration::ZERO), Some((640, 480)));
+
+        pending.set((640, 480), start);
+        pending.clear();
+        assert_eq!(pending.take(start + quiet, quiet), None);
+    }
+
+    #[test]
+    fn ice_candidates_are_counted_by_type() {
+        use crate::webrtc::signaling::parse_ice_candidate;
+        let state = state_with(Config::default());
+        for line in [
+            "candidate:1 1 udp 2122260223 192.168.1.2 54321 typ host",
+            "candidate:2 1 tcp 1518280447 192.168.1.2 9 typ host tcptype active",
+            "candidate:3 1 udp 1686052607 203.0.113.7 54321 typ srflx raddr 0.0.0.0 rport 0",
+            "candidate:4 1 udp 41885439 198.51.100.1 3478 typ relay",
+        ] {
+            state.record_ice_candidate(&parse_ice_candidate(line).unwrap());
         }
+        let stats: serde_json::Value = serde_json::from_str(&state.stats_json()).unwrap();
+        assert_eq!(stats["ice_candidates_total"], 4);
+        assert_eq!(stats["ice_candidates_tcp"], 1);
+        assert_eq!(stats["ice_candidates"], serde_json::json!({ "host": 2, "srflx": 1, "prflx": 0, "relay": 1 }));
+    }
+
+    #[test]
+    fn keyframes_are_replayed_only_at_their_pipeline_size() {
+        let state = state_with(Config::default());
+        state.set_display_size(1280, 720);
+        state.set_keyframe_cache(vec![vec![0x80, 0x60]], 1280, 720);
+        assert_eq!(state.get_keyframe_cache().len(), 1);
+
+        // The display is already 1920x1080 while the old pipeline drains its
+        // last keyframe: it keeps the size it was encoded at
+        state.set_display_size(1920, 1080);
+        state.set_keyframe_cache(vec![vec![0x80, 0x60]], 1280, 720);
+        assert!(state.get_keyframe_cache().is_empty());
+        state.set_keyframe_cache(vec![vec![0x80, 0x60]], 1920, 1080);
+        assert_eq!(state.get_keyframe_cache().len(), 1);
+    }
+
+    fn state_with(config: Config) -> Arc<SharedState> {
+        SharedState::for_test(config).0
+    }
+
+    #[test]
+    fn stats_requests_are_throttled() {
+        let state = state_with(Config::default());
+        assert!(state.request_stats_now());
+        assert!(!state.request_stats_now());
+        assert!(state.take_stats_request());
+        assert!(!state.take_stats_request());
+        // Accepted again once the interval has passed
+        *state.last_stats_request.lock().unwrap() = Instant::now().checked_sub(STATS_REQUEST_MIN_INTERVAL);
+        assert!(state.request_stats_now());
+        assert!(state.take_stats_request());
+    }
+
+    #[test]
+    fn on_demand_snapshot_leaves_the_periodic_window_alone() {
+        let mut published = RuntimeStats { total_frames: 100, ..Default::default() };
+        let mut snapshot = published.clone();
+        snapshot.apply_window(&StatsWindow { frames: 15, bytes: 1000, ..Default::default() }, 0.5);
+        assert_eq!((snapshot.fps, snapshot.bandwidth, snapshot.total_frames), (30.0, 16_000, 115));
+        assert_eq!(published.total_frames, 100);
+        // The periodic tick folds in the whole window, counted once
+        published.apply_window(&StatsWindow { frames: 30, bytes: 2000, ..Default::default() }, 1.0);
+        assert_eq!((published.fps, published.total_frames), (30.0, 130));
+    }
+
+    #[test]
+    fn disabled_clipboard_skips_the_deferred_selection_read() {
+        let state = state_with(Config::default());
+        assert_eq!(state.deferred_clipboard_read(Some("text/plain".into())).as_deref(), Some("text/plain"));
+        assert_eq!(state.deferred_clipboard_read(None), None);
+        let mut config = Config::default();
+        config.input.enable_clipboard = false;
+        let state = state_with(config);
+        assert_eq!(state.deferred_clipboard_read(Some("text/plain".into())), None);
+    }
+
+    fn resize_reply(state: &SharedState, session_id: &str, payload: &str) -> serde_json::Value {
+        let reply = state.handle_resize_request(session_id, payload);
+        serde_json::from_str(reply.strip_prefix("resize,").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn resize_requests_reply_with_the_applied_size() {
+        let mut config = Config::default();
+        config.display.max_width = 2560;
+        config.display.max_height = 1440;
+        let state = state_with(config);
+
+        let reply = resize_reply(&state, "s1", "1280x720");
+        assert_eq!((reply["width"].as_u64(), reply["height"].as_u64()), (Some(1280), Some(720)));
+        assert_eq!(reply["clamped"], false);
+
+        let reply = resize_reply(&state, "s1", "3840x2160");
+        assert_eq!((reply["width"].as_u64(), reply["height"].as_u64()), (Some(2560), Some(1440)));
+        assert_eq!(reply["requested"], serde_json::json!({ "width": 3840, "height": 2160 }));
+        assert_eq!(reply["clamped"], true);
+
+        for payload in ["3840", "wide x tall", "0x720"] {
+            let reply = resize_reply(&state, "s1", payload);
+            assert!(reply["error"].is_string(), "{}", payload);
+            assert_eq!(reply["max"], serde_json::json!({ "width": 2560, "height": 1440 }));
+            assert!(reply.get("width").is_none());
+        }
+    }
+
+    #[test]
+    fn resize_is_refused_to_sessions_without_control() {
+        let mut config = Config::default();
+        config.input.control_policy = crate::config::ControlPolicy::First;
+        let state = state_with(config);
+        state.update_control(|control| {
+            control.join("holder");
+            control.join("viewer");
+            control.request("holder")
+        });
+        let reply = resize_reply(&state, "viewer", "1280x720");
+        assert_eq!(reply["error"], "another session has input control");
+        assert_eq!(state.pending_resize.lock().unwrap().take(Instant::now(), Duration::ZERO), None);
+        assert_eq!(resize_reply(&state, "holder", "1280x720")["width"], 1280);
     }
 }
@@ -0,0 +1,129 @@
+//! Per-session congestion state from the egress bandwidth estimate
+//!
+//! A session flips to "constrained" only after its estimate has stayed below
+//! the low threshold for the hold time, and back only after it has stayed
+//! above the (higher) restore threshold for the hold time, so a noisy
+//! estimate hovering around one value cannot make the resolution flap.
+//!
+//! The estimate comes from the feedback selected by
+//! `webrtc.congestion_control`. The other mechanism is stripped from the
+//! browser's offer before it is answered, so the answer (and the browser)
+//! only ever use the selected one.
+
+use std::time::{Duration, Instant};
+
+use crate::config::{CongestionControl, WebRTCConfig};
+
+/// Drop the offer's lines for the feedback mechanism not selected by `cc`
+pub fn restrict_offer_feedback(offer_sdp: &str, cc: CongestionControl) -> String {
+    offer_sdp
+        .split_inclusive('\n')
+        .filter(|line| {
+            let line = line.trim_end();
+            match cc {
+                CongestionControl::TransportCc => !(line.starts_with("a=rtcp-fb:") && line.ends_with(" goog-remb")),
+                CongestionControl::GoogRemb => {
+                    !((line.starts_with("a=rtcp-fb:") && line.ends_with(" transport-cc"))
+                        || (line.starts_with("a=extmap:") && line.contains("transport-wide-cc")))
+                }
+            }
+        })
+        .collect()
+}
+
+pub struct CongestionTracker {
+    below_bps: u64,
+    above_bps: u64,
+    hold: Duration,
+    constrained: bool,
+    /// When the estimate first crossed toward the other state
+    crossing_since: Option<Instant>,
+}
+
+impl CongestionTracker {
+    pub fn new(config: &WebRTCConfig) -> Self {
+        Self {
+            below_bps: config.congestion_downscale_below_kbps as u64 * 1000,
+            above_bps: config.congestion_restore_above_kbps as u64 * 1000,
+            hold: Duration::from_secs(config.congestion_hold_secs),
+            constrained: false,
+            crossing_since: None,
+        }
+    }
+
+    /// Feed a new estimate; returns the new state when it changes
+    pub fn update(&mut self, estimate_bps: u64, now: Instant) -> Option<bool> {
+        let crossing = if self.constrained {
+            estimate_bps > self.above_bps
+        } else {
+            estimate_bps < self.below_bps
+        };
+        if !crossing {
+            self.crossing_since = None;
+            return None;
+        }
+        let since = *self.crossing_since.get_or_insert(now);
+        if now.duration_since(since) < self.hold {
+            return None;
+        }
+        self.constrained = !self.constrained;
+        self.crossing_since = None;
+        Some(self.constrained)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> CongestionTracker {
+        CongestionTracker::new(&WebRTCConfig {
+            congestion_downscale_below_kbps: 1500,
+            congestion_restore_above_kbps: 3000,
+            congestion_hold_secs: 5,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn switches_only_after_the_hold_time() {
+        let t0 = Instant::now();
+        let mut t = tracker();
+        assert_eq!(t.update(1_000_000, t0), None);
+        assert_eq!(t.update(1_000_000, t0 + Duration::from_secs(4)), None);
+        assert_eq!(t.update(1_000_000, t0 + Duration::from_secs(5)), Some(true));
+        assert_eq!(t.update(4_000_000, t0 + Duration::from_secs(6)), None);
+        assert_eq!(t.update(4_000_000, t0 + Duration::from_secs(11)), Some(false));
+    }
+
+    const OFFER: &str = "v=0\r\n\
+m=video 9 UDP/TLS/RTP/SAVPF 96\r\n\
+a=extmap:3 http://www.ietf.org/id/draft-holmer-rmcat-transport-wide-cc-extensions-01\r\n\
+a=rtpmap:96 H264/90000\r\n\
+a=rtcp-fb:96 goog-remb\r\n\
+a=rtcp-fb:96 transport-cc\r\n\
+a=rtcp-fb:96 nack\r\n";
+
+    #[test]
+    fn offer_keeps_only_the_selected_feedback() {
+        let twcc = restrict_offer_feedback(OFFER, CongestionControl::TransportCc);
+        assert!(!twcc.contains("goog-remb"));
+        assert!(twcc.contains("a=rtcp-fb:96 transport-cc\r\n") && twcc.contains("transport-wide-cc"));
+
+        let remb = restrict_offer_feedback(OFFER, CongestionControl::GoogRemb);
+        assert!(!remb.contains("transport-cc") && !remb.contains("transport-wide-cc"));
+        assert!(remb.contains("a=rtcp-fb:96 goog-remb\r\n") && remb.ends_with("a=rtcp-fb:96 nack\r\n"));
+    }
+
+    #[test]
+    fn estimate_between_thresholds_resets_the_hold() {
+        let t0 = Instant::now();
+        let mut t = tracker();
+        t.update(1_000_000, t0);
+        // Back in the dead band: the low period starts over
+        assert_eq!(t.update(2_000_000, t0 + Duration::from_secs(3)), None);
+        assert_eq!(t.update(1_000_000, t0 + Duration::from_secs(4)), None);
+        assert_eq!(t.update(1_000_000, t0 + Duration::from_secs(8)), None);
+        assert_eq!(t.update(1_000_000, t0 + Duration::from_secs(9)), Some(true));
+    }
+}
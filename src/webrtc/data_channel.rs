@@ -9,6 +9,43 @@
 use super::WebRTCError;
 use crate::input::{InputEvent, InputEventData};
 
+/// Version of the compact binary input protocol (negotiated with `bin_input,<version>`)
+pub const BINARY_INPUT_VERSION: u8 = 1;
+
+/// Binary input frame tags. Tag 0x01 is reserved for file upload chunks.
+pub const BIN_MOUSE_MOVE: u8 = 0x10;
+pub const BIN_MOUSE_MOVE_RELATIVE: u8 = 0x11;
+pub const BIN_MOUSE_BUTTON: u8 = 0x12;
+pub const BIN_MOUSE_WHEEL: u8 = 0x13;
+pub const BIN_KEY: u8 = 0x14;
+
+/// Stream a `audio,on|off` / `video,on|off` message toggles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaToggle {
+    Audio,
+    Video,
+}
+
+/// Parse a per-session media enable message into the stream and its new state
+pub fn parse_media_toggle(text: &str) -> Option<(MediaToggle, bool)> {
+    let (kind, state) = text.split_once(',')?;
+    let kind = match kind {
+        "audio" => MediaToggle::Audio,
+        "video" => MediaToggle::Video,
+        _ => return None,
+    };
+    match state {
+        "on" => Some((kind, true)),
+        "off" => Some((kind, false)),
+        _ => None,
+    }
+}
+
+/// Returns true if a binary DataChannel frame carries an input event
+pub fn is_binary_input_frame(data: &[u8]) -> bool {
+    matches!(data.first(), Some(&(BIN_MOUSE_MOVE..=BIN_KEY)))
+}
+
 /// Input message parser for DataChannel text protocol.
 ///
 /// This is a stateless parser — the actual DataChannel lifecycle
@@ -21,6 +58,7 @@ impl InputDataChannel {
     /// Supports the same protocol as WebSocket:
     /// - Mouse move: `m,x,y` or `m,x,y,buttons`
     /// - Relative mouse: `m2,dx,dy,buttons,0`
+    /// - Cursor warp: `mw,x,y` (absolute, keeps the button state)
     /// - Mouse button: `b,button,pressed`
     /// - Mouse wheel: `w,dx,dy`
     /// - Keyboard: `k,keysym,pressed`
@@ -68,6 +106,18 @@ impl InputDataChannel {
                 }
             }
 
+            "mw" => {
+                if parts.len() < 3 {
+                    return Err(WebRTCError::DataChannelError("Invalid cursor warp format".to_string()));
+                }
+                event.event_type = InputEvent::MouseMove;
+                event.mouse_x = parts[1].parse()
+                    .map_err(|_| WebRTCError::DataChannelError("Invalid mouse X".to_string()))?;
+                event.mouse_y = parts[2].parse()
+                    .map_err(|_| WebRTCError::DataChannelError("Invalid mouse Y".to_string()))?;
+                event.text = "warp".to_string();
+            }
+
             "b" => {
                 if parts.len() < 3 {
                     return Err(WebRTCError::DataChannelError("Invalid mouse button format".to_string()));
@@ -167,6 +217,71 @@ impl InputDataChannel {
 
         Ok(event)
     }
+
+    /// Parse a compact binary input frame (all integers little-endian)
+    ///
+    /// - Mouse move: `0x10, x:i32, y:i32, buttons:u32`
+    /// - Relative mouse: `0x11, dx:i32, dy:i32, buttons:u32`
+    /// - Mouse button: `0x12, button:u8, pressed:u8`
+    /// - Mouse wheel: `0x13, dx:i16, dy:i16`
+    /// - Keyboard: `0x14, keysym:u32, pressed:u8`
+    pub fn parse_input_binary(data: &[u8]) -> Result<InputEventData, WebRTCError> {
+        let (&tag, body) = data
+            .split_first()
+            .ok_or_else(|| WebRTCError::DataChannelError("Empty binary input frame".to_string()))?;
+
+        let expected = match tag {
+            BIN_MOUSE_MOVE | BIN_MOUSE_MOVE_RELATIVE => 12,
+            BIN_MOUSE_BUTTON => 2,
+            BIN_MOUSE_WHEEL => 4,
+            BIN_KEY => 5,
+            _ => {
+                return Err(WebRTCError::DataChannelError(format!("Unknown binary input tag: {:#04x}", tag)));
+            }
+        };
+        if body.len() != expected {
+            return Err(WebRTCError::DataChannelError(format!(
+                "Binary input frame {:#04x} has {} bytes, expected {}",
+                tag,
+                body.len(),
+                expected
+            )));
+        }
+
+        let i32_at = |i: usize| i32::from_le_bytes([body[i], body[i + 1], body[i + 2], body[i + 3]]);
+        let u32_at = |i: usize| u32::from_le_bytes([body[i], body[i + 1], body[i + 2], body[i + 3]]);
+        let i16_at = |i: usize| i16::from_le_bytes([body[i], body[i + 1]]);
+
+        let mut event = InputEventData::default();
+        match tag {
+            BIN_MOUSE_MOVE | BIN_MOUSE_MOVE_RELATIVE => {
+                event.event_type = InputEvent::MouseMove;
+                event.mouse_x = i32_at(0);
+                event.mouse_y = i32_at(4);
+                event.button_mask = u32_at(8);
+                if tag == BIN_MOUSE_MOVE_RELATIVE {
+                    event.text = "relative".to_string();
+                }
+            }
+            BIN_MOUSE_BUTTON => {
+                event.event_type = InputEvent::MouseButton;
+                event.mouse_button = body[0];
+                event.button_pressed = body[1] != 0;
+            }
+            BIN_MOUSE_WHEEL => {
+                event.event_type = InputEvent::MouseWheel;
+                event.wheel_delta_x = i16_at(0);
+                event.wheel_delta_y = i16_at(2);
+            }
+            _ => {
+                event.event_type = InputEvent::Keyboard;
+                event.keysym = u32_at(0);
+                event.key_pressed = body[4] != 0;
+            }
+        }
+
+        Ok(event)
+    }
 }
 
 /// Format an outgoing message for the DataChannel
@@ -178,6 +293,15 @@ pub fn format_output_message(msg_type: &str, data: &str) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn parse_media_toggles() {
+        assert_eq!(parse_media_toggle("audio,off"), Some((MediaToggle::Audio, false)));
+        assert_eq!(parse_media_toggle("video,on"), Some((MediaToggle::Video, true)));
+        assert_eq!(parse_media_toggle("video,pause"), None);
+        assert_eq!(parse_media_toggle("audio"), None);
+        assert_eq!(parse_media_toggle("m,1,2"), None);
+    }
+
     #[test]
     fn test_parse_mouse_move() {
         let event = InputDataChannel::parse_input_text("m,100,200").unwrap();
@@ -193,6 +317,15 @@ mod tests {
         assert_eq!(event.button_mask, 3);
     }
 
+    #[test]
+    fn test_parse_cursor_warp() {
+        let event = InputDataChannel::parse_input_text("mw,640,360").unwrap();
+        assert_eq!(event.event_type, InputEvent::MouseMove);
+        assert_eq!((event.mouse_x, event.mouse_y), (640, 360));
+        assert_eq!(event.text, "warp");
+        assert!(InputDataChannel::parse_input_text("mw,640").is_err());
+    }
+
     #[test]
     fn test_parse_mouse_button() {
         let event = InputDataChannel::parse_input_text("b,1,1").unwrap();
@@ -231,4 +364,36 @@ mod tests {
         assert_eq!(event.event_type, InputEvent::TextInput);
         assert_eq!(event.text, "hello,world");
     }
+
+    #[test]
+    fn test_parse_binary_mouse_move() {
+        let mut frame = vec![BIN_MOUSE_MOVE];
+        frame.extend_from_slice(&100i32.to_le_bytes());
+        frame.extend_from_slice(&(-5i32).to_le_bytes());
+        frame.extend_from_slice(&3u32.to_le_bytes());
+        assert!(is_binary_input_frame(&frame));
+        let event = InputDataChannel::parse_input_binary(&frame).unwrap();
+        assert_eq!(event.event_type, InputEvent::MouseMove);
+        assert_eq!(event.mouse_x, 100);
+        assert_eq!(event.mouse_y, -5);
+        assert_eq!(event.button_mask, 3);
+        assert!(event.text.is_empty());
+    }
+
+    #[test]
+    fn test_parse_binary_key() {
+        let mut frame = vec![BIN_KEY];
+        frame.extend_from_slice(&0xff08u32.to_le_bytes());
+        frame.push(1);
+        let event = InputDataChannel::parse_input_binary(&frame).unwrap();
+        assert_eq!(event.event_type, InputEvent::Keyboard);
+        assert_eq!(event.keysym, 0xff08);
+        assert!(event.key_pressed);
+    }
+
+    #[test]
+    fn test_parse_binary_rejects_bad_length() {
+        assert!(InputDataChannel::parse_input_binary(&[BIN_MOUSE_BUTTON, 1]).is_err());
+        assert!(!is_binary_input_frame(&[0x01, 0, 0]));
+    }
 }
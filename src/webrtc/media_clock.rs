@@ -0,0 +1,198 @@
+//! Common capture clock for audio/video sync
+//!
+//! str0m builds the RTCP sender reports of a stream from the wallclock
+//! passed with each RTP packet, giving the browser the NTP↔RTP mapping it
+//! needs to line tracks up. Audio and video RTP timestamps have unrelated
+//! origins, so both tracks pass the time their media was *captured* on the
+//! same monotonic clock (`Instant`), not the time the packet happens to be
+//! written: audio packets carry the capture time of their first sample.
+//! Video RTP timestamps are the encoder pipeline's running time at 90 kHz,
+//! so the first frame pushed into a pipeline anchors the mapping for its
+//! whole lifetime; frames the encoder drops cannot shift it. Encode latency
+//! then shows up as a constant offset the browser compensates for, instead
+//! of as drift.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::media_track::rtp_util;
+
+/// RTP clock rate of all video codecs
+pub const VIDEO_CLOCK_RATE: u32 = 90_000;
+
+/// An RTP timestamp known to have been captured at a given instant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtpClock {
+    rtp: u32,
+    at: Instant,
+    rate: u32,
+}
+
+impl RtpClock {
+    pub fn new(rtp: u32, at: Instant, rate: u32) -> Self {
+        Self { rtp, at, rate }
+    }
+
+    /// Capture time of `timestamp`, extrapolated from this anchor. Handles
+    /// wraparound and timestamps before the anchor (up to half the range).
+    pub fn instant_of(&self, timestamp: u32) -> Instant {
+        let delta = rtp_util::timestamp_delta(self.rtp, timestamp);
+        let offset = Duration::from_secs_f64(delta.unsigned_abs() as f64 / self.rate as f64);
+        if delta >= 0 {
+            self.at + offset
+        } else {
+            self.at.checked_sub(offset).unwrap_or(self.at)
+        }
+    }
+}
+
+/// RTP timestamp the video payloader gives a buffer with running time
+/// `running_time_ns`: it runs at 90 kHz from running time zero, with no
+/// random offset
+pub fn video_rtp_timestamp(running_time_ns: u64) -> u32 {
+    (running_time_ns as u128 * VIDEO_CLOCK_RATE as u128 / 1_000_000_000) as u32
+}
+
+/// Push times of frames still in the encoder, keyed by the RTP timestamp
+/// they come out with. The encoder may drop frames, so packets are matched
+/// by timestamp rather than by order.
+///
+/// The first push also anchors the pipeline's capture clock; `clear` starts
+/// over for a new pipeline.
+#[derive(Debug)]
+pub struct PushTimes {
+    entries: VecDeque<(u32, Instant)>,
+    capacity: usize,
+    anchor: Option<RtpClock>,
+}
+
+impl PushTimes {
+    pub fn new(capacity: usize) -> Self {
+        Self { entries: VecDeque::with_capacity(capacity), capacity, anchor: None }
+    }
+
+    /// Note a frame pushed at `at` that will carry timestamp `rtp`. True if
+    /// it is the pipeline's first and anchored its clock.
+    pub fn push(&mut self, rtp: u32, at: Instant) -> bool {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((rtp, at));
+        if self.anchor.is_some() {
+            return false;
+        }
+        self.anchor = Some(RtpClock::new(rtp, at, VIDEO_CLOCK_RATE));
+        true
+    }
+
+    /// Capture clock of the current pipeline, once a frame was pushed
+    pub fn clock(&self) -> Option<RtpClock> {
+        self.anchor
+    }
+
+    /// Push time of the frame with timestamp `rtp`. Frames pushed before
+    /// it that never came out were dropped by the encoder and are
+    /// forgotten.
+    pub fn take(&mut self, rtp: u32) -> Option<Instant> {
+        while let Some(&(ts, at)) = self.entries.front() {
+            match rtp_util::timestamp_delta(ts, rtp) {
+                0 => {
+                    self.entries.pop_front();
+                    return Some(at);
+                }
+                delta if delta > 0 => {
+                    self.entries.pop_front();
+                }
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// Forget everything, for a new pipeline
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.anchor = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_timestamps_across_wraparound() {
+        let at = Instant::now() + Duration::from_secs(10);
+        let clock = RtpClock::new(u32::MAX - 8_999, at, VIDEO_CLOCK_RATE);
+        assert_eq!(clock.instant_of(u32::MAX - 8_999), at);
+        // 0.2 s later, past the wrap
+        assert_eq!(clock.instant_of(9_000), at + Duration::from_millis(200));
+        assert_eq!(clock.instant_of(u32::MAX - 17_999), at - Duration::from_millis(100));
+    }
+
+    #[test]
+    fn payloader_timestamps_follow_running_time() {
+        assert_eq!(video_rtp_timestamp(0), 0);
+        assert_eq!(video_rtp_timestamp(1_000_000_000), 90_000);
+        // One 30 fps frame, truncated like the payloader does
+        assert_eq!(video_rtp_timestamp(33_333_333), 2_999);
+        // Past about 13.25 hours the timestamp wraps
+        assert_eq!(video_rtp_timestamp(50_000 * 1_000_000_000), 205_032_704);
+    }
+
+    #[test]
+    fn push_times_survive_dropped_frames() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut times = PushTimes::new(8);
+        for (i, rtp) in [0u32, 3_000, 6_000, 9_000].into_iter().enumerate() {
+            times.push(rtp, at(i as u64 * 33));
+        }
+        // The encoder dropped the frame at 3000
+        assert_eq!(times.take(0), Some(at(0)));
+        assert_eq!(times.take(6_000), Some(at(66)));
+        // A frame whose push is unknown leaves later entries alone
+        assert_eq!(times.take(7_500), None);
+        assert_eq!(times.take(9_000), Some(at(99)));
+        assert_eq!(times.take(9_000), None);
+    }
+
+    #[test]
+    fn push_times_keep_the_newest_entries() {
+        let start = Instant::now();
+        let mut times = PushTimes::new(2);
+        times.push(1, start);
+        times.push(2, start);
+        times.push(3, start);
+        assert_eq!(times.take(1), None);
+        assert_eq!(times.take(2), Some(start));
+    }
+
+    #[test]
+    fn capture_mapping_stays_monotonic_across_dropped_frames() {
+        let start = Instant::now();
+        let mut times = PushTimes::new(16);
+        // 30 fps, each frame stamped with the running time it was pushed at
+        let frames: Vec<(u32, Instant)> = (0..12u64)
+            .map(|n| (video_rtp_timestamp(n * 33_333_333), start + Duration::from_nanos(n * 33_333_333)))
+            .collect();
+        for (n, &(rtp, at)) in frames.iter().enumerate() {
+            assert_eq!(times.push(rtp, at), n == 0);
+        }
+        let clock = times.clock().unwrap();
+        // The encoder dropped frames 2, 3 and 7
+        let mut last = None;
+        for (_, &(rtp, at)) in frames.iter().enumerate().filter(|(n, _)| ![2, 3, 7].contains(n)) {
+            assert_eq!(times.take(rtp), Some(at));
+            let mapped = clock.instant_of(rtp);
+            // Within one 90 kHz tick of the push
+            assert!(mapped.max(at) - mapped.min(at) < Duration::from_micros(12));
+            assert!(last.map_or(true, |last| mapped > last));
+            last = Some(mapped);
+        }
+        // A new pipeline gets a new anchor
+        times.clear();
+        assert_eq!(times.clock(), None);
+        assert!(times.push(0, start + Duration::from_secs(1)));
+    }
+}
@@ -23,6 +23,12 @@ pub mod rtp_util {
         Some(u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]))
     }
 
+    /// Signed distance from timestamp `from` to `to` (RFC 3550 serial
+    /// arithmetic): correct across the 32-bit wrap for gaps under 2^31
+    pub fn timestamp_delta(from: u32, to: u32) -> i32 {
+        to.wrapping_sub(from) as i32
+    }
+
     /// Extract SSRC from RTP packet
     pub fn get_ssrc(packet: &[u8]) -> Option<u32> {
         if packet.len() < 12 {
@@ -79,9 +85,91 @@ pub mod rtp_util {
     }
 }
 
+/// Collects the packets of the latest keyframe for the keyframe cache
+#[derive(Debug, Default)]
+pub struct KeyframeAssembler {
+    packets: Vec<Vec<u8>>,
+    /// RTP timestamp of the keyframe being collected
+    timestamp: Option<u32>,
+}
+
+impl KeyframeAssembler {
+    /// Feed one outgoing packet; returns the keyframe once its last packet
+    /// (marker bit) goes out. A keyframe whose timestamp ends without a
+    /// marker may be missing packets and is dropped.
+    pub fn push(&mut self, packet: &[u8], is_keyframe: bool) -> Option<Vec<Vec<u8>>> {
+        let ts = rtp_util::get_timestamp(packet)?;
+        if self.timestamp.is_some_and(|t| t != ts) {
+            log::debug!("Dropping incomplete keyframe ({} packets)", self.packets.len());
+            self.reset();
+        }
+        if self.timestamp.is_none() {
+            if !is_keyframe {
+                return None;
+            }
+            self.timestamp = Some(ts);
+        }
+        self.packets.push(packet.to_vec());
+        if rtp_util::is_marker_set(packet) {
+            self.timestamp = None;
+            return Some(std::mem::take(&mut self.packets));
+        }
+        None
+    }
+
+    /// Forget a half-collected keyframe (e.g. the encoded size changed)
+    pub fn reset(&mut self) {
+        self.packets.clear();
+        self.timestamp = None;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::rtp_util::*;
+    use super::KeyframeAssembler;
+
+    fn packet(ts: u32, marker: bool) -> Vec<u8> {
+        let mut p = vec![0x80, if marker { 0xE0 } else { 0x60 }, 0, 1];
+        p.extend_from_slice(&ts.to_be_bytes());
+        p.extend_from_slice(&[0x12, 0x34, 0x56, 0x78, 0x65]);
+        p
+    }
+
+    #[test]
+    fn timestamps_compare_across_the_wrap() {
+        assert_eq!(timestamp_delta(u32::MAX - 1499, 1500), 3000);
+        assert_eq!(timestamp_delta(1500, u32::MAX - 1499), -3000);
+        assert_eq!(timestamp_delta(7, 7), 0);
+    }
+
+    #[test]
+    fn keyframe_assembly_spans_the_wrap() {
+        let mut kf = KeyframeAssembler::default();
+        assert_eq!(kf.push(&packet(u32::MAX, false), true), None);
+        assert_eq!(kf.push(&packet(u32::MAX, true), true).map(|p| p.len()), Some(2));
+        // The first frame after the wrap starts a new keyframe
+        assert_eq!(kf.push(&packet(0, false), true), None);
+        assert_eq!(kf.push(&packet(0, true), false).map(|p| p.len()), Some(2));
+        // And a partial one right before the wrap is still dropped
+        assert_eq!(kf.push(&packet(u32::MAX - 2999, false), true), None);
+        assert_eq!(kf.push(&packet(2, true), false), None);
+    }
+
+    #[test]
+    fn partial_keyframe_is_never_cached() {
+        let mut kf = KeyframeAssembler::default();
+        // Keyframe cut short by a flush: its timestamp ends without a marker
+        assert_eq!(kf.push(&packet(1000, false), true), None);
+        assert_eq!(kf.push(&packet(1000, false), true), None);
+        assert_eq!(kf.push(&packet(4000, false), false), None);
+        assert_eq!(kf.push(&packet(4000, true), false), None);
+
+        // A complete one, including its non-IDR fragments, is returned
+        assert_eq!(kf.push(&packet(7000, false), true), None);
+        let complete = kf.push(&packet(7000, true), false).unwrap();
+        assert_eq!(complete, vec![packet(7000, false), packet(7000, true)]);
+    }
 
     #[test]
     fn test_rtp_parsing() {
@@ -6,7 +6,9 @@
 //! - RTP video/audio transmission
 //! - DataChannel for input events
 
+pub mod congestion;
 pub mod data_channel;
+pub mod media_clock;
 pub mod media_track;
 pub mod rtc_session;
 pub mod session;
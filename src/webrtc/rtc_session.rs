@@ -5,10 +5,13 @@
 //! text forwarding through a single event loop.
 
 use super::tcp_framing::{frame_packet, TcpFrameDecoder};
-use super::data_channel::InputDataChannel;
+use super::data_channel::{self, InputDataChannel, MediaToggle};
+use super::media_clock::RtpClock;
 use super::media_track::rtp_util;
+use super::congestion::{self, CongestionTracker};
 use super::WebRTCError;
 use crate::clipboard::ClipboardReceiver;
+use crate::config::{CongestionControl, VideoCodec, WebRTCConfig};
 use crate::file_upload::FileUploadHandler;
 use crate::input::{InputEvent, InputEventData};
 use crate::runtime_settings::RuntimeSettings;
@@ -24,12 +27,40 @@ use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 
 use str0m::channel::{ChannelData, ChannelId};
-use str0m::media::{MediaKind, Mid, Pt};
+use str0m::config::DtlsCert;
+use str0m::format::{Codec, FormatParams};
+use str0m::media::{Frequency, MediaKind, Mid, Pt};
 use str0m::net::{self, Protocol};
 use str0m::rtp::SeqNo;
+use str0m::bwe::{Bitrate, BweKind};
 use str0m::{Candidate, Event, IceConnectionState, Input, Output, Rtc};
 use str0m::change::SdpOffer;
 
+/// Opus PT in our codec config; an answer takes the offer's PT instead
+const OPUS_PT: u8 = 111;
+
+/// Payload types the answer settled on for the codecs we send
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NegotiatedPts {
+    /// PT of the configured video codec
+    pub video: Option<Pt>,
+    /// Opus PT
+    pub audio: Option<Pt>,
+}
+
+impl NegotiatedPts {
+    /// PT every packet of `kind` is written with. None when the answer has
+    /// no matching codec: the media is then not sent at all, since any
+    /// guessed PT (GStreamer's 96, Opus' usual 111) may belong to another
+    /// codec in this session.
+    pub fn pt_for(&self, kind: MediaKind) -> Option<Pt> {
+        match kind {
+            MediaKind::Video => self.video,
+            MediaKind::Audio => self.audio,
+        }
+    }
+}
+
 /// A single str0m WebRTC session bound to a TCP connection.
 pub struct RtcSession {
     /// Unique session ID
@@ -42,26 +73,79 @@ pub struct RtcSession {
     pub audio_mid: Option<Mid>,
     /// DataChannel ID for input
     pub dc_id: Option<ChannelId>,
-    /// Negotiated video payload type (discovered from SDP, e.g. H264 PT)
-    video_pt: Option<Pt>,
-    /// Negotiated audio payload type (discovered from SDP)
-    audio_pt: Option<Pt>,
+    /// Negotiated payload types, the only ones media is written with
+    pts: NegotiatedPts,
     /// Whether the session is connected
     pub connected: bool,
-    /// RTP sequence counter for video (str0m RTP mode needs us to supply seq)
+    /// RTP sequence counter for video (str0m RTP mode needs us to supply
+    /// seq). Extended sequence numbers: str0m puts the low 16 bits on the
+    /// wire, so the counter itself never wraps.
     video_seq: u64,
-    /// RTP sequence counter for audio
+    /// RTP sequence counter for audio, extended like `video_seq`
     audio_seq: u64,
+    /// Client negotiated the compact binary input protocol (`bin_input,1`)
+    binary_input: bool,
+    /// When to replay the cached keyframe (set once DTLS/SRTP is up)
+    keyframe_replay_at: Option<Instant>,
+    /// Bandwidth-estimate hysteresis (None when congestion downscale is off)
+    congestion: Option<CongestionTracker>,
+    /// Feedback mechanism negotiated for the bandwidth estimate
+    congestion_control: CongestionControl,
+    /// Client wants audio forwarded (`audio,on|off`)
+    audio_enabled: bool,
+    /// Client wants video forwarded (`video,on|off`)
+    video_enabled: bool,
+    /// Codec the encoder produces, to pick its PT from the answer
+    video_codec: VideoCodec,
+    /// The VP9 stream is 10-bit (profile 2) rather than profile 0
+    vp9_profile2: bool,
+    /// Host header the client signaled through, for its per-host UI config
+    client_host: Option<String>,
 }
 
 impl RtcSession {
     /// Create a new RtcSession with str0m configured for ICE-lite + RTP mode.
-    pub fn new(id: String) -> Self {
+    /// `audio_fec` is signalled as `useinbandfec` in the Opus fmtp;
+    /// `video_bit_depth` is what the encoder actually produces.
+    pub fn new(
+        id: String,
+        dtls_cert: Option<DtlsCert>,
+        config: &WebRTCConfig,
+        audio_fec: bool,
+        video_bit_depth: u8,
+    ) -> Self {
         let now = Instant::now();
-        let rtc = Rtc::builder()
+        let mut builder = Rtc::builder()
             .set_ice_lite(true)
-            .set_rtp_mode(true)
-            .build(now);
+            .set_rtp_mode(true);
+        // Bandwidth estimation is only needed to drive congestion downscale.
+        // str0m's own estimator runs on transport-cc feedback; with REMB the
+        // browser's estimate is used as is.
+        let congestion = if config.congestion_downscale {
+            if config.congestion_control == CongestionControl::TransportCc {
+                builder = builder.enable_bwe(Some(Bitrate::kbps(config.video_bitrate as u64)));
+            }
+            Some(CongestionTracker::new(config))
+        } else {
+            None
+        };
+        // A persisted certificate keeps the SDP fingerprint stable;
+        // otherwise str0m generates a fresh one per session
+        if let Some(cert) = dtls_cert {
+            builder = builder.set_dtls_cert(cert);
+        }
+        // str0m's default Opus entry, but with useinbandfec following
+        // audio.fec instead of always on
+        builder = builder.enable_opus(false);
+        builder.codec_config().add_config(
+            Pt::new_with_value(OPUS_PT),
+            None,
+            Codec::Opus,
+            Frequency::FORTY_EIGHT_KHZ,
+            Some(2),
+            FormatParams { min_p_time: Some(10), use_inband_fec: Some(audio_fec), ..Default::default() },
+        );
+        let rtc = builder.build(now);
 
         Self {
             id,
@@ -69,11 +153,19 @@ impl RtcSession {
             video_mid: None,
             audio_mid: None,
             dc_id: None,
-            video_pt: None,
-            audio_pt: None,
+            pts: NegotiatedPts::default(),
             connected: false,
             video_seq: 0,
             audio_seq: 0,
+            binary_input: false,
+            keyframe_replay_at: None,
+            congestion,
+            congestion_control: config.congestion_control,
+            audio_enabled: true,
+            video_enabled: true,
+            video_codec: config.video_codec,
+            vp9_profile2: config.video_codec == VideoCodec::VP9 && video_bit_depth == 10,
+            client_host: None,
         }
     }
 
@@ -92,7 +184,8 @@ impl RtcSession {
 
     /// Accept an SDP offer and return the SDP answer string.
     pub fn accept_offer(&mut self, offer_sdp: &str) -> Result<String, WebRTCError> {
-        let offer = SdpOffer::from_sdp_string(offer_sdp)
+        let offer_sdp = congestion::restrict_offer_feedback(offer_sdp, self.congestion_control);
+        let offer = SdpOffer::from_sdp_string(&offer_sdp)
             .map_err(|e| WebRTCError::SdpError(format!("Failed to parse SDP offer: {}", e)))?;
 
         let answer = self.rtc.sdp_api().accept_offer(offer)
@@ -102,8 +195,62 @@ impl RtcSession {
         Ok(answer.to_sdp_string())
     }
 
+    /// Read the negotiated payload types from the codec config in one pass.
+    /// Only meaningful after `accept_offer`.
+    pub fn discover_pts(&self) -> NegotiatedPts {
+        use str0m::format::Codec;
+        let video_codec = match self.video_codec {
+            VideoCodec::H264 => Codec::H264,
+            VideoCodec::VP8 => Codec::Vp8,
+            VideoCodec::VP9 => Codec::Vp9,
+            VideoCodec::AV1 => Codec::Av1,
+        };
+        // VP9 goes out on a PT negotiated for the profile actually encoded
+        let vp9_profile = if self.vp9_profile2 { 2 } else { 0 };
+        let mut other_vp9 = None;
+        let mut pts = NegotiatedPts::default();
+        for p in self.rtc.codec_config().params() {
+            let spec = p.spec();
+            match spec.codec {
+                Codec::Vp9 if video_codec == Codec::Vp9 => {
+                    if spec.format.profile_id.unwrap_or(0) != vp9_profile {
+                        other_vp9 = other_vp9.or(Some(p.pt()));
+                    } else if pts.video.is_none() {
+                        pts.video = Some(p.pt());
+                    }
+                }
+                c if c == video_codec && pts.video.is_none() => pts.video = Some(p.pt()),
+                Codec::Opus if pts.audio.is_none() => pts.audio = Some(p.pt()),
+                _ => {}
+            }
+        }
+        if pts.video.is_none() && other_vp9.is_some() {
+            warn!("Session {} did not negotiate VP9 profile {}, sending it anyway", self.id, vp9_profile);
+            pts.video = other_vp9;
+        }
+        pts
+    }
+
+    /// Use payload types discovered earlier (possibly for another session
+    /// with the same offer codec section)
+    pub fn set_negotiated_pts(&mut self, pts: NegotiatedPts) {
+        self.pts = pts;
+    }
+
+    /// Payload types media is written with
+    pub fn negotiated_pts(&self) -> NegotiatedPts {
+        self.pts
+    }
+
+    /// Remember the Host header the session was signaled through
+    pub fn set_client_host(&mut self, host: Option<&str>) {
+        self.client_host = host.map(str::to_string);
+    }
+
     /// Write a video RTP packet from GStreamer into str0m.
-    pub fn write_video_rtp(&mut self, rtp_data: &[u8]) -> Result<(), WebRTCError> {
+    /// `clock` maps the timestamp to its capture time for sender reports;
+    /// without one the packet is stamped with the current time.
+    pub fn write_video_rtp(&mut self, rtp_data: &[u8], clock: Option<RtpClock>) -> Result<(), WebRTCError> {
         let mid = match self.video_mid {
             Some(mid) => mid,
             None => return Ok(()), // Not yet negotiated
@@ -116,9 +263,8 @@ impl RtcSession {
         // Use the negotiated PT from SDP, not the GStreamer-assigned one.
         // GStreamer uses PT 96 by default for H.264, but str0m may have
         // mapped PT 96 to VP8 — we must use the actual H.264 PT from SDP.
-        let pt = match self.video_pt {
-            Some(pt) => pt,
-            None => Pt::new_with_value(rtp_util::get_payload_type(rtp_data).unwrap_or(96)),
+        let Some(pt) = self.pts.pt_for(MediaKind::Video) else {
+            return Ok(());
         };
         let marker = rtp_util::is_marker_set(rtp_data);
         let timestamp = rtp_util::get_timestamp(rtp_data).unwrap_or(0);
@@ -137,14 +283,14 @@ impl RtcSession {
                 pt,
                 seq,
                 timestamp,
-                Instant::now(),
+                clock.map_or_else(Instant::now, |c| c.instant_of(timestamp)),
                 marker,
                 str0m::rtp::ExtensionValues::default(),
                 true, // nackable
                 payload,
             );
             if self.video_seq == 1 {
-                info!("Session {} first write_rtp pt={:?} ok={}", self.id, pt, result.is_ok());
+                info!("Session {} first write_rtp pt={:?} ssrc={:?} ok={}", self.id, pt, stream_tx.ssrc(), result.is_ok());
             }
         } else if self.video_seq == 1 {
             warn!("Session {} stream_tx_by_mid({:?}) returned None", self.id, mid);
@@ -154,10 +300,17 @@ impl RtcSession {
     }
 
     /// Write an audio RTP packet (Opus) into str0m.
-    pub fn write_audio_rtp(&mut self, opus_data: &[u8], timestamp: u32) -> Result<(), WebRTCError> {
-        let mid = match self.audio_mid {
-            Some(mid) => mid,
-            None => return Ok(()),
+    /// Set `marker` on the first packet after frames suppressed by DTX.
+    /// `captured_at` goes into the sender reports, on the same clock as video.
+    pub fn write_audio_rtp(
+        &mut self,
+        opus_data: &[u8],
+        timestamp: u32,
+        captured_at: Instant,
+        marker: bool,
+    ) -> Result<(), WebRTCError> {
+        let (Some(mid), Some(pt)) = (self.audio_mid, self.pts.pt_for(MediaKind::Audio)) else {
+            return Ok(());
         };
 
         let seq_no = SeqNo::from(self.audio_seq);
@@ -165,15 +318,18 @@ impl RtcSession {
 
         if let Some(stream_tx) = self.rtc.direct_api().stream_tx_by_mid(mid, None) {
             let _ = stream_tx.write_rtp(
-                self.audio_pt.unwrap_or(Pt::new_with_value(111)),
+                pt,
                 seq_no,
                 timestamp,
-                Instant::now(),
-                false, // continuous audio stream, no silence suppression
+                captured_at,
+                marker, // start of a talkspurt (RFC 7587)
                 str0m::rtp::ExtensionValues::default(),
                 false, // not nackable for audio
                 opus_data.to_vec(),
             );
+            if self.audio_seq == 1 {
+                info!("Session {} first audio write_rtp pt={:?} ssrc={:?}", self.id, pt, stream_tx.ssrc());
+            }
         }
 
         Ok(())
@@ -221,6 +377,16 @@ pub async fn drive_session(
     let session_id = session.id.clone();
     info!("Session {} drive loop started (peer: {})", session_id, peer_addr);
 
+    if !shared_state.hooks.on_session_connected(&session_id, peer_addr) {
+        info!("Session {} refused by hook", session_id);
+        shared_state.decrement_webrtc_sessions();
+        return;
+    }
+    shared_state.emit_event("session_connected", serde_json::json!({
+        "session_id": session_id,
+        "peer": peer_addr.to_string(),
+    }));
+
     // Disable Nagle's algorithm for low-latency RTP delivery
     if let Err(e) = tcp_stream.set_nodelay(true) {
         warn!("Session {} failed to set TCP_NODELAY: {}", session_id, e);
@@ -229,6 +395,7 @@ pub async fn drive_session(
     let last_pong = Arc::new(AtomicU64::new(now_millis()));
     let ctx = EventContext {
         input_tx: &input_tx,
+        session_id: Arc::from(session_id.as_str()),
         upload_handler: &upload_handler,
         clipboard: &clipboard,
         runtime_settings: &runtime_settings,
@@ -263,12 +430,20 @@ pub async fn drive_session(
         Ok(t) => next_timeout = t,
         Err(e) => {
             error!("Session {} initial drain failed: {}", session_id, e);
+            shared_state.hooks.on_session_closed(&session_id);
+            shared_state.emit_event("session_closed", serde_json::json!({ "session_id": session_id }));
             return;
         }
     }
+    // Control can be requested by or granted to this session from now on
+    shared_state.control.lock().unwrap().join(&session_id);
 
     loop {
-        let delay = next_timeout.saturating_duration_since(Instant::now());
+        let wake_at = match session.keyframe_replay_at {
+            Some(at) => next_timeout.min(at),
+            None => next_timeout,
+        };
+        let delay = wake_at.saturating_duration_since(Instant::now());
         let mut fatal = false;
 
         tokio::select! {
@@ -325,11 +500,12 @@ pub async fn drive_session(
             // Video RTP from GStreamer → str0m
             result = rtp_rx.recv() => {
                 match result {
-                    Some(pkt) if session.connected => {
-                        let _ = session.write_video_rtp(&pkt);
+                    Some(pkt) if session.connected && session.video_enabled => {
+                        let clock = shared_state.video_clock();
+                        let _ = session.write_video_rtp(&pkt, clock);
                         // Drain all pending RTP packets in one go
                         while let Ok(pkt) = rtp_rx.try_recv() {
-                            let _ = session.write_video_rtp(&pkt);
+                            let _ = session.write_video_rtp(&pkt, clock);
                         }
                     }
                     Some(_) => {}
@@ -340,12 +516,21 @@ pub async fn drive_session(
             // Audio RTP → str0m
             result = audio_rx.recv() => {
                 match result {
+                    // While muted the timestamp keeps advancing, so audio
+                    // resumes in sync with video
                     Some(pkt) if session.connected => {
-                        let _ = session.write_audio_rtp(&pkt.data, audio_timestamp);
-                        audio_timestamp = audio_timestamp.wrapping_add(samples_per_frame);
                         // Drain all pending audio packets in one go
-                        while let Ok(pkt) = audio_rx.try_recv() {
-                            let _ = session.write_audio_rtp(&pkt.data, audio_timestamp);
+                        for pkt in std::iter::once(pkt).chain(std::iter::from_fn(|| audio_rx.try_recv().ok())) {
+                            // Frames suppressed by DTX still take up time
+                            audio_timestamp = audio_timestamp.wrapping_add(pkt.gap_frames.wrapping_mul(samples_per_frame));
+                            if session.audio_enabled {
+                                let _ = session.write_audio_rtp(
+                                    &pkt.data,
+                                    audio_timestamp,
+                                    pkt.captured_at,
+                                    pkt.gap_frames > 0,
+                                );
+                            }
                             audio_timestamp = audio_timestamp.wrapping_add(samples_per_frame);
                         }
                     }
@@ -394,6 +579,10 @@ pub async fn drive_session(
                 break;
             }
         }
+        if session.keyframe_replay_at.is_some_and(|at| Instant::now() >= at) {
+            session.keyframe_replay_at = None;
+            replay_cached_keyframe(&mut session, &shared_state);
+        }
         // str0m Sans-I/O requires a Timeout input to timestamp queued RTP
         // packets so the pacer can emit them.  Without this, write_rtp()
         // packets sit in the send queue with a sentinel timestamp and are
@@ -409,7 +598,34 @@ pub async fn drive_session(
     }
 
     info!("Session {} drive loop ended", session_id);
+    if !session.video_enabled {
+        shared_state.set_session_video_paused(false);
+    }
     shared_state.decrement_webrtc_sessions();
+    shared_state.set_session_congested(&session_id, None);
+    shared_state.update_control(|control| control.leave(&session_id));
+    if shared_state.config.input.multi_seat || shared_state.config.input.record_dir.is_some() {
+        ctx.send_input(InputEventData {
+            event_type: InputEvent::SessionClosed,
+            ..Default::default()
+        });
+    }
+    shared_state.hooks.on_session_closed(&session_id);
+    shared_state.emit_event("session_closed", serde_json::json!({ "session_id": session_id }));
+}
+
+/// Send the last cached keyframe so the browser can start decoding without
+/// waiting for the next IDR, and ask the encoder for a fresh one.
+fn replay_cached_keyframe(session: &mut RtcSession, shared_state: &SharedState) {
+    let packets = shared_state.get_keyframe_cache();
+    if !packets.is_empty() && session.video_mid.is_some() {
+        let clock = shared_state.video_clock();
+        for pkt in &packets {
+            let _ = session.write_video_rtp(pkt, clock);
+        }
+        info!("Session {} replayed cached keyframe ({} packets)", session.id, packets.len());
+    }
+    shared_state.request_keyframe();
 }
 
 /// Drain all pending str0m outputs: transmit packets, handle events, get next timeout.
@@ -472,6 +688,8 @@ async fn drain_outputs(
 /// Context passed to event handlers so they can dispatch DataChannel messages.
 struct EventContext<'a> {
     input_tx: &'a mpsc::UnboundedSender<InputEventData>,
+    /// Tags input events so multi-seat mode can route them to a seat
+    session_id: Arc<str>,
     upload_handler: &'a Arc<Mutex<FileUploadHandler>>,
     clipboard: &'a Arc<Mutex<ClipboardReceiver>>,
     runtime_settings: &'a Arc<RuntimeSettings>,
@@ -479,39 +697,46 @@ struct EventContext<'a> {
     last_pong: &'a Arc<AtomicU64>,
 }
 
+impl EventContext<'_> {
+    fn send_input(&self, mut event: InputEventData) {
+        let always = matches!(event.event_type, InputEvent::Ping | InputEvent::SessionClosed);
+        if !always && !self.shared_state.input_allowed(&self.session_id) {
+            return;
+        }
+        event.session_id = Some(self.session_id.clone());
+        self.shared_state.map_stream_input(&mut event);
+        let _ = self.input_tx.send(event);
+    }
+}
+
 /// Handle a str0m event.
 fn handle_event(session: &mut RtcSession, event: Event, ctx: &EventContext) {
     match event {
         Event::Connected => {
             session.connected = true;
+            if session.congestion.is_some() {
+                ctx.shared_state.set_session_congested(&session.id, Some(false));
+            }
+            // DTLS is done and SRTP keys exist, so media written from here on
+            // is decryptable; the optional delay covers lossy links
+            let delay = ctx.shared_state.config.webrtc.keyframe_replay_delay_ms;
+            session.keyframe_replay_at = Some(Instant::now() + Duration::from_millis(delay));
             info!("Session {} WebRTC connected", session.id);
         }
 
         Event::MediaAdded(media) => {
             match media.kind {
+                // PTs were resolved by the SessionManager right after the offer
                 MediaKind::Video => {
                     session.video_mid = Some(media.mid);
-                    // Discover negotiated H.264 PT from codec config
-                    for p in session.rtc.codec_config().params() {
-                        if p.spec().codec == str0m::format::Codec::H264 {
-                            session.video_pt = Some(p.pt());
-                            info!("Session {} video PT: {:?} (H264)", session.id, p.pt());
-                            break;
-                        }
-                    }
-                    info!("Session {} video mid: {:?}", session.id, media.mid);
+                    info!(
+                        "Session {} video mid: {:?} PT: {:?} ({})",
+                        session.id, media.mid, session.pts.video, session.video_codec.as_str()
+                    );
                 }
                 MediaKind::Audio => {
                     session.audio_mid = Some(media.mid);
-                    // Discover negotiated Opus PT from codec config
-                    for p in session.rtc.codec_config().params() {
-                        if p.spec().codec == str0m::format::Codec::Opus {
-                            session.audio_pt = Some(p.pt());
-                            info!("Session {} audio PT: {:?}", session.id, p.pt());
-                            break;
-                        }
-                    }
-                    info!("Session {} audio mid: {:?}", session.id, media.mid);
+                    info!("Session {} audio mid: {:?} PT: {:?}", session.id, media.mid, session.pts.audio);
                 }
             }
         }
@@ -527,6 +752,13 @@ fn handle_event(session: &mut RtcSession, event: Event, ctx: &EventContext) {
             session.dc_id = Some(id);
             info!("Session {} DataChannel '{}' opened (id={:?})", session.id, label, id);
             ctx.shared_state.datachannel_open_count.fetch_add(1, Ordering::Relaxed);
+            if ctx.shared_state.config.input.multi_seat {
+                // Lets the client pick its own entry out of `seats,` updates
+                let _ = session.send_datachannel_text(&format!("seat,{}", session.id));
+            }
+            for msg in ctx.shared_state.session_state_bundle(session.client_host.as_deref()) {
+                let _ = session.send_datachannel_text(&msg);
+            }
         }
 
         Event::ChannelData(data) => {
@@ -544,6 +776,33 @@ fn handle_event(session: &mut RtcSession, event: Event, ctx: &EventContext) {
             ctx.shared_state.request_keyframe();
         }
 
+        Event::EgressBitrateEstimate(kind) => {
+            let estimate = match (kind, session.congestion_control) {
+                (BweKind::Twcc(bitrate), CongestionControl::TransportCc)
+                | (BweKind::Remb(_, bitrate), CongestionControl::GoogRemb) => bitrate.as_u64(),
+                (kind, _) => {
+                    debug!("Session {} ignoring {:?} estimate (not negotiated)", session.id, kind);
+                    return;
+                }
+            };
+            if let Some(ref mut tracker) = session.congestion {
+                if let Some(congested) = tracker.update(estimate, Instant::now()) {
+                    info!(
+                        "Session {} {} (estimate {} kbps)",
+                        session.id,
+                        if congested { "bandwidth-constrained" } else { "no longer constrained" },
+                        estimate / 1000
+                    );
+                    ctx.shared_state.set_session_congested(&session.id, Some(congested));
+                    ctx.shared_state.emit_event("congestion", serde_json::json!({
+                        "session_id": session.id,
+                        "constrained": congested,
+                        "estimate_kbps": estimate / 1000,
+                    }));
+                }
+            }
+        }
+
         _ => {
             debug!("Session {} unhandled event: {:?}", session.id, event);
         }
@@ -553,6 +812,18 @@ fn handle_event(session: &mut RtcSession, event: Event, ctx: &EventContext) {
 /// Handle incoming DataChannel data — reuses the existing input parsing logic.
 fn handle_datachannel_data(session: &mut RtcSession, data: ChannelData, ctx: &EventContext) {
     if data.binary {
+        // Binary input frames are only accepted once negotiated
+        if session.binary_input && data_channel::is_binary_input_frame(&data.data) {
+            match InputDataChannel::parse_input_binary(&data.data) {
+                Ok(event) => {
+                    ctx.send_input(event);
+                }
+                Err(e) => {
+                    debug!("Session {} DC binary input error: {}", session.id, e);
+                }
+            }
+            return;
+        }
         // Binary data → file upload handler
         ctx.upload_handler.lock().unwrap_or_else(|e| e.into_inner())
             .handle_binary(&data.data);
@@ -590,25 +861,41 @@ fn handle_datachannel_data(session: &mut RtcSession, data: ChannelData, ctx: &Ev
     if ctx.runtime_settings.handle_simple_message(text) {
         return;
     }
+    if let Some(payload) = text.strip_prefix("bin_input,") {
+        // Handshake: client asks for a protocol version, server echoes the one
+        // it will accept (0 = text only).
+        let requested = payload.parse::<u8>().unwrap_or(0);
+        session.binary_input = requested == data_channel::BINARY_INPUT_VERSION;
+        let accepted = if session.binary_input { data_channel::BINARY_INPUT_VERSION } else { 0 };
+        info!("Session {} binary input protocol: {}", session.id, accepted);
+        let _ = session.send_datachannel_text(&format!("bin_input,{}", accepted));
+        return;
+    }
+    if let Some((kind, enabled)) = data_channel::parse_media_toggle(text) {
+        set_media_enabled(session, kind, enabled, ctx.shared_state);
+        return;
+    }
+    if crate::control::is_control_request(text) {
+        if let Some(reply) = ctx.shared_state.handle_control_request(&session.id, text) {
+            let _ = session.send_datachannel_text(&reply);
+        }
+        return;
+    }
+    if text == "getstats" {
+        // Throttled requests just get the next periodic update
+        ctx.shared_state.request_stats_now();
+        return;
+    }
     if text == "kr" {
-        let _ = ctx.input_tx.send(InputEventData {
+        ctx.send_input(InputEventData {
             event_type: InputEvent::KeyboardReset,
             ..Default::default()
         });
         return;
     }
-    if text.starts_with("s,") || text.starts_with("SET_NATIVE_CURSOR_RENDERING,") {
-        return;
-    }
-    if text.starts_with("r,") {
-        let payload = text.trim_start_matches("r,");
-        if let Some((w, h)) = payload.split_once('x') {
-            if let (Ok(width), Ok(height)) = (w.parse::<u32>(), h.parse::<u32>()) {
-                if width > 0 && height > 0 && width <= 7680 && height <= 4320 {
-                    ctx.shared_state.resize_display(width, height);
-                }
-            }
-        }
+    if let Some(payload) = text.strip_prefix("r,") {
+        let reply = ctx.shared_state.handle_resize_request(&session.id, payload);
+        let _ = session.send_datachannel_text(&reply);
         return;
     }
     if text.starts_with("_arg_fps,") {
@@ -642,7 +929,7 @@ fn handle_datachannel_data(session: &mut RtcSession, data: ChannelData, ctx: &Ev
             let mut event = InputEventData::default();
             event.event_type = InputEvent::WindowFocus;
             event.window_id = window_id;
-            let _ = ctx.input_tx.send(event);
+            ctx.send_input(event);
         }
         return;
     }
@@ -651,7 +938,7 @@ fn handle_datachannel_data(session: &mut RtcSession, data: ChannelData, ctx: &Ev
             let mut event = InputEventData::default();
             event.event_type = InputEvent::WindowClose;
             event.window_id = window_id;
-            let _ = ctx.input_tx.send(event);
+            ctx.send_input(event);
         }
         return;
     }
@@ -659,7 +946,7 @@ fn handle_datachannel_data(session: &mut RtcSession, data: ChannelData, ctx: &Ev
     // Fall through to input event parsing (mouse, keyboard, etc.)
     match InputDataChannel::parse_input_text(text) {
         Ok(event) => {
-            let _ = ctx.input_tx.send(event);
+            ctx.send_input(event);
         }
         Err(e) => {
             debug!("Session {} DC parse error: {}", session.id, e);
@@ -667,6 +954,26 @@ fn handle_datachannel_data(session: &mut RtcSession, data: ChannelData, ctx: &Ev
     }
 }
 
+/// Apply `audio,on|off` / `video,on|off`. Resuming video replays the
+/// cached keyframe and asks for a fresh one so decoding restarts at once.
+fn set_media_enabled(session: &mut RtcSession, kind: MediaToggle, enabled: bool, shared_state: &SharedState) {
+    let current = match kind {
+        MediaToggle::Audio => &mut session.audio_enabled,
+        MediaToggle::Video => &mut session.video_enabled,
+    };
+    if *current == enabled {
+        return;
+    }
+    *current = enabled;
+    info!("Session {} {:?} {}", session.id, kind, if enabled { "enabled" } else { "disabled" });
+    if kind == MediaToggle::Video {
+        shared_state.set_session_video_paused(!enabled);
+        if enabled && session.connected {
+            session.keyframe_replay_at = Some(Instant::now());
+        }
+    }
+}
+
 fn now_millis() -> u64 {
     use std::time::{SystemTime, UNIX_EPOCH};
     SystemTime::now()
@@ -5,10 +5,12 @@
 //! - ICE-TCP connection acceptance and routing
 //! - Session state tracking and cleanup
 
-use super::rtc_session::{self, RtcSession};
+use super::rtc_session::{self, NegotiatedPts, RtcSession};
+use str0m::config::DtlsCert;
+use super::signaling::{offer_codec_key, sdp_candidates, strip_mdns_candidates};
 use super::WebRTCError;
 use crate::clipboard::ClipboardReceiver;
-use crate::config::WebRTCConfig;
+use crate::config::{NetworkProfile, WebRTCConfig};
 use crate::file_upload::{FileUploadHandler, FileUploadSettings};
 use crate::input::InputEventData;
 use crate::runtime_settings::RuntimeSettings;
@@ -18,6 +20,7 @@ use super::tcp_framing::frame_packet;
 use log::{info, warn, debug};
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
@@ -28,6 +31,8 @@ use str0m::{Input, Output};
 
 /// How long a pending session can wait for a TCP connection before being reaped.
 const PENDING_SESSION_TTL: Duration = Duration::from_secs(30);
+/// Distinct offer codec sections remembered before the PT cache is reset.
+const PT_CACHE_CAPACITY: usize = 64;
 /// Max time to wait for a complete initial RFC 4571 frame.
 const INITIAL_FRAME_TIMEOUT: Duration = Duration::from_secs(2);
 /// Read chunk size while assembling the first RFC 4571 frame.
@@ -59,6 +64,35 @@ pub struct SessionManager {
     max_sessions: usize,
     /// The listen address for TCP passive candidates
     listen_addr: SocketAddr,
+    /// Persisted DTLS certificate shared by all sessions (None = per-session)
+    dtls_cert: Option<DtlsCert>,
+    /// Negotiated payload types by offer codec section
+    pt_cache: PtCache,
+}
+
+/// Negotiated payload types keyed by `offer_codec_key` and the encoded bit
+/// depth. Reconnects from the same browser send the same codec list and get
+/// the same answer PTs, so the codec config only has to be scanned for the
+/// first of them.
+#[derive(Default)]
+struct PtCache {
+    entries: Mutex<HashMap<(u64, u8), NegotiatedPts>>,
+}
+
+impl PtCache {
+    /// Cached PTs for `key`, or the result of `discover` (then cached)
+    fn get_or_discover(&self, key: (u64, u8), discover: impl FnOnce() -> NegotiatedPts) -> (NegotiatedPts, bool) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(pts) = entries.get(&key) {
+            return (*pts, true);
+        }
+        if entries.len() >= PT_CACHE_CAPACITY {
+            entries.clear();
+        }
+        let pts = discover();
+        entries.insert(key, pts);
+        (pts, false)
+    }
 }
 
 /// A pending session wraps an RtcSession with a creation timestamp for TTL cleanup.
@@ -92,6 +126,8 @@ impl SessionManager {
             shared_state,
             max_sessions,
             listen_addr,
+            dtls_cert: None,
+            pt_cache: PtCache::default(),
         };
 
         // Spawn a background task to reap stale pending sessions
@@ -104,6 +140,12 @@ impl SessionManager {
         mgr
     }
 
+    /// Use `cert` for every new session's DTLS handshake, so the SDP
+    /// fingerprint is the same across sessions and restarts.
+    pub fn set_dtls_cert(&mut self, cert: DtlsCert) {
+        self.dtls_cert = Some(cert);
+    }
+
     /// Create a new session and process the SDP offer.
     ///
     /// Returns (session_id, answer_sdp).
@@ -117,22 +159,53 @@ impl SessionManager {
         let session_id = uuid::Uuid::new_v4().to_string();
 
         // Create str0m Rtc instance
-        let mut session = RtcSession::new(session_id.clone());
+        let video_bit_depth = self.shared_state.video_bit_depth.load(Ordering::Relaxed);
+        let mut session = RtcSession::new(
+            session_id.clone(),
+            self.dtls_cert.clone(),
+            &self.config,
+            self.shared_state.config.audio.fec,
+            video_bit_depth,
+        );
 
         // Determine the ICE candidate address.
         // If the browser connected via a tunnel/proxy, use the Host header
         // so the ICE-TCP candidate points to the same public address.
-        let candidate_addr = resolve_candidate_addr(&self.config, client_host, self.listen_addr).await;
+        let candidate_addrs = gather_candidate_addrs(&self.config, client_host, self.listen_addr).await;
+        let candidate_addr = candidate_addrs[0];
+
+        // Add TCP passive candidates (the first one is the primary)
+        for addr in &candidate_addrs {
+            session.add_local_tcp_candidate(*addr)?;
+            info!("Session {} added TCP candidate: {} (host header: {:?})", session_id, addr, client_host);
+        }
 
-        // Add TCP passive candidate
-        session.add_local_tcp_candidate(candidate_addr)?;
-        info!("Session {} added TCP candidate: {} (host header: {:?})", session_id, candidate_addr, client_host);
+        // Count the browser's candidates bundled in the offer (trickled ones
+        // are counted by the signaling handler)
+        for candidate in sdp_candidates(offer_sdp) {
+            self.shared_state.record_ice_candidate(&candidate);
+        }
+
+        // Chrome hides host candidates behind mDNS names. We never dial the
+        // browser (it connects to our passive TCP candidate), so drop them
+        // rather than make str0m parse a hostname where it expects an IP.
+        let (offer_sdp, mdns_dropped) = strip_mdns_candidates(offer_sdp);
+        let offer_sdp = offer_sdp.as_str();
+        if mdns_dropped > 0 {
+            debug!("Session {} ignored {} mDNS (.local) offer candidate(s)", session_id, mdns_dropped);
+        }
 
         // Accept the SDP offer and generate answer
         info!("Session {} SDP offer ({} bytes): {:?}", session_id, offer_sdp.len(), &offer_sdp[..offer_sdp.len().min(200)]);
         let answer_sdp = session.accept_offer(offer_sdp)?;
         info!("Session {} SDP answer generated ({} bytes):\n{}", session_id, answer_sdp.len(), answer_sdp);
 
+        let key = (offer_codec_key(offer_sdp), video_bit_depth);
+        let (pts, cached) = self.pt_cache.get_or_discover(key, || session.discover_pts());
+        debug!("Session {} payload types {:?} (cached: {})", session_id, pts, cached);
+        session.set_negotiated_pts(pts);
+        session.set_client_host(client_host);
+
         // Check capacity and insert under a single write lock to avoid TOCTOU race
         let mut pending = self.pending_sessions.write().await;
         if pending.len() >= self.max_sessions {
@@ -334,7 +407,7 @@ impl SessionManager {
             FileUploadHandler::new(self.upload_settings.clone())
         ));
         let clipboard = Arc::new(Mutex::new(
-            ClipboardReceiver::new(self.shared_state.clone())
+            ClipboardReceiver::for_session(self.shared_state.clone(), Arc::from(session_id.as_str()))
         ));
         let runtime_settings = self.runtime_settings.clone();
 
@@ -427,38 +500,58 @@ async fn drain_initial_outputs(
     Ok(())
 }
 
+/// Parse a Host header value that is an IP literal ("1.2.3.4:8008",
+/// "[::1]:8008" or "1.2.3.4") without touching DNS.
+fn parse_host_literal(host: &str, default_port: u16) -> Option<SocketAddr> {
+    if let Ok(addr) = host.parse::<SocketAddr>() {
+        return Some(addr);
+    }
+    if let Some((h, p)) = host.rsplit_once(':') {
+        if let (Ok(ip), Ok(port)) = (h.parse::<std::net::IpAddr>(), p.parse::<u16>()) {
+            return Some(SocketAddr::new(ip, port));
+        }
+    }
+    host.parse::<std::net::IpAddr>().ok().map(|ip| SocketAddr::new(ip, default_port))
+}
+
 /// Parse a Host header value (e.g. "example.com:8008" or "1.2.3.4:8008")
 /// into a SocketAddr. Falls back to `default_port` if no port is specified.
 async fn parse_host_to_addr(host: &str, default_port: u16) -> Option<SocketAddr> {
-    // Try direct parse first (covers "1.2.3.4:8008")
-    if let Ok(addr) = host.parse::<SocketAddr>() {
+    if let Some(addr) = parse_host_literal(host, default_port) {
         return Some(addr);
     }
-    // Try as "host:port" where host is a domain or bare IP
+    // Domain name — resolve it
     if let Some((h, p)) = host.rsplit_once(':') {
         if let Ok(port) = p.parse::<u16>() {
-            if let Ok(ip) = h.parse::<std::net::IpAddr>() {
-                return Some(SocketAddr::new(ip, port));
-            }
-            // Domain name — resolve it
             return tokio::net::lookup_host((h, port)).await.ok()?.next();
         }
     }
-    // Bare host without port
-    if let Ok(ip) = host.parse::<std::net::IpAddr>() {
-        return Some(SocketAddr::new(ip, default_port));
-    }
     tokio::net::lookup_host((host, default_port)).await.ok()?.next()
 }
 
-async fn resolve_candidate_addr(
+/// Gather the TCP passive candidate addresses for a session, in preference
+/// order: `public_candidate`, the Host header, then the listen address.
+///
+/// All candidates are host-type (ICE-lite TCP has no srflx/relay), so the
+/// order alone decides which ones survive the `ice_max_candidates` cap.
+/// Host header resolution may hit DNS; it is bounded by
+/// `ice_gathering_timeout_ms` and skipped on expiry.
+///
+/// With the `lan` network profile the public candidate is ignored and the
+/// Host header is only used when it is an IP literal, so nothing leaves the
+/// machine before the answer is sent. Always returns at least one address.
+async fn gather_candidate_addrs(
     config: &WebRTCConfig,
     client_host: Option<&str>,
     listen_addr: SocketAddr,
-) -> SocketAddr {
-    if let Some(ref public_candidate) = config.public_candidate {
+) -> Vec<SocketAddr> {
+    let max = config.ice_max_candidates.max(1);
+    let lan = config.network_profile == NetworkProfile::Lan;
+    let mut addrs: Vec<SocketAddr> = Vec::new();
+
+    if let (false, Some(public_candidate)) = (lan, config.public_candidate.as_ref()) {
         match public_candidate.parse::<SocketAddr>() {
-            Ok(addr) => return addr,
+            Ok(addr) => addrs.push(addr),
             Err(e) => {
                 warn!(
                     "Invalid public_candidate '{}': {} (falling back to other sources)",
@@ -468,13 +561,131 @@ async fn resolve_candidate_addr(
         }
     }
 
-    if config.candidate_from_host_header {
+    if addrs.len() < max && config.candidate_from_host_header {
         if let Some(host) = client_host {
-            if let Some(addr) = parse_host_to_addr(host, listen_addr.port()).await {
-                return addr;
+            let resolved = if lan {
+                parse_host_literal(host, listen_addr.port())
+            } else {
+                let budget = Duration::from_millis(config.ice_gathering_timeout_ms);
+                match tokio::time::timeout(budget, parse_host_to_addr(host, listen_addr.port())).await {
+                    Ok(addr) => addr,
+                    Err(_) => {
+                        warn!(
+                            "ICE candidate gathering timed out after {:?} resolving host '{}'; continuing with {} candidate(s)",
+                            budget, host, addrs.len()
+                        );
+                        None
+                    }
+                }
+            };
+            if let Some(addr) = resolved {
+                if !addrs.contains(&addr) {
+                    addrs.push(addr);
+                }
             }
         }
     }
 
-    listen_addr
+    if addrs.len() < max && !addrs.contains(&listen_addr) {
+        addrs.push(listen_addr);
+    }
+
+    addrs.truncate(max);
+    addrs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use str0m::media::Pt;
+
+    #[test]
+    fn pt_cache_discovers_once_per_offer_shape() {
+        let cache = PtCache::default();
+        let pts = NegotiatedPts { video: Some(Pt::new_with_value(102)), audio: Some(Pt::new_with_value(111)) };
+
+        let (first, cached) = cache.get_or_discover((1, 8), || pts);
+        assert_eq!((first, cached), (pts, false));
+        let (again, cached) = cache.get_or_discover((1, 8), || panic!("should be cached"));
+        assert_eq!((again, cached), (pts, true));
+
+        // A different offer shape is discovered separately
+        let (other, cached) = cache.get_or_discover((2, 8), NegotiatedPts::default);
+        assert_eq!((other, cached), (NegotiatedPts::default(), false));
+        // So is the same offer for a 10-bit stream
+        let (other, cached) = cache.get_or_discover((1, 10), NegotiatedPts::default);
+        assert_eq!((other, cached), (NegotiatedPts::default(), false));
+    }
+
+    /// Browser offer receiving video with the given `a=rtpmap`/`a=fmtp` lines
+    fn video_offer(codecs: &[(u8, &str, Option<&str>)]) -> String {
+        let pts: Vec<String> = codecs.iter().map(|(pt, _, _)| pt.to_string()).collect();
+        let mut sdp = format!(
+            "v=0\r\no=- 4611731400430051336 2 IN IP4 127.0.0.1\r\ns=-\r\nt=0 0\r\n\
+             a=group:BUNDLE 0\r\na=msid-semantic: WMS\r\n\
+             m=video 9 UDP/TLS/RTP/SAVPF {}\r\nc=IN IP4 0.0.0.0\r\na=rtcp:9 IN IP4 0.0.0.0\r\n\
+             a=ice-ufrag:Ab1c\r\na=ice-pwd:0123456789abcdefghijklmn\r\n\
+             a=fingerprint:sha-256 {}\r\na=setup:actpass\r\na=mid:0\r\na=recvonly\r\na=rtcp-mux\r\n",
+            pts.join(" "),
+            ["AB"; 32].join(":"),
+        );
+        for (pt, rtpmap, fmtp) in codecs {
+            sdp.push_str(&format!("a=rtpmap:{} {}\r\n", pt, rtpmap));
+            if let Some(fmtp) = fmtp {
+                sdp.push_str(&format!("a=fmtp:{} {}\r\n", pt, fmtp));
+            }
+        }
+        sdp
+    }
+
+    /// Video PT a session encoding `codec` at `bit_depth` picks for `offer`
+    fn negotiated_video_pt(codec: crate::config::VideoCodec, bit_depth: u8, offer: &str) -> Option<Pt> {
+        let config = WebRTCConfig { video_codec: codec, ..Default::default() };
+        let mut session = RtcSession::new("test".into(), None, &config, false, bit_depth);
+        session.accept_offer(offer).unwrap();
+        session.discover_pts().video
+    }
+
+    #[test]
+    fn vp9_pt_follows_the_encoded_bit_depth() {
+        use crate::config::VideoCodec;
+        let offer = video_offer(&[
+            (96, "VP8/90000", None),
+            (98, "VP9/90000", Some("profile-id=0")),
+            (100, "VP9/90000", Some("profile-id=2")),
+        ]);
+        assert_eq!(negotiated_video_pt(VideoCodec::VP9, 10, &offer), Some(Pt::new_with_value(100)));
+        // 10-bit configured but the encoder fell back to 8-bit: profile 0
+        assert_eq!(negotiated_video_pt(VideoCodec::VP9, 8, &offer), Some(Pt::new_with_value(98)));
+    }
+
+    #[test]
+    fn media_is_written_with_negotiated_pts_only() {
+        use str0m::media::MediaKind;
+        // GStreamer payloads H.264 as 96, which this answer gave to VP8
+        let pts = NegotiatedPts { video: Some(Pt::new_with_value(102)), audio: Some(Pt::new_with_value(109)) };
+        assert_eq!(pts.pt_for(MediaKind::Video), Some(Pt::new_with_value(102)));
+        assert_eq!(pts.pt_for(MediaKind::Audio), Some(Pt::new_with_value(109)));
+        // Nothing negotiated: nothing is sent rather than a guessed PT
+        let none = NegotiatedPts::default();
+        assert_eq!((none.pt_for(MediaKind::Video), none.pt_for(MediaKind::Audio)), (None, None));
+    }
+
+    #[test]
+    fn h264_is_written_with_its_negotiated_pt_not_96() {
+        use crate::config::VideoCodec;
+        use str0m::media::MediaKind;
+        // GStreamer payloads H.264 as 96, which this browser gave to VP8
+        let offer = video_offer(&[
+            (96, "VP8/90000", None),
+            (102, "H264/90000", Some("level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f")),
+        ]);
+        let config = WebRTCConfig { video_codec: VideoCodec::H264, ..Default::default() };
+        let mut session = RtcSession::new("test".into(), None, &config, false, 8);
+        let answer = session.accept_offer(&offer).unwrap();
+        let pts = session.discover_pts();
+        session.set_negotiated_pts(pts);
+        assert_eq!(session.negotiated_pts().pt_for(MediaKind::Video), Some(Pt::new_with_value(102)));
+        assert!(answer.contains("a=rtpmap:102 H264/90000"));
+    }
 }
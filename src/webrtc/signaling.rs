@@ -164,6 +164,87 @@ impl SignalingMessage {
     }
 }
 
+/// Transport and type of an ICE candidate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IceCandidateInfo {
+    /// "udp" or "tcp" (lowercased)
+    pub transport: String,
+    /// "host", "srflx", "prflx" or "relay"
+    pub candidate_type: String,
+    /// Connection address: an IP literal or an mDNS `<uuid>.local` name
+    pub address: String,
+}
+
+impl IceCandidateInfo {
+    /// Host candidate obfuscated behind an mDNS name (Chrome/Safari default).
+    /// We never dial remote candidates (ICE-lite with a passive TCP
+    /// candidate; the browser connects to us), so these are harmless.
+    pub fn is_mdns(&self) -> bool {
+        self.address.to_ascii_lowercase().ends_with(".local")
+    }
+}
+
+/// Parse an ICE candidate attribute, with or without the `a=` prefix:
+/// `candidate:<foundation> <component> <transport> <priority> <ip> <port> typ <type> ...`
+pub fn parse_ice_candidate(line: &str) -> Option<IceCandidateInfo> {
+    let line = line.trim();
+    let line = line.strip_prefix("a=").unwrap_or(line);
+    let rest = line.strip_prefix("candidate:")?;
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    if fields.len() < 8 || fields[6] != "typ" {
+        return None;
+    }
+    Some(IceCandidateInfo {
+        transport: fields[2].to_ascii_lowercase(),
+        candidate_type: fields[7].to_ascii_lowercase(),
+        address: fields[4].to_string(),
+    })
+}
+
+/// All candidates carried in an SDP blob (`a=candidate:` lines)
+pub fn sdp_candidates(sdp: &str) -> impl Iterator<Item = IceCandidateInfo> + '_ {
+    sdp.lines()
+        .filter(|l| l.starts_with("a=candidate:"))
+        .filter_map(parse_ice_candidate)
+}
+
+/// Remove `.local` (mDNS) candidates from an SDP blob and return the
+/// rewritten SDP with the number of lines dropped. The address cannot be
+/// parsed as an IP and the server has no use for remote candidates anyway.
+pub fn strip_mdns_candidates(sdp: &str) -> (String, usize) {
+    let mut out = String::with_capacity(sdp.len());
+    let mut dropped = 0;
+    for line in sdp.split_inclusive('\n') {
+        let is_mdns = line.starts_with("a=candidate:")
+            && parse_ice_candidate(line).is_some_and(|c| c.is_mdns());
+        if is_mdns {
+            dropped += 1;
+        } else {
+            out.push_str(line);
+        }
+    }
+    (out, dropped)
+}
+
+/// Fingerprint of the codec section of an SDP offer (m-lines, rtpmap,
+/// fmtp and rtcp-fb). Offers from the same browser build hash equal even
+/// though ICE credentials, fingerprints and candidates differ.
+pub fn offer_codec_key(sdp: &str) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    for line in sdp.lines() {
+        let line = line.trim_end();
+        if line.starts_with("m=")
+            || line.starts_with("a=rtpmap:")
+            || line.starts_with("a=fmtp:")
+            || line.starts_with("a=rtcp-fb:")
+        {
+            line.hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
 /// Signaling handler trait for processing messages
 pub trait SignalingHandler: Send + Sync {
     /// Handle an incoming signaling message
@@ -304,6 +385,33 @@ mod tests {
         assert!(json.contains("session123"));
     }
 
+    #[test]
+    fn test_parse_ice_candidate() {
+        let info = parse_ice_candidate(
+            "candidate:842163049 1 TCP 1677729535 203.0.113.5 9 typ srflx raddr 0.0.0.0 rport 0 tcptype active",
+        ).unwrap();
+        assert_eq!(info.transport, "tcp");
+        assert_eq!(info.candidate_type, "srflx");
+
+        let info = parse_ice_candidate("a=candidate:1 1 udp 2122260223 192.168.1.2 54321 typ host").unwrap();
+        assert_eq!(info.transport, "udp");
+        assert_eq!(info.candidate_type, "host");
+        assert_eq!(info.address, "192.168.1.2");
+        assert!(!info.is_mdns());
+
+        assert!(parse_ice_candidate("").is_none());
+        assert!(parse_ice_candidate("candidate:1 1 udp").is_none());
+    }
+
+    #[test]
+    fn test_sdp_candidates() {
+        let sdp = "v=0\r\nm=video 9 UDP/TLS/RTP/SAVPF 96\r\n\
+                   a=candidate:1 1 udp 2122260223 192.168.1.2 54321 typ host\r\n\
+                   a=candidate:2 1 tcp 1518280447 192.168.1.2 9 typ host tcptype active\r\n";
+        let transports: Vec<String> = sdp_candidates(sdp).map(|c| c.transport).collect();
+        assert_eq!(transports, vec!["udp", "tcp"]);
+    }
+
     #[test]
     fn test_error_message() {
         let msg = SignalingMessage::error("INVALID_SDP", "SDP parsing failed", Some("sess1".to_string()));
@@ -311,4 +419,65 @@ mod tests {
         assert!(json.contains("error"));
         assert!(json.contains("INVALID_SDP"));
     }
+
+    #[test]
+    fn test_mdns_candidates_rely_on_passive_tcp() {
+        let line = "candidate:1 1 udp 2122260223 0f3c8a2e-4b1d-4c1e-9a7f-2d6b1e5c9a10.local 54321 typ host";
+        assert!(parse_ice_candidate(line).unwrap().is_mdns());
+
+        let offer = "v=0\r\n\
+                     a=candidate:1 1 udp 2122260223 0f3c8a2e-4b1d-4c1e-9a7f-2d6b1e5c9a10.local 54321 typ host\r\n\
+                     a=candidate:2 1 tcp 1518280447 0f3c8a2e-4b1d-4c1e-9a7f-2d6b1e5c9a10.local 9 typ host tcptype active\r\n\
+                     a=candidate:3 1 udp 1686052607 203.0.113.5 40000 typ srflx raddr 0.0.0.0 rport 0\r\n\
+                     a=mid:0\r\n";
+        let (sdp, dropped) = strip_mdns_candidates(offer);
+        assert_eq!(dropped, 2);
+        assert!(!sdp.contains(".local"));
+        assert!(sdp.contains("typ srflx"));
+        assert!(sdp.ends_with("a=mid:0\r\n"));
+
+        // An offer with only mDNS candidates leaves the browser with nothing
+        // for us to dial: connectivity comes solely from our passive candidate
+        let only_mdns = "v=0\r\na=candidate:1 1 udp 2122260223 abc.local 54321 typ host\r\n";
+        let (sdp, _) = strip_mdns_candidates(only_mdns);
+        assert_eq!(sdp_candidates(&sdp).count(), 0);
+    }
+
+    #[test]
+    fn test_passive_tcp_candidates_are_kept_unless_mdns() {
+        // Firefox offers passive TCP host candidates on real addresses: they
+        // parse as host/tcp and survive the mDNS filter untouched
+        let passive = "a=candidate:4 1 TCP 2105524479 192.168.1.2 9 typ host tcptype passive";
+        let info = parse_ice_candidate(passive).unwrap();
+        assert_eq!(info.transport, "tcp");
+        assert_eq!(info.candidate_type, "host");
+        assert!(!info.is_mdns());
+
+        let offer = format!(
+            "v=0\r\n{}\r\n\
+             a=candidate:5 1 tcp 1518280447 abc.local 9 typ host tcptype passive\r\n",
+            passive
+        );
+        let (sdp, dropped) = strip_mdns_candidates(&offer);
+        assert_eq!(dropped, 1);
+        assert!(sdp.contains(passive));
+        let kept: Vec<IceCandidateInfo> = sdp_candidates(&sdp).collect();
+        assert_eq!(kept, vec![info]);
+    }
+
+    #[test]
+    fn offer_codec_key_ignores_per_connection_lines() {
+        let offer = |ufrag: &str, port: u16| {
+            format!(
+                "v=0\r\nm=video 9 UDP/TLS/RTP/SAVPF 96 102\r\na=ice-ufrag:{}\r\n\
+                 a=rtpmap:96 VP8/90000\r\na=rtpmap:102 H264/90000\r\n\
+                 a=fmtp:102 profile-level-id=42e01f\r\n\
+                 a=candidate:1 1 udp 2122260223 10.0.0.2 {} typ host\r\n",
+                ufrag, port
+            )
+        };
+        assert_eq!(offer_codec_key(&offer("abcd", 50000)), offer_codec_key(&offer("wxyz", 51000)));
+        let other_pt = offer("abcd", 50000).replace("102", "103");
+        assert_ne!(offer_codec_key(&offer("abcd", 50000)), offer_codec_key(&other_pt));
+    }
 }
@@ -25,8 +25,8 @@
  *   limitations under the License.
  */
 
-import { WebRTCDemo } from "./lib/webrtc.js?v=24";
-import { WebRTCDemoSignaling } from "./lib/signaling.js?v=1";
+import { WebRTCDemo } from "./lib/webrtc.js?v=25";
+import { WebRTCDemoSignaling } from "./lib/signaling.js?v=2";
 import { stringToBase64 } from "./lib/util.js?v=1";
 import { Input } from "./lib/input2.js?v=18";
 
@@ -2364,6 +2364,18 @@ export default function webrtc() {
 				}
 			}
 
+			// HTML follows the plain text of the same selection: put both on
+			// the system clipboard so rich-text apps get formatting
+			webrtc.onclipboardhtml = (html) => {
+				if (clipboardStatus !== 'enabled') return;
+				if (!navigator.clipboard || !navigator.clipboard.write || typeof ClipboardItem === 'undefined') return;
+				var item = new ClipboardItem({
+					'text/plain': new Blob([webrtc._remoteClipboard || ''], { type: 'text/plain' }),
+					'text/html': new Blob([html], { type: 'text/html' }),
+				});
+				navigator.clipboard.write([item]).catch(() => {});
+			}
+
 			function doCopyToSystem(text) {
 				_clipTA.value = text;
 				_clipTA.select();
@@ -2414,7 +2426,12 @@ export default function webrtc() {
 						return;
 					}
 					_lastSentClipboard = text;
-					webrtc.sendDataChannelMessage('cw,' + stringToBase64(text));
+					var html = e.clipboardData.getData('text/html');
+					if (html) {
+						webrtc.sendDataChannelMessage('ch,' + stringToBase64(html) + ',' + stringToBase64(text));
+					} else {
+						webrtc.sendDataChannelMessage('cw,' + stringToBase64(text));
+					}
 				}
 			});
 
@@ -2474,6 +2491,39 @@ export default function webrtc() {
 				});
 			}
 
+			// Multi-seat: other sessions' cursors, drawn over the video in
+			// their seat colour. Positions are server display pixels.
+			webrtc.onseatsupdate = (seats) => {
+				let layer = document.getElementById('remote-cursors');
+				if (!layer) {
+					layer = document.createElement('div');
+					layer.id = 'remote-cursors';
+					layer.style.position = 'fixed';
+					layer.style.inset = '0';
+					layer.style.pointerEvents = 'none';
+					layer.style.zIndex = '999998';
+					document.body.appendChild(layer);
+				}
+				const rect = videoElement.getBoundingClientRect();
+				const size = webrtc.displaySize || { width: videoElement.videoWidth, height: videoElement.videoHeight };
+				layer.replaceChildren();
+				if (!size.width || !size.height) return;
+				seats.forEach((s) => {
+					const marker = document.createElement('div');
+					marker.title = s.session;
+					marker.style.position = 'absolute';
+					marker.style.left = `${rect.left + s.x * rect.width / size.width}px`;
+					marker.style.top = `${rect.top + s.y * rect.height / size.height}px`;
+					marker.style.width = '0';
+					marker.style.height = '0';
+					// Arrow pointing up-left with its tip on the cursor position
+					marker.style.borderTop = `14px solid ${s.color}`;
+					marker.style.borderRight = '10px solid transparent';
+					marker.style.filter = 'drop-shadow(0 0 1px #fff)';
+					layer.appendChild(marker);
+				});
+			}
+
 			webrtc.onsystemaction = (action) => {
 				webrtc._setStatus("Executing system action: " + action);
 				if (action === 'reload') {
@@ -2495,6 +2545,13 @@ export default function webrtc() {
 				window.system_stats = stats;
 			}
 
+			// Sent on connect, so late joiners get the same UI config
+			// (visibility, capabilities) as a fresh GET /ui-config
+			webrtc.onuiconfig = (config) => {
+				window.ui_config = config;
+				window.postMessage({ type: 'uiConfig', payload: config }, window.location.origin);
+			}
+
 			webrtc.onserversettings = (obj) => {
 				console.log("Received server settings payload:", obj.settings);
 				const changes = sanitizeAndStoreSettings(obj.settings);
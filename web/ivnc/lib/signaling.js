@@ -23,6 +23,36 @@
 /*eslint no-unused-vars: ["error", { "vars": "local" }]*/
 
 
+/**
+ * WebSocket subprotocol for deflate-compressed signaling (docs/PROTOCOL.md §3.1.3).
+ */
+const DEFLATE_SUBPROTOCOL = 'ivnc-deflate';
+
+/**
+ * Whether the browser can inflate raw deflate streams.
+ *
+ * @returns {boolean}
+ */
+function deflateRawSupported() {
+    try {
+        new DecompressionStream('deflate-raw');
+        return true;
+    } catch (e) {
+        return false;
+    }
+}
+
+/**
+ * Inflates a raw deflate binary frame into UTF-8 text.
+ *
+ * @param {ArrayBuffer} data
+ * @returns {Promise<string>}
+ */
+function inflateRaw(data) {
+    const stream = new Blob([data]).stream().pipeThrough(new DecompressionStream('deflate-raw'));
+    return new Response(stream).text();
+}
+
 /**
 * @typedef {Object} WebRTCDemoSignaling
 * @property {function} ondebug - Callback fired when a new debug message is set.
@@ -115,6 +145,13 @@ export class WebRTCDemoSignaling {
         this.retry_count = 0;
 
         this.currRes = null;
+
+        /**
+         * Keeps server messages in order while compressed ones are inflated.
+         * @private
+         * @type {Promise}
+         */
+        this._recv_chain = Promise.resolve();
     }
 
     /**
@@ -231,15 +268,28 @@ export class WebRTCDemoSignaling {
      * @param {Event} event The event: https://developer.mozilla.org/en-US/docs/Web/API/MessageEvent
      */
     _onServerMessage(event) {
-        this._setDebug("server message: " + event.data);
+        this._recv_chain = this._recv_chain
+            .then(() => typeof event.data === 'string' ? event.data : inflateRaw(event.data))
+            .then((text) => this._onServerText(text))
+            .catch((e) => this._setError("failed to handle server message: " + e));
+    }
+
+    /**
+     * Handles one text message from the server, after decompression.
+     *
+     * @private
+     * @param {string} data
+     */
+    _onServerText(data) {
+        this._setDebug("server message: " + data);
 
-        if (event.data === "HELLO") {
+        if (data === "HELLO") {
             this._setStatus("Registered with server.");
             this._setupCall();
             return;
         }
 
-        if (event.data === "SESSION_OK") {
+        if (data === "SESSION_OK") {
             this._setStatus("Session established, creating offer.");
             // Signal the WebRTCDemo to create and send an offer
             if (this.onready !== null) {
@@ -248,8 +298,8 @@ export class WebRTCDemoSignaling {
             return;
         }
 
-        if (event.data.startsWith("ERROR")) {
-            if (event.data === "ERROR peer '0' not found") {
+        if (data.startsWith("ERROR")) {
+            if (data === "ERROR peer '0' not found") {
                 this._setDebug("Peer not found. Retrying...");
                 setTimeout(() => {
                     this._setupCall();
@@ -261,12 +311,12 @@ export class WebRTCDemoSignaling {
         // Attempt to parse JSON SDP or ICE message
         var msg;
         try {
-            msg = JSON.parse(event.data);
+            msg = JSON.parse(data);
         } catch (e) {
             if (e instanceof SyntaxError) {
-                this._setError("error parsing message as JSON: " + event.data);
+                this._setError("error parsing message as JSON: " + data);
             } else {
-                this._setError("failed to parse message: " + event.data);
+                this._setError("failed to parse message: " + data);
             }
             return;
         }
@@ -316,7 +366,12 @@ export class WebRTCDemoSignaling {
         this.state = 'connecting';
         this._setStatus("Connecting to server.");
 
-        this._ws_conn = new WebSocket(this._server);
+        // Offer compressed signaling when the browser can inflate it; the
+        // server answers large messages (the SDP answer) as binary frames
+        this._ws_conn = deflateRawSupported()
+            ? new WebSocket(this._server, [DEFLATE_SUBPROTOCOL])
+            : new WebSocket(this._server);
+        this._ws_conn.binaryType = 'arraybuffer';
 
         // Bind event handlers.
         this._ws_conn.addEventListener('open', this._onServerOpen.bind(this));
@@ -133,6 +133,12 @@ export class WebRTCDemo {
 		 */
 		this.onclipboardcontent = null;
 
+		/**
+		 * Fired with HTML clipboard content, after the plain text of the same selection
+		 * @type {function}
+		 */
+		this.onclipboardhtml = null;
+
 		/**
 		 * @type {function}
 		 */
@@ -192,6 +198,29 @@ export class WebRTCDemo {
 		 * @type {function}
 		 */
 		this.ontaskbarupdate = null;
+
+		/**
+		 * @type {function}
+		 */
+		this.onuiconfig = null;
+
+		/**
+		 * Called with the other sessions' cursors (multi-seat mode).
+		 * @type {function}
+		 */
+		this.onseatsupdate = null;
+
+		/**
+		 * This session's id, from the server's `seat` message.
+		 * @type {String}
+		 */
+		this.seatId = null;
+
+		/**
+		 * Server display size in pixels, from `display_size`.
+		 * @type {{width: number, height: number}}
+		 */
+		this.displaySize = null;
 	}
 
 	/**
@@ -507,9 +536,14 @@ export class WebRTCDemo {
 				this._setDebug("received clipboard contents, length: " + text.length);
 				this.onclipboardcontent(text);
 			}
+		} else if (msg.type === 'clipboard_html') {
+			if (msg.data !== null && this.onclipboardhtml !== null) {
+				this.onclipboardhtml(base64ToString(typeof msg.data === 'string' ? msg.data : ''));
+			}
 		} else if (msg.type === 'clipboard_start') {
-			// Start of chunked clipboard transfer
+			// Start of chunked clipboard transfer: clipboard_start,mime,size
 			this.clipboardcontent.length = 0;
+			this.clipboardmime = String(msg.data).split(',')[0];
 		} else if (msg.type === 'clipboard_data') {
 			// Chunk of clipboard data (base64)
 			if (msg.data !== null) {
@@ -519,10 +553,15 @@ export class WebRTCDemo {
 			// End of chunked clipboard transfer
 			var text = base64ToString(this.clipboardcontent.join(''));
 			this._setDebug("received chunked clipboard contents, length: " + text.length);
-			if (this.onclipboardcontent !== null) {
+			if (this.clipboardmime === 'text/html') {
+				if (this.onclipboardhtml !== null) {
+					this.onclipboardhtml(text);
+				}
+			} else if (this.onclipboardcontent !== null) {
 				this.onclipboardcontent(text);
 			}
 			this.clipboardcontent.length = 0;
+			this.clipboardmime = null;
 		} else if (msg.type === 'clipboard_binary') {
 			// Binary clipboard: clipboard_binary,mime,base64
 			this._setDebug("received binary clipboard");
@@ -581,6 +620,20 @@ export class WebRTCDemo {
 			if (this.ontaskbarupdate !== null && msg.data !== null) {
 				this.ontaskbarupdate(msg.data);
 			}
+		} else if (msg.type === 'ui_config') {
+			if (this.onuiconfig !== null && msg.data !== null) {
+				this.onuiconfig(msg.data);
+			}
+		} else if (msg.type === 'display_size') {
+			if (msg.data !== null && msg.data.width > 0 && msg.data.height > 0) {
+				this.displaySize = { width: msg.data.width, height: msg.data.height };
+			}
+		} else if (msg.type === 'seat') {
+			this.seatId = String(msg.data);
+		} else if (msg.type === 'seats') {
+			if (this.onseatsupdate !== null && Array.isArray(msg.data)) {
+				this.onseatsupdate(msg.data.filter((s) => String(s.session) !== this.seatId));
+			}
 		} else if (msg.type === 'window_state') {
 			this._setDebug("received window state: " + JSON.stringify(msg.data));
 		} else {